@@ -29,6 +29,7 @@ const CMD_TABLE: &[(&str, CmdHandler)] = &[
     ("help", do_help),
     ("ls", do_ls),
     ("mkdir", do_mkdir),
+    ("mv", do_mv),
     ("pwd", do_pwd),
     ("rm", do_rm),
     ("uname", do_uname),
@@ -196,6 +197,36 @@ fn do_rm(args: &str) {
     }
 }
 
+fn do_mv(args: &str) {
+    let paths = args.split_whitespace().collect::<Vec<_>>();
+    if paths.len() < 2 {
+        print_err!("mv", "missing operand");
+        return;
+    }
+    let (dst, srcs) = paths.split_last().unwrap();
+    let dst_is_dir = fs::metadata(dst).is_ok_and(|md| md.is_dir());
+    if srcs.len() > 1 && !dst_is_dir {
+        print_err!("mv", format_args!("target '{dst}'"), "Not a directory");
+        return;
+    }
+
+    fn mv_one(src: &str, dst: &str, dst_is_dir: bool) -> io::Result<()> {
+        if dst_is_dir {
+            // move into the directory with the same name
+            let name = src.trim_end_matches('/').rsplit('/').next().unwrap_or(src);
+            fs::rename(src, &(String::from(dst) + "/" + name))
+        } else {
+            fs::rename(src, dst)
+        }
+    }
+
+    for src in srcs {
+        if let Err(e) = mv_one(src, dst, dst_is_dir) {
+            print_err!("mv", format_args!("cannot move '{src}'"), e.as_str());
+        }
+    }
+}
+
 fn do_cd(mut args: &str) {
     if args.is_empty() {
         args = "/";
@@ -36,8 +36,12 @@ pub enum AxError {
     BadState,
     /// The connection was refused by the remote server,
     ConnectionRefused,
+    /// Cross-device or cross-filesystem (hard) link or rename.
+    CrossesDevices,
     /// A non-empty directory was specified where an empty directory was expected.
     DirectoryNotEmpty,
+    /// Loop in the filesystem or too many symbolic links encountered.
+    FilesystemLoop,
     /// Data not valid for the operation were encountered.
     ///
     /// Unlike [`InvalidInput`], this typically means that the operation
@@ -72,6 +76,8 @@ pub enum AxError {
     /// An error returned when an operation could not be completed because an
     /// "end of file" was reached prematurely.
     UnexpectedEof,
+    /// Too many (hard) links to the same filesystem object.
+    TooManyLinks,
     /// This operation is unsupported or unimplemented.
     Unsupported,
     /// The operation needs to block to complete, but the blocking operation was
@@ -201,7 +207,9 @@ impl From<AxError> for LinuxError {
             AlreadyExists => LinuxError::EEXIST,
             BadAddress | BadState => LinuxError::EFAULT,
             ConnectionRefused => LinuxError::ECONNREFUSED,
+            CrossesDevices => LinuxError::EXDEV,
             DirectoryNotEmpty => LinuxError::ENOTEMPTY,
+            FilesystemLoop => LinuxError::ELOOP,
             InvalidInput | InvalidData => LinuxError::EINVAL,
             Io => LinuxError::EIO,
             IsADirectory => LinuxError::EISDIR,
@@ -212,6 +220,7 @@ impl From<AxError> for LinuxError {
             PermissionDenied => LinuxError::EACCES,
             ResourceBusy => LinuxError::EBUSY,
             StorageFull => LinuxError::ENOSPC,
+            TooManyLinks => LinuxError::EMLINK,
             Unsupported => LinuxError::ENOSYS,
             UnexpectedEof | WriteZero => LinuxError::EIO,
             WouldBlock => LinuxError::EAGAIN,
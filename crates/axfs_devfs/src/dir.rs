@@ -1,4 +1,5 @@
 use alloc::collections::BTreeMap;
+use alloc::string::String;
 use alloc::sync::{Arc, Weak};
 use axfs_vfs::{VfsDirEntry, VfsNodeAttr, VfsNodeOps, VfsNodeRef, VfsNodeType};
 use axfs_vfs::{VfsError, VfsResult};
@@ -9,7 +10,7 @@ use spin::RwLock;
 /// It implements [`axfs_vfs::VfsNodeOps`].
 pub struct DirNode {
     parent: RwLock<Weak<dyn VfsNodeOps>>,
-    children: RwLock<BTreeMap<&'static str, VfsNodeRef>>,
+    children: RwLock<BTreeMap<String, VfsNodeRef>>,
 }
 
 impl DirNode {
@@ -25,17 +26,39 @@ impl DirNode {
         *self.parent.write() = parent.map_or(Weak::<Self>::new() as _, Arc::downgrade);
     }
 
-    /// Create a subdirectory at this directory.
-    pub fn mkdir(self: &Arc<Self>, name: &'static str) -> Arc<Self> {
+    /// Create a subdirectory at this directory, or returns the existing one.
+    ///
+    /// A node of other types with the same name is replaced.
+    pub fn mkdir(self: &Arc<Self>, name: &str) -> Arc<Self> {
+        if let Some(dir) = self.get_dir(name) {
+            return dir;
+        }
         let parent = self.clone() as VfsNodeRef;
         let node = Self::new(Some(&parent));
-        self.children.write().insert(name, node.clone());
+        self.children.write().insert(name.into(), node.clone());
         node
     }
 
     /// Add a node to this directory.
-    pub fn add(&self, name: &'static str, node: VfsNodeRef) {
-        self.children.write().insert(name, node);
+    ///
+    /// A node with the same name is replaced.
+    pub fn add(&self, name: &str, node: VfsNodeRef) {
+        self.children.write().insert(name.into(), node);
+    }
+
+    /// Remove a node from this directory, returns the removed node, or `None`
+    /// if it does not exist.
+    pub fn remove_node(&self, name: &str) -> Option<VfsNodeRef> {
+        self.children.write().remove(name)
+    }
+
+    /// Returns the subdirectory with the given name, or `None` if it does not
+    /// exist or is not a directory of this filesystem.
+    pub fn get_dir(&self, name: &str) -> Option<Arc<Self>> {
+        let children = self.children.read();
+        let node = children.get(name).filter(|n| n.as_any().is::<Self>())?;
+        // SAFETY: the node is checked to be a `DirNode`.
+        Some(unsafe { Arc::from_raw(Arc::into_raw(node.clone()) as *const Self) })
     }
 }
 
@@ -104,7 +127,7 @@ impl VfsNodeOps for DirNode {
         } else if name.is_empty() || name == "." || name == ".." {
             Ok(()) // already exists
         } else {
-            Err(VfsError::PermissionDenied) // do not support to create nodes dynamically
+            Err(VfsError::PermissionDenied) // nodes are only added by drivers
         }
     }
 
@@ -123,10 +146,15 @@ impl VfsNodeOps for DirNode {
                     .remove(rest),
             }
         } else {
-            Err(VfsError::PermissionDenied) // do not support to remove nodes dynamically
+            Err(VfsError::PermissionDenied) // nodes are only removed by drivers
         }
     }
 
+    fn rename(&self, src_path: &str, dst_path: &str) -> VfsResult {
+        log::debug!("rename at devfs: {} -> {}", src_path, dst_path);
+        Err(VfsError::PermissionDenied) // nodes are only renamed by drivers
+    }
+
     axfs_vfs::impl_vfs_dir_default! {}
 }
 
@@ -8,6 +8,7 @@ extern crate alloc;
 
 mod dir;
 mod null;
+mod random;
 mod zero;
 
 #[cfg(test)]
@@ -15,10 +16,11 @@ mod tests;
 
 pub use self::dir::DirNode;
 pub use self::null::NullDev;
+pub use self::random::RandomDev;
 pub use self::zero::ZeroDev;
 
 use alloc::sync::Arc;
-use axfs_vfs::{VfsNodeRef, VfsOps, VfsResult};
+use axfs_vfs::{FileSystemInfo, VfsNodeRef, VfsOps, VfsResult};
 use spin::once::Once;
 
 /// A device filesystem that implements [`axfs_vfs::VfsOps`].
@@ -36,16 +38,33 @@ impl DeviceFileSystem {
         }
     }
 
-    /// Create a subdirectory at the root directory.
-    pub fn mkdir(&self, name: &'static str) -> Arc<DirNode> {
+    /// Create a subdirectory at the root directory, or returns the existing
+    /// one.
+    pub fn mkdir(&self, name: &str) -> Arc<DirNode> {
         self.root.mkdir(name)
     }
 
-    /// Add a node to the root directory.
+    /// Add a node at `path` relative to the root directory.
     ///
-    /// The node must implement [`axfs_vfs::VfsNodeOps`], and be wrapped in [`Arc`].
-    pub fn add(&self, name: &'static str, node: VfsNodeRef) {
-        self.root.add(name, node);
+    /// Missing directories in the path are created, and a node with the same
+    /// path is replaced. The node must implement [`axfs_vfs::VfsNodeOps`], and
+    /// be wrapped in [`Arc`].
+    pub fn add(&self, path: &str, node: VfsNodeRef) {
+        let mut names = path.split('/').filter(|s| !s.is_empty());
+        let Some(name) = names.next_back() else {
+            return;
+        };
+        let dir = names.fold(self.root.clone(), |dir, name| dir.mkdir(name));
+        dir.add(name, node);
+    }
+
+    /// Remove the node at `path` relative to the root directory, returns the
+    /// removed node, or `None` if it does not exist.
+    pub fn remove(&self, path: &str) -> Option<VfsNodeRef> {
+        let mut names = path.split('/').filter(|s| !s.is_empty());
+        let name = names.next_back()?;
+        let dir = names.try_fold(self.root.clone(), |dir, name| dir.get_dir(name))?;
+        dir.remove_node(name)
     }
 }
 
@@ -59,6 +78,15 @@ impl VfsOps for DeviceFileSystem {
         Ok(())
     }
 
+    fn statfs(&self) -> VfsResult<FileSystemInfo> {
+        Ok(FileSystemInfo {
+            fs_type: 0x1373, // `DEVFS_SUPER_MAGIC` on Linux
+            block_size: 4096,
+            name_max: 255,
+            ..Default::default()
+        })
+    }
+
     fn root_dir(&self) -> VfsNodeRef {
         self.root.clone()
     }
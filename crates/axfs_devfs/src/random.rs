@@ -0,0 +1,62 @@
+use axfs_vfs::{VfsNodeAttr, VfsNodeOps, VfsNodePerm, VfsNodeType, VfsResult};
+use core::sync::atomic::{AtomicU64, Ordering::SeqCst};
+
+/// A random device behaves like `/dev/random` and `/dev/urandom`.
+///
+/// It returns pseudo random bytes when read, which are generated by the same
+/// linear congruential generator as `libax::rand`. It is **not**
+/// cryptographically secure. Writes are discarded.
+pub struct RandomDev {
+    seed: AtomicU64,
+}
+
+impl RandomDev {
+    /// Create a new random device with the given seed.
+    pub const fn new(seed: u64) -> Self {
+        Self {
+            seed: AtomicU64::new(seed),
+        }
+    }
+
+    fn next_u32(&self) -> u32 {
+        let seed = self.seed.load(SeqCst);
+        let new_seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1);
+        self.seed.store(new_seed, SeqCst);
+        (new_seed >> 33) as u32
+    }
+}
+
+impl Default for RandomDev {
+    fn default() -> Self {
+        Self::new(0xa2ce_a2ce)
+    }
+}
+
+impl VfsNodeOps for RandomDev {
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        Ok(VfsNodeAttr::new(
+            VfsNodePerm::default_file(),
+            VfsNodeType::CharDevice,
+            0,
+            0,
+        ))
+    }
+
+    fn read_at(&self, _offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        for chunk in buf.chunks_mut(4) {
+            let bytes = self.next_u32().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+        Ok(buf.len())
+    }
+
+    fn write_at(&self, _offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        Ok(buf.len())
+    }
+
+    fn truncate(&self, _size: u64) -> VfsResult {
+        Ok(())
+    }
+
+    axfs_vfs::impl_vfs_non_dir_default! {}
+}
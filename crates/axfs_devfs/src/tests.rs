@@ -91,6 +91,41 @@ fn test_get_parent(devfs: &DeviceFileSystem) -> VfsResult {
     Ok(())
 }
 
+fn test_add_remove(devfs: &DeviceFileSystem) -> VfsResult {
+    let root = devfs.root_dir();
+    let name = String::from("vda");
+    devfs.add(&name, Arc::new(ZeroDev));
+    devfs.add("/input//mice", Arc::new(NullDev));
+    devfs.add("foo/bar/random", Arc::new(RandomDev::default()));
+    assert_eq!(
+        root.clone().lookup("vda")?.get_attr()?.file_type(),
+        VfsNodeType::CharDevice
+    );
+    assert!(root.clone().lookup("input")?.get_attr()?.is_dir());
+
+    // existing directories are reused
+    assert!(root.clone().lookup("foo/bar/f1").is_ok());
+    assert!(root.clone().lookup("foo/f2").is_ok());
+
+    // random bytes
+    let node = root.clone().lookup("foo/bar/random")?;
+    let (mut buf1, mut buf2) = ([0; 7], [0; 7]);
+    assert_eq!(node.read_at(0, &mut buf1)?, 7);
+    assert_eq!(node.read_at(0, &mut buf2)?, 7);
+    assert_ne!(buf1, [0; 7]);
+    assert_ne!(buf1, buf2);
+
+    assert!(devfs.remove("vda").is_some());
+    assert!(devfs.remove("vda").is_none());
+    assert!(devfs.remove("input/mice/").is_some());
+    assert!(devfs.remove("null/foo").is_none());
+    assert!(devfs.remove("none/foo").is_none());
+    assert_eq!(root.clone().lookup("vda").err(), Some(VfsError::NotFound));
+    assert_eq!(root.lookup("input/mice").err(), Some(VfsError::NotFound));
+
+    Ok(())
+}
+
 #[test]
 fn test_devfs() {
     // .
@@ -112,4 +147,5 @@ fn test_devfs() {
 
     test_devfs_ops(&devfs).unwrap();
     test_get_parent(&devfs).unwrap();
+    test_add_remove(&devfs).unwrap();
 }
@@ -0,0 +1,246 @@
+//! Parsers of the archive formats: the "new" (`newc`) format of `cpio`, and
+//! the POSIX `ustar` format of `tar` with the GNU and pax extensions for long
+//! names.
+
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::str;
+
+use axfs_vfs::{VfsError, VfsNodePerm, VfsNodeType, VfsResult};
+
+/// A file, directory or symbolic link in the archive.
+pub(crate) struct Entry {
+    /// Path relative to the root, may start with `/` or `./`.
+    pub path: String,
+    pub ty: VfsNodeType,
+    pub perm: VfsNodePerm,
+    pub uid: u32,
+    pub gid: u32,
+    /// Time of last modification, in seconds since the UNIX epoch.
+    pub mtime: u64,
+    /// Contents of the file, or the target of the symbolic link.
+    pub data: &'static [u8],
+    /// Whether it is a hard link to another file in the archive.
+    pub link: Option<Link>,
+}
+
+/// How hard links to the same file are identified.
+pub(crate) enum Link {
+    /// In `cpio`, by the inode number, and the contents are stored only in
+    /// the last entry of them.
+    Ino(u64),
+    /// In `tar`, by the path of the first entry of them.
+    Path(String),
+}
+
+/// Parses the archive, whose format is detected by its magic number. An empty
+/// archive has no entries.
+pub(crate) fn parse(data: &'static [u8]) -> VfsResult<Vec<Entry>> {
+    if data.is_empty() {
+        Ok(Vec::new())
+    } else if data.starts_with(b"070701") || data.starts_with(b"070702") {
+        parse_cpio(data)
+    } else if data.get(257..262) == Some(b"ustar") {
+        parse_tar(data)
+    } else {
+        log::warn!("initramfs: unknown archive format");
+        Err(VfsError::InvalidData)
+    }
+}
+
+fn parse_cpio(data: &'static [u8]) -> VfsResult<Vec<Entry>> {
+    const HEADER_SIZE: usize = 110;
+    let mut entries = Vec::new();
+    let mut pos = 0;
+    loop {
+        let header = slice(data, pos, HEADER_SIZE)?;
+        if &header[..6] != b"070701" && &header[..6] != b"070702" {
+            return Err(VfsError::InvalidData);
+        }
+        // 13 fields of 8 hexadecimal digits after the magic number
+        let field = |idx: usize| {
+            let start = 6 + idx * 8;
+            parse_number(&header[start..start + 8], 16)
+        };
+        let name_len = field(11)? as usize;
+        let name = slice(data, pos + HEADER_SIZE, name_len)?;
+        let name = str::from_utf8(name.strip_suffix(b"\0").unwrap_or(name))
+            .map_err(|_| VfsError::InvalidData)?;
+        let data_start = align_up(pos + HEADER_SIZE + name_len, 4);
+        let file_data = slice(data, data_start, field(6)? as usize)?;
+        pos = align_up(data_start + file_data.len(), 4);
+        if name == "TRAILER!!!" {
+            break;
+        }
+
+        let (mode, nlink) = (field(1)? as u32, field(4)?);
+        let Some(ty) = node_type(mode >> 12) else {
+            log::warn!(
+                "initramfs: unsupported file type of {:?}: {:#o}",
+                name,
+                mode
+            );
+            continue;
+        };
+        entries.push(Entry {
+            path: name.to_string(),
+            ty,
+            perm: VfsNodePerm::from_bits_truncate(mode as u16),
+            uid: field(2)? as u32,
+            gid: field(3)? as u32,
+            mtime: field(5)?,
+            data: file_data,
+            link: (ty.is_file() && nlink > 1).then(|| Link::Ino(field(0).unwrap())),
+        });
+    }
+    Ok(entries)
+}
+
+fn parse_tar(data: &'static [u8]) -> VfsResult<Vec<Entry>> {
+    const BLOCK_SIZE: usize = 512;
+    let mut entries = Vec::new();
+    let mut pos = 0;
+    // long names given by the extension headers before the entry
+    let mut long_name = None;
+    let mut long_link = None;
+    // the archive ends with two zero blocks, which may be omitted
+    while let Some(header) = data.get(pos..pos + BLOCK_SIZE) {
+        if header.iter().all(|&b| b == 0) {
+            break;
+        }
+        if &header[257..262] != b"ustar" {
+            return Err(VfsError::InvalidData);
+        }
+        // the checksum is calculated with the checksum field as spaces
+        let checksum = parse_number(&header[148..156], 8)?;
+        let sum = header.iter().map(|&b| b as u64).sum::<u64>()
+            - header[148..156].iter().map(|&b| b as u64).sum::<u64>()
+            + b' ' as u64 * 8;
+        if sum != checksum {
+            log::warn!("initramfs: bad checksum of the tar header at {:#x}", pos);
+            return Err(VfsError::InvalidData);
+        }
+        let size = parse_number(&header[124..136], 8)? as usize;
+        let file_data = slice(data, pos + BLOCK_SIZE, size)?;
+        pos += BLOCK_SIZE + align_up(size, BLOCK_SIZE);
+
+        let ty = match header[156] {
+            b'0' | b'\0' | b'1' | b'7' => VfsNodeType::File,
+            b'2' => VfsNodeType::SymLink,
+            b'5' => VfsNodeType::Dir,
+            b'L' => {
+                long_name = Some(cstr(file_data)?);
+                continue;
+            }
+            b'K' => {
+                long_link = Some(cstr(file_data)?);
+                continue;
+            }
+            b'x' => {
+                for (key, value) in pax_records(file_data)? {
+                    match key {
+                        "path" => long_name = Some(value),
+                        "linkpath" => long_link = Some(value),
+                        _ => {}
+                    }
+                }
+                continue;
+            }
+            b'g' => continue,
+            ty => {
+                log::warn!("initramfs: unsupported type {:?} in tar", ty as char);
+                (long_name, long_link) = (None, None);
+                continue;
+            }
+        };
+        let link_name = match long_link.take() {
+            Some(name) => name,
+            None => cstr(&header[157..257])?,
+        };
+        let path = match long_name.take() {
+            Some(name) => name.into(),
+            // the old GNU format has other fields in place of the prefix
+            None if &header[257..263] != b"ustar\0" => cstr(&header[..100])?.into(),
+            None => match (cstr(&header[345..500])?, cstr(&header[..100])?) {
+                ("", name) => name.into(),
+                (prefix, name) => format!("{}/{}", prefix, name),
+            },
+        };
+        let mode = parse_number(&header[100..108], 8)?;
+        let hard_link = header[156] == b'1';
+        entries.push(Entry {
+            path,
+            ty,
+            perm: VfsNodePerm::from_bits_truncate(mode as u16),
+            uid: parse_number(&header[108..116], 8)? as u32,
+            gid: parse_number(&header[116..124], 8)? as u32,
+            mtime: parse_number(&header[136..148], 8)?,
+            data: if ty.is_symlink() {
+                link_name.as_bytes()
+            } else {
+                file_data
+            },
+            link: hard_link.then(|| Link::Path(link_name.to_string())),
+        });
+    }
+    Ok(entries)
+}
+
+/// Parses the records of a pax extended header, each of which is
+/// `"<length> <key>=<value>\n"`.
+fn pax_records(mut data: &'static [u8]) -> VfsResult<Vec<(&'static str, &'static str)>> {
+    let mut records = Vec::new();
+    while !data.is_empty() {
+        let space = data.iter().position(|&b| b == b' ');
+        let space = space.ok_or(VfsError::InvalidData)?;
+        let len = parse_number(&data[..space], 10)? as usize;
+        let record = data.get(space + 1..len).ok_or(VfsError::InvalidData)?;
+        let record = str::from_utf8(record).map_err(|_| VfsError::InvalidData)?;
+        let (key, value) = record
+            .strip_suffix('\n')
+            .and_then(|r| r.split_once('='))
+            .ok_or(VfsError::InvalidData)?;
+        records.push((key, value));
+        data = &data[len..];
+    }
+    Ok(records)
+}
+
+/// Returns `len` bytes at `start` of the archive, or fails if it is truncated.
+fn slice(data: &'static [u8], start: usize, len: usize) -> VfsResult<&'static [u8]> {
+    data.get(start..start + len).ok_or_else(|| {
+        log::warn!("initramfs: truncated archive");
+        VfsError::InvalidData
+    })
+}
+
+/// Parses a NUL-terminated (or NUL-padded) string.
+fn cstr(bytes: &'static [u8]) -> VfsResult<&'static str> {
+    let len = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    str::from_utf8(&bytes[..len]).map_err(|_| VfsError::InvalidData)
+}
+
+/// Parses a number in the given radix, ignoring the NULs and spaces around it.
+fn parse_number(bytes: &[u8], radix: u32) -> VfsResult<u64> {
+    let s = str::from_utf8(bytes).map_err(|_| VfsError::InvalidData)?;
+    let s = s.trim_matches(|c| c == '\0' || c == ' ');
+    if s.is_empty() {
+        return Ok(0);
+    }
+    u64::from_str_radix(s, radix).map_err(|_| VfsError::InvalidData)
+}
+
+/// Converts the file type bits (`S_IFMT >> 12`) of the mode.
+fn node_type(bits: u32) -> Option<VfsNodeType> {
+    match bits {
+        0o04 => Some(VfsNodeType::Dir),
+        0o10 => Some(VfsNodeType::File),
+        0o12 => Some(VfsNodeType::SymLink),
+        _ => None,
+    }
+}
+
+const fn align_up(pos: usize, align: usize) -> usize {
+    (pos + align - 1) & !(align - 1)
+}
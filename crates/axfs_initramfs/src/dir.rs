@@ -0,0 +1,151 @@
+use alloc::collections::BTreeMap;
+use alloc::sync::{Arc, Weak};
+use alloc::{string::String, vec::Vec};
+
+use axfs_vfs::{VfsDirEntry, VfsNodeAttr, VfsNodeOps, VfsNodeRef, VfsNodeType};
+use axfs_vfs::{VfsError, VfsResult, VfsSetAttr};
+use spin::RwLock;
+
+use crate::meta::NodeMeta;
+
+/// The directory node in the initramfs.
+///
+/// It implements [`axfs_vfs::VfsNodeOps`].
+pub struct DirNode {
+    this: Weak<DirNode>,
+    pub(super) meta: NodeMeta,
+    parent: RwLock<Weak<dyn VfsNodeOps>>,
+    children: RwLock<BTreeMap<String, VfsNodeRef>>,
+}
+
+impl DirNode {
+    pub(super) fn new(meta: NodeMeta, parent: Option<Weak<dyn VfsNodeOps>>) -> Arc<Self> {
+        Arc::new_cyclic(|this| Self {
+            this: this.clone(),
+            meta,
+            parent: RwLock::new(parent.unwrap_or_else(|| Weak::<Self>::new())),
+            children: RwLock::new(BTreeMap::new()),
+        })
+    }
+
+    pub(super) fn set_parent(&self, parent: Option<&VfsNodeRef>) {
+        *self.parent.write() = parent.map_or(Weak::<Self>::new() as _, Arc::downgrade);
+    }
+
+    pub(super) fn this(&self) -> Option<Arc<Self>> {
+        self.this.upgrade()
+    }
+
+    pub(super) fn weak(&self) -> Weak<dyn VfsNodeOps> {
+        self.this.clone()
+    }
+
+    /// Returns a string list of all entries in this directory.
+    pub fn get_entries(&self) -> Vec<String> {
+        self.children.read().keys().cloned().collect()
+    }
+
+    /// Checks whether a node with the given name exists in this directory.
+    pub fn exist(&self, name: &str) -> bool {
+        self.children.read().contains_key(name)
+    }
+
+    /// Returns the node with the given name in this directory.
+    pub(super) fn child(&self, name: &str) -> Option<VfsNodeRef> {
+        self.children.read().get(name).cloned()
+    }
+
+    /// Adds `node` to this directory with the given name, replacing the
+    /// existing one, as a later entry in the archive overwrites an earlier one
+    /// when extracted.
+    pub(super) fn insert(&self, name: &str, node: VfsNodeRef) {
+        if self.children.write().insert(name.into(), node).is_some() {
+            log::warn!("initramfs: duplicate entry {:?}", name);
+        }
+    }
+}
+
+impl VfsNodeOps for DirNode {
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        let mut attr = self.meta.fill_attr(VfsNodeAttr::new_dir(4096, 0));
+        // `.` and the entry in the parent, plus `..` of each subdirectory
+        let children = self.children.read();
+        let subdirs = children
+            .values()
+            .filter(|n| n.as_any().is::<Self>())
+            .count();
+        attr.set_nlink(2 + subdirs as u64);
+        Ok(attr)
+    }
+
+    fn set_attr(&self, _attr: &VfsSetAttr) -> VfsResult {
+        Err(VfsError::PermissionDenied)
+    }
+
+    fn parent(&self) -> Option<VfsNodeRef> {
+        self.parent.read().upgrade()
+    }
+
+    fn lookup(self: Arc<Self>, path: &str) -> VfsResult<VfsNodeRef> {
+        let (name, rest) = split_path(path);
+        let node = match name {
+            "" | "." => Ok(self.clone() as VfsNodeRef),
+            ".." => self.parent().ok_or(VfsError::NotFound),
+            _ => self.child(name).ok_or(VfsError::NotFound),
+        }?;
+
+        if let Some(rest) = rest {
+            node.lookup(rest)
+        } else {
+            Ok(node)
+        }
+    }
+
+    fn read_dir(&self, start_idx: usize, dirents: &mut [VfsDirEntry]) -> VfsResult<usize> {
+        let children = self.children.read();
+        let mut children = children.iter().skip(start_idx.max(2) - 2);
+        for (i, ent) in dirents.iter_mut().enumerate() {
+            match i + start_idx {
+                0 => *ent = VfsDirEntry::new(".", VfsNodeType::Dir),
+                1 => *ent = VfsDirEntry::new("..", VfsNodeType::Dir),
+                _ => {
+                    if let Some((name, node)) = children.next() {
+                        *ent = VfsDirEntry::new(name, node.get_attr().unwrap().file_type());
+                    } else {
+                        return Ok(i);
+                    }
+                }
+            }
+        }
+        Ok(dirents.len())
+    }
+
+    fn create(&self, _path: &str, _ty: VfsNodeType) -> VfsResult {
+        Err(VfsError::PermissionDenied)
+    }
+
+    fn remove(&self, _path: &str) -> VfsResult {
+        Err(VfsError::PermissionDenied)
+    }
+
+    fn symlink(&self, _path: &str, _target: &str) -> VfsResult {
+        Err(VfsError::PermissionDenied)
+    }
+
+    fn link(&self, _path: &str, _node: &VfsNodeRef) -> VfsResult {
+        Err(VfsError::PermissionDenied)
+    }
+
+    fn rename(&self, _src_path: &str, _dst_path: &str) -> VfsResult {
+        Err(VfsError::PermissionDenied)
+    }
+
+    axfs_vfs::impl_vfs_dir_default! {}
+}
+
+fn split_path(path: &str) -> (&str, Option<&str>) {
+    let trimmed_path = path.trim_start_matches('/');
+    trimmed_path.find('/').map_or((trimmed_path, None), |n| {
+        (&trimmed_path[..n], Some(&trimmed_path[n + 1..]))
+    })
+}
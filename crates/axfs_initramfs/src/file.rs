@@ -0,0 +1,52 @@
+use axfs_vfs::{impl_vfs_non_dir_default, VfsNodeAttr, VfsNodeOps, VfsResult};
+use axfs_vfs::{VfsError, VfsSetAttr};
+
+use crate::meta::NodeMeta;
+
+/// The file node in the initramfs, whose contents are in the archive.
+///
+/// It implements [`axfs_vfs::VfsNodeOps`].
+pub struct FileNode {
+    pub(super) meta: NodeMeta,
+    data: &'static [u8],
+}
+
+impl FileNode {
+    pub(super) fn new(meta: NodeMeta, data: &'static [u8]) -> Self {
+        Self { meta, data }
+    }
+}
+
+impl VfsNodeOps for FileNode {
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        let size = self.data.len() as u64;
+        let attr = VfsNodeAttr::new_file(size, size.div_ceil(512));
+        Ok(self.meta.fill_attr(attr))
+    }
+
+    fn set_attr(&self, _attr: &VfsSetAttr) -> VfsResult {
+        Err(VfsError::PermissionDenied)
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        let start = self.data.len().min(offset as usize);
+        let end = self.data.len().min(start + buf.len());
+        let src = &self.data[start..end];
+        buf[..src.len()].copy_from_slice(src);
+        Ok(src.len())
+    }
+
+    fn write_at(&self, _offset: u64, _buf: &[u8]) -> VfsResult<usize> {
+        Err(VfsError::PermissionDenied)
+    }
+
+    fn fsync(&self) -> VfsResult {
+        Ok(())
+    }
+
+    fn truncate(&self, _size: u64) -> VfsResult {
+        Err(VfsError::PermissionDenied)
+    }
+
+    impl_vfs_non_dir_default! {}
+}
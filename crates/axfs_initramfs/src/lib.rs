@@ -0,0 +1,279 @@
+//! Initial RAM filesystem used by [ArceOS](https://github.com/rcore-os/arceos).
+//!
+//! It is a read-only filesystem built from an archive in memory, usually one
+//! embedded in the kernel image with [`include_bytes!`], so that files are
+//! available without any disks. The archive can be in either format:
+//!
+//! - The "new" format of `cpio` (`newc`, with or without checksums), as the
+//!   initramfs of Linux, e.g., created by `find . | cpio -o -H newc`.
+//! - The POSIX `ustar` format of `tar`, e.g., created by
+//!   `tar --format=ustar -cf`. The GNU and pax extensions for long names are
+//!   supported as well.
+//!
+//! Regular files, directories, symbolic links and hard links are extracted,
+//! and other kinds of entries are skipped. File contents are not copied, they
+//! are read from the archive directly. All operations that modify the
+//! filesystem fail with [`PermissionDenied`](axfs_vfs::VfsError::PermissionDenied).
+
+#![cfg_attr(not(test), no_std)]
+
+extern crate alloc;
+
+mod archive;
+mod dir;
+mod file;
+mod meta;
+mod symlink;
+
+#[cfg(test)]
+mod tests;
+
+pub use self::dir::DirNode;
+pub use self::file::FileNode;
+pub use self::symlink::SymlinkNode;
+
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use axfs_vfs::{FileSystemInfo, VfsError, VfsNodePerm, VfsNodeRef, VfsNodeType};
+use axfs_vfs::{VfsOps, VfsResult};
+use spin::{once::Once, Mutex};
+
+use self::archive::{Entry, Link};
+use self::meta::NodeMeta;
+
+/// An initial RAM filesystem that implements [`axfs_vfs::VfsOps`].
+pub struct InitRamFileSystem {
+    parent: Once<VfsNodeRef>,
+    root: Arc<DirNode>,
+    size: usize,
+    /// The inode number of the next node, which is also the number of nodes
+    /// plus one.
+    next_ino: Mutex<u64>,
+}
+
+impl InitRamFileSystem {
+    /// Create a new instance from a `cpio` or `tar` archive, whose format is
+    /// detected automatically. An empty archive gives an empty filesystem.
+    ///
+    /// Returns [`InvalidData`](VfsError::InvalidData) if the archive is
+    /// malformed or in an unknown format.
+    pub fn new(archive: &'static [u8]) -> VfsResult<Self> {
+        let entries = archive::parse(archive)?;
+        let mut builder = Builder::new(&entries);
+        for entry in entries {
+            builder.add(entry)?;
+        }
+        Ok(Self {
+            parent: Once::new(),
+            root: builder.root,
+            size: archive.len(),
+            next_ino: Mutex::new(builder.next_ino),
+        })
+    }
+
+    /// Creates the directory at `path` and its parents if they do not exist,
+    /// so that other filesystems can be mounted on it.
+    ///
+    /// It is the only change that can be made to the filesystem, as mount
+    /// points cannot be created by [`VfsNodeOps::create`] when it is the root.
+    ///
+    /// [`VfsNodeOps::create`]: axfs_vfs::VfsNodeOps::create
+    pub fn add_mount_point(&self, path: &str) -> VfsResult {
+        let mut next_ino = self.next_ino.lock();
+        let mut builder = Builder::with_root(self.root.clone(), *next_ino);
+        let result = builder.make_dirs(&normalize(path)?);
+        *next_ino = builder.next_ino;
+        result.map(|_| ())
+    }
+
+    /// Returns the root directory node in [`Arc<DirNode>`](DirNode).
+    pub fn root_dir_node(&self) -> Arc<DirNode> {
+        self.root.clone()
+    }
+}
+
+impl VfsOps for InitRamFileSystem {
+    fn mount(&self, _path: &str, mount_point: VfsNodeRef) -> VfsResult {
+        if let Some(parent) = mount_point.parent() {
+            self.root.set_parent(Some(self.parent.call_once(|| parent)));
+        } else {
+            self.root.set_parent(None);
+        }
+        Ok(())
+    }
+
+    fn statfs(&self) -> VfsResult<FileSystemInfo> {
+        Ok(FileSystemInfo {
+            fs_type: 0x8584_58f6, // `RAMFS_MAGIC` on Linux
+            block_size: 512,
+            blocks: self.size.div_ceil(512) as u64,
+            blocks_free: 0,
+            blocks_avail: 0,
+            files: *self.next_ino.lock() - 1,
+            files_free: 0,
+            name_max: 255,
+        })
+    }
+
+    fn root_dir(&self) -> VfsNodeRef {
+        self.root.clone()
+    }
+}
+
+/// Builds the tree of nodes from the entries of the archive.
+struct Builder {
+    root: Arc<DirNode>,
+    next_ino: u64,
+    /// Files with hard links in `cpio` by their inode numbers in the archive.
+    links: BTreeMap<u64, VfsNodeRef>,
+    /// Contents of the files with hard links in `cpio`, which are only in the
+    /// last entry of them.
+    link_data: BTreeMap<u64, &'static [u8]>,
+}
+
+impl Builder {
+    fn new(entries: &[Entry]) -> Self {
+        let link_data = entries
+            .iter()
+            .filter_map(|entry| match entry.link {
+                Some(Link::Ino(ino)) if !entry.data.is_empty() => Some((ino, entry.data)),
+                _ => None,
+            })
+            .collect();
+        let root = DirNode::new(NodeMeta::new(1, VfsNodePerm::default_dir()), None);
+        Self {
+            link_data,
+            ..Self::with_root(root, 2)
+        }
+    }
+
+    fn with_root(root: Arc<DirNode>, next_ino: u64) -> Self {
+        Self {
+            root,
+            next_ino,
+            links: BTreeMap::new(),
+            link_data: BTreeMap::new(),
+        }
+    }
+
+    fn alloc_ino(&mut self) -> u64 {
+        self.next_ino += 1;
+        self.next_ino - 1
+    }
+
+    /// Returns the directory at `path`, creating the missing ones on the way
+    /// as the archive may not contain entries of all parent directories.
+    fn make_dirs(&mut self, path: &[&str]) -> VfsResult<Arc<DirNode>> {
+        let mut dir = self.root.clone();
+        for name in path {
+            let child = match dir.child(name) {
+                Some(child) => child,
+                None => {
+                    let meta = NodeMeta::new(self.alloc_ino(), VfsNodePerm::default_dir());
+                    let child = DirNode::new(meta, Some(dir.weak()));
+                    dir.insert(name, child.clone());
+                    child
+                }
+            };
+            dir = as_dir(child)?;
+        }
+        Ok(dir)
+    }
+
+    fn add(&mut self, entry: Entry) -> VfsResult {
+        let path = normalize(&entry.path)?;
+        let Some((name, parent)) = path.split_last() else {
+            // the root directory itself, which is `.` in most archives
+            if entry.ty.is_dir() {
+                self.root.meta.update(&entry);
+            }
+            return Ok(());
+        };
+        let dir = self.make_dirs(parent)?;
+        let node: VfsNodeRef = match entry.ty {
+            VfsNodeType::Dir => {
+                if let Some(Ok(old)) = dir.child(name).map(as_dir) {
+                    old.meta.update(&entry);
+                    return Ok(());
+                }
+                let meta = NodeMeta::from_entry(self.alloc_ino(), &entry);
+                DirNode::new(meta, Some(dir.weak()))
+            }
+            VfsNodeType::SymLink => {
+                let target = core::str::from_utf8(entry.data).map_err(|_| VfsError::InvalidData)?;
+                let meta = NodeMeta::from_entry(self.alloc_ino(), &entry);
+                Arc::new(SymlinkNode::new(meta, target))
+            }
+            _ => match &entry.link {
+                Some(Link::Path(target)) => {
+                    let node = self.lookup(target)?;
+                    node_meta(&node).ok_or(VfsError::InvalidData)?.link();
+                    node
+                }
+                Some(Link::Ino(ino)) if self.links.contains_key(ino) => {
+                    let node = self.links[ino].clone();
+                    node_meta(&node).unwrap().link();
+                    node
+                }
+                Some(Link::Ino(ino)) => {
+                    let meta = NodeMeta::from_entry(self.alloc_ino(), &entry);
+                    let data = self.link_data.get(ino).copied().unwrap_or_default();
+                    let node: VfsNodeRef = Arc::new(FileNode::new(meta, data));
+                    self.links.insert(*ino, node.clone());
+                    node
+                }
+                None => {
+                    let meta = NodeMeta::from_entry(self.alloc_ino(), &entry);
+                    Arc::new(FileNode::new(meta, entry.data))
+                }
+            },
+        };
+        dir.insert(name, node);
+        Ok(())
+    }
+
+    /// Looks up the target of a hard link in `tar`.
+    fn lookup(&self, path: &str) -> VfsResult<VfsNodeRef> {
+        normalize(path)?
+            .into_iter()
+            .try_fold(self.root.clone() as VfsNodeRef, |dir, name| {
+                dir.lookup(name)
+            })
+            .map_err(|_| {
+                log::warn!("initramfs: hard link to missing {:?}", path);
+                VfsError::InvalidData
+            })
+    }
+}
+
+/// Splits `path` in the archive into names, ignoring leading `/` and `.`
+/// components. `..` is not allowed.
+fn normalize(path: &str) -> VfsResult<Vec<&str>> {
+    let names = path
+        .split('/')
+        .filter(|name| !name.is_empty() && *name != ".")
+        .collect::<Vec<_>>();
+    if names.contains(&"..") {
+        log::warn!("initramfs: invalid path {:?}", path);
+        return Err(VfsError::InvalidData);
+    }
+    Ok(names)
+}
+
+fn as_dir(node: VfsNodeRef) -> VfsResult<Arc<DirNode>> {
+    match node.as_any().downcast_ref::<DirNode>() {
+        Some(dir) => dir.this().ok_or(VfsError::NotFound),
+        None => Err(VfsError::NotADirectory),
+    }
+}
+
+/// Returns the metadata of a file or symbolic link in the initramfs.
+fn node_meta(node: &VfsNodeRef) -> Option<&NodeMeta> {
+    let any = node.as_any();
+    if let Some(file) = any.downcast_ref::<FileNode>() {
+        Some(&file.meta)
+    } else {
+        any.downcast_ref::<SymlinkNode>().map(|link| &link.meta)
+    }
+}
@@ -0,0 +1,73 @@
+use core::time::Duration;
+
+use axfs_vfs::{VfsNodeAttr, VfsNodePerm};
+use spin::RwLock;
+
+use crate::archive::Entry;
+
+/// The metadata shared by all kinds of nodes in the initramfs: the inode
+/// number, permission, owner, link count and time of last modification.
+///
+/// It is only changed while the filesystem is built from the archive.
+pub(crate) struct NodeMeta {
+    ino: u64,
+    inner: RwLock<MetaInner>,
+}
+
+struct MetaInner {
+    perm: VfsNodePerm,
+    nlink: u64,
+    uid: u32,
+    gid: u32,
+    mtime: Duration,
+}
+
+impl NodeMeta {
+    /// Creates the metadata of a node with one link, owned by root.
+    pub fn new(ino: u64, perm: VfsNodePerm) -> Self {
+        Self {
+            ino,
+            inner: RwLock::new(MetaInner {
+                perm,
+                nlink: 1,
+                uid: 0,
+                gid: 0,
+                mtime: Duration::ZERO,
+            }),
+        }
+    }
+
+    /// Creates the metadata of the node of `entry`.
+    pub fn from_entry(ino: u64, entry: &Entry) -> Self {
+        let meta = Self::new(ino, entry.perm);
+        meta.update(entry);
+        meta
+    }
+
+    /// Takes the permission, owner and time from `entry`, e.g., when a
+    /// directory is created before its own entry in the archive.
+    pub fn update(&self, entry: &Entry) {
+        let mut inner = self.inner.write();
+        inner.perm = entry.perm;
+        inner.uid = entry.uid;
+        inner.gid = entry.gid;
+        inner.mtime = Duration::from_secs(entry.mtime);
+    }
+
+    /// Fills the metadata into `attr`. All times are the time of last
+    /// modification in the archive.
+    pub fn fill_attr(&self, mut attr: VfsNodeAttr) -> VfsNodeAttr {
+        let inner = self.inner.read();
+        attr.set_perm(inner.perm);
+        attr.set_ino(self.ino);
+        attr.set_nlink(inner.nlink);
+        attr.set_owner(inner.uid, inner.gid);
+        attr.set_times(inner.mtime, inner.mtime, inner.mtime);
+        attr
+    }
+
+    /// Adds a hard link to the node.
+    pub fn link(&self) {
+        self.inner.write().nlink += 1;
+    }
+}
@@ -0,0 +1,36 @@
+use alloc::string::String;
+use axfs_vfs::{impl_vfs_non_dir_default, VfsNodeAttr, VfsNodeOps, VfsResult};
+use axfs_vfs::{VfsError, VfsSetAttr};
+
+use crate::meta::NodeMeta;
+
+/// The symbolic link node in the initramfs.
+///
+/// It implements [`axfs_vfs::VfsNodeOps`].
+pub struct SymlinkNode {
+    pub(super) meta: NodeMeta,
+    target: &'static str,
+}
+
+impl SymlinkNode {
+    pub(super) fn new(meta: NodeMeta, target: &'static str) -> Self {
+        Self { meta, target }
+    }
+}
+
+impl VfsNodeOps for SymlinkNode {
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        let attr = VfsNodeAttr::new_symlink(self.target.len() as _, 0);
+        Ok(self.meta.fill_attr(attr))
+    }
+
+    fn set_attr(&self, _attr: &VfsSetAttr) -> VfsResult {
+        Err(VfsError::PermissionDenied)
+    }
+
+    fn readlink(&self) -> VfsResult<String> {
+        Ok(self.target.into())
+    }
+
+    impl_vfs_non_dir_default! {}
+}
@@ -0,0 +1,254 @@
+use std::sync::Arc;
+
+use axfs_vfs::{VfsDirEntry, VfsError, VfsNodeRef, VfsNodeType, VfsOps, VfsResult};
+
+use crate::*;
+
+const DIR: u32 = 0o040000;
+const FILE: u32 = 0o100000;
+const SYMLINK: u32 = 0o120000;
+
+/// Creates a `cpio` archive in the `newc` format, whose entries are
+/// `(name, mode, ino, nlink, data)`.
+fn cpio(entries: &[(&str, u32, u32, u32, &[u8])]) -> &'static [u8] {
+    fn pad(buf: &mut Vec<u8>) {
+        buf.resize(buf.len().next_multiple_of(4), 0);
+    }
+    let mut buf = Vec::new();
+    let trailer = ("TRAILER!!!", 0, 0, 1, &[][..]);
+    for &(name, mode, ino, nlink, data) in entries.iter().chain([&trailer]) {
+        let fields = [
+            ino,
+            mode,
+            1000,
+            100,
+            nlink,
+            1_700_000_000,
+            data.len() as u32,
+        ];
+        buf.extend_from_slice(b"070701");
+        for field in fields.iter().chain(&[0, 0, 0, 0, name.len() as u32 + 1, 0]) {
+            buf.extend_from_slice(format!("{:08x}", field).as_bytes());
+        }
+        buf.extend_from_slice(name.as_bytes());
+        buf.push(0);
+        pad(&mut buf);
+        buf.extend_from_slice(data);
+        pad(&mut buf);
+    }
+    Box::leak(buf.into_boxed_slice())
+}
+
+/// Creates a `tar` archive in the `ustar` format, whose entries are
+/// `(name, type, mode, link name, data)`.
+fn tar(entries: &[(&str, u8, u32, &str, &[u8])]) -> &'static [u8] {
+    let mut buf = Vec::new();
+    for &(name, ty, mode, link, data) in entries {
+        let mut header = [0u8; 512];
+        let mut put = |offset: usize, bytes: &[u8]| {
+            header[offset..offset + bytes.len()].copy_from_slice(bytes);
+        };
+        // the name is split into the prefix if too long
+        match name.len() {
+            0..=100 => put(0, name.as_bytes()),
+            _ => {
+                let (prefix, name) = name.rsplit_once('/').unwrap();
+                put(0, name.as_bytes());
+                put(345, prefix.as_bytes());
+            }
+        }
+        put(100, format!("{:07o}\0", mode).as_bytes());
+        put(108, b"0001750\0");
+        put(116, b"0000144\0");
+        put(124, format!("{:011o}\0", data.len()).as_bytes());
+        put(136, format!("{:011o}\0", 1_700_000_000).as_bytes());
+        put(148, b"        ");
+        put(156, &[ty]);
+        put(157, link.as_bytes());
+        put(257, b"ustar\x0000");
+        let sum = header.iter().map(|&b| b as u32).sum::<u32>();
+        header[148..156].copy_from_slice(format!("{:06o}\0 ", sum).as_bytes());
+        buf.extend_from_slice(&header);
+        buf.extend_from_slice(data);
+        buf.resize(buf.len().next_multiple_of(512), 0);
+    }
+    buf.resize(buf.len() + 1024, 0);
+    Box::leak(buf.into_boxed_slice())
+}
+
+fn read_to_string(node: &VfsNodeRef) -> VfsResult<String> {
+    let mut buf = [0; 64];
+    let len = node.read_at(0, &mut buf)?;
+    Ok(String::from_utf8(buf[..len].to_vec()).unwrap())
+}
+
+fn list(dir: &VfsNodeRef) -> VfsResult<Vec<String>> {
+    const EMPTY: VfsDirEntry = VfsDirEntry::default();
+    let mut dirents = [EMPTY; 4];
+    let mut names = Vec::new();
+    loop {
+        let n = dir.read_dir(names.len(), &mut dirents)?;
+        if n == 0 {
+            return Ok(names);
+        }
+        for ent in &dirents[..n] {
+            names.push(String::from_utf8(ent.name_as_bytes().to_vec()).unwrap());
+        }
+    }
+}
+
+#[test]
+fn test_initramfs_cpio() -> VfsResult {
+    let archive = cpio(&[
+        (".", DIR | 0o700, 1, 2, b""),
+        ("bin", DIR | 0o755, 2, 2, b""),
+        ("bin/busybox", FILE | 0o755, 3, 2, b""),
+        ("bin/sh", FILE | 0o755, 3, 2, b"#!busybox"),
+        ("etc/hosts", FILE | 0o644, 4, 1, b"127.0.0.1 localhost"),
+        ("etc/init", SYMLINK | 0o777, 5, 1, b"../bin/sh"),
+        ("dev/null", 0o020666, 6, 1, b""),
+    ]);
+    let fs = InitRamFileSystem::new(archive)?;
+    let root = fs.root_dir();
+    assert_eq!(list(&root)?, [".", "..", "bin", "etc"]);
+    assert_eq!(root.get_attr()?.perm().bits(), 0o700);
+    assert_eq!(root.get_attr()?.nlink(), 4);
+
+    // the contents of hard links are in the last entry of them
+    let busybox = root.clone().lookup("bin/busybox")?;
+    let sh = root.clone().lookup("/bin/./sh")?;
+    assert!(Arc::ptr_eq(&busybox, &sh));
+    assert_eq!(read_to_string(&busybox)?, "#!busybox");
+    assert_eq!(busybox.get_attr()?.nlink(), 2);
+
+    let hosts = root.clone().lookup("etc/hosts")?;
+    let attr = hosts.get_attr()?;
+    assert_eq!((attr.size(), attr.perm().bits()), (19, 0o644));
+    assert_eq!((attr.uid(), attr.gid()), (1000, 100));
+    assert_eq!(attr.mtime().as_secs(), 1_700_000_000);
+    let mut buf = [0; 16];
+    assert_eq!(hosts.read_at(10, &mut buf)?, 9);
+    assert_eq!(&buf[..9], b"localhost");
+    assert_eq!(hosts.read_at(100, &mut buf)?, 0);
+
+    // missing parent directories are created, other kinds of nodes skipped
+    let etc = root.clone().lookup("etc")?;
+    assert_eq!(etc.get_attr()?.perm().bits(), 0o755);
+    assert!(Arc::ptr_eq(&etc.parent().unwrap(), &root));
+    assert_eq!(list(&etc)?, [".", "..", "hosts", "init"]);
+    assert_eq!(etc.clone().lookup("init")?.readlink()?, "../bin/sh");
+    assert_eq!(root.clone().lookup("dev").err(), Some(VfsError::NotFound));
+
+    let inos = ["", "bin", "bin/sh", "etc", "etc/hosts", "etc/init"]
+        .map(|path| root.clone().lookup(path).unwrap().get_attr().unwrap().ino());
+    assert!((1..inos.len()).all(|i| !inos[..i].contains(&inos[i])));
+    assert_eq!(fs.statfs()?.files, inos.len() as u64);
+    Ok(())
+}
+
+#[test]
+fn test_initramfs_tar() -> VfsResult {
+    let long_dir = "a".repeat(50);
+    let long_path = format!("{}/{}", long_dir, "b".repeat(60));
+    let pax_path = format!("{}/{}", long_dir, "c".repeat(60));
+    let pax = format!("{} path={}\n", pax_path.len() + 10, pax_path);
+    let gnu_path = format!("{}/{}", long_dir, "d".repeat(60));
+    let archive = tar(&[
+        ("./", b'5', 0o755, "", b""),
+        ("./etc/", b'5', 0o750, "", b""),
+        ("./etc/hosts", b'0', 0o644, "", b"localhost"),
+        ("./etc/hosts2", b'1', 0o644, "./etc/hosts", b""),
+        ("./init", b'2', 0o777, "etc/hosts", b""),
+        (&long_path, b'0', 0o644, "", b"prefix"),
+        ("././@PaxHeader", b'x', 0o644, "", pax.as_bytes()),
+        ("truncated", b'0', 0o644, "", b"pax"),
+        (
+            "././@LongLink",
+            b'L',
+            0o644,
+            "",
+            format!("{}\0", gnu_path).as_bytes(),
+        ),
+        ("truncated", b'0', 0o644, "", b"gnu"),
+        ("./fifo", b'6', 0o644, "", b""),
+    ]);
+    let fs = InitRamFileSystem::new(archive)?;
+    let root = fs.root_dir();
+    assert_eq!(list(&root)?, [".", "..", long_dir.as_str(), "etc", "init"]);
+
+    let etc = root.clone().lookup("etc")?;
+    assert_eq!(etc.get_attr()?.perm().bits(), 0o750);
+    let hosts = etc.clone().lookup("hosts")?;
+    assert_eq!(read_to_string(&hosts)?, "localhost");
+    assert!(Arc::ptr_eq(&hosts, &etc.lookup("hosts2")?));
+    assert_eq!(hosts.get_attr()?.nlink(), 2);
+    assert_eq!(root.clone().lookup("init")?.readlink()?, "etc/hosts");
+
+    let read = |path: &str| read_to_string(&root.clone().lookup(path)?);
+    assert_eq!(read(&long_path)?, "prefix");
+    assert_eq!(read(&pax_path)?, "pax");
+    assert_eq!(read(&gnu_path)?, "gnu");
+    assert_eq!(list(&root.clone().lookup(&long_dir)?)?.len(), 5);
+    Ok(())
+}
+
+#[test]
+fn test_initramfs_read_only() -> VfsResult {
+    let archive = cpio(&[("dir/file", FILE | 0o644, 1, 1, b"data")]);
+    let fs = InitRamFileSystem::new(archive)?;
+    let root = fs.root_dir();
+    let file = root.clone().lookup("dir/file")?;
+    assert_eq!(file.write_at(0, b"x"), Err(VfsError::PermissionDenied));
+    assert_eq!(file.truncate(0), Err(VfsError::PermissionDenied));
+    assert_eq!(
+        file.set_attr(&Default::default()),
+        Err(VfsError::PermissionDenied)
+    );
+    assert_eq!(file.fsync(), Ok(()));
+    for result in [
+        root.create("new", VfsNodeType::File),
+        root.remove("dir/file"),
+        root.symlink("link", "dir"),
+        root.link("link", &file),
+        root.rename("dir", "dir2"),
+    ] {
+        assert_eq!(result, Err(VfsError::PermissionDenied));
+    }
+    assert_eq!(read_to_string(&file)?, "data");
+
+    // except mount points
+    fs.add_mount_point("/dir/mnt/a")?;
+    fs.add_mount_point("dev")?;
+    assert_eq!(list(&root)?, [".", "..", "dev", "dir"]);
+    assert_eq!(list(&root.clone().lookup("dir/mnt")?)?, [".", "..", "a"]);
+    assert_eq!(
+        fs.add_mount_point("dir/file/a"),
+        Err(VfsError::NotADirectory)
+    );
+
+    let info = fs.statfs()?;
+    assert_eq!(info.fs_type, 0x8584_58f6);
+    assert_eq!((info.blocks_free, info.files_free), (0, 0));
+    assert_eq!(info.files, 6);
+    Ok(())
+}
+
+#[test]
+fn test_initramfs_invalid() {
+    let fs = InitRamFileSystem::new(b"").unwrap();
+    assert_eq!(list(&fs.root_dir()).unwrap(), [".", ".."]);
+
+    let invalid = |archive: &'static [u8]| InitRamFileSystem::new(archive).err();
+    assert_eq!(invalid(b"not an archive"), Some(VfsError::InvalidData));
+    let archive = cpio(&[("../escape", FILE | 0o644, 1, 1, b"")]);
+    assert_eq!(invalid(archive), Some(VfsError::InvalidData));
+    let archive = cpio(&[("file", FILE | 0o644, 1, 1, b"data")]);
+    assert_eq!(invalid(&archive[..120]), Some(VfsError::InvalidData));
+
+    let archive = tar(&[("file", b'0', 0o644, "", b"data")]).to_vec();
+    let mut corrupted = archive.clone();
+    corrupted[0] = b'F';
+    assert_eq!(invalid(corrupted.leak()), Some(VfsError::InvalidData));
+    let archive = tar(&[("link", b'1', 0o644, "missing", b"")]);
+    assert_eq!(invalid(archive), Some(VfsError::InvalidData));
+}
@@ -0,0 +1,203 @@
+//! Overlay filesystem used by [ArceOS](https://github.com/rcore-os/arceos).
+//!
+//! It stacks a writable upper filesystem over a read-only lower one, e.g., a
+//! RAM filesystem over an immutable root image. Both are implementations of
+//! [`axfs_vfs::VfsOps`], and the lower one is never modified:
+//!
+//! - Files that exist in both layers are taken from the upper one, and
+//!   directories that exist in both layers are merged.
+//! - A node in the lower layer is copied up to the upper layer, along with its
+//!   parent directories, when it is written or its attributes are changed.
+//! - A node removed from the lower layer is hidden by a **whiteout**, an empty
+//!   file named `.wh.<name>` in the upper layer. A directory created over a
+//!   whiteout is **opaque**, i.e. it hides the directory of the same name in
+//!   the lower layer, which is marked by a `.wh..wh..opq` file in it.
+//!
+//! The changes to the upper layer are serialized by an [`OverlayLock`], which
+//! is held while both layers are read and written. It is a spin lock by
+//! default, and should be a sleeping lock if the layers may block, see
+//! [`OverlayFileSystem::with_lock`].
+//!
+//! Names starting with `.wh.` are reserved in the overlay. Directories that
+//! exist in the lower layer cannot be renamed, it fails with
+//! [`CrossesDevices`](axfs_vfs::VfsError::CrossesDevices) as `EXDEV` on Linux.
+
+#![cfg_attr(not(test), no_std)]
+
+extern crate alloc;
+
+mod node;
+
+#[cfg(test)]
+mod tests;
+
+pub use self::node::OverlayNode;
+
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use axfs_vfs::{FileSystemInfo, VfsNodeOps, VfsNodeRef, VfsOps, VfsResult};
+use spin::{Mutex, RwLock};
+
+/// The layers of an overlay filesystem, shared by all of its nodes.
+struct Overlay {
+    upper: Arc<dyn VfsOps>,
+    lower: Arc<dyn VfsOps>,
+    /// The parent of the mount point, which is the parent of the root.
+    parent: RwLock<Option<Weak<dyn VfsNodeOps>>>,
+    /// Nodes in use by their paths, so that a file opened twice is the same
+    /// node and sees the copy-up done through any of them.
+    nodes: Mutex<BTreeMap<String, Weak<OverlayNode>>>,
+    /// Serializes the lookups of the nodes not in use, so that only one node
+    /// is created for each path.
+    lookup_lock: Box<dyn OverlayLock>,
+    /// Serializes the changes to the upper layer.
+    lock: Box<dyn OverlayLock>,
+}
+
+/// A lock of an overlay filesystem, which is held while the layers are read
+/// and written.
+///
+/// The layers may block, e.g. to wait for a disk, so the lock should let other
+/// tasks run while it is contended. Otherwise, a task spinning on the lock may
+/// keep the task that holds it from running on a cooperative scheduler.
+pub trait OverlayLock: Send + Sync {
+    /// Calls `f` with the lock held.
+    fn with_lock(&self, f: &mut dyn FnMut());
+}
+
+impl OverlayLock for Mutex<()> {
+    fn with_lock(&self, f: &mut dyn FnMut()) {
+        let _guard = self.lock();
+        f();
+    }
+}
+
+/// Calls `f` with `lock` held, returns the result of `f`.
+fn locked<R>(lock: &dyn OverlayLock, f: impl FnOnce() -> R) -> R {
+    let mut f = Some(f);
+    let mut ret = None;
+    lock.with_lock(&mut || ret = f.take().map(|f| f()));
+    ret.expect("the lock did not call the function")
+}
+
+impl Overlay {
+    fn root(&self) -> Arc<OverlayNode> {
+        self.nodes.lock()[""].upgrade().unwrap()
+    }
+
+    /// Looks up the node at `path` from the root, which contains no `.` or
+    /// `..` components.
+    fn node_at(&self, path: &str) -> VfsResult<Arc<OverlayNode>> {
+        path.split('/')
+            .filter(|name| !name.is_empty())
+            .try_fold(self.root(), |dir, name| dir.child(name))
+    }
+
+    /// Updates the paths of the nodes at `src` or under it after it is moved
+    /// to `dst`.
+    fn move_nodes(&self, src: &str, dst: &str) {
+        let moved = {
+            let mut nodes = self.nodes.lock();
+            let paths = nodes
+                .keys()
+                .filter(|path| is_prefix(src, path))
+                .cloned()
+                .collect::<Vec<_>>();
+            paths
+                .into_iter()
+                .map(|path| {
+                    let node = nodes.remove(&path).unwrap();
+                    let path = String::from(dst) + &path[src.len()..];
+                    nodes.insert(path.clone(), node.clone());
+                    (node, path)
+                })
+                .collect::<Vec<_>>()
+        };
+        // nodes may be dropped here, which locks `nodes`
+        for (node, path) in moved {
+            if let Some(node) = node.upgrade() {
+                node.set_path(path);
+            }
+        }
+    }
+
+    /// Removes the node at `path` from the nodes in use, after it is removed
+    /// or replaced.
+    fn forget(&self, path: &str) {
+        self.nodes.lock().remove(path);
+    }
+}
+
+/// An overlay filesystem that implements [`axfs_vfs::VfsOps`].
+pub struct OverlayFileSystem {
+    overlay: Arc<Overlay>,
+    root: Arc<OverlayNode>,
+}
+
+impl OverlayFileSystem {
+    /// Create a new instance that stacks `upper` over `lower`, which are
+    /// locked by spin locks.
+    ///
+    /// All changes are made in `upper`, which is usually empty at first.
+    pub fn new(upper: Arc<dyn VfsOps>, lower: Arc<dyn VfsOps>) -> Self {
+        Self::with_lock::<Mutex<()>>(upper, lower)
+    }
+
+    /// Create a new instance that stacks `upper` over `lower`, which are
+    /// locked by locks of type `L`.
+    pub fn with_lock<L>(upper: Arc<dyn VfsOps>, lower: Arc<dyn VfsOps>) -> Self
+    where
+        L: OverlayLock + Default + 'static,
+    {
+        let (upper_root, lower_root) = (upper.root_dir(), lower.root_dir());
+        let overlay = Arc::new(Overlay {
+            upper,
+            lower,
+            parent: RwLock::new(None),
+            nodes: Mutex::new(BTreeMap::new()),
+            lookup_lock: Box::new(L::default()),
+            lock: Box::new(L::default()),
+        });
+        let root = OverlayNode::new_root(&overlay, upper_root, lower_root);
+        Self { overlay, root }
+    }
+
+    /// Returns the root directory node in [`Arc<OverlayNode>`](OverlayNode).
+    pub fn root_dir_node(&self) -> Arc<OverlayNode> {
+        self.root.clone()
+    }
+}
+
+impl VfsOps for OverlayFileSystem {
+    fn mount(&self, path: &str, mount_point: VfsNodeRef) -> VfsResult {
+        *self.overlay.parent.write() = mount_point.parent().map(|p| Arc::downgrade(&p));
+        self.overlay.upper.mount(path, mount_point.clone())?;
+        self.overlay.lower.mount(path, mount_point)
+    }
+
+    fn umount(&self) -> VfsResult {
+        *self.overlay.parent.write() = None;
+        self.overlay.upper.umount()?;
+        self.overlay.lower.umount()
+    }
+
+    fn statfs(&self) -> VfsResult<FileSystemInfo> {
+        // the free space is that of the upper layer
+        let mut info = self.overlay.upper.statfs()?;
+        info.fs_type = 0x794c_7630; // `OVERLAYFS_SUPER_MAGIC` on Linux
+        Ok(info)
+    }
+
+    fn root_dir(&self) -> VfsNodeRef {
+        self.root.clone()
+    }
+}
+
+/// Whether `path` is `prefix` or a path under it.
+fn is_prefix(prefix: &str, path: &str) -> bool {
+    path.strip_prefix(prefix)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
@@ -0,0 +1,614 @@
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::string::{String, ToString};
+use alloc::sync::{Arc, Weak};
+use alloc::{format, vec};
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use axfs_vfs::{VfsDirEntry, VfsError, VfsNodeAttr, VfsNodeOps, VfsNodeRef, VfsNodeType};
+use axfs_vfs::{VfsResult, VfsSetAttr};
+use spin::RwLock;
+
+use crate::{locked, Overlay};
+
+/// The prefix of the whiteouts, which hide the nodes in the lower layer.
+const WHITEOUT_PREFIX: &str = ".wh.";
+
+/// The name of the file that makes a directory in the upper layer opaque.
+const OPAQUE_MARKER: &str = ".wh..wh..opq";
+
+static NEXT_INO: AtomicU64 = AtomicU64::new(1);
+
+/// A file, directory or symbolic link in the overlay filesystem.
+///
+/// It implements [`axfs_vfs::VfsNodeOps`] by forwarding the operations to the
+/// node in the upper layer if there is one, otherwise to the node in the lower
+/// layer, which is copied up before it is changed.
+pub struct OverlayNode {
+    this: Weak<OverlayNode>,
+    overlay: Arc<Overlay>,
+    /// Path from the root of the overlay, empty for the root.
+    path: RwLock<String>,
+    ty: VfsNodeType,
+    /// Inode numbers of the layers collide, so the node has its own one.
+    ino: u64,
+    upper: RwLock<Option<VfsNodeRef>>,
+    /// The node in the lower layer, `None` if the node is only in the upper
+    /// layer, or it is an opaque directory.
+    lower: Option<VfsNodeRef>,
+    removed: AtomicBool,
+}
+
+impl OverlayNode {
+    fn new(
+        overlay: &Arc<Overlay>,
+        path: String,
+        ty: VfsNodeType,
+        upper: Option<VfsNodeRef>,
+        lower: Option<VfsNodeRef>,
+    ) -> Arc<Self> {
+        Arc::new_cyclic(|this| Self {
+            this: this.clone(),
+            overlay: overlay.clone(),
+            path: RwLock::new(path),
+            ty,
+            ino: NEXT_INO.fetch_add(1, Ordering::Relaxed),
+            upper: RwLock::new(upper),
+            lower,
+            removed: AtomicBool::new(false),
+        })
+    }
+
+    pub(super) fn new_root(
+        overlay: &Arc<Overlay>,
+        upper: VfsNodeRef,
+        lower: VfsNodeRef,
+    ) -> Arc<Self> {
+        let root = Self::new(
+            overlay,
+            String::new(),
+            VfsNodeType::Dir,
+            Some(upper),
+            Some(lower),
+        );
+        overlay
+            .nodes
+            .lock()
+            .insert(String::new(), Arc::downgrade(&root));
+        root
+    }
+
+    pub(super) fn set_path(&self, path: String) {
+        *self.path.write() = path;
+    }
+
+    fn path(&self) -> String {
+        self.path.read().clone()
+    }
+
+    fn upper(&self) -> Option<VfsNodeRef> {
+        self.upper.read().clone()
+    }
+
+    /// Returns the node that is visible in the overlay, the one in the upper
+    /// layer if any.
+    fn real(&self) -> VfsNodeRef {
+        self.upper()
+            .or_else(|| self.lower.clone())
+            .expect("overlay node without layers")
+    }
+
+    /// Returns the node in the upper layer, copying it up if it is only in
+    /// the lower layer.
+    fn upper_for_write(&self) -> VfsResult<VfsNodeRef> {
+        if let Some(upper) = self.upper() {
+            return Ok(upper);
+        }
+        locked(&*self.overlay.lock, || self.copy_up())
+    }
+
+    /// Copies the node and its parent directories up to the upper layer if
+    /// they are not there, returns the node in the upper layer.
+    ///
+    /// The lock of the overlay must be held.
+    fn copy_up(&self) -> VfsResult<VfsNodeRef> {
+        if let Some(upper) = self.upper() {
+            return Ok(upper);
+        }
+        if self.removed.load(Ordering::Acquire) {
+            return Err(VfsError::NotFound);
+        }
+        let path = self.path();
+        let (parent, name) = path.rsplit_once('/').unwrap_or(("", &path));
+        let dir = self.overlay.node_at(parent)?.copy_up()?;
+        let lower = self.lower.as_ref().unwrap();
+        let attr = lower.get_attr()?;
+        log::debug!("copy up {:?} at overlayfs: {}", attr.file_type(), path);
+        match attr.file_type() {
+            VfsNodeType::Dir | VfsNodeType::File => dir.create(name, attr.file_type())?,
+            VfsNodeType::SymLink => dir.symlink(name, &lower.readlink()?)?,
+            _ => return Err(VfsError::Unsupported),
+        }
+        let upper = dir.clone().lookup(name)?;
+        if let Err(e) = copy_node(lower, &upper, &attr) {
+            dir.remove(name).ok();
+            return Err(e);
+        }
+        *self.upper.write() = Some(upper.clone());
+        Ok(upper)
+    }
+
+    /// Looks up the child named `name` in this directory.
+    pub(super) fn child(&self, name: &str) -> VfsResult<Arc<Self>> {
+        if !self.ty.is_dir() {
+            return Err(VfsError::NotADirectory);
+        }
+        if name.starts_with(WHITEOUT_PREFIX) {
+            return Err(VfsError::NotFound);
+        }
+        let path = join(&self.path(), name);
+        let in_use = || self.overlay.nodes.lock().get(&path).and_then(Weak::upgrade);
+        if let Some(node) = in_use() {
+            return Ok(node);
+        }
+        // the layers may block, so they are not looked up with `nodes` locked
+        locked(&*self.overlay.lookup_lock, || match in_use() {
+            Some(node) => Ok(node),
+            None => self.lookup_child(name, path.clone()),
+        })
+    }
+
+    /// Looks up the child named `name` at `path` in the layers, and adds it
+    /// to the nodes in use.
+    ///
+    /// The lookup lock of the overlay must be held.
+    fn lookup_child(&self, name: &str, path: String) -> VfsResult<Arc<Self>> {
+        let mut upper = None;
+        let mut lower_visible = true;
+        if let Some(dir) = self.upper() {
+            match dir.clone().lookup(name) {
+                Ok(node) => upper = Some(node),
+                Err(VfsError::NotFound) => lower_visible = !exists(&dir, &whiteout(name))?,
+                Err(e) => return Err(e),
+            }
+        }
+        let lower = match &self.lower {
+            Some(dir) if lower_visible => match dir.clone().lookup(name) {
+                Ok(node) => Some(node),
+                Err(VfsError::NotFound) => None,
+                Err(e) => return Err(e),
+            },
+            _ => None,
+        };
+        let (ty, lower) = match (&upper, lower) {
+            (Some(node), lower) => {
+                let ty = node.get_attr()?.file_type();
+                // directories in both layers are merged, unless the upper one
+                // is opaque
+                let lower = match lower {
+                    Some(lower) if ty.is_dir() && lower.get_attr()?.is_dir() => {
+                        (!exists(node, OPAQUE_MARKER)?).then_some(lower)
+                    }
+                    _ => None,
+                };
+                (ty, lower)
+            }
+            (None, Some(node)) => (node.get_attr()?.file_type(), Some(node)),
+            (None, None) => return Err(VfsError::NotFound),
+        };
+
+        let node = Self::new(&self.overlay, path.clone(), ty, upper, lower);
+        self.overlay
+            .nodes
+            .lock()
+            .insert(path, Arc::downgrade(&node));
+        Ok(node)
+    }
+
+    /// Whether `name` is in this directory in the lower layer, so that a
+    /// whiteout is needed to hide it.
+    fn in_lower(&self, name: &str) -> VfsResult<bool> {
+        match &self.lower {
+            Some(dir) => exists(dir, name),
+            None => Ok(false),
+        }
+    }
+
+    /// Returns the names and types of the entries in this directory, merged
+    /// from both layers.
+    fn entries(&self) -> VfsResult<BTreeMap<String, VfsNodeType>> {
+        let mut entries = BTreeMap::new();
+        let mut hidden = BTreeSet::new();
+        if let Some(dir) = self.upper() {
+            for (name, ty) in read_entries(&dir)? {
+                if let Some(name) = name.strip_prefix(WHITEOUT_PREFIX) {
+                    hidden.insert(name.to_string());
+                } else {
+                    entries.insert(name, ty);
+                }
+            }
+        }
+        if let Some(dir) = &self.lower {
+            for (name, ty) in read_entries(dir)? {
+                if !name.starts_with(WHITEOUT_PREFIX) && !hidden.contains(&name) {
+                    entries.entry(name).or_insert(ty);
+                }
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Looks up the directory containing the last component of `path`,
+    /// returns it along with the last component.
+    fn lookup_parent<'a>(&self, path: &'a str) -> VfsResult<(Arc<Self>, &'a str)> {
+        let path = path.trim_end_matches('/');
+        let (parent, name) = path.rsplit_once('/').unwrap_or(("", path));
+        if name.is_empty() || name == "." || name == ".." {
+            return Err(VfsError::InvalidInput);
+        }
+        let this = self.this.upgrade().ok_or(VfsError::NotFound)?;
+        let parent = this.lookup(parent)?;
+        match parent.as_any().downcast_ref::<Self>() {
+            Some(dir) => Ok((dir.this.upgrade().ok_or(VfsError::NotFound)?, name)),
+            None if parent.get_attr()?.is_dir() => Err(VfsError::CrossesDevices),
+            None => Err(VfsError::NotADirectory),
+        }
+    }
+
+    /// Creates a new node named `name` in this directory by `create`, which
+    /// is called with the directory in the upper layer.
+    fn create_with<F>(&self, name: &str, create: F) -> VfsResult
+    where
+        F: FnOnce(&VfsNodeRef) -> VfsResult,
+    {
+        if name.starts_with(WHITEOUT_PREFIX) {
+            return Err(VfsError::InvalidInput);
+        }
+        locked(&*self.overlay.lock, || {
+            if self.child(name).is_ok() {
+                return Err(VfsError::AlreadyExists);
+            }
+            let dir = self.copy_up()?;
+            create(&dir)?;
+            let whiteout = whiteout(name);
+            if exists(&dir, &whiteout)? {
+                // a new directory must not be merged with the removed one
+                if dir.clone().lookup(name)?.get_attr()?.is_dir() {
+                    dir.create(&join(name, OPAQUE_MARKER), VfsNodeType::File)?;
+                }
+                dir.remove(&whiteout)?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Removes the node named `name` in this directory, hiding it with a
+    /// whiteout if it is in the lower layer.
+    ///
+    /// The lock of the overlay must be held.
+    fn remove_child(&self, name: &str) -> VfsResult {
+        let node = self.child(name)?;
+        if node.ty.is_dir() && !node.entries()?.is_empty() {
+            return Err(VfsError::DirectoryNotEmpty);
+        }
+        let in_lower = self.in_lower(name)?;
+        if in_lower {
+            self.copy_up()?;
+        }
+        if let Some(upper) = node.upper() {
+            if node.ty.is_dir() {
+                // only whiteouts are left in it
+                for (name, _) in read_entries(&upper)? {
+                    upper.remove(&name)?;
+                }
+            }
+            self.upper().unwrap().remove(name)?;
+        }
+        if in_lower {
+            let whiteout = whiteout(name);
+            self.upper().unwrap().create(&whiteout, VfsNodeType::File)?;
+        }
+        node.removed.store(true, Ordering::Release);
+        self.overlay.forget(&node.path());
+        Ok(())
+    }
+
+    /// Moves the node `src_name` in the directory `src` to `dst_name` in the
+    /// directory `dst`, replacing the existing one.
+    ///
+    /// The lock of the overlay must be held.
+    fn move_node(src: &Arc<Self>, src_name: &str, dst: &Arc<Self>, dst_name: &str) -> VfsResult {
+        let node = src.child(src_name)?;
+        if dst_name.starts_with(WHITEOUT_PREFIX) {
+            return Err(VfsError::InvalidInput);
+        }
+        let old = match dst.child(dst_name) {
+            Ok(old) if Arc::ptr_eq(&old, &node) => return Ok(()),
+            Ok(old) => Some(old),
+            Err(VfsError::NotFound) => None,
+            Err(e) => return Err(e),
+        };
+        if node.ty.is_dir() {
+            if node.lower.is_some() {
+                // the directory would have to be copied up as a whole
+                return Err(VfsError::CrossesDevices);
+            }
+            if crate::is_prefix(&node.path(), &dst.path()) {
+                // a directory cannot be moved into itself
+                return Err(VfsError::InvalidInput);
+            }
+        }
+        if let Some(old) = &old {
+            match (node.ty.is_dir(), old.ty.is_dir()) {
+                (false, true) => return Err(VfsError::IsADirectory),
+                (true, false) => return Err(VfsError::NotADirectory),
+                (true, true) => dst.remove_child(dst_name)?,
+                (false, false) => {}
+            }
+        }
+
+        let upper = node.copy_up()?;
+        let src_dir = src.copy_up()?;
+        let dst_dir = dst.copy_up()?;
+        let src_path = node.path();
+        let dst_path = join(&dst.path(), dst_name);
+        log::debug!("move at overlayfs: {} -> {}", src_path, dst_path);
+        let upper_root = src.overlay.upper.root_dir();
+        upper_root.rename(&src_path, &dst_path)?;
+        if let Some(old) = old {
+            old.removed.store(true, Ordering::Release);
+            src.overlay.forget(&old.path());
+        }
+
+        let whiteout = whiteout(dst_name);
+        if exists(&dst_dir, &whiteout)? {
+            dst_dir.remove(&whiteout)?;
+        }
+        if node.ty.is_dir() && dst.in_lower(dst_name)? && !exists(&upper, OPAQUE_MARKER)? {
+            upper.create(OPAQUE_MARKER, VfsNodeType::File)?;
+        }
+        if src.in_lower(src_name)? {
+            src_dir.create(&self::whiteout(src_name), VfsNodeType::File)?;
+        }
+        src.overlay.move_nodes(&src_path, &dst_path);
+        Ok(())
+    }
+}
+
+impl VfsNodeOps for OverlayNode {
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        let mut attr = self.real().get_attr()?;
+        attr.set_ino(self.ino);
+        Ok(attr)
+    }
+
+    fn set_attr(&self, attr: &VfsSetAttr) -> VfsResult {
+        self.upper_for_write()?.set_attr(attr)
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        self.real().read_at(offset, buf)
+    }
+
+    fn write_at(&self, offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        if self.ty.is_dir() {
+            return Err(VfsError::IsADirectory);
+        }
+        self.upper_for_write()?.write_at(offset, buf)
+    }
+
+    fn fsync(&self) -> VfsResult {
+        match self.upper() {
+            Some(upper) => upper.fsync(),
+            None if self.ty.is_dir() => Err(VfsError::IsADirectory),
+            None => Ok(()),
+        }
+    }
+
+    fn truncate(&self, size: u64) -> VfsResult {
+        if self.ty.is_dir() {
+            return Err(VfsError::IsADirectory);
+        }
+        self.upper_for_write()?.truncate(size)
+    }
+
+    fn parent(&self) -> Option<VfsNodeRef> {
+        if !self.ty.is_dir() {
+            return None;
+        }
+        let path = self.path();
+        match path.rsplit_once('/') {
+            _ if path.is_empty() => self.overlay.parent.read().as_ref()?.upgrade(),
+            Some((parent, _)) => Some(self.overlay.node_at(parent).ok()?),
+            None => Some(self.overlay.root()),
+        }
+    }
+
+    fn lookup(self: Arc<Self>, path: &str) -> VfsResult<VfsNodeRef> {
+        if !self.ty.is_dir() {
+            return Err(VfsError::NotADirectory);
+        }
+        let (name, rest) = split_path(path);
+        let node = match name {
+            "" | "." => Ok(self.clone() as VfsNodeRef),
+            ".." => self.parent().ok_or(VfsError::NotFound),
+            _ => self.child(name).map(|node| node as VfsNodeRef),
+        }?;
+
+        if let Some(rest) = rest {
+            node.lookup(rest)
+        } else {
+            Ok(node)
+        }
+    }
+
+    fn read_dir(&self, start_idx: usize, dirents: &mut [VfsDirEntry]) -> VfsResult<usize> {
+        if !self.ty.is_dir() {
+            return Err(VfsError::NotADirectory);
+        }
+        let entries = self.entries()?;
+        let mut entries = entries.iter().skip(start_idx.max(2) - 2);
+        for (i, ent) in dirents.iter_mut().enumerate() {
+            match i + start_idx {
+                0 => *ent = VfsDirEntry::new(".", VfsNodeType::Dir),
+                1 => *ent = VfsDirEntry::new("..", VfsNodeType::Dir),
+                _ => {
+                    if let Some((name, ty)) = entries.next() {
+                        *ent = VfsDirEntry::new(name, *ty);
+                    } else {
+                        return Ok(i);
+                    }
+                }
+            }
+        }
+        Ok(dirents.len())
+    }
+
+    fn create(&self, path: &str, ty: VfsNodeType) -> VfsResult {
+        log::debug!("create {:?} at overlayfs: {}", ty, path);
+        let (dir, name) = self.lookup_parent(path)?;
+        match ty {
+            VfsNodeType::Dir | VfsNodeType::File => dir.create_with(name, |d| d.create(name, ty)),
+            _ => Err(VfsError::Unsupported),
+        }
+    }
+
+    fn remove(&self, path: &str) -> VfsResult {
+        log::debug!("remove at overlayfs: {}", path);
+        let (dir, name) = self.lookup_parent(path)?;
+        locked(&*self.overlay.lock, || dir.remove_child(name))
+    }
+
+    fn symlink(&self, path: &str, target: &str) -> VfsResult {
+        log::debug!("symlink {} -> {} at overlayfs", path, target);
+        let (dir, name) = self.lookup_parent(path)?;
+        dir.create_with(name, |d| d.symlink(name, target))
+    }
+
+    fn link(&self, path: &str, node: &VfsNodeRef) -> VfsResult {
+        log::debug!("link at overlayfs: {}", path);
+        let (dir, name) = self.lookup_parent(path)?;
+        let node = match node.as_any().downcast_ref::<Self>() {
+            Some(node) if Arc::ptr_eq(&node.overlay, &self.overlay) => node,
+            _ => return Err(VfsError::CrossesDevices),
+        };
+        if node.ty.is_dir() {
+            return Err(VfsError::PermissionDenied);
+        }
+        let upper = node.upper_for_write()?;
+        dir.create_with(name, |d| d.link(name, &upper))
+    }
+
+    fn rename(&self, src_path: &str, dst_path: &str) -> VfsResult {
+        log::debug!("rename at overlayfs: {} -> {}", src_path, dst_path);
+        let (src_dir, src_name) = self.lookup_parent(src_path)?;
+        let (dst_dir, dst_name) = self.lookup_parent(dst_path)?;
+        locked(&*self.overlay.lock, || {
+            Self::move_node(&src_dir, src_name, &dst_dir, dst_name)
+        })
+    }
+
+    fn readlink(&self) -> VfsResult<String> {
+        self.real().readlink()
+    }
+
+    fn as_any(&self) -> &dyn core::any::Any {
+        self
+    }
+}
+
+impl Drop for OverlayNode {
+    fn drop(&mut self) {
+        let mut nodes = self.overlay.nodes.lock();
+        let path = self.path.get_mut();
+        // the path may have been taken by a new node
+        if nodes.get(path).is_some_and(|node| node.strong_count() == 0) {
+            nodes.remove(path);
+        }
+    }
+}
+
+/// Copies the contents and attributes of `src` in the lower layer to the new
+/// node `dst` in the upper layer.
+fn copy_node(src: &VfsNodeRef, dst: &VfsNodeRef, attr: &VfsNodeAttr) -> VfsResult {
+    if attr.is_file() {
+        let mut buf = vec![0; 4096];
+        let mut offset = 0;
+        loop {
+            let len = src.read_at(offset, &mut buf)?;
+            if len == 0 {
+                break;
+            }
+            let mut written = 0;
+            while written < len {
+                let n = dst.write_at(offset + written as u64, &buf[written..len])?;
+                if n == 0 {
+                    return Err(VfsError::StorageFull);
+                }
+                written += n;
+            }
+            offset += len as u64;
+        }
+    }
+    let set_attr = VfsSetAttr {
+        perm: (!attr.is_symlink()).then_some(attr.perm()),
+        uid: Some(attr.uid()),
+        gid: Some(attr.gid()),
+        atime: Some(attr.atime()),
+        mtime: Some(attr.mtime()),
+    };
+    match dst.set_attr(&set_attr) {
+        Err(VfsError::Unsupported) | Ok(()) => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Reads all entries of the directory `dir` except `.` and `..`.
+fn read_entries(dir: &VfsNodeRef) -> VfsResult<BTreeMap<String, VfsNodeType>> {
+    const EMPTY: VfsDirEntry = VfsDirEntry::default();
+    let mut entries = BTreeMap::new();
+    let mut dirents = [EMPTY; 16];
+    let mut idx = 0;
+    loop {
+        let n = dir.read_dir(idx, &mut dirents)?;
+        if n == 0 {
+            break;
+        }
+        for ent in &dirents[..n] {
+            let name =
+                core::str::from_utf8(ent.name_as_bytes()).map_err(|_| VfsError::InvalidData)?;
+            if name != "." && name != ".." {
+                entries.insert(name.to_string(), ent.entry_type());
+            }
+        }
+        idx += n;
+    }
+    Ok(entries)
+}
+
+/// Whether `name` exists in the directory `dir`.
+fn exists(dir: &VfsNodeRef, name: &str) -> VfsResult<bool> {
+    match dir.clone().lookup(name) {
+        Ok(_) => Ok(true),
+        Err(VfsError::NotFound) => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+fn whiteout(name: &str) -> String {
+    format!("{WHITEOUT_PREFIX}{name}")
+}
+
+fn join(dir: &str, name: &str) -> String {
+    if dir.is_empty() {
+        name.to_string()
+    } else {
+        format!("{dir}/{name}")
+    }
+}
+
+fn split_path(path: &str) -> (&str, Option<&str>) {
+    let trimmed_path = path.trim_start_matches('/');
+    trimmed_path.find('/').map_or((trimmed_path, None), |n| {
+        (&trimmed_path[..n], Some(&trimmed_path[n + 1..]))
+    })
+}
@@ -0,0 +1,230 @@
+use std::sync::Arc;
+
+use axfs_ramfs::RamFileSystem;
+use axfs_vfs::{VfsDirEntry, VfsError, VfsNodePerm, VfsNodeRef, VfsNodeType};
+use axfs_vfs::{VfsResult, VfsSetAttr};
+
+use crate::*;
+
+fn read_to_string(node: &VfsNodeRef) -> VfsResult<String> {
+    let mut buf = [0; 64];
+    let len = node.read_at(0, &mut buf)?;
+    Ok(String::from_utf8(buf[..len].to_vec()).unwrap())
+}
+
+fn list(dir: &VfsNodeRef) -> VfsResult<Vec<String>> {
+    const EMPTY: VfsDirEntry = VfsDirEntry::default();
+    let mut dirents = [EMPTY; 4];
+    let mut names = Vec::new();
+    loop {
+        let n = dir.read_dir(names.len(), &mut dirents)?;
+        if n == 0 {
+            return Ok(names);
+        }
+        for ent in &dirents[..n] {
+            names.push(String::from_utf8(ent.name_as_bytes().to_vec()).unwrap());
+        }
+    }
+}
+
+/// Creates the lower layer:
+///
+/// ```text
+/// /
+/// ├── etc/
+/// │   ├── hosts     "localhost"
+/// │   └── conf.d/
+/// │       └── a     "a"
+/// ├── bin/
+/// │   └── sh
+/// └── sh -> bin/sh
+/// ```
+fn new_lower() -> VfsResult<Arc<RamFileSystem>> {
+    let fs = Arc::new(RamFileSystem::default());
+    let root = fs.root_dir();
+    root.create("etc", VfsNodeType::Dir)?;
+    root.create("etc/conf.d", VfsNodeType::Dir)?;
+    root.create("bin", VfsNodeType::Dir)?;
+    root.create("bin/sh", VfsNodeType::File)?;
+    root.create("etc/hosts", VfsNodeType::File)?;
+    root.create("etc/conf.d/a", VfsNodeType::File)?;
+    root.symlink("sh", "bin/sh")?;
+    let hosts = root.clone().lookup("etc/hosts")?;
+    hosts.write_at(0, b"localhost")?;
+    hosts.set_attr(&VfsSetAttr {
+        perm: Some(VfsNodePerm::from_bits_truncate(0o644)),
+        ..Default::default()
+    })?;
+    root.clone().lookup("etc/conf.d/a")?.write_at(0, b"a")?;
+    Ok(fs)
+}
+
+#[test]
+fn test_overlayfs_copy_up() -> VfsResult {
+    let lower = new_lower()?;
+    let upper = Arc::new(RamFileSystem::default());
+    let fs = OverlayFileSystem::new(upper.clone(), lower.clone());
+    let root = fs.root_dir();
+
+    let hosts = root.clone().lookup("etc/hosts")?;
+    assert_eq!(read_to_string(&hosts)?, "localhost");
+    assert!(upper.root_dir().lookup("etc").is_err());
+
+    // the file is copied up with its parent, the lower layer is unchanged
+    assert_eq!(hosts.write_at(9, b" overlay")?, 8);
+    assert_eq!(read_to_string(&hosts)?, "localhost overlay");
+    let copied = upper.root_dir().lookup("etc/hosts")?;
+    assert_eq!(read_to_string(&copied)?, "localhost overlay");
+    assert_eq!(copied.get_attr()?.perm().bits(), 0o644);
+    let orig = lower.root_dir().lookup("etc/hosts")?;
+    assert_eq!(read_to_string(&orig)?, "localhost");
+
+    // nodes opened before the copy-up see it as well
+    let hosts2 = root.clone().lookup("/etc/./hosts")?;
+    assert!(Arc::ptr_eq(&hosts, &hosts2));
+    hosts2.truncate(4)?;
+    assert_eq!(read_to_string(&hosts)?, "loca");
+    assert_eq!(hosts.get_attr()?.ino(), hosts2.get_attr()?.ino());
+
+    // the other entries of the directory are still merged
+    let etc = root.clone().lookup("etc")?;
+    assert_eq!(list(&etc)?, [".", "..", "conf.d", "hosts"]);
+    assert!(Arc::ptr_eq(&etc.parent().unwrap(), &root));
+    assert_eq!(read_to_string(&etc.lookup("conf.d/a")?)?, "a");
+
+    // symbolic links are copied up as they are
+    root.clone()
+        .lookup("sh")?
+        .set_attr(&VfsSetAttr::default())?;
+    assert_eq!(upper.root_dir().lookup("sh")?.readlink()?, "bin/sh");
+    Ok(())
+}
+
+#[test]
+fn test_overlayfs_whiteout() -> VfsResult {
+    let lower = new_lower()?;
+    let upper = Arc::new(RamFileSystem::default());
+    let fs = OverlayFileSystem::new(upper.clone(), lower.clone());
+    let root = fs.root_dir();
+
+    // removed nodes in the lower layer are hidden by whiteouts
+    root.remove("etc/hosts")?;
+    assert_eq!(
+        root.clone().lookup("etc/hosts").err(),
+        Some(VfsError::NotFound)
+    );
+    assert!(upper.root_dir().lookup("etc/.wh.hosts").is_ok());
+    assert!(lower.root_dir().lookup("etc/hosts").is_ok());
+    assert_eq!(
+        root.clone().lookup("etc/.wh.hosts").err(),
+        Some(VfsError::NotFound)
+    );
+    assert_eq!(
+        root.create("etc/.wh.x", VfsNodeType::File),
+        Err(VfsError::InvalidInput)
+    );
+
+    assert_eq!(root.remove("etc"), Err(VfsError::DirectoryNotEmpty));
+    root.remove("etc/conf.d/a")?;
+    root.remove("etc/conf.d")?;
+    assert_eq!(list(&root.clone().lookup("etc")?)?, [".", ".."]);
+    root.remove("etc")?;
+    assert_eq!(list(&root)?, [".", "..", "bin", "sh"]);
+
+    // a new file replaces the whiteout
+    root.create("etc", VfsNodeType::File)?;
+    assert_eq!(root.clone().lookup("etc")?.get_attr()?.size(), 0);
+    root.remove("etc")?;
+
+    // a new directory is opaque, it is not merged with the removed one
+    root.create("etc", VfsNodeType::Dir)?;
+    root.create("etc/passwd", VfsNodeType::File)?;
+    assert_eq!(list(&root.clone().lookup("etc")?)?, [".", "..", "passwd"]);
+    assert_eq!(list(&root)?, [".", "..", "bin", "etc", "sh"]);
+    assert!(upper.root_dir().lookup(".wh.etc").is_err());
+    Ok(())
+}
+
+#[test]
+fn test_overlayfs_rename() -> VfsResult {
+    let lower = new_lower()?;
+    let upper = Arc::new(RamFileSystem::default());
+    let fs = OverlayFileSystem::new(upper, lower);
+    let root = fs.root_dir();
+
+    let hosts = root.clone().lookup("etc/hosts")?;
+    root.rename("etc/hosts", "hosts")?;
+    assert_eq!(
+        root.clone().lookup("etc/hosts").err(),
+        Some(VfsError::NotFound)
+    );
+    assert!(Arc::ptr_eq(&root.clone().lookup("hosts")?, &hosts));
+    assert_eq!(read_to_string(&hosts)?, "localhost");
+
+    // a file replaces the one in the lower layer
+    root.rename("hosts", "bin/sh")?;
+    assert_eq!(
+        read_to_string(&root.clone().lookup("bin/sh")?)?,
+        "localhost"
+    );
+
+    // only directories in the upper layer can be renamed
+    assert_eq!(root.rename("etc", "etc2"), Err(VfsError::CrossesDevices));
+    root.create("tmp", VfsNodeType::Dir)?;
+    root.create("tmp/a", VfsNodeType::Dir)?;
+    root.create("tmp/a/b", VfsNodeType::File)?;
+    let b = root.clone().lookup("tmp/a/b")?;
+    assert_eq!(root.rename("tmp", "tmp/a/c"), Err(VfsError::InvalidInput));
+    root.rename("tmp/a", "a")?;
+    assert!(Arc::ptr_eq(&root.clone().lookup("a/b")?, &b));
+
+    // and they are opaque when replacing ones in the lower layer
+    root.remove("etc/conf.d/a")?;
+    root.remove("etc/conf.d")?;
+    root.rename("a", "etc")?;
+    assert_eq!(list(&root.clone().lookup("etc")?)?, [".", "..", "b"]);
+    assert_eq!(list(&root)?, [".", "..", "bin", "etc", "sh", "tmp"]);
+    Ok(())
+}
+
+#[test]
+fn test_overlayfs_lock() -> VfsResult {
+    use std::cell::Cell;
+
+    thread_local! {
+        static LOCKED: Cell<usize> = const { Cell::new(0) };
+    }
+
+    /// A sleeping lock that counts how many times it is taken.
+    #[derive(Default)]
+    struct CountedLock(std::sync::Mutex<()>);
+
+    impl OverlayLock for CountedLock {
+        fn with_lock(&self, f: &mut dyn FnMut()) {
+            let _guard = self.0.lock().unwrap();
+            LOCKED.set(LOCKED.get() + 1);
+            f();
+        }
+    }
+
+    let fs = OverlayFileSystem::with_lock::<CountedLock>(
+        Arc::new(RamFileSystem::default()),
+        new_lower()?,
+    );
+    let root = fs.root_dir();
+
+    // the lookups of new nodes and the changes take the locks
+    let hosts = root.clone().lookup("etc/hosts")?;
+    assert_eq!(LOCKED.get(), 2);
+    // `etc` is not in use, so it is looked up again to be copied up
+    hosts.write_at(0, b"127.0.0.1")?;
+    assert_eq!(LOCKED.get(), 4);
+    assert_eq!(read_to_string(&hosts)?, "127.0.0.1");
+
+    // but not the nodes in use
+    let etc = root.clone().lookup("etc")?;
+    assert_eq!(LOCKED.get(), 5);
+    assert!(Arc::ptr_eq(&etc.lookup("hosts")?, &hosts));
+    assert_eq!(LOCKED.get(), 5);
+    Ok(())
+}
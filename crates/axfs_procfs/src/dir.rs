@@ -0,0 +1,151 @@
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::sync::{Arc, Weak};
+use axfs_vfs::{VfsDirEntry, VfsNodeAttr, VfsNodeOps, VfsNodeRef, VfsNodeType};
+use axfs_vfs::{VfsError, VfsResult};
+use spin::RwLock;
+
+/// The directory node in the proc filesystem.
+///
+/// It implements [`axfs_vfs::VfsNodeOps`].
+pub struct DirNode {
+    parent: RwLock<Weak<dyn VfsNodeOps>>,
+    children: RwLock<BTreeMap<String, VfsNodeRef>>,
+}
+
+impl DirNode {
+    pub(super) fn new(parent: Option<&VfsNodeRef>) -> Arc<Self> {
+        let parent = parent.map_or(Weak::<Self>::new() as _, Arc::downgrade);
+        Arc::new(Self {
+            parent: RwLock::new(parent),
+            children: RwLock::new(BTreeMap::new()),
+        })
+    }
+
+    pub(super) fn set_parent(&self, parent: Option<&VfsNodeRef>) {
+        *self.parent.write() = parent.map_or(Weak::<Self>::new() as _, Arc::downgrade);
+    }
+
+    /// Create a subdirectory at this directory, or returns the existing one.
+    ///
+    /// A node of other types with the same name is replaced.
+    pub fn mkdir(self: &Arc<Self>, name: &str) -> Arc<Self> {
+        let mut children = self.children.write();
+        if let Some(node) = children.get(name).filter(|n| n.as_any().is::<Self>()) {
+            // SAFETY: the node is checked to be a `DirNode`.
+            return unsafe { Arc::from_raw(Arc::into_raw(node.clone()) as *const Self) };
+        }
+        let parent = self.clone() as VfsNodeRef;
+        let node = Self::new(Some(&parent));
+        children.insert(name.into(), node.clone());
+        node
+    }
+
+    /// Add a node to this directory.
+    pub fn add(&self, name: &str, node: VfsNodeRef) {
+        self.children.write().insert(name.into(), node);
+    }
+}
+
+impl VfsNodeOps for DirNode {
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        Ok(VfsNodeAttr::new_dir(4096, 0))
+    }
+
+    fn parent(&self) -> Option<VfsNodeRef> {
+        self.parent.read().upgrade()
+    }
+
+    fn lookup(self: Arc<Self>, path: &str) -> VfsResult<VfsNodeRef> {
+        let (name, rest) = split_path(path);
+        let node = match name {
+            "" | "." => Ok(self.clone() as VfsNodeRef),
+            ".." => self.parent().ok_or(VfsError::NotFound),
+            _ => self
+                .children
+                .read()
+                .get(name)
+                .cloned()
+                .ok_or(VfsError::NotFound),
+        }?;
+
+        if let Some(rest) = rest {
+            node.lookup(rest)
+        } else {
+            Ok(node)
+        }
+    }
+
+    fn read_dir(&self, start_idx: usize, dirents: &mut [VfsDirEntry]) -> VfsResult<usize> {
+        let children = self.children.read();
+        let mut children = children.iter().skip(start_idx.max(2) - 2);
+        for (i, ent) in dirents.iter_mut().enumerate() {
+            match i + start_idx {
+                0 => *ent = VfsDirEntry::new(".", VfsNodeType::Dir),
+                1 => *ent = VfsDirEntry::new("..", VfsNodeType::Dir),
+                _ => {
+                    if let Some((name, node)) = children.next() {
+                        *ent = VfsDirEntry::new(name, node.get_attr().unwrap().file_type());
+                    } else {
+                        return Ok(i);
+                    }
+                }
+            }
+        }
+        Ok(dirents.len())
+    }
+
+    fn create(&self, path: &str, ty: VfsNodeType) -> VfsResult {
+        log::debug!("create {:?} at procfs: {}", ty, path);
+        let (name, rest) = split_path(path);
+        if let Some(rest) = rest {
+            match name {
+                "" | "." => self.create(rest, ty),
+                ".." => self.parent().ok_or(VfsError::NotFound)?.create(rest, ty),
+                _ => self
+                    .children
+                    .read()
+                    .get(name)
+                    .ok_or(VfsError::NotFound)?
+                    .create(rest, ty),
+            }
+        } else if name.is_empty() || name == "." || name == ".." {
+            Ok(()) // already exists
+        } else {
+            Err(VfsError::PermissionDenied) // nodes are only added by the kernel
+        }
+    }
+
+    fn remove(&self, path: &str) -> VfsResult {
+        log::debug!("remove at procfs: {}", path);
+        let (name, rest) = split_path(path);
+        if let Some(rest) = rest {
+            match name {
+                "" | "." => self.remove(rest),
+                ".." => self.parent().ok_or(VfsError::NotFound)?.remove(rest),
+                _ => self
+                    .children
+                    .read()
+                    .get(name)
+                    .ok_or(VfsError::NotFound)?
+                    .remove(rest),
+            }
+        } else {
+            Err(VfsError::PermissionDenied) // nodes are only removed by the kernel
+        }
+    }
+
+    fn rename(&self, src_path: &str, dst_path: &str) -> VfsResult {
+        log::debug!("rename at procfs: {} -> {}", src_path, dst_path);
+        Err(VfsError::PermissionDenied) // nodes are only renamed by the kernel
+    }
+
+    axfs_vfs::impl_vfs_dir_default! {}
+}
+
+fn split_path(path: &str) -> (&str, Option<&str>) {
+    let trimmed_path = path.trim_start_matches('/');
+    trimmed_path.find('/').map_or((trimmed_path, None), |n| {
+        (&trimmed_path[..n], Some(&trimmed_path[n + 1..]))
+    })
+}
@@ -0,0 +1,50 @@
+use alloc::{boxed::Box, string::String};
+use axfs_vfs::{impl_vfs_non_dir_default, VfsNodeAttr, VfsNodeOps, VfsNodePerm};
+use axfs_vfs::{VfsError, VfsResult};
+
+/// A file whose content is generated on read.
+///
+/// It has no size, and cannot be written or truncated.
+pub struct ProcFile {
+    read: Box<dyn Fn() -> String + Send + Sync>,
+}
+
+impl ProcFile {
+    /// Create a file whose content is generated by `read` each time it is
+    /// read.
+    pub fn new<F>(read: F) -> Self
+    where
+        F: Fn() -> String + Send + Sync + 'static,
+    {
+        Self {
+            read: Box::new(read),
+        }
+    }
+}
+
+impl VfsNodeOps for ProcFile {
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        let mut attr = VfsNodeAttr::new_file(0, 0);
+        attr.set_perm(VfsNodePerm::from_bits_truncate(0o444));
+        Ok(attr)
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        let content = (self.read)();
+        let start = content.len().min(offset as usize);
+        let end = content.len().min(start + buf.len());
+        let src = &content.as_bytes()[start..end];
+        buf[..src.len()].copy_from_slice(src);
+        Ok(src.len())
+    }
+
+    fn write_at(&self, _offset: u64, _buf: &[u8]) -> VfsResult<usize> {
+        Err(VfsError::PermissionDenied)
+    }
+
+    fn truncate(&self, _size: u64) -> VfsResult {
+        Err(VfsError::PermissionDenied)
+    }
+
+    impl_vfs_non_dir_default! {}
+}
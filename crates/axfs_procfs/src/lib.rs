@@ -0,0 +1,101 @@
+//! Pseudo filesystem exposing kernel states, used by
+//! [ArceOS](https://github.com/rcore-os/arceos).
+//!
+//! Files in the filesystem do not store any data, their contents are
+//! generated by the given closures each time they are read, like `/proc` on
+//! Linux.
+//!
+//! The implementation is based on [`axfs_vfs`].
+
+#![cfg_attr(not(test), no_std)]
+
+extern crate alloc;
+
+mod dir;
+mod file;
+
+#[cfg(test)]
+mod tests;
+
+pub use self::dir::DirNode;
+pub use self::file::ProcFile;
+
+use alloc::{string::String, sync::Arc};
+use axfs_vfs::{FileSystemInfo, VfsNodeRef, VfsOps, VfsResult};
+use spin::once::Once;
+
+/// A process and kernel information filesystem that implements
+/// [`axfs_vfs::VfsOps`].
+pub struct ProcFileSystem {
+    parent: Once<VfsNodeRef>,
+    root: Arc<DirNode>,
+}
+
+impl ProcFileSystem {
+    /// Create a new instance.
+    pub fn new() -> Self {
+        Self {
+            parent: Once::new(),
+            root: DirNode::new(None),
+        }
+    }
+
+    /// Create a subdirectory at the root directory, or returns the existing
+    /// one.
+    pub fn mkdir(&self, name: &str) -> Arc<DirNode> {
+        self.root.mkdir(name)
+    }
+
+    /// Add a node to the root directory.
+    ///
+    /// The node must implement [`axfs_vfs::VfsNodeOps`], and be wrapped in [`Arc`].
+    pub fn add(&self, name: &str, node: VfsNodeRef) {
+        self.root.add(name, node);
+    }
+
+    /// Add a file whose content is generated by `read`, at `path` relative to
+    /// the root directory.
+    ///
+    /// Missing directories in the path are created.
+    pub fn add_file<F>(&self, path: &str, read: F)
+    where
+        F: Fn() -> String + Send + Sync + 'static,
+    {
+        let mut names = path.split('/').filter(|s| !s.is_empty());
+        let Some(name) = names.next_back() else {
+            return;
+        };
+        let dir = names.fold(self.root.clone(), |dir, name| dir.mkdir(name));
+        dir.add(name, Arc::new(ProcFile::new(read)));
+    }
+}
+
+impl VfsOps for ProcFileSystem {
+    fn mount(&self, _path: &str, mount_point: VfsNodeRef) -> VfsResult {
+        if let Some(parent) = mount_point.parent() {
+            self.root.set_parent(Some(self.parent.call_once(|| parent)));
+        } else {
+            self.root.set_parent(None);
+        }
+        Ok(())
+    }
+
+    fn statfs(&self) -> VfsResult<FileSystemInfo> {
+        Ok(FileSystemInfo {
+            fs_type: 0x9fa0, // `PROC_SUPER_MAGIC` on Linux
+            block_size: 4096,
+            name_max: 255,
+            ..Default::default()
+        })
+    }
+
+    fn root_dir(&self) -> VfsNodeRef {
+        self.root.clone()
+    }
+}
+
+impl Default for ProcFileSystem {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,103 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use axfs_vfs::{VfsDirEntry, VfsError, VfsNodeOps, VfsNodeType, VfsResult};
+
+use crate::*;
+
+static READS: AtomicUsize = AtomicUsize::new(0);
+
+fn read_all(node: &VfsNodeRef) -> VfsResult<String> {
+    let mut buf = [0; 4];
+    let mut content = Vec::new();
+    loop {
+        let n = node.read_at(content.len() as u64, &mut buf)?;
+        if n == 0 {
+            return Ok(String::from_utf8(content).unwrap());
+        }
+        content.extend_from_slice(&buf[..n]);
+    }
+}
+
+fn test_procfs_ops(procfs: &ProcFileSystem) -> VfsResult {
+    let root = procfs.root_dir();
+    assert!(root.get_attr()?.is_dir());
+    assert_eq!(
+        root.clone().lookup("cpuinfo").err(),
+        Some(VfsError::NotFound)
+    );
+    assert_eq!(
+        root.clone().lookup("version/").err(),
+        Some(VfsError::NotADirectory)
+    );
+
+    // contents are generated on each read
+    let node = root.clone().lookup("///version")?;
+    let attr = node.get_attr()?;
+    assert_eq!(attr.file_type(), VfsNodeType::File);
+    assert_eq!((attr.size(), attr.perm().bits()), (0, 0o444));
+    assert_eq!(read_all(&node)?, "ArceOS 0.1.0\n");
+    let node = root.clone().lookup("./net/../net//sockets")?;
+    let mut buf = [0; 16];
+    assert_eq!(node.read_at(0, &mut buf)?, 9);
+    assert_eq!(&buf[..9], b"reads: 0\n");
+    assert_eq!(node.read_at(0, &mut buf)?, 9);
+    assert_eq!(&buf[..9], b"reads: 1\n");
+    assert_eq!(node.read_at(5, &mut buf)?, 4);
+    assert_eq!(&buf[..4], b": 2\n");
+    assert_eq!(node.read_at(100, &mut buf)?, 0);
+
+    assert_eq!(
+        node.write_at(0, b"data").err(),
+        Some(VfsError::PermissionDenied)
+    );
+    assert_eq!(
+        root.create("foo", VfsNodeType::File).err(),
+        Some(VfsError::PermissionDenied)
+    );
+    assert_eq!(
+        root.remove("version").err(),
+        Some(VfsError::PermissionDenied)
+    );
+
+    const EMPTY: VfsDirEntry = VfsDirEntry::default();
+    let mut dirents = [EMPTY; 8];
+    assert_eq!(root.read_dir(0, &mut dirents)?, 4);
+    assert_eq!(dirents[2].name_as_bytes(), b"net");
+    assert_eq!(dirents[2].entry_type(), VfsNodeType::Dir);
+    assert_eq!(dirents[3].name_as_bytes(), b"version");
+    Ok(())
+}
+
+fn test_get_parent(procfs: &ProcFileSystem) -> VfsResult {
+    let root = procfs.root_dir();
+    assert!(root.parent().is_none());
+
+    let node = root.clone().lookup("net/sockets")?;
+    assert!(node.parent().is_none());
+    let node = root.clone().lookup("net/..")?;
+    assert!(Arc::ptr_eq(&node, &root));
+    Ok(())
+}
+
+#[test]
+fn test_procfs() {
+    // .
+    // ├── net
+    // │   └── sockets
+    // └── version
+
+    let procfs = ProcFileSystem::new();
+    procfs.add_file("version", || "ArceOS 0.1.0\n".into());
+    procfs.add_file("/net/sockets", || {
+        format!("reads: {}\n", READS.fetch_add(1, Ordering::Relaxed))
+    });
+
+    // existing directories are reused
+    let net = procfs.mkdir("net");
+    assert!(Arc::ptr_eq(&net, &procfs.mkdir("net")));
+    assert!(net.clone().lookup("sockets").is_ok());
+
+    test_procfs_ops(&procfs).unwrap();
+    test_get_parent(&procfs).unwrap();
+}
@@ -3,27 +3,36 @@ use alloc::sync::{Arc, Weak};
 use alloc::{string::String, vec::Vec};
 
 use axfs_vfs::{VfsDirEntry, VfsNodeAttr, VfsNodeOps, VfsNodeRef, VfsNodeType};
-use axfs_vfs::{VfsError, VfsResult};
+use axfs_vfs::{VfsError, VfsNodePerm, VfsResult, VfsSetAttr};
 use spin::RwLock;
 
 use crate::file::FileNode;
+use crate::meta::NodeMeta;
+use crate::symlink::SymlinkNode;
+use crate::usage::Usage;
 
 /// The directory node in the RAM filesystem.
 ///
 /// It implements [`axfs_vfs::VfsNodeOps`].
 pub struct DirNode {
     this: Weak<DirNode>,
+    meta: NodeMeta,
     parent: RwLock<Weak<dyn VfsNodeOps>>,
     children: RwLock<BTreeMap<String, VfsNodeRef>>,
 }
 
 impl DirNode {
-    pub(super) fn new(parent: Option<Weak<dyn VfsNodeOps>>) -> Arc<Self> {
-        Arc::new_cyclic(|this| Self {
+    pub(super) fn new(
+        parent: Option<Weak<dyn VfsNodeOps>>,
+        usage: &Arc<Usage>,
+    ) -> VfsResult<Arc<Self>> {
+        let meta = NodeMeta::new(VfsNodePerm::default_dir(), usage)?;
+        Ok(Arc::new_cyclic(|this| Self {
             this: this.clone(),
+            meta,
             parent: RwLock::new(parent.unwrap_or_else(|| Weak::<Self>::new())),
             children: RwLock::new(BTreeMap::new()),
-        })
+        }))
     }
 
     pub(super) fn set_parent(&self, parent: Option<&VfsNodeRef>) {
@@ -46,12 +55,48 @@ impl DirNode {
             log::error!("AlreadyExists {}", name);
             return Err(VfsError::AlreadyExists);
         }
+        let usage = self.meta.usage();
         let node: VfsNodeRef = match ty {
-            VfsNodeType::File => Arc::new(FileNode::new()),
-            VfsNodeType::Dir => Self::new(Some(self.this.clone())),
+            VfsNodeType::File => Arc::new(FileNode::new(usage)?),
+            VfsNodeType::Dir => Self::new(Some(self.this.clone()), usage)?,
             _ => return Err(VfsError::Unsupported),
         };
         self.children.write().insert(name.into(), node);
+        self.meta.touch_modify();
+        Ok(())
+    }
+
+    /// Creates a new symbolic link with the given name and target in this
+    /// directory.
+    pub fn create_symlink(&self, name: &str, target: &str) -> VfsResult {
+        let mut children = self.children.write();
+        if children.contains_key(name) {
+            return Err(VfsError::AlreadyExists);
+        }
+        let node = SymlinkNode::new(target, self.meta.usage())?;
+        children.insert(name.into(), Arc::new(node));
+        self.meta.touch_modify();
+        Ok(())
+    }
+
+    /// Adds the file or symbolic link `node` to this directory with the given
+    /// name, as a hard link.
+    pub fn link_node(&self, name: &str, node: &VfsNodeRef) -> VfsResult {
+        let any = node.as_any();
+        if any.is::<DirNode>() {
+            return Err(VfsError::PermissionDenied);
+        } else if !any.is::<FileNode>() && !any.is::<SymlinkNode>() {
+            return Err(VfsError::CrossesDevices);
+        }
+        let mut children = self.children.write();
+        if children.contains_key(name) {
+            return Err(VfsError::AlreadyExists);
+        }
+        children.insert(name.into(), node.clone());
+        if let Some(meta) = node_meta(node) {
+            meta.link();
+        }
+        self.meta.touch_modify();
         Ok(())
     }
 
@@ -64,14 +109,128 @@ impl DirNode {
                 return Err(VfsError::DirectoryNotEmpty);
             }
         }
-        children.remove(name);
+        if let Some(node) = children.remove(name) {
+            unlink(&node);
+        }
+        self.meta.touch_modify();
+        Ok(())
+    }
+
+    /// Looks up the directory containing the last component of `path`,
+    /// returns it along with the last component.
+    fn lookup_parent<'a>(&self, path: &'a str) -> VfsResult<(Arc<Self>, &'a str)> {
+        let path = path.trim_end_matches('/');
+        let (parent, name) = path.rsplit_once('/').unwrap_or(("", path));
+        if name.is_empty() || name == "." || name == ".." {
+            return Err(VfsError::InvalidInput);
+        }
+        let this = self.this.upgrade().ok_or(VfsError::NotFound)?;
+        let parent = this.lookup(parent)?;
+        match parent.as_any().downcast_ref::<Self>() {
+            Some(dir) => Ok((dir.this.upgrade().ok_or(VfsError::NotFound)?, name)),
+            None if parent.get_attr()?.is_dir() => Err(VfsError::CrossesDevices),
+            None => Err(VfsError::NotADirectory),
+        }
+    }
+
+    /// Checks whether `node` can replace the existing node `old` in `dst`,
+    /// returns `false` if they are the same node and nothing needs to be done.
+    fn check_replace(node: &VfsNodeRef, old: &VfsNodeRef, src: &Arc<Self>) -> VfsResult<bool> {
+        if Arc::ptr_eq(node, old) {
+            return Ok(false);
+        }
+        let is_dir = node.as_any().is::<Self>();
+        match old.as_any().downcast_ref::<Self>() {
+            Some(_) if !is_dir => Err(VfsError::IsADirectory),
+            // `src` is locked, and it is not empty anyway
+            Some(old) if core::ptr::eq(old, src.as_ref()) => Err(VfsError::DirectoryNotEmpty),
+            Some(old) if !old.children.read().is_empty() => Err(VfsError::DirectoryNotEmpty),
+            None if is_dir => Err(VfsError::NotADirectory),
+            _ => Ok(true),
+        }
+    }
+
+    /// Moves the node `src_name` in the directory `src` to `dst_name` in the
+    /// directory `dst`, replacing the existing one.
+    fn move_node(src: &Arc<Self>, src_name: &str, dst: &Arc<Self>, dst_name: &str) -> VfsResult {
+        if Arc::ptr_eq(src, dst) {
+            let mut children = src.children.write();
+            let node = children.get(src_name).ok_or(VfsError::NotFound)?.clone();
+            if src_name == dst_name {
+                return Ok(());
+            }
+            if let Some(old) = children.get(dst_name) {
+                if !Self::check_replace(&node, old, src)? {
+                    return Ok(());
+                }
+            }
+            children.remove(src_name);
+            if let Some(old) = children.insert(dst_name.into(), node.clone()) {
+                unlink(&old);
+            }
+            touch_change(&node);
+            src.meta.touch_modify();
+            return Ok(());
+        }
+
+        // lock the directories in a fixed order to avoid deadlocks
+        let (mut src_children, mut dst_children) = if Arc::as_ptr(src) < Arc::as_ptr(dst) {
+            let src_children = src.children.write();
+            (src_children, dst.children.write())
+        } else {
+            let dst_children = dst.children.write();
+            (src.children.write(), dst_children)
+        };
+        let node = src_children
+            .get(src_name)
+            .ok_or(VfsError::NotFound)?
+            .clone();
+        let moved_dir = node.as_any().downcast_ref::<Self>();
+        if moved_dir.is_some() {
+            // a directory cannot be moved into itself
+            let mut ancestor = Some(dst.clone() as VfsNodeRef);
+            while let Some(dir) = ancestor {
+                if Arc::ptr_eq(&dir, &node) {
+                    return Err(VfsError::InvalidInput);
+                }
+                ancestor = dir.parent();
+            }
+        }
+        if let Some(old) = dst_children.get(dst_name) {
+            if !Self::check_replace(&node, old, src)? {
+                return Ok(());
+            }
+        }
+        src_children.remove(src_name);
+        if let Some(dir) = moved_dir {
+            dir.set_parent(Some(&(dst.clone() as VfsNodeRef)));
+        }
+        if let Some(old) = dst_children.insert(dst_name.into(), node.clone()) {
+            unlink(&old);
+        }
+        touch_change(&node);
+        src.meta.touch_modify();
+        dst.meta.touch_modify();
         Ok(())
     }
 }
 
 impl VfsNodeOps for DirNode {
     fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
-        Ok(VfsNodeAttr::new_dir(4096, 0))
+        let mut attr = self.meta.fill_attr(VfsNodeAttr::new_dir(4096, 0));
+        // `.` and the entry in the parent, plus `..` of each subdirectory
+        let children = self.children.read();
+        let subdirs = children
+            .values()
+            .filter(|n| n.as_any().is::<Self>())
+            .count();
+        attr.set_nlink(2 + subdirs as u64);
+        Ok(attr)
+    }
+
+    fn set_attr(&self, attr: &VfsSetAttr) -> VfsResult {
+        self.meta.set_attr(attr);
+        Ok(())
     }
 
     fn parent(&self) -> Option<VfsNodeRef> {
@@ -165,6 +324,64 @@ impl VfsNodeOps for DirNode {
         }
     }
 
+    fn symlink(&self, path: &str, target: &str) -> VfsResult {
+        log::debug!("symlink {} -> {} at ramfs", path, target);
+        let (name, rest) = split_path(path);
+        if let Some(rest) = rest {
+            match name {
+                "" | "." => self.symlink(rest, target),
+                ".." => self
+                    .parent()
+                    .ok_or(VfsError::NotFound)?
+                    .symlink(rest, target),
+                _ => {
+                    let subdir = self
+                        .children
+                        .read()
+                        .get(name)
+                        .ok_or(VfsError::NotFound)?
+                        .clone();
+                    subdir.symlink(rest, target)
+                }
+            }
+        } else if name.is_empty() || name == "." || name == ".." {
+            Err(VfsError::AlreadyExists)
+        } else {
+            self.create_symlink(name, target)
+        }
+    }
+
+    fn link(&self, path: &str, node: &VfsNodeRef) -> VfsResult {
+        log::debug!("link at ramfs: {}", path);
+        let (name, rest) = split_path(path);
+        if let Some(rest) = rest {
+            match name {
+                "" | "." => self.link(rest, node),
+                ".." => self.parent().ok_or(VfsError::NotFound)?.link(rest, node),
+                _ => {
+                    let subdir = self
+                        .children
+                        .read()
+                        .get(name)
+                        .ok_or(VfsError::NotFound)?
+                        .clone();
+                    subdir.link(rest, node)
+                }
+            }
+        } else if name.is_empty() || name == "." || name == ".." {
+            Err(VfsError::AlreadyExists)
+        } else {
+            self.link_node(name, node)
+        }
+    }
+
+    fn rename(&self, src_path: &str, dst_path: &str) -> VfsResult {
+        log::debug!("rename at ramfs: {} -> {}", src_path, dst_path);
+        let (src_dir, src_name) = self.lookup_parent(src_path)?;
+        let (dst_dir, dst_name) = self.lookup_parent(dst_path)?;
+        Self::move_node(&src_dir, src_name, &dst_dir, dst_name)
+    }
+
     axfs_vfs::impl_vfs_dir_default! {}
 }
 
@@ -174,3 +391,29 @@ fn split_path(path: &str) -> (&str, Option<&str>) {
         (&trimmed_path[..n], Some(&trimmed_path[n + 1..]))
     })
 }
+
+/// Returns the metadata of a node in the RAM filesystem.
+fn node_meta(node: &VfsNodeRef) -> Option<&NodeMeta> {
+    let any = node.as_any();
+    if let Some(file) = any.downcast_ref::<FileNode>() {
+        Some(&file.meta)
+    } else if let Some(link) = any.downcast_ref::<SymlinkNode>() {
+        Some(&link.meta)
+    } else {
+        any.downcast_ref::<DirNode>().map(|dir| &dir.meta)
+    }
+}
+
+/// Drops a link to the node whose entry has been removed.
+fn unlink(node: &VfsNodeRef) {
+    if let Some(meta) = node_meta(node) {
+        meta.unlink();
+    }
+}
+
+/// Updates the time of last status change of the node.
+fn touch_change(node: &VfsNodeRef) {
+    if let Some(meta) = node_meta(node) {
+        meta.touch_change();
+    }
+}
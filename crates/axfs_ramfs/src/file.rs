@@ -1,56 +1,135 @@
-use alloc::vec::Vec;
-use axfs_vfs::{impl_vfs_non_dir_default, VfsNodeAttr, VfsNodeOps, VfsResult};
+use alloc::collections::BTreeMap;
+use alloc::{boxed::Box, sync::Arc, vec};
+use core::ops::Range;
+
+use axfs_vfs::{
+    impl_vfs_non_dir_default, VfsError, VfsNodeAttr, VfsNodeOps, VfsNodePerm, VfsResult, VfsSetAttr,
+};
 use spin::RwLock;
 
+use crate::meta::NodeMeta;
+use crate::usage::{Usage, PAGE_SIZE};
+
 /// The file node in the RAM filesystem.
 ///
 /// It implements [`axfs_vfs::VfsNodeOps`].
 pub struct FileNode {
-    content: RwLock<Vec<u8>>,
+    pub(super) meta: NodeMeta,
+    content: RwLock<Content>,
+}
+
+/// The contents of a file, stored in page-sized chunks so that growing a
+/// large file does not reallocate all of it. Pages that have never been
+/// written are holes, which read as zeros and take no space.
+#[derive(Default)]
+struct Content {
+    size: u64,
+    pages: BTreeMap<u64, Box<[u8]>>,
 }
 
 impl FileNode {
-    pub(super) const fn new() -> Self {
-        Self {
-            content: RwLock::new(Vec::new()),
-        }
+    pub(super) fn new(usage: &Arc<Usage>) -> VfsResult<Self> {
+        Ok(Self {
+            meta: NodeMeta::new(VfsNodePerm::default_file(), usage)?,
+            content: RwLock::new(Content::default()),
+        })
     }
 }
 
 impl VfsNodeOps for FileNode {
     fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
-        Ok(VfsNodeAttr::new_file(self.content.read().len() as _, 0))
+        let content = self.content.read();
+        let blocks = content.pages.len() as u64 * (PAGE_SIZE as u64 / 512);
+        let attr = VfsNodeAttr::new_file(content.size, blocks);
+        Ok(self.meta.fill_attr(attr))
+    }
+
+    fn set_attr(&self, attr: &VfsSetAttr) -> VfsResult {
+        self.meta.set_attr(attr);
+        Ok(())
     }
 
     fn truncate(&self, size: u64) -> VfsResult {
         let mut content = self.content.write();
-        if size < content.len() as u64 {
-            content.truncate(size as _);
-        } else {
-            content.resize(size as _, 0);
+        if size < content.size {
+            let freed = content.pages.split_off(&size.div_ceil(PAGE_SIZE as u64));
+            self.meta.usage().free_pages(freed.len() as u64);
+            // zero the rest of the last page, in case the file grows again
+            let tail = (size % PAGE_SIZE as u64) as usize;
+            if let Some(page) = content.pages.get_mut(&(size / PAGE_SIZE as u64)) {
+                page[tail..].fill(0);
+            }
         }
+        content.size = size;
+        self.meta.touch_modify();
         Ok(())
     }
 
     fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
         let content = self.content.read();
-        let start = content.len().min(offset as usize);
-        let end = content.len().min(offset as usize + buf.len());
-        let src = &content[start..end];
-        buf[..src.len()].copy_from_slice(src);
-        Ok(src.len())
+        let end = content.size.min(offset.saturating_add(buf.len() as u64));
+        let len = end.saturating_sub(offset) as usize;
+        for (idx, src, dst) in chunks(offset, len) {
+            match content.pages.get(&idx) {
+                Some(page) => buf[dst].copy_from_slice(&page[src]),
+                None => buf[dst].fill(0),
+            }
+        }
+        self.meta.touch_access();
+        Ok(len)
     }
 
     fn write_at(&self, offset: u64, buf: &[u8]) -> VfsResult<usize> {
-        let offset = offset as usize;
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let end = offset
+            .checked_add(buf.len() as u64)
+            .ok_or(VfsError::InvalidInput)?;
         let mut content = self.content.write();
-        if offset + buf.len() > content.len() {
-            content.resize(offset + buf.len(), 0);
+        // take all the new pages at once, so that nothing is written if the
+        // filesystem is full
+        let new_pages = chunks(offset, buf.len())
+            .filter(|(idx, ..)| !content.pages.contains_key(idx))
+            .count();
+        self.meta.usage().alloc_pages(new_pages as u64)?;
+        for (idx, dst, src) in chunks(offset, buf.len()) {
+            let page = content
+                .pages
+                .entry(idx)
+                .or_insert_with(|| vec![0; PAGE_SIZE].into_boxed_slice());
+            page[dst].copy_from_slice(&buf[src]);
         }
-        let dst = &mut content[offset..offset + buf.len()];
-        dst.copy_from_slice(&buf[..dst.len()]);
+        content.size = content.size.max(end);
+        self.meta.touch_modify();
         Ok(buf.len())
     }
 
     impl_vfs_non_dir_default! {}
 }
+
+impl Drop for FileNode {
+    fn drop(&mut self) {
+        let pages = self.content.get_mut().pages.len();
+        self.meta.usage().free_pages(pages as u64);
+    }
+}
+
+/// Splits `len` bytes from `offset` of a file into pieces within pages.
+///
+/// Yields the page index, the range in the page and the range in the buffer
+/// of each piece.
+fn chunks(offset: u64, len: usize) -> impl Iterator<Item = (u64, Range<usize>, Range<usize>)> {
+    let mut done = 0;
+    core::iter::from_fn(move || {
+        if done >= len {
+            return None;
+        }
+        let pos = offset + done as u64;
+        let start = (pos % PAGE_SIZE as u64) as usize;
+        let n = (PAGE_SIZE - start).min(len - done);
+        let piece = (pos / PAGE_SIZE as u64, start..start + n, done..done + n);
+        done += n;
+        Some(piece)
+    })
+}
@@ -8,29 +8,44 @@ extern crate alloc;
 
 mod dir;
 mod file;
+mod meta;
+mod symlink;
+mod usage;
 
 #[cfg(test)]
 mod tests;
 
 pub use self::dir::DirNode;
 pub use self::file::FileNode;
+pub use self::symlink::SymlinkNode;
 
 use alloc::sync::Arc;
-use axfs_vfs::{VfsNodeRef, VfsOps, VfsResult};
+use axfs_vfs::{FileSystemInfo, VfsNodeRef, VfsOps, VfsResult};
 use spin::once::Once;
 
+use self::usage::{Usage, PAGE_SIZE};
+
 /// A RAM filesystem that implements [`axfs_vfs::VfsOps`].
 pub struct RamFileSystem {
     parent: Once<VfsNodeRef>,
     root: Arc<DirNode>,
+    usage: Arc<Usage>,
 }
 
 impl RamFileSystem {
     /// Create a new instance.
-    pub fn new() -> Self {
+    ///
+    /// File contents can take at most `max_bytes` bytes, rounded down to whole
+    /// pages, and there can be at most `max_inodes` nodes including the root
+    /// directory. `None` means unlimited. Operations exceeding the limits fail
+    /// with [`StorageFull`](axfs_vfs::VfsError::StorageFull).
+    pub fn new(max_bytes: Option<u64>, max_inodes: Option<u64>) -> Self {
+        // there is always room for the root directory
+        let usage = Arc::new(Usage::new(max_bytes, max_inodes.map(|n| n.max(1))));
         Self {
             parent: Once::new(),
-            root: DirNode::new(None),
+            root: DirNode::new(None, &usage).unwrap(),
+            usage,
         }
     }
 
@@ -50,13 +65,30 @@ impl VfsOps for RamFileSystem {
         Ok(())
     }
 
+    fn statfs(&self) -> VfsResult<FileSystemInfo> {
+        let (max_pages, pages) = self.usage.pages();
+        let (max_inodes, inodes) = self.usage.inodes();
+        let blocks_free = max_pages.map_or(0, |max| max.saturating_sub(pages));
+        Ok(FileSystemInfo {
+            fs_type: 0x0102_1994, // `TMPFS_MAGIC` on Linux
+            block_size: PAGE_SIZE as u64,
+            blocks: max_pages.unwrap_or(0),
+            blocks_free,
+            blocks_avail: blocks_free,
+            files: max_inodes.unwrap_or(0),
+            files_free: max_inodes.map_or(0, |max| max.saturating_sub(inodes)),
+            name_max: 255,
+        })
+    }
+
     fn root_dir(&self) -> VfsNodeRef {
         self.root.clone()
     }
 }
 
 impl Default for RamFileSystem {
+    /// Creates an unlimited RAM filesystem.
     fn default() -> Self {
-        Self::new()
+        Self::new(None, None)
     }
 }
@@ -0,0 +1,132 @@
+use alloc::sync::Arc;
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::time::Duration;
+
+use axfs_vfs::{time::current_time, VfsNodeAttr, VfsNodePerm, VfsResult, VfsSetAttr};
+use spin::RwLock;
+
+use crate::usage::Usage;
+
+static NEXT_INO: AtomicU64 = AtomicU64::new(1);
+
+/// The metadata shared by all kinds of nodes in the RAM filesystem: the inode
+/// number, permission, owner, link count and times.
+///
+/// It also holds the inode of the node in the filesystem [`Usage`], which is
+/// released when dropped.
+pub(crate) struct NodeMeta {
+    ino: u64,
+    usage: Arc<Usage>,
+    inner: RwLock<MetaInner>,
+}
+
+struct MetaInner {
+    perm: VfsNodePerm,
+    nlink: u64,
+    uid: u32,
+    gid: u32,
+    atime: Duration,
+    mtime: Duration,
+    ctime: Duration,
+}
+
+impl NodeMeta {
+    /// Creates the metadata of a new node with one link, all times are set to
+    /// the current time.
+    ///
+    /// Returns [`StorageFull`](axfs_vfs::VfsError::StorageFull) if there are no
+    /// inodes left in `usage`.
+    pub fn new(perm: VfsNodePerm, usage: &Arc<Usage>) -> VfsResult<Self> {
+        usage.alloc_inode()?;
+        let now = current_time();
+        Ok(Self {
+            ino: NEXT_INO.fetch_add(1, Ordering::Relaxed),
+            usage: usage.clone(),
+            inner: RwLock::new(MetaInner {
+                perm,
+                nlink: 1,
+                uid: 0,
+                gid: 0,
+                atime: now,
+                mtime: now,
+                ctime: now,
+            }),
+        })
+    }
+
+    /// Returns the usage of the filesystem the node belongs to.
+    pub fn usage(&self) -> &Arc<Usage> {
+        &self.usage
+    }
+
+    /// Fills the metadata into `attr`.
+    pub fn fill_attr(&self, mut attr: VfsNodeAttr) -> VfsNodeAttr {
+        let inner = self.inner.read();
+        attr.set_perm(inner.perm);
+        attr.set_ino(self.ino);
+        attr.set_nlink(inner.nlink);
+        attr.set_owner(inner.uid, inner.gid);
+        attr.set_times(inner.atime, inner.mtime, inner.ctime);
+        attr
+    }
+
+    /// Changes the attributes given in `attr`, and updates the time of last
+    /// status change.
+    pub fn set_attr(&self, attr: &VfsSetAttr) {
+        let mut inner = self.inner.write();
+        if let Some(perm) = attr.perm {
+            inner.perm = perm;
+        }
+        if let Some(uid) = attr.uid {
+            inner.uid = uid;
+        }
+        if let Some(gid) = attr.gid {
+            inner.gid = gid;
+        }
+        if let Some(atime) = attr.atime {
+            inner.atime = atime;
+        }
+        if let Some(mtime) = attr.mtime {
+            inner.mtime = mtime;
+        }
+        inner.ctime = current_time();
+    }
+
+    /// Updates the time of last access.
+    pub fn touch_access(&self) {
+        self.inner.write().atime = current_time();
+    }
+
+    /// Updates the times of last modification and last status change.
+    pub fn touch_modify(&self) {
+        let now = current_time();
+        let mut inner = self.inner.write();
+        inner.mtime = now;
+        inner.ctime = now;
+    }
+
+    /// Updates the time of last status change.
+    pub fn touch_change(&self) {
+        self.inner.write().ctime = current_time();
+    }
+
+    /// Adds a hard link to the node.
+    pub fn link(&self) {
+        let mut inner = self.inner.write();
+        inner.nlink += 1;
+        inner.ctime = current_time();
+    }
+
+    /// Drops a hard link to the node.
+    pub fn unlink(&self) {
+        let mut inner = self.inner.write();
+        inner.nlink = inner.nlink.saturating_sub(1);
+        inner.ctime = current_time();
+    }
+}
+
+impl Drop for NodeMeta {
+    fn drop(&mut self) {
+        self.usage.free_inode();
+    }
+}
@@ -0,0 +1,42 @@
+use alloc::{string::String, sync::Arc};
+use axfs_vfs::VfsSetAttr;
+use axfs_vfs::{impl_vfs_non_dir_default, VfsNodeAttr, VfsNodeOps, VfsNodePerm, VfsResult};
+
+use crate::meta::NodeMeta;
+use crate::usage::Usage;
+
+/// The symbolic link node in the RAM filesystem.
+///
+/// It implements [`axfs_vfs::VfsNodeOps`].
+pub struct SymlinkNode {
+    pub(super) meta: NodeMeta,
+    target: String,
+}
+
+impl SymlinkNode {
+    pub(super) fn new(target: &str, usage: &Arc<Usage>) -> VfsResult<Self> {
+        Ok(Self {
+            meta: NodeMeta::new(VfsNodePerm::from_bits_truncate(0o777), usage)?,
+            target: target.into(),
+        })
+    }
+}
+
+impl VfsNodeOps for SymlinkNode {
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        let attr = VfsNodeAttr::new_symlink(self.target.len() as _, 0);
+        Ok(self.meta.fill_attr(attr))
+    }
+
+    fn set_attr(&self, attr: &VfsSetAttr) -> VfsResult {
+        self.meta.set_attr(attr);
+        Ok(())
+    }
+
+    fn readlink(&self) -> VfsResult<String> {
+        self.meta.touch_access();
+        Ok(self.target.clone())
+    }
+
+    impl_vfs_non_dir_default! {}
+}
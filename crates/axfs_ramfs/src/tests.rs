@@ -1,6 +1,8 @@
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
 
-use axfs_vfs::{VfsError, VfsNodeType, VfsResult};
+use axfs_vfs::{VfsError, VfsNodePerm, VfsNodeType, VfsResult, VfsSetAttr};
 
 use crate::*;
 
@@ -98,7 +100,7 @@ fn test_ramfs() {
     // ├── f1
     // └── f2
 
-    let ramfs = RamFileSystem::new();
+    let ramfs = RamFileSystem::new(None, None);
     let root = ramfs.root_dir();
     root.create("f1", VfsNodeType::File).unwrap();
     root.create("f2", VfsNodeType::File).unwrap();
@@ -134,3 +136,229 @@ fn test_ramfs() {
     assert_eq!(root.remove("./foo"), Ok(()));
     assert!(ramfs.root_dir_node().get_entries().is_empty());
 }
+
+#[test]
+fn test_ramfs_links() {
+    let ramfs = RamFileSystem::new(None, None);
+    let root = ramfs.root_dir();
+    root.create("foo", VfsNodeType::Dir).unwrap();
+    root.create("foo/f1", VfsNodeType::File).unwrap();
+
+    // symbolic links are not followed by `lookup`
+    assert_eq!(root.symlink("foo/link", "../f1"), Ok(()));
+    assert_eq!(
+        root.symlink("./foo/link", "f1").err(),
+        Some(VfsError::AlreadyExists)
+    );
+    let link = root.clone().lookup("foo/link").unwrap();
+    assert!(link.get_attr().unwrap().is_symlink());
+    assert_eq!(link.get_attr().unwrap().size(), 5);
+    assert_eq!(link.readlink().as_deref(), Ok("../f1"));
+    assert_eq!(link.lookup("x").err(), Some(VfsError::NotADirectory));
+    let f1 = root.clone().lookup("foo/f1").unwrap();
+    assert_eq!(f1.readlink().err(), Some(VfsError::InvalidInput));
+
+    // hard links refer to the same node
+    assert_eq!(root.link("f2", &f1), Ok(()));
+    assert!(Arc::ptr_eq(&root.clone().lookup("f2").unwrap(), &f1));
+    assert_eq!(root.link("f2", &f1).err(), Some(VfsError::AlreadyExists));
+    let foo = root.clone().lookup("foo").unwrap();
+    assert_eq!(
+        root.link("foo2", &foo).err(),
+        Some(VfsError::PermissionDenied)
+    );
+    assert_eq!(root.remove("foo/f1"), Ok(()));
+    assert_eq!(f1.write_at(0, b"test"), Ok(4));
+    assert_eq!(
+        root.clone()
+            .lookup("f2")
+            .unwrap()
+            .get_attr()
+            .unwrap()
+            .size(),
+        4
+    );
+
+    assert_eq!(root.remove("foo/link"), Ok(()));
+    assert_eq!(root.remove("foo"), Ok(()));
+    assert_eq!(root.remove("f2"), Ok(()));
+    assert!(ramfs.root_dir_node().get_entries().is_empty());
+}
+
+#[test]
+fn test_ramfs_rename() {
+    let ramfs = RamFileSystem::new(None, None);
+    let root = ramfs.root_dir();
+    root.create("foo", VfsNodeType::Dir).unwrap();
+    root.create("foo/bar", VfsNodeType::Dir).unwrap();
+    root.create("foo/f1", VfsNodeType::File).unwrap();
+    root.create("f2", VfsNodeType::File).unwrap();
+
+    // replace a file, and move a directory to another parent
+    let f1 = root.clone().lookup("foo/f1").unwrap();
+    assert_eq!(root.rename("foo/f1", "f2"), Ok(()));
+    assert!(Arc::ptr_eq(&root.clone().lookup("f2").unwrap(), &f1));
+    assert_eq!(root.rename("./foo/bar/", "bar"), Ok(()));
+    let bar = root.clone().lookup("bar").unwrap();
+    assert!(Arc::ptr_eq(&bar.parent().unwrap(), &root));
+    assert_eq!(ramfs.root_dir_node().get_entries(), ["bar", "f2", "foo"]);
+
+    // error cases
+    assert_eq!(root.rename("f1", "f3").err(), Some(VfsError::NotFound));
+    assert_eq!(root.rename("f2", "bar").err(), Some(VfsError::IsADirectory));
+    assert_eq!(
+        root.rename("bar", "f2").err(),
+        Some(VfsError::NotADirectory)
+    );
+    assert_eq!(
+        root.rename("foo", "foo/bar").err(),
+        Some(VfsError::InvalidInput)
+    );
+    assert_eq!(root.rename("f2", "..").err(), Some(VfsError::InvalidInput));
+    root.create("bar/f4", VfsNodeType::File).unwrap();
+    assert_eq!(
+        root.rename("foo", "bar").err(),
+        Some(VfsError::DirectoryNotEmpty)
+    );
+    assert_eq!(
+        root.rename("bar/f4", "bar").err(),
+        Some(VfsError::IsADirectory)
+    );
+    assert_eq!(root.rename("bar", "foo"), Ok(()));
+    assert_eq!(ramfs.root_dir_node().get_entries(), ["f2", "foo"]);
+}
+
+static TIME: AtomicU64 = AtomicU64::new(0);
+
+fn fake_clock() -> Duration {
+    Duration::from_secs(TIME.load(Ordering::Relaxed))
+}
+
+#[test]
+fn test_ramfs_attrs() {
+    axfs_vfs::time::set_clock(fake_clock);
+    TIME.store(100, Ordering::Relaxed);
+    let ramfs = RamFileSystem::new(None, None);
+    let root = ramfs.root_dir();
+    root.create("foo", VfsNodeType::Dir).unwrap();
+    root.create("foo/f1", VfsNodeType::File).unwrap();
+    let foo = root.clone().lookup("foo").unwrap();
+    let f1 = root.clone().lookup("foo/f1").unwrap();
+    let attr = f1.get_attr().unwrap();
+    assert_ne!(attr.ino(), foo.get_attr().unwrap().ino());
+    assert_eq!((attr.nlink(), attr.uid(), attr.gid()), (1, 0, 0));
+    assert_eq!(attr.atime(), Duration::from_secs(100));
+    assert_eq!(attr.mtime(), Duration::from_secs(100));
+    assert_eq!(attr.ctime(), Duration::from_secs(100));
+    assert_eq!(root.get_attr().unwrap().nlink(), 3);
+
+    // writes change the file, creations change the directory
+    TIME.store(200, Ordering::Relaxed);
+    assert_eq!(f1.write_at(0, b"test"), Ok(4));
+    let attr = f1.get_attr().unwrap();
+    assert_eq!(attr.atime(), Duration::from_secs(100));
+    assert_eq!(attr.mtime(), Duration::from_secs(200));
+    assert_eq!(foo.get_attr().unwrap().mtime(), Duration::from_secs(100));
+    root.create("foo/f2", VfsNodeType::File).unwrap();
+    assert_eq!(foo.get_attr().unwrap().mtime(), Duration::from_secs(200));
+    TIME.store(300, Ordering::Relaxed);
+    assert_eq!(f1.read_at(0, &mut [0; 4]), Ok(4));
+    assert_eq!(f1.get_attr().unwrap().atime(), Duration::from_secs(300));
+
+    // chmod, chown and utimes
+    let set = VfsSetAttr {
+        perm: Some(VfsNodePerm::from_bits_truncate(0o600)),
+        uid: Some(1000),
+        gid: Some(100),
+        mtime: Some(Duration::from_secs(50)),
+        ..Default::default()
+    };
+    assert_eq!(f1.set_attr(&set), Ok(()));
+    let attr = f1.get_attr().unwrap();
+    assert_eq!(attr.perm().bits(), 0o600);
+    assert_eq!((attr.uid(), attr.gid()), (1000, 100));
+    assert_eq!(attr.atime(), Duration::from_secs(300));
+    assert_eq!(attr.mtime(), Duration::from_secs(50));
+    assert_eq!(attr.ctime(), Duration::from_secs(300));
+
+    // hard links
+    assert_eq!(root.link("f3", &f1), Ok(()));
+    assert_eq!(f1.get_attr().unwrap().nlink(), 2);
+    assert_eq!(root.rename("f3", "foo/f2"), Ok(()));
+    assert_eq!(f1.get_attr().unwrap().nlink(), 2);
+    assert_eq!(root.remove("foo/f1"), Ok(()));
+    assert_eq!(f1.get_attr().unwrap().nlink(), 1);
+}
+
+#[test]
+fn test_ramfs_limits() {
+    const PAGE: u64 = 4096;
+    let ramfs = RamFileSystem::new(Some(4 * PAGE + 100), Some(4));
+    let root = ramfs.root_dir();
+    let info = ramfs.statfs().unwrap();
+    assert_eq!((info.blocks, info.blocks_free), (4, 4));
+    assert_eq!((info.files, info.files_free), (4, 3));
+
+    // the root directory takes one inode
+    root.create("f1", VfsNodeType::File).unwrap();
+    root.create("dir", VfsNodeType::Dir).unwrap();
+    assert_eq!(root.symlink("link", "f1"), Ok(()));
+    assert_eq!(
+        root.create("f2", VfsNodeType::File),
+        Err(VfsError::StorageFull)
+    );
+    assert_eq!(root.symlink("link2", "f1"), Err(VfsError::StorageFull));
+    assert_eq!(root.remove("dir"), Ok(()));
+    assert_eq!(ramfs.statfs().unwrap().files_free, 1);
+    root.create("f2", VfsNodeType::File).unwrap();
+
+    // contents take whole pages, holes take none
+    let f1 = root.clone().lookup("f1").unwrap();
+    let data = (0..3 * PAGE + 10).map(|i| i as u8).collect::<Vec<_>>();
+    assert_eq!(f1.write_at(0, &data), Ok(data.len()));
+    assert_eq!(ramfs.statfs().unwrap().blocks_free, 0);
+    assert_eq!(f1.get_attr().unwrap().blocks(), 4 * 8);
+    let f2 = root.clone().lookup("f2").unwrap();
+    assert_eq!(f2.write_at(0, b"x"), Err(VfsError::StorageFull));
+    assert_eq!(f2.truncate(100 * PAGE), Ok(()));
+    assert_eq!(f2.get_attr().unwrap().size(), 100 * PAGE);
+    assert_eq!(f2.get_attr().unwrap().blocks(), 0);
+    let mut buf = [1; 16];
+    assert_eq!(f2.read_at(50 * PAGE, &mut buf), Ok(16));
+    assert_eq!(buf, [0; 16]);
+
+    // writes within existing pages need no space, nothing is written if
+    // new pages cannot be taken
+    assert_eq!(f1.write_at(PAGE - 2, b"abcd"), Ok(4));
+    assert_eq!(
+        f1.write_at(4 * PAGE - 2, b"efgh"),
+        Err(VfsError::StorageFull)
+    );
+    assert_eq!(f1.get_attr().unwrap().size(), data.len() as u64);
+    let mut buf = vec![0; data.len() + 10];
+    assert_eq!(f1.read_at(0, &mut buf), Ok(data.len()));
+    assert_eq!(&buf[PAGE as usize - 2..][..4], b"abcd");
+    assert_eq!(
+        buf[2 * PAGE as usize..data.len()],
+        data[2 * PAGE as usize..]
+    );
+
+    // truncating releases the pages, and the stale tail reads as zeros
+    assert_eq!(f1.truncate(PAGE + 1), Ok(()));
+    assert_eq!(ramfs.statfs().unwrap().blocks_free, 2);
+    assert_eq!(f1.truncate(2 * PAGE), Ok(()));
+    let mut buf = [1; 4];
+    assert_eq!(f1.read_at(PAGE, &mut buf), Ok(4));
+    assert_eq!(buf, [b'c', 0, 0, 0]);
+
+    // removed files are released once they are no longer used
+    assert_eq!(root.remove("f1"), Ok(()));
+    assert_eq!(ramfs.statfs().unwrap().blocks_free, 2);
+    drop(f1);
+    let info = ramfs.statfs().unwrap();
+    assert_eq!((info.blocks_free, info.files_free), (4, 1));
+
+    // unlimited
+    let info = RamFileSystem::default().statfs().unwrap();
+    assert_eq!((info.blocks, info.blocks_free, info.files), (0, 0, 0));
+}
@@ -0,0 +1,76 @@
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use axfs_vfs::{VfsError, VfsResult};
+
+/// Size of the chunks in which file contents are stored.
+pub(crate) const PAGE_SIZE: usize = 4096;
+
+/// The space used by a RAM filesystem, shared by all of its nodes.
+///
+/// Nodes take one inode each, and files take one page for each [`PAGE_SIZE`]
+/// chunk of their contents that has been written. Both are released when the
+/// node is dropped, i.e. when it is unlinked and no longer opened.
+pub(crate) struct Usage {
+    max_pages: Option<u64>,
+    max_inodes: Option<u64>,
+    pages: AtomicU64,
+    inodes: AtomicU64,
+}
+
+impl Usage {
+    /// Creates an empty usage with the given limits, `None` for unlimited.
+    pub fn new(max_bytes: Option<u64>, max_inodes: Option<u64>) -> Self {
+        Self {
+            max_pages: max_bytes.map(|bytes| bytes / PAGE_SIZE as u64),
+            max_inodes,
+            pages: AtomicU64::new(0),
+            inodes: AtomicU64::new(0),
+        }
+    }
+
+    /// Returns the limit and the current number of pages.
+    pub fn pages(&self) -> (Option<u64>, u64) {
+        (self.max_pages, self.pages.load(Ordering::Relaxed))
+    }
+
+    /// Returns the limit and the current number of inodes.
+    pub fn inodes(&self) -> (Option<u64>, u64) {
+        (self.max_inodes, self.inodes.load(Ordering::Relaxed))
+    }
+
+    /// Takes `count` pages, returns [`VfsError::StorageFull`] if there is not
+    /// enough space left.
+    pub fn alloc_pages(&self, count: u64) -> VfsResult {
+        alloc(&self.pages, self.max_pages, count)
+    }
+
+    /// Gives back `count` pages taken by [`Usage::alloc_pages`].
+    pub fn free_pages(&self, count: u64) {
+        self.pages.fetch_sub(count, Ordering::Relaxed);
+    }
+
+    /// Takes an inode for a new node, returns [`VfsError::StorageFull`] if the
+    /// limit is reached.
+    pub fn alloc_inode(&self) -> VfsResult {
+        alloc(&self.inodes, self.max_inodes, 1)
+    }
+
+    /// Gives back an inode taken by [`Usage::alloc_inode`].
+    pub fn free_inode(&self) {
+        self.inodes.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+fn alloc(used: &AtomicU64, max: Option<u64>, count: u64) -> VfsResult {
+    let mut old = used.load(Ordering::Relaxed);
+    loop {
+        let new = old.checked_add(count).ok_or(VfsError::StorageFull)?;
+        if max.is_some_and(|max| new > max) {
+            return Err(VfsError::StorageFull);
+        }
+        match used.compare_exchange_weak(old, new, Ordering::Relaxed, Ordering::Relaxed) {
+            Ok(_) => return Ok(()),
+            Err(cur) => old = cur,
+        }
+    }
+}
@@ -1,10 +1,9 @@
 //! Virtual filesystem interfaces used by [ArceOS](https://github.com/rcore-os/arceos).
 //!
-//! A filesystem is a set of files and directories (symbol links are not
-//! supported currently), collectively referred to as **nodes**, which are
-//! conceptually similar to [inodes] in Linux. A file system needs to implement
-//! the [`VfsOps`] trait, its files and directories need to implement the
-//! [`VfsNodeOps`] trait.
+//! A filesystem is a set of files, directories and symbolic links, collectively
+//! referred to as **nodes**, which are conceptually similar to [inodes] in
+//! Linux. A file system needs to implement the [`VfsOps`] trait, its nodes need
+//! to implement the [`VfsNodeOps`] trait.
 //!
 //! The [`VfsOps`] trait provides the following operations on a filesystem:
 //!
@@ -14,14 +13,15 @@
 //! - [`statfs()`](VfsOps::statfs): Get the attributes of the filesystem.
 //! - [`root_dir()`](VfsOps::root_dir): Get root directory of the filesystem.
 //!
-//! The [`VfsNodeOps`] trait provides the following operations on a file or a
-//! directory:
+//! The [`VfsNodeOps`] trait provides the following operations on a file, a
+//! directory, or a symbolic link:
 //!
-//! | Operation | Description | file/directory |
+//! | Operation | Description | file/directory/symlink |
 //! | --- | --- | --- |
-//! | [`open()`](VfsNodeOps::open) | Do something when the node is opened | both |
-//! | [`release()`](VfsNodeOps::release) | Do something when the node is closed | both |
-//! | [`get_attr()`](VfsNodeOps::get_attr) | Get the attributes of the node | both |
+//! | [`open()`](VfsNodeOps::open) | Do something when the node is opened | all |
+//! | [`release()`](VfsNodeOps::release) | Do something when the node is closed | all |
+//! | [`get_attr()`](VfsNodeOps::get_attr) | Get the attributes of the node | all |
+//! | [`set_attr()`](VfsNodeOps::set_attr) | Change the permission, owner or times of the node | all |
 //! | [`read_at()`](VfsNodeOps::read_at) | Read data from the file | file |
 //! | [`write_at()`](VfsNodeOps::write_at) | Write data to the file | file |
 //! | [`fsync()`](VfsNodeOps::fsync) | Synchronize the file data to disk | file |
@@ -31,6 +31,15 @@
 //! | [`create()`](VfsNodeOps::create) | Create a new node with the given path | directory |
 //! | [`remove()`](VfsNodeOps::remove) | Remove the node with the given path | directory |
 //! | [`read_dir()`](VfsNodeOps::read_dir) | Read directory entries | directory |
+//! | [`symlink()`](VfsNodeOps::symlink) | Create a symbolic link with the given path | directory |
+//! | [`link()`](VfsNodeOps::link) | Create a hard link to a node with the given path | directory |
+//! | [`rename()`](VfsNodeOps::rename) | Rename or move a node to another path | directory |
+//! | [`readlink()`](VfsNodeOps::readlink) | Read the target of the symbolic link | symlink |
+//!
+//! Symbolic links are not followed by [`lookup()`](VfsNodeOps::lookup), it is
+//! up to the users of filesystems (e.g., path resolution in `axfs`).
+//!
+//! Node times are taken from the clock set by [`time::set_clock`].
 //!
 //! [inodes]: https://en.wikipedia.org/wiki/Inode
 
@@ -42,10 +51,12 @@ mod macros;
 mod structs;
 
 pub mod path;
+pub mod time;
 
-use alloc::sync::Arc;
+use alloc::{string::String, sync::Arc};
 use axerrno::{ax_err, AxError, AxResult};
 
+pub use self::structs::VfsSetAttr;
 pub use self::structs::{FileSystemInfo, VfsDirEntry, VfsNodeAttr, VfsNodePerm, VfsNodeType};
 
 /// A wrapper of [`Arc<dyn VfsNodeOps>`].
@@ -100,6 +111,12 @@ pub trait VfsNodeOps: Send + Sync {
         ax_err!(Unsupported)
     }
 
+    /// Change the attributes of the node given in `attr`, the time of last
+    /// status change is updated as well.
+    fn set_attr(&self, _attr: &VfsSetAttr) -> VfsResult {
+        ax_err!(Unsupported)
+    }
+
     // file operations:
 
     /// Read data from the file at the given offset.
@@ -155,6 +172,41 @@ pub trait VfsNodeOps: Send + Sync {
         ax_err!(Unsupported)
     }
 
+    /// Create a symbolic link with the given `path` in the directory, which
+    /// points to `target`.
+    ///
+    /// The `target` is stored as is, it may not exist.
+    fn symlink(&self, _path: &str, _target: &str) -> VfsResult {
+        ax_err!(Unsupported)
+    }
+
+    /// Create a hard link with the given `path` in the directory to `node`.
+    ///
+    /// The `node` must be a non-directory node in the same filesystem,
+    /// otherwise return [`CrossesDevices`](AxError::CrossesDevices).
+    fn link(&self, _path: &str, _node: &VfsNodeRef) -> VfsResult {
+        ax_err!(Unsupported)
+    }
+
+    /// Renames or moves the node at `src_path` to `dst_path`, both paths are
+    /// relative to this directory and in the same filesystem.
+    ///
+    /// If `dst_path` exists, it is replaced. The replacement should be atomic,
+    /// i.e., `dst_path` always exists during the operation, but filesystems
+    /// that cannot do so (e.g., FAT) may remove it first, and must document
+    /// it. A file cannot replace a directory and vice versa, and a directory
+    /// can only replace an empty one.
+    fn rename(&self, _src_path: &str, _dst_path: &str) -> VfsResult {
+        ax_err!(Unsupported)
+    }
+
+    // symbolic link operations:
+
+    /// Read the target path of the symbolic link.
+    fn readlink(&self) -> VfsResult<String> {
+        ax_err!(InvalidInput)
+    }
+
     /// Convert `&self` to [`&dyn Any`][1] that can use
     /// [`Any::downcast_ref`][2].
     ///
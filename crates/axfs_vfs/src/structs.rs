@@ -1,8 +1,30 @@
-/// Filesystem attributes.
+use core::time::Duration;
+
+/// Filesystem attributes, returned by [`VfsOps::statfs`].
+///
+/// The block and inode counts are zero if the filesystem has no limit on
+/// them, e.g. RAM and pseudo filesystems.
 ///
-/// Currently not used.
-#[non_exhaustive]
-pub struct FileSystemInfo;
+/// [`VfsOps::statfs`]: crate::VfsOps::statfs
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FileSystemInfo {
+    /// Type of the filesystem, the same magic number as `f_type` on Linux.
+    pub fs_type: u64,
+    /// Size of a block, in bytes.
+    pub block_size: u64,
+    /// Total number of blocks.
+    pub blocks: u64,
+    /// Number of free blocks.
+    pub blocks_free: u64,
+    /// Number of free blocks available to unprivileged users.
+    pub blocks_avail: u64,
+    /// Total number of inodes.
+    pub files: u64,
+    /// Number of free inodes.
+    pub files_free: u64,
+    /// Maximum length of file names, in bytes.
+    pub name_max: u64,
+}
 
 /// Node (file/directory) attributes.
 #[allow(dead_code)]
@@ -16,6 +38,39 @@ pub struct VfsNodeAttr {
     size: u64,
     /// Number of 512B blocks allocated.
     blocks: u64,
+    /// Inode number, unique in the filesystem.
+    ino: u64,
+    /// Number of hard links.
+    nlink: u64,
+    /// User ID of the owner.
+    uid: u32,
+    /// Group ID of the owner.
+    gid: u32,
+    /// Time of last access, since the UNIX epoch.
+    atime: Duration,
+    /// Time of last modification, since the UNIX epoch.
+    mtime: Duration,
+    /// Time of last status change, since the UNIX epoch.
+    ctime: Duration,
+}
+
+/// Node attributes to change by [`VfsNodeOps::set_attr`].
+///
+/// The fields that are `None` are left unchanged.
+///
+/// [`VfsNodeOps::set_attr`]: crate::VfsNodeOps::set_attr
+#[derive(Debug, Clone, Copy, Default)]
+pub struct VfsSetAttr {
+    /// New permission mode (`chmod`).
+    pub perm: Option<VfsNodePerm>,
+    /// New user ID of the owner (`chown`).
+    pub uid: Option<u32>,
+    /// New group ID of the owner (`chown`).
+    pub gid: Option<u32>,
+    /// New time of last access (`utimes`).
+    pub atime: Option<Duration>,
+    /// New time of last modification (`utimes`).
+    pub mtime: Option<Duration>,
 }
 
 bitflags::bitflags! {
@@ -149,6 +204,11 @@ impl VfsNodeType {
         matches!(self, Self::Dir)
     }
 
+    /// Whether the node is a symbolic link.
+    pub const fn is_symlink(self) -> bool {
+        matches!(self, Self::SymLink)
+    }
+
     /// Returns a character representation of the node type.
     ///
     /// For example, `d` for directory, `-` for regular file, etc.
@@ -168,34 +228,41 @@ impl VfsNodeType {
 impl VfsNodeAttr {
     /// Creates a new `VfsNodeAttr` with the given permission mode, type, size
     /// and number of blocks.
+    ///
+    /// The node has one link and is owned by root, its inode number and
+    /// timestamps are zero.
     pub const fn new(mode: VfsNodePerm, ty: VfsNodeType, size: u64, blocks: u64) -> Self {
         Self {
             mode,
             ty,
             size,
             blocks,
+            ino: 0,
+            nlink: 1,
+            uid: 0,
+            gid: 0,
+            atime: Duration::ZERO,
+            mtime: Duration::ZERO,
+            ctime: Duration::ZERO,
         }
     }
 
     /// Creates a new `VfsNodeAttr` for a file, with the default file permission.
     pub const fn new_file(size: u64, blocks: u64) -> Self {
-        Self {
-            mode: VfsNodePerm::default_file(),
-            ty: VfsNodeType::File,
-            size,
-            blocks,
-        }
+        Self::new(VfsNodePerm::default_file(), VfsNodeType::File, size, blocks)
     }
 
     /// Creates a new `VfsNodeAttr` for a directory, with the default directory
     /// permission.
     pub const fn new_dir(size: u64, blocks: u64) -> Self {
-        Self {
-            mode: VfsNodePerm::default_dir(),
-            ty: VfsNodeType::Dir,
-            size,
-            blocks,
-        }
+        Self::new(VfsNodePerm::default_dir(), VfsNodeType::Dir, size, blocks)
+    }
+
+    /// Creates a new `VfsNodeAttr` for a symbolic link, whose size is the
+    /// length of the target path. The permission is always `0o777`.
+    pub const fn new_symlink(size: u64, blocks: u64) -> Self {
+        let perm = VfsNodePerm::from_bits_truncate(0o777);
+        Self::new(perm, VfsNodeType::SymLink, size, blocks)
     }
 
     /// Returns the size of the node.
@@ -223,6 +290,65 @@ impl VfsNodeAttr {
         self.ty
     }
 
+    /// Returns the inode number of the node.
+    pub const fn ino(&self) -> u64 {
+        self.ino
+    }
+
+    /// Sets the inode number of the node.
+    pub fn set_ino(&mut self, ino: u64) {
+        self.ino = ino
+    }
+
+    /// Returns the number of hard links to the node.
+    pub const fn nlink(&self) -> u64 {
+        self.nlink
+    }
+
+    /// Sets the number of hard links to the node.
+    pub fn set_nlink(&mut self, nlink: u64) {
+        self.nlink = nlink
+    }
+
+    /// Returns the user ID of the owner.
+    pub const fn uid(&self) -> u32 {
+        self.uid
+    }
+
+    /// Returns the group ID of the owner.
+    pub const fn gid(&self) -> u32 {
+        self.gid
+    }
+
+    /// Sets the user ID and the group ID of the owner.
+    pub fn set_owner(&mut self, uid: u32, gid: u32) {
+        self.uid = uid;
+        self.gid = gid;
+    }
+
+    /// Returns the time of last access.
+    pub const fn atime(&self) -> Duration {
+        self.atime
+    }
+
+    /// Returns the time of last modification.
+    pub const fn mtime(&self) -> Duration {
+        self.mtime
+    }
+
+    /// Returns the time of last status change.
+    pub const fn ctime(&self) -> Duration {
+        self.ctime
+    }
+
+    /// Sets the times of last access, last modification and last status
+    /// change.
+    pub fn set_times(&mut self, atime: Duration, mtime: Duration, ctime: Duration) {
+        self.atime = atime;
+        self.mtime = mtime;
+        self.ctime = ctime;
+    }
+
     /// Whether the node is a file.
     pub const fn is_file(&self) -> bool {
         self.ty.is_file()
@@ -232,6 +358,11 @@ impl VfsNodeAttr {
     pub const fn is_dir(&self) -> bool {
         self.ty.is_dir()
     }
+
+    /// Whether the node is a symbolic link.
+    pub const fn is_symlink(&self) -> bool {
+        self.ty.is_symlink()
+    }
 }
 
 impl VfsDirEntry {
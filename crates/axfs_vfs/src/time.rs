@@ -0,0 +1,28 @@
+//! The clock used by filesystems to stamp node times.
+//!
+//! Filesystems do not depend on a particular time source. The kernel sets
+//! one with [`set_clock`] when it is initialized, before that all timestamps
+//! are zero.
+
+use core::sync::atomic::{AtomicPtr, Ordering};
+use core::time::Duration;
+
+static CLOCK: AtomicPtr<()> = AtomicPtr::new(core::ptr::null_mut());
+
+/// Sets the clock that returns the current time, as the duration since the
+/// UNIX epoch.
+pub fn set_clock(clock: fn() -> Duration) {
+    CLOCK.store(clock as *mut (), Ordering::Release);
+}
+
+/// Returns the current time from the clock set by [`set_clock`], or zero if
+/// it has not been set.
+pub fn current_time() -> Duration {
+    let clock = CLOCK.load(Ordering::Acquire);
+    if clock.is_null() {
+        return Duration::ZERO;
+    }
+    // SAFETY: the pointer is only stored by `set_clock` from a `fn` pointer.
+    let clock: fn() -> Duration = unsafe { core::mem::transmute(clock) };
+    clock()
+}
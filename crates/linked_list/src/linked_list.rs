@@ -154,6 +154,17 @@ impl<G: AdapterWrapped> List<G> {
         unsafe { self.list.push_back(ptr.as_ref()) }
     }
 
+    /// Adds the given object to the front of the list.
+    ///
+    /// It is dropped if it's already on this (or another) list; this can happen for
+    /// reference-counted objects, so dropping means decrementing the reference count.
+    pub fn push_front(&mut self, data: G::Wrapped) {
+        let ptr = data.into_pointer();
+
+        // SAFETY: We took ownership of the entry, so it is safe to insert it.
+        unsafe { self.list.push_front(ptr.as_ref()) }
+    }
+
     /// Inserts the given object after `existing`.
     ///
     /// It is dropped if it's already on this (or another) list; this can happen for
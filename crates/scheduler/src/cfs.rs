@@ -66,8 +66,8 @@ impl<T> CFSTask<T> {
         self.init_vruntime.store(v, Ordering::Release);
     }
 
-    // Simple Implementation: no change in vruntime.
-    // Only modifying priority of current process is supported currently.
+    // The vruntime so far is kept, only the future ticks are weighted by the
+    // new nice value.
     fn set_priority(&self, nice: isize) {
         let current_init_vruntime = self.get_vruntime();
         self.init_vruntime
@@ -143,10 +143,16 @@ impl<T> BaseScheduler for CFScheduler<T> {
     }
 
     fn remove_task(&mut self, task: &Self::SchedItem) -> Option<Self::SchedItem> {
-        if let Some((_, tmp)) = self
+        let key = (task.get_vruntime(), task.get_id());
+        // the key may be the same as a task from another scheduler
+        if !self
             .ready_queue
-            .remove_entry(&(task.clone().get_vruntime(), task.clone().get_id()))
+            .get(&key)
+            .is_some_and(|t| Arc::ptr_eq(t, task))
         {
+            return None;
+        }
+        if let Some((_, tmp)) = self.ready_queue.remove_entry(&key) {
             if let Some(((min_vruntime, _), _)) = self.ready_queue.first_key_value() {
                 self.min_vruntime = Some(AtomicIsize::new(*min_vruntime));
             } else {
@@ -166,6 +172,18 @@ impl<T> BaseScheduler for CFScheduler<T> {
         }
     }
 
+    fn pick_next_task_if(
+        &mut self,
+        cond: impl FnOnce(&Self::SchedItem) -> bool,
+    ) -> Option<Self::SchedItem> {
+        let (_, task) = self.ready_queue.first_key_value()?;
+        if cond(task) {
+            self.pick_next_task()
+        } else {
+            None
+        }
+    }
+
     fn put_prev_task(&mut self, prev: Self::SchedItem, _preempt: bool) {
         let taskid = self.id_pool.fetch_add(1, Ordering::Release);
         prev.set_id(taskid);
@@ -181,10 +199,27 @@ impl<T> BaseScheduler for CFScheduler<T> {
 
     fn set_priority(&mut self, task: &Self::SchedItem, prio: isize) -> bool {
         if (-20..=19).contains(&prio) {
-            task.set_priority(prio);
+            // a ready task is keyed by its vruntime, re-insert it in case the
+            // key changes
+            let key = (task.get_vruntime(), task.get_id());
+            if self
+                .ready_queue
+                .get(&key)
+                .is_some_and(|t| Arc::ptr_eq(t, task))
+            {
+                let task = self.ready_queue.remove(&key).unwrap();
+                task.set_priority(prio);
+                self.ready_queue.insert((task.get_vruntime(), key.1), task);
+            } else {
+                task.set_priority(prio);
+            }
             true
         } else {
             false
         }
     }
+
+    fn get_priority(&self, task: &Self::SchedItem) -> isize {
+        task.nice.load(Ordering::Acquire)
+    }
 }
@@ -0,0 +1,381 @@
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use core::ops::Deref;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use crate::BaseScheduler;
+
+/// The fixed-point shift of bandwidths, where `1 << BW_SHIFT` is a full CPU.
+const BW_SHIFT: u32 = 20;
+
+/// The timing parameters of a periodic (or sporadic) task, all in timer ticks.
+///
+/// Each job of the task is released at least `period` ticks after the last
+/// one, and may run for `runtime` ticks, which should be done in `deadline`
+/// ticks after it is released.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EDFParams {
+    /// The runtime budget of each job.
+    pub runtime: u64,
+    /// The relative deadline of each job.
+    pub deadline: u64,
+    /// The minimum interval between the releases of two jobs.
+    pub period: u64,
+}
+
+impl EDFParams {
+    /// Creates a new [`EDFParams`].
+    pub const fn new(runtime: u64, deadline: u64, period: u64) -> Self {
+        Self {
+            runtime,
+            deadline,
+            period,
+        }
+    }
+
+    /// Whether `0 < runtime <= deadline <= period` holds.
+    pub const fn is_valid(&self) -> bool {
+        0 < self.runtime && self.runtime <= self.deadline && self.deadline <= self.period
+    }
+
+    /// The share of a CPU needed before the deadline, `runtime / deadline`.
+    fn bandwidth(&self) -> u64 {
+        (self.runtime << BW_SHIFT) / self.deadline
+    }
+}
+
+/// A task wrapper for the [`EDFScheduler`].
+///
+/// It adds the timing parameters, and the deadline and the remaining budget
+/// of the current job. A task without parameters is a best-effort task, and
+/// the parameters are set by [`EDFScheduler::set_params`].
+pub struct EDFTask<T> {
+    inner: T,
+    runtime: AtomicU64,
+    deadline: AtomicU64,
+    period: AtomicU64, // 0 for best-effort tasks
+    abs_deadline: AtomicU64,
+    next_release: AtomicU64,
+    budget: AtomicU64,
+    seq: AtomicU64,
+}
+
+impl<T> EDFTask<T> {
+    /// Creates a new best-effort [`EDFTask`] from the inner task struct.
+    pub const fn new(inner: T) -> Self {
+        Self {
+            inner,
+            runtime: AtomicU64::new(0),
+            deadline: AtomicU64::new(0),
+            period: AtomicU64::new(0),
+            abs_deadline: AtomicU64::new(0),
+            next_release: AtomicU64::new(0),
+            budget: AtomicU64::new(0),
+            seq: AtomicU64::new(0),
+        }
+    }
+
+    /// Returns the timing parameters, or [`None`] for a best-effort task.
+    pub fn params(&self) -> Option<EDFParams> {
+        match self.period.load(Ordering::Acquire) {
+            0 => None,
+            period => Some(EDFParams::new(
+                self.runtime.load(Ordering::Acquire),
+                self.deadline.load(Ordering::Acquire),
+                period,
+            )),
+        }
+    }
+
+    /// Returns the absolute deadline of the current job, in the ticks of the
+    /// scheduler clock.
+    pub fn abs_deadline(&self) -> u64 {
+        self.abs_deadline.load(Ordering::Acquire)
+    }
+
+    /// Returns the remaining runtime budget of the current job.
+    pub fn budget(&self) -> u64 {
+        self.budget.load(Ordering::Acquire)
+    }
+
+    fn is_edf(&self) -> bool {
+        self.period.load(Ordering::Acquire) != 0
+    }
+
+    fn is_throttled(&self) -> bool {
+        self.is_edf() && self.budget() == 0
+    }
+
+    fn set_params(&self, params: Option<EDFParams>) {
+        let params = params.unwrap_or(EDFParams::new(0, 0, 0));
+        self.runtime.store(params.runtime, Ordering::Release);
+        self.deadline.store(params.deadline, Ordering::Release);
+        self.period.store(params.period, Ordering::Release);
+    }
+
+    /// Releases a new job at `now`, with a full budget.
+    fn replenish(&self, now: u64) {
+        let deadline = self.deadline.load(Ordering::Acquire);
+        let period = self.period.load(Ordering::Acquire);
+        self.abs_deadline.store(now + deadline, Ordering::Release);
+        self.next_release.store(now + period, Ordering::Release);
+        self.budget
+            .store(self.runtime.load(Ordering::Acquire), Ordering::Release);
+    }
+
+    /// Returns a reference to the inner task struct.
+    pub const fn inner(&self) -> &T {
+        &self.inner
+    }
+}
+
+impl<T> Deref for EDFTask<T> {
+    type Target = T;
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+/// An [Earliest Deadline First][1] (EDF) scheduler (preemptive).
+///
+/// The ready job of the earliest absolute deadline always runs first. A job is
+/// throttled when its runtime budget runs out, until the next job is released
+/// a period after the current one. Best-effort tasks run in FIFO order when
+/// there are no ready jobs.
+///
+/// Timing parameters are only set by [`set_params`], which admits the task
+/// only if the total bandwidth (`runtime / deadline`) of the tasks admitted by
+/// the scheduler does not exceed one CPU, so that all their deadlines are met.
+/// The bandwidth is reserved in the scheduler until the task is dropped or its
+/// parameters are cleared, so a task with parameters must not be moved to
+/// another scheduler.
+///
+/// Admission is not done by [`add_task`], which cannot fail and is called
+/// again each time the task wakes up, so it only enqueues the task with the
+/// parameters admitted before. A task whose parameters are rejected keeps
+/// its old ones (or stays a best-effort task), and is still added as usual.
+///
+/// Time is counted in timer ticks by [`task_tick`] and [`idle_tick`]. Task
+/// priorities are not supported.
+///
+/// [1]: https://en.wikipedia.org/wiki/Earliest_deadline_first_scheduling
+/// [`add_task`]: BaseScheduler::add_task
+/// [`task_tick`]: BaseScheduler::task_tick
+/// [`idle_tick`]: EDFScheduler::idle_tick
+/// [`set_params`]: EDFScheduler::set_params
+pub struct EDFScheduler<T> {
+    ready_queue: BTreeMap<(u64, u64), Arc<EDFTask<T>>>, // (abs_deadline, seq)
+    throttled: BTreeMap<(u64, u64), Arc<EDFTask<T>>>,   // (next_release, seq)
+    background: VecDeque<Arc<EDFTask<T>>>,
+    admitted: Vec<Weak<EDFTask<T>>>,
+    clock: u64,
+    seq: u64,
+}
+
+impl<T> EDFScheduler<T> {
+    /// Creates a new empty [`EDFScheduler`].
+    pub const fn new() -> Self {
+        Self {
+            ready_queue: BTreeMap::new(),
+            throttled: BTreeMap::new(),
+            background: VecDeque::new(),
+            admitted: Vec::new(),
+            clock: 0,
+            seq: 0,
+        }
+    }
+    /// get the name of scheduler
+    pub fn scheduler_name() -> &'static str {
+        "Earliest Deadline First"
+    }
+
+    /// Returns the number of ticks passed.
+    pub fn clock(&self) -> u64 {
+        self.clock
+    }
+
+    /// Advances the clock by a tick when no task is running, which should be
+    /// called at each timer tick instead of [`task_tick`].
+    ///
+    /// [`task_tick`]: BaseScheduler::task_tick
+    pub fn idle_tick(&mut self) {
+        self.advance_clock();
+    }
+
+    /// Sets the timing parameters of a task, which is either in the scheduler
+    /// or running, and releases a new job of it. The task becomes a
+    /// best-effort one if `params` is [`None`].
+    ///
+    /// Returns `false` if the parameters are invalid, or the task fails the
+    /// admission test, in which case the task is unchanged.
+    pub fn set_params(
+        &mut self,
+        task: &<Self as BaseScheduler>::SchedItem,
+        params: Option<EDFParams>,
+    ) -> bool {
+        if let Some(params) = &params {
+            if !self.admit(task, params) {
+                return false;
+            }
+        }
+        let queued = self.dequeue(task);
+        task.set_params(params);
+        task.replenish(self.clock);
+        if let Some(task) = queued {
+            self.enqueue(task);
+        }
+        true
+    }
+
+    /// Checks whether the task can be admitted with the parameters, and
+    /// reserves its bandwidth if so.
+    fn admit(&mut self, task: &Arc<EDFTask<T>>, params: &EDFParams) -> bool {
+        if !params.is_valid() {
+            return false;
+        }
+        self.admitted.retain(|t| t.strong_count() > 0);
+        let is_self = |t: &Weak<EDFTask<T>>| core::ptr::eq(t.as_ptr(), Arc::as_ptr(task));
+        let reserved: u64 = self
+            .admitted
+            .iter()
+            .filter(|t| !is_self(t))
+            .filter_map(|t| t.upgrade()?.params())
+            .map(|p| p.bandwidth())
+            .sum();
+        if reserved + params.bandwidth() > 1 << BW_SHIFT {
+            return false;
+        }
+        if !self.admitted.iter().any(is_self) {
+            self.admitted.push(Arc::downgrade(task));
+        }
+        true
+    }
+
+    fn enqueue(&mut self, task: Arc<EDFTask<T>>) {
+        self.seq += 1;
+        task.seq.store(self.seq, Ordering::Release);
+        if !task.is_edf() {
+            self.background.push_back(task);
+        } else if task.is_throttled() {
+            let release = task.next_release.load(Ordering::Acquire);
+            self.throttled.insert((release, self.seq), task);
+        } else {
+            self.ready_queue
+                .insert((task.abs_deadline(), self.seq), task);
+        }
+    }
+
+    fn dequeue(&mut self, task: &Arc<EDFTask<T>>) -> Option<Arc<EDFTask<T>>> {
+        let seq = task.seq.load(Ordering::Acquire);
+        let (queue, key) = if !task.is_edf() {
+            let idx = self.background.iter().position(|t| Arc::ptr_eq(t, task))?;
+            return self.background.remove(idx);
+        } else if task.is_throttled() {
+            let release = task.next_release.load(Ordering::Acquire);
+            (&mut self.throttled, (release, seq))
+        } else {
+            (&mut self.ready_queue, (task.abs_deadline(), seq))
+        };
+        // the key may be the same as a task from another scheduler
+        if !queue.get(&key).is_some_and(|t| Arc::ptr_eq(t, task)) {
+            return None;
+        }
+        queue.remove(&key)
+    }
+
+    /// Advances the clock by a tick, and releases the throttled tasks whose
+    /// next jobs are due.
+    fn advance_clock(&mut self) {
+        self.clock += 1;
+        while let Some(entry) = self.throttled.first_entry() {
+            if entry.key().0 > self.clock {
+                break;
+            }
+            let task = entry.remove();
+            task.replenish(self.clock);
+            self.enqueue(task);
+        }
+    }
+}
+
+impl<T> Default for EDFScheduler<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> BaseScheduler for EDFScheduler<T> {
+    type SchedItem = Arc<EDFTask<T>>;
+
+    fn init(&mut self) {}
+
+    fn add_task(&mut self, task: Self::SchedItem) {
+        // a new job is released if it wakes up a period after the last one
+        if task.is_edf() && self.clock >= task.next_release.load(Ordering::Acquire) {
+            task.replenish(self.clock);
+        }
+        self.enqueue(task);
+    }
+
+    fn remove_task(&mut self, task: &Self::SchedItem) -> Option<Self::SchedItem> {
+        self.dequeue(task)
+    }
+
+    fn pick_next_task(&mut self) -> Option<Self::SchedItem> {
+        if let Some((_, task)) = self.ready_queue.pop_first() {
+            Some(task)
+        } else {
+            self.background.pop_front()
+        }
+    }
+
+    fn pick_next_task_if(
+        &mut self,
+        cond: impl FnOnce(&Self::SchedItem) -> bool,
+    ) -> Option<Self::SchedItem> {
+        let task = match self.ready_queue.first_key_value() {
+            Some((_, task)) => task,
+            None => self.background.front()?,
+        };
+        if cond(task) {
+            self.pick_next_task()
+        } else {
+            None
+        }
+    }
+
+    fn put_prev_task(&mut self, prev: Self::SchedItem, preempt: bool) {
+        if preempt && !prev.is_edf() {
+            self.background.push_front(prev);
+        } else {
+            self.enqueue(prev);
+        }
+    }
+
+    fn task_tick(&mut self, current: &Self::SchedItem) -> bool {
+        self.advance_clock();
+        if current.is_edf() {
+            let budget = current.budget();
+            current
+                .budget
+                .store(budget.saturating_sub(1), Ordering::Release);
+            budget <= 1
+                || self
+                    .ready_queue
+                    .first_key_value()
+                    .is_some_and(|(&(deadline, _), _)| deadline < current.abs_deadline())
+        } else {
+            !self.ready_queue.is_empty()
+        }
+    }
+
+    fn set_priority(&mut self, _task: &Self::SchedItem, _prio: isize) -> bool {
+        false
+    }
+
+    fn get_priority(&self, _task: &Self::SchedItem) -> isize {
+        0
+    }
+}
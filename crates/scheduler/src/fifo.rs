@@ -88,6 +88,19 @@ impl<T> BaseScheduler for FifoScheduler<T> {
         self.ready_queue.pop_front()
     }
 
+    fn pick_next_task_if(
+        &mut self,
+        cond: impl FnOnce(&Self::SchedItem) -> bool,
+    ) -> Option<Self::SchedItem> {
+        let task = self.ready_queue.pop_front()?;
+        if cond(&task) {
+            Some(task)
+        } else {
+            self.ready_queue.push_front(task);
+            None
+        }
+    }
+
     fn put_prev_task(&mut self, prev: Self::SchedItem, _preempt: bool) {
         self.ready_queue.push_back(prev);
     }
@@ -99,4 +112,8 @@ impl<T> BaseScheduler for FifoScheduler<T> {
     fn set_priority(&mut self, _task: &Self::SchedItem, _prio: isize) -> bool {
         false
     }
+
+    fn get_priority(&self, _task: &Self::SchedItem) -> isize {
+        0
+    }
 }
@@ -5,13 +5,21 @@
 //! - [`FifoScheduler`]: FIFO (First-In-First-Out) scheduler (cooperative).
 //! - [`RRScheduler`]: Round-robin scheduler (preemptive).
 //! - [`CFScheduler`]: Completely Fair Scheduler (preemptive).
+//! - [`RTScheduler`]: Real-time scheduler with static priorities (preemptive).
+//! - [`RTCFScheduler`]: Real-time tasks scheduled before the normal ones,
+//!   which are scheduled by the CFS (preemptive).
+//! - [`EDFScheduler`]: Earliest Deadline First scheduler for periodic tasks
+//!   (preemptive).
 
 #![cfg_attr(not(test), no_std)]
 #![feature(const_mut_refs)]
 
 mod cfs;
+mod edf;
 mod fifo;
 mod round_robin;
+mod rt;
+mod rt_cfs;
 
 #[cfg(test)]
 mod tests;
@@ -19,8 +27,11 @@ mod tests;
 extern crate alloc;
 
 pub use cfs::{CFSTask, CFScheduler};
+pub use edf::{EDFParams, EDFScheduler, EDFTask};
 pub use fifo::{FifoScheduler, FifoTask};
 pub use round_robin::{RRScheduler, RRTask};
+pub use rt::{RTPolicy, RTScheduler, RTTask, RT_PRIO_LEVELS};
+pub use rt_cfs::{RTCFSTask, RTCFScheduler};
 
 /// The base scheduler trait that all schedulers should implement.
 ///
@@ -49,6 +60,16 @@ pub trait BaseScheduler {
     /// Returns [`None`] if there is not runnable task.
     fn pick_next_task(&mut self) -> Option<Self::SchedItem>;
 
+    /// Picks the next task to run like [`pick_next_task`], but only if `cond`
+    /// returns `true` for it. Otherwise the scheduler is left unchanged and
+    /// [`None`] is returned.
+    ///
+    /// [`pick_next_task`]: BaseScheduler::pick_next_task
+    fn pick_next_task_if(
+        &mut self,
+        cond: impl FnOnce(&Self::SchedItem) -> bool,
+    ) -> Option<Self::SchedItem>;
+
     /// Puts the previous task back to the scheduler. The previous task is
     /// usually placed at the end of the ready queue, making it less likely
     /// to be re-scheduled.
@@ -64,6 +85,14 @@ pub trait BaseScheduler {
     /// `current` is the current running task.
     fn task_tick(&mut self, current: &Self::SchedItem) -> bool;
 
-    /// set priority for a task
+    /// Sets the priority of a task, which is either in the scheduler or
+    /// running. A task in the scheduler is re-positioned if its order changes.
+    ///
+    /// Returns `false` if the priority is invalid, or the scheduler does not
+    /// support priorities.
     fn set_priority(&mut self, task: &Self::SchedItem, prio: isize) -> bool;
+
+    /// Gets the priority of a task. It is 0 if the scheduler does not support
+    /// priorities.
+    fn get_priority(&self, task: &Self::SchedItem) -> isize;
 }
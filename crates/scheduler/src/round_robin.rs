@@ -93,6 +93,17 @@ impl<T, const S: usize> BaseScheduler for RRScheduler<T, S> {
         self.ready_queue.pop_front()
     }
 
+    fn pick_next_task_if(
+        &mut self,
+        cond: impl FnOnce(&Self::SchedItem) -> bool,
+    ) -> Option<Self::SchedItem> {
+        if cond(self.ready_queue.front()?) {
+            self.ready_queue.pop_front()
+        } else {
+            None
+        }
+    }
+
     fn put_prev_task(&mut self, prev: Self::SchedItem, preempt: bool) {
         if prev.time_slice() > 0 && preempt {
             self.ready_queue.push_front(prev)
@@ -110,4 +121,8 @@ impl<T, const S: usize> BaseScheduler for RRScheduler<T, S> {
     fn set_priority(&mut self, _task: &Self::SchedItem, _prio: isize) -> bool {
         false
     }
+
+    fn get_priority(&self, _task: &Self::SchedItem) -> isize {
+        0
+    }
 }
@@ -0,0 +1,286 @@
+use alloc::{collections::VecDeque, sync::Arc};
+use core::ops::Deref;
+use core::sync::atomic::{AtomicIsize, AtomicU8, AtomicUsize, Ordering};
+
+use crate::BaseScheduler;
+
+/// The number of real-time priorities, ranging from 0 to 99. Larger values
+/// mean higher priorities.
+pub const RT_PRIO_LEVELS: usize = 100;
+
+/// The scheduling policy of a task.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RTPolicy {
+    /// Not a real-time task. It is scheduled by the [`CFScheduler`] in the
+    /// [`RTCFScheduler`], or as a [`RoundRobin`] one by the [`RTScheduler`].
+    ///
+    /// [`CFScheduler`]: crate::CFScheduler
+    /// [`RTCFScheduler`]: crate::RTCFScheduler
+    /// [`RoundRobin`]: RTPolicy::RoundRobin
+    Normal = 0,
+    /// A real-time task that runs until it blocks, yields, or is preempted by
+    /// a task of higher priority, like `SCHED_FIFO` in Linux.
+    Fifo = 1,
+    /// A real-time task like [`Fifo`], but also gives up the CPU to the tasks
+    /// of the same priority when its time slice runs out, like `SCHED_RR` in
+    /// Linux.
+    ///
+    /// [`Fifo`]: RTPolicy::Fifo
+    RoundRobin = 2,
+}
+
+impl From<u8> for RTPolicy {
+    fn from(policy: u8) -> Self {
+        match policy {
+            1 => Self::Fifo,
+            2 => Self::RoundRobin,
+            _ => Self::Normal,
+        }
+    }
+}
+
+/// A task wrapper for the [`RTScheduler`].
+///
+/// It adds a scheduling policy, a static priority and a time slice counter.
+pub struct RTTask<T, const MAX_TIME_SLICE: usize> {
+    inner: T,
+    policy: AtomicU8,
+    prio: AtomicUsize,
+    time_slice: AtomicIsize,
+}
+
+impl<T, const S: usize> RTTask<T, S> {
+    /// Creates a new [`RTTask`] from the inner task struct, with the
+    /// [`Normal`](RTPolicy::Normal) policy and priority 0.
+    pub const fn new(inner: T) -> Self {
+        Self {
+            inner,
+            policy: AtomicU8::new(RTPolicy::Normal as u8),
+            prio: AtomicUsize::new(0),
+            time_slice: AtomicIsize::new(S as isize),
+        }
+    }
+
+    /// Returns the scheduling policy of the task.
+    pub fn policy(&self) -> RTPolicy {
+        self.policy.load(Ordering::Acquire).into()
+    }
+
+    /// Returns the real-time priority of the task.
+    pub fn rt_priority(&self) -> usize {
+        self.prio.load(Ordering::Acquire)
+    }
+
+    pub(crate) fn is_rt(&self) -> bool {
+        self.policy() != RTPolicy::Normal
+    }
+
+    pub(crate) fn set_policy(&self, policy: RTPolicy, prio: usize) {
+        self.policy.store(policy as u8, Ordering::Release);
+        self.prio.store(prio, Ordering::Release);
+    }
+
+    /// Returns `true` if the time slice runs out.
+    pub(crate) fn tick_time_slice(&self) -> bool {
+        let old_slice = self.time_slice.fetch_sub(1, Ordering::Release);
+        old_slice <= 1 && self.policy() != RTPolicy::Fifo
+    }
+
+    /// Whether the task should be placed at the front of its priority band
+    /// when it is preempted.
+    pub(crate) fn keeps_position(&self, preempt: bool) -> bool {
+        if preempt
+            && (self.policy() == RTPolicy::Fifo || self.time_slice.load(Ordering::Acquire) > 0)
+        {
+            true
+        } else {
+            self.time_slice.store(S as isize, Ordering::Release);
+            false
+        }
+    }
+
+    /// Returns a reference to the inner task struct.
+    pub const fn inner(&self) -> &T {
+        &self.inner
+    }
+}
+
+impl<T, const S: usize> Deref for RTTask<T, S> {
+    type Target = T;
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+/// Ready queues of all real-time priorities, with a bitmap of the non-empty
+/// ones to find the highest priority in O(1) time.
+pub(crate) struct PrioQueues<I> {
+    queues: [VecDeque<I>; RT_PRIO_LEVELS],
+    bitmap: u128,
+}
+
+impl<I: Deref> PrioQueues<I> {
+    pub fn new() -> Self {
+        Self {
+            queues: core::array::from_fn(|_| VecDeque::new()),
+            bitmap: 0,
+        }
+    }
+
+    /// Returns the highest priority of the ready tasks.
+    pub fn highest_prio(&self) -> Option<usize> {
+        if self.bitmap == 0 {
+            None
+        } else {
+            Some((u128::BITS - 1 - self.bitmap.leading_zeros()) as usize)
+        }
+    }
+
+    pub fn push(&mut self, prio: usize, task: I, front: bool) {
+        if front {
+            self.queues[prio].push_front(task);
+        } else {
+            self.queues[prio].push_back(task);
+        }
+        self.bitmap |= 1 << prio;
+    }
+
+    pub fn pop(&mut self) -> Option<I> {
+        let prio = self.highest_prio()?;
+        let task = self.queues[prio].pop_front();
+        if self.queues[prio].is_empty() {
+            self.bitmap &= !(1 << prio);
+        }
+        task
+    }
+
+    /// Returns the task that [`pop`](Self::pop) would remove.
+    pub fn peek(&self) -> Option<&I> {
+        self.queues[self.highest_prio()?].front()
+    }
+
+    /// Removes the task from the queue of the priority, it may take O(n) time.
+    pub fn remove(&mut self, prio: usize, task: &I) -> Option<I> {
+        let queue = &mut self.queues[prio];
+        let idx = queue
+            .iter()
+            .position(|t| core::ptr::eq(t.deref(), task.deref()))?;
+        let task = queue.remove(idx);
+        if queue.is_empty() {
+            self.bitmap &= !(1 << prio);
+        }
+        task
+    }
+}
+
+/// A real-time scheduler with static priorities.
+///
+/// The task of the highest priority always runs first. Tasks of the same
+/// priority are scheduled in the order they become ready, and a task of the
+/// [`RoundRobin`] policy is also preempted when its time slice runs out.
+///
+/// The priorities range from 0 to 99, each of which has a ready queue. The
+/// highest non-empty queue is found in O(1) time by a bitmap. Tasks of the
+/// [`Normal`] policy are scheduled as [`RoundRobin`] ones.
+///
+/// [`RoundRobin`]: RTPolicy::RoundRobin
+/// [`Normal`]: RTPolicy::Normal
+pub struct RTScheduler<T, const MAX_TIME_SLICE: usize> {
+    ready_queues: PrioQueues<Arc<RTTask<T, MAX_TIME_SLICE>>>,
+}
+
+impl<T, const S: usize> RTScheduler<T, S> {
+    /// Creates a new empty [`RTScheduler`].
+    pub fn new() -> Self {
+        Self {
+            ready_queues: PrioQueues::new(),
+        }
+    }
+    /// get the name of scheduler
+    pub fn scheduler_name() -> &'static str {
+        "Real-time"
+    }
+
+    /// Sets the scheduling policy and the real-time priority of a task, which
+    /// is either in the scheduler or running.
+    ///
+    /// Returns `false` if the priority is not less than [`RT_PRIO_LEVELS`].
+    pub fn set_policy(
+        &mut self,
+        task: &<Self as BaseScheduler>::SchedItem,
+        policy: RTPolicy,
+        prio: usize,
+    ) -> bool {
+        if prio >= RT_PRIO_LEVELS {
+            return false;
+        }
+        match self.ready_queues.remove(task.rt_priority(), task) {
+            Some(task) => {
+                task.set_policy(policy, prio);
+                self.ready_queues.push(prio, task, false);
+            }
+            None => task.set_policy(policy, prio),
+        }
+        true
+    }
+}
+
+impl<T, const S: usize> Default for RTScheduler<T, S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const S: usize> BaseScheduler for RTScheduler<T, S> {
+    type SchedItem = Arc<RTTask<T, S>>;
+
+    fn init(&mut self) {}
+
+    fn add_task(&mut self, task: Self::SchedItem) {
+        self.ready_queues.push(task.rt_priority(), task, false);
+    }
+
+    fn remove_task(&mut self, task: &Self::SchedItem) -> Option<Self::SchedItem> {
+        self.ready_queues.remove(task.rt_priority(), task)
+    }
+
+    fn pick_next_task(&mut self) -> Option<Self::SchedItem> {
+        self.ready_queues.pop()
+    }
+
+    fn pick_next_task_if(
+        &mut self,
+        cond: impl FnOnce(&Self::SchedItem) -> bool,
+    ) -> Option<Self::SchedItem> {
+        if cond(self.ready_queues.peek()?) {
+            self.ready_queues.pop()
+        } else {
+            None
+        }
+    }
+
+    fn put_prev_task(&mut self, prev: Self::SchedItem, preempt: bool) {
+        let front = prev.keeps_position(preempt);
+        self.ready_queues.push(prev.rt_priority(), prev, front);
+    }
+
+    fn task_tick(&mut self, current: &Self::SchedItem) -> bool {
+        let expired = current.tick_time_slice();
+        expired
+            || self
+                .ready_queues
+                .highest_prio()
+                .is_some_and(|prio| prio > current.rt_priority())
+    }
+
+    fn set_priority(&mut self, task: &Self::SchedItem, prio: isize) -> bool {
+        (0..RT_PRIO_LEVELS as isize).contains(&prio)
+            && self.set_policy(task, task.policy(), prio as usize)
+    }
+
+    fn get_priority(&self, task: &Self::SchedItem) -> isize {
+        task.rt_priority() as isize
+    }
+}
@@ -0,0 +1,149 @@
+use alloc::sync::Arc;
+
+use crate::rt::{PrioQueues, RT_PRIO_LEVELS};
+use crate::{BaseScheduler, CFSTask, CFScheduler, RTPolicy, RTTask};
+
+/// A task wrapper for the [`RTCFScheduler`], which is both a [`CFSTask`] and
+/// an [`RTTask`].
+pub type RTCFSTask<T, const MAX_TIME_SLICE: usize> = CFSTask<RTTask<T, MAX_TIME_SLICE>>;
+
+/// A scheduler of two classes: real-time tasks scheduled as by the
+/// [`RTScheduler`], and normal tasks scheduled by the [`CFScheduler`].
+///
+/// A real-time task always runs before normal tasks, and preempts them at the
+/// next timer tick when it becomes ready. Normal tasks only run when there is
+/// no ready real-time task.
+///
+/// The class of a task is decided by its [`RTPolicy`], which is
+/// [`Normal`](RTPolicy::Normal) for new tasks.
+///
+/// [`RTScheduler`]: crate::RTScheduler
+pub struct RTCFScheduler<T, const MAX_TIME_SLICE: usize> {
+    rt_queues: PrioQueues<Arc<RTCFSTask<T, MAX_TIME_SLICE>>>,
+    cfs: CFScheduler<RTTask<T, MAX_TIME_SLICE>>,
+}
+
+impl<T, const S: usize> RTCFScheduler<T, S> {
+    /// Creates a new empty [`RTCFScheduler`].
+    pub fn new() -> Self {
+        Self {
+            rt_queues: PrioQueues::new(),
+            cfs: CFScheduler::new(),
+        }
+    }
+    /// get the name of scheduler
+    pub fn scheduler_name() -> &'static str {
+        "Real-time + Completely Fair"
+    }
+
+    /// Sets the scheduling policy and the priority of a task, which is either
+    /// in the scheduler or running.
+    ///
+    /// The priority is the real-time priority from 0 to 99 for real-time
+    /// policies, or the nice value from -20 to 19 for the
+    /// [`Normal`](RTPolicy::Normal) policy. Returns `false` if it is out of
+    /// the range.
+    pub fn set_policy(
+        &mut self,
+        task: &<Self as BaseScheduler>::SchedItem,
+        policy: RTPolicy,
+        prio: isize,
+    ) -> bool {
+        if policy == RTPolicy::Normal {
+            if !task.is_rt() {
+                return self.cfs.set_priority(task, prio);
+            } else if !(-20..=19).contains(&prio) {
+                return false;
+            }
+        } else if !(0..RT_PRIO_LEVELS as isize).contains(&prio) {
+            return false;
+        }
+
+        // move the task to the queue of the new class
+        let queued = self.remove_task(task);
+        if policy == RTPolicy::Normal {
+            task.set_policy(policy, 0);
+            self.cfs.set_priority(task, prio);
+        } else {
+            task.set_policy(policy, prio as usize);
+        }
+        if let Some(task) = queued {
+            self.add_task(task);
+        }
+        true
+    }
+}
+
+impl<T, const S: usize> Default for RTCFScheduler<T, S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const S: usize> BaseScheduler for RTCFScheduler<T, S> {
+    type SchedItem = Arc<RTCFSTask<T, S>>;
+
+    fn init(&mut self) {}
+
+    fn add_task(&mut self, task: Self::SchedItem) {
+        if task.is_rt() {
+            self.rt_queues.push(task.rt_priority(), task, false);
+        } else {
+            self.cfs.add_task(task);
+        }
+    }
+
+    fn remove_task(&mut self, task: &Self::SchedItem) -> Option<Self::SchedItem> {
+        if task.is_rt() {
+            self.rt_queues.remove(task.rt_priority(), task)
+        } else {
+            self.cfs.remove_task(task)
+        }
+    }
+
+    fn pick_next_task(&mut self) -> Option<Self::SchedItem> {
+        self.rt_queues.pop().or_else(|| self.cfs.pick_next_task())
+    }
+
+    fn pick_next_task_if(
+        &mut self,
+        cond: impl FnOnce(&Self::SchedItem) -> bool,
+    ) -> Option<Self::SchedItem> {
+        match self.rt_queues.peek() {
+            Some(task) => cond(task).then(|| self.rt_queues.pop()).flatten(),
+            None => self.cfs.pick_next_task_if(cond),
+        }
+    }
+
+    fn put_prev_task(&mut self, prev: Self::SchedItem, preempt: bool) {
+        if prev.is_rt() {
+            let front = prev.keeps_position(preempt);
+            self.rt_queues.push(prev.rt_priority(), prev, front);
+        } else {
+            self.cfs.put_prev_task(prev, preempt);
+        }
+    }
+
+    fn task_tick(&mut self, current: &Self::SchedItem) -> bool {
+        let highest_rt_prio = self.rt_queues.highest_prio();
+        if current.is_rt() {
+            let expired = current.tick_time_slice();
+            expired || highest_rt_prio.is_some_and(|prio| prio > current.rt_priority())
+        } else {
+            let expired = self.cfs.task_tick(current);
+            expired || highest_rt_prio.is_some()
+        }
+    }
+
+    fn set_priority(&mut self, task: &Self::SchedItem, prio: isize) -> bool {
+        self.set_policy(task, task.policy(), prio)
+    }
+
+    fn get_priority(&self, task: &Self::SchedItem) -> isize {
+        if task.is_rt() {
+            task.rt_priority() as isize
+        } else {
+            self.cfs.get_priority(task)
+        }
+    }
+}
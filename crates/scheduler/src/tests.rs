@@ -28,6 +28,24 @@ macro_rules! def_test_sched {
                 assert_eq!(n, NUM_TASKS);
             }
 
+            #[test]
+            fn test_pick_next_task_if() {
+                const NUM_TASKS: usize = 11;
+
+                let mut scheduler = <$scheduler>::new();
+                for i in 0..NUM_TASKS {
+                    scheduler.add_task(Arc::new(<$task>::new(i)));
+                }
+
+                for i in 0..NUM_TASKS {
+                    // a rejected task stays the next one
+                    assert!(scheduler.pick_next_task_if(|_| false).is_none());
+                    let next = scheduler.pick_next_task_if(|t| *t.inner() == i);
+                    assert_eq!(*next.unwrap().inner(), i);
+                }
+                assert!(scheduler.pick_next_task_if(|_| true).is_none());
+            }
+
             #[test]
             fn bench_yield() {
                 const NUM_TASKS: usize = 1_000_000;
@@ -82,3 +100,187 @@ macro_rules! def_test_sched {
 def_test_sched!(fifo, FifoScheduler::<usize>, FifoTask::<usize>);
 def_test_sched!(rr, RRScheduler::<usize, 5>, RRTask::<usize, 5>);
 def_test_sched!(cfs, CFScheduler::<usize>, CFSTask::<usize>);
+def_test_sched!(rt, RTScheduler::<usize, 5>, RTTask::<usize, 5>);
+def_test_sched!(edf, EDFScheduler::<usize>, EDFTask::<usize>);
+
+#[test]
+fn test_cfs_set_priority() {
+    use crate::*;
+    use alloc::sync::Arc;
+
+    let mut scheduler = CFScheduler::new();
+    let tasks = (0..2)
+        .map(|i| Arc::new(CFSTask::new(i)))
+        .collect::<Vec<_>>();
+    for t in &tasks {
+        scheduler.add_task(t.clone());
+    }
+
+    // demote a ready task
+    assert!(scheduler.set_priority(&tasks[1], 19));
+    assert!(!scheduler.set_priority(&tasks[1], 20));
+    assert_eq!(scheduler.get_priority(&tasks[0]), 0);
+    assert_eq!(scheduler.get_priority(&tasks[1]), 19);
+
+    let mut runs = [0; 2];
+    for _ in 0..100 {
+        let next = scheduler.pick_next_task().unwrap();
+        runs[*next.inner()] += 1;
+        scheduler.task_tick(&next);
+        scheduler.put_prev_task(next, false);
+    }
+    assert!(runs[1] > 0 && runs[1] * 20 < runs[0], "{:?}", runs);
+    assert!(scheduler.remove_task(&tasks[0]).is_some());
+    assert!(scheduler.remove_task(&tasks[1]).is_some());
+    assert!(scheduler.pick_next_task().is_none());
+}
+
+#[test]
+fn test_rt_priority() {
+    use crate::*;
+    use alloc::sync::Arc;
+
+    let mut scheduler = RTScheduler::<usize, 5>::new();
+    let tasks = (0..4).map(|i| Arc::new(RTTask::new(i))).collect::<Vec<_>>();
+    for t in &tasks {
+        scheduler.add_task(t.clone());
+    }
+    assert!(scheduler.set_policy(&tasks[1], RTPolicy::Fifo, 50));
+    assert!(scheduler.set_policy(&tasks[2], RTPolicy::RoundRobin, 99));
+    assert!(scheduler.set_priority(&tasks[3], 50));
+    assert!(!scheduler.set_priority(&tasks[3], 100));
+    assert_eq!(scheduler.get_priority(&tasks[3]), 50);
+
+    // the highest priority first, then in order within a priority
+    let next = scheduler.pick_next_task().unwrap();
+    assert_eq!(*next.inner(), 2);
+    for _ in 0..10 {
+        // the only task of priority 99 keeps running after its time slice
+        assert!(!scheduler.task_tick(&next) || scheduler.get_priority(&next) == 99);
+    }
+    scheduler.put_prev_task(next, false);
+    assert_eq!(*scheduler.pick_next_task().unwrap().inner(), 2);
+
+    let next = scheduler.pick_next_task().unwrap();
+    assert_eq!(*next.inner(), 1);
+    // a FIFO task is not preempted by the time slice, but by a higher priority
+    for _ in 0..10 {
+        assert!(!scheduler.task_tick(&next));
+    }
+    assert!(scheduler.set_priority(&tasks[0], 60));
+    assert!(scheduler.task_tick(&next));
+    scheduler.put_prev_task(next, true);
+    let order = core::iter::from_fn(|| scheduler.pick_next_task())
+        .map(|t| *t.inner())
+        .collect::<Vec<_>>();
+    assert_eq!(order, [0, 1, 3]);
+}
+
+#[test]
+fn test_rt_cfs() {
+    use crate::*;
+    use alloc::sync::Arc;
+
+    let mut scheduler = RTCFScheduler::<usize, 5>::new();
+    let tasks = (0..3)
+        .map(|i| Arc::new(RTCFSTask::new(RTTask::new(i))))
+        .collect::<Vec<_>>();
+    for t in &tasks {
+        scheduler.add_task(t.clone());
+    }
+    assert!(scheduler.set_priority(&tasks[0], 19));
+    assert!(!scheduler.set_priority(&tasks[0], 20));
+    assert!(scheduler.set_policy(&tasks[2], RTPolicy::Fifo, 10));
+    assert!(!scheduler.set_policy(&tasks[1], RTPolicy::Fifo, -1));
+    assert_eq!(scheduler.get_priority(&tasks[0]), 19);
+    assert_eq!(scheduler.get_priority(&tasks[2]), 10);
+    // the normal tasks are not picked ahead of a rejected real-time task
+    assert!(scheduler
+        .pick_next_task_if(|t| t.policy() == RTPolicy::Normal)
+        .is_none());
+
+    // the real-time task runs first, and is never preempted by normal tasks
+    let next = scheduler.pick_next_task().unwrap();
+    assert_eq!(**next.inner(), 2);
+    for _ in 0..10 {
+        assert!(!scheduler.task_tick(&next));
+    }
+    scheduler.put_prev_task(next, false);
+    assert_eq!(**scheduler.pick_next_task().unwrap().inner(), 2);
+
+    // normal tasks are preempted by a ready real-time task
+    let next = scheduler.pick_next_task().unwrap();
+    assert_eq!(next.policy(), RTPolicy::Normal);
+    assert!(scheduler.set_policy(&tasks[1 - **next.inner()], RTPolicy::RoundRobin, 0));
+    assert!(scheduler.task_tick(&next));
+    scheduler.put_prev_task(next, true);
+    let next = scheduler.pick_next_task().unwrap();
+    assert_eq!(next.policy(), RTPolicy::RoundRobin);
+
+    // and back to normal
+    assert!(scheduler.set_policy(&next, RTPolicy::Normal, 0));
+    scheduler.put_prev_task(next, false);
+    let mut n = 0;
+    while let Some(t) = scheduler.pick_next_task() {
+        assert_eq!(t.policy(), RTPolicy::Normal);
+        n += 1;
+    }
+    assert_eq!(n, 2);
+}
+
+#[test]
+fn test_edf() {
+    use crate::*;
+    use alloc::sync::Arc;
+
+    let mut scheduler = EDFScheduler::new();
+    let tasks = [0, 1, 2, 3].map(|i| Arc::new(EDFTask::new(i)));
+
+    // admission control
+    assert!(scheduler.set_params(&tasks[0], Some(EDFParams::new(1, 4, 4))));
+    assert!(scheduler.set_params(&tasks[1], Some(EDFParams::new(2, 5, 5))));
+    assert!(!scheduler.set_params(&tasks[2], Some(EDFParams::new(4, 10, 10))));
+    assert_eq!(tasks[2].params(), None);
+    assert!(!scheduler.set_params(&tasks[2], Some(EDFParams::new(2, 1, 10))));
+    assert!(scheduler.set_params(&tasks[2], Some(EDFParams::new(2, 10, 10))));
+    assert!(!scheduler.set_priority(&tasks[0], 1));
+    for t in &tasks {
+        scheduler.add_task(t.clone());
+    }
+
+    // run for two hyper-periods, with 85% of the time for the periodic tasks
+    let mut runs = [0; 4];
+    let mut current = scheduler.pick_next_task();
+    for _ in 0..40 {
+        for t in &tasks[..3] {
+            assert!(
+                t.budget() == 0 || t.abs_deadline() > scheduler.clock(),
+                "task {} missed its deadline",
+                t.inner()
+            );
+        }
+        let next = current.as_ref().unwrap();
+        runs[*next.inner()] += 1;
+        if scheduler.task_tick(next) {
+            scheduler.put_prev_task(current.take().unwrap(), true);
+            current = scheduler.pick_next_task();
+        }
+    }
+    assert_eq!(runs, [10, 16, 8, 6]);
+
+    // a throttled task is released by idle ticks
+    let mut scheduler = EDFScheduler::new();
+    let task = Arc::new(EDFTask::new(0));
+    assert!(scheduler.set_params(&task, Some(EDFParams::new(1, 2, 3))));
+    scheduler.add_task(task.clone());
+    let next = scheduler.pick_next_task().unwrap();
+    assert!(scheduler.task_tick(&next));
+    scheduler.put_prev_task(next, true);
+    for _ in 0..2 {
+        assert!(scheduler.pick_next_task().is_none());
+        scheduler.idle_tick();
+    }
+    assert_eq!(scheduler.pick_next_task().unwrap().budget(), 1);
+    assert!(scheduler.set_params(&task, None));
+    assert_eq!(task.params(), None);
+}
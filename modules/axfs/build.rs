@@ -0,0 +1,23 @@
+use std::path::PathBuf;
+use std::{env, fs};
+
+fn main() {
+    println!("cargo:rerun-if-env-changed=AX_INITRAMFS");
+    if env::var_os("CARGO_FEATURE_INITRAMFS").is_some() {
+        let path = match env::var("AX_INITRAMFS") {
+            Ok(path) if !path.is_empty() => {
+                println!("cargo:rerun-if-changed={}", path);
+                fs::canonicalize(&path)
+                    .unwrap_or_else(|e| panic!("failed to find initramfs {:?}: {}", path, e))
+            }
+            _ => {
+                // embed an empty archive, which gives an empty initramfs
+                println!("cargo:warning=AX_INITRAMFS is not set, the initramfs is empty");
+                let path = PathBuf::from(env::var("OUT_DIR").unwrap()).join("initramfs.cpio");
+                fs::write(&path, b"").unwrap();
+                path
+            }
+        };
+        println!("cargo:rustc-env=AX_INITRAMFS_PATH={}", path.display());
+    }
+}
@@ -26,5 +26,63 @@ create_test_img() {
 	sudo umount mnt
 }
 
+create_ext2_img() {
+	local name=$1
+	local blkcount=$2
+	local root=$(mktemp -d)
+	for i in $(seq 1 1000); do
+	  echo "Rust is cool!" >>"$root/long.txt"
+	done
+	echo "Rust is cool!" >>"$root/short.txt"
+	mkdir -p "$root/very/long/path"
+	echo "Rust is cool!" >>"$root/very/long/path/test.txt"
+	mkdir -p "$root/very-long-dir-name"
+	echo "Rust is cool!" >>"$root/very-long-dir-name/very-long-file-name.txt"
+	rm -f "$name"
+	mkfs.ext2 -b 1024 -I 128 -L "Test!" -d "$root" "$name" $blkcount
+	rm -rf "$root"
+}
+
+# The journal has two committed transactions that are not replayed yet: the
+# first one writes the data blocks of `journal.txt` and `revoked.txt`, and the
+# second one revokes the latter.
+create_ext3_img() {
+	local name=$1
+	local blkcount=$2
+	local root=$(mktemp -d)
+	for i in $(seq 1 1000); do
+	  echo "Rust is cool!" >>"$root/long.txt"
+	done
+	echo "Rust is cool!" >>"$root/short.txt"
+	mkdir -p "$root/very/long/path"
+	echo "Rust is cool!" >>"$root/very/long/path/test.txt"
+	mkdir -p "$root/very-long-dir-name"
+	echo "Rust is cool!" >>"$root/very-long-dir-name/very-long-file-name.txt"
+	echo "Not replayed" >"$root/journal.txt"
+	echo "Not replayed" >"$root/revoked.txt"
+	rm -f "$name"
+	mkfs.ext3 -b 1024 -I 128 -L "Test!" -J size=1 -d "$root" "$name" $blkcount
+	rm -rf "$root"
+
+	local blocks=$(for f in journal.txt revoked.txt; do
+	  debugfs -R "bmap $f 0" "$name" 2>/dev/null
+	done | paste -sd,)
+	local data=$(mktemp)
+	for i in 1 2; do
+	  printf 'Replayed! :)\n' | dd of="$data" bs=1024 seek=$((i - 1)) conv=sync 2>/dev/null
+	done
+	debugfs -w -f - "$name" <<-EOF
+		jo
+		jw -b $blocks $data
+		jc
+		jo
+		jw -r ${blocks#*,}
+		jc
+	EOF
+	rm -f "$data"
+}
+
 create_test_img "$CUR_DIR/fat16.img" 2500 16
 create_test_img "$CUR_DIR/fat32.img" 34000 32
+create_ext2_img "$CUR_DIR/ext2.img" 2048
+create_ext3_img "$CUR_DIR/ext3.img" 4096
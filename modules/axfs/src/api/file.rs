@@ -1,5 +1,6 @@
+use axfs_vfs::VfsSetAttr;
 use axio::{prelude::*, Result, SeekFrom};
-use core::fmt;
+use core::{fmt, time::Duration};
 
 use crate::fops;
 
@@ -16,7 +17,7 @@ pub struct File {
 }
 
 /// Metadata information about a file.
-pub struct Metadata(fops::FileAttr);
+pub struct Metadata(pub(super) fops::FileAttr);
 
 /// Options and flags which can be used to configure how a file is opened.
 #[derive(Clone, Debug)]
@@ -101,6 +102,43 @@ impl Metadata {
         self.0.perm()
     }
 
+    /// Returns the inode number of the file, unique in its filesystem.
+    pub const fn ino(&self) -> u64 {
+        self.0.ino()
+    }
+
+    /// Returns the number of hard links to the file.
+    pub const fn nlink(&self) -> u64 {
+        self.0.nlink()
+    }
+
+    /// Returns the user ID of the owner of the file.
+    pub const fn uid(&self) -> u32 {
+        self.0.uid()
+    }
+
+    /// Returns the group ID of the owner of the file.
+    pub const fn gid(&self) -> u32 {
+        self.0.gid()
+    }
+
+    /// Returns the last access time, as the duration since the UNIX epoch.
+    pub const fn accessed(&self) -> Duration {
+        self.0.atime()
+    }
+
+    /// Returns the last modification time, as the duration since the UNIX
+    /// epoch.
+    pub const fn modified(&self) -> Duration {
+        self.0.mtime()
+    }
+
+    /// Returns the last status change time, as the duration since the UNIX
+    /// epoch.
+    pub const fn changed(&self) -> Duration {
+        self.0.ctime()
+    }
+
     /// Returns the inner raw metadata [`fops::FileAttr`].
     pub const fn raw_metadata(&self) -> &fops::FileAttr {
         &self.0
@@ -113,6 +151,7 @@ impl fmt::Debug for Metadata {
             .field("file_type", &self.0.file_type())
             .field("is_dir", &self.0.is_dir())
             .field("is_file", &self.0.is_file())
+            .field("modified", &self.0.mtime())
             .finish_non_exhaustive()
     }
 }
@@ -156,6 +195,44 @@ impl File {
     pub fn metadata(&self) -> Result<Metadata> {
         self.inner.get_attr().map(Metadata)
     }
+
+    /// Changes the permissions on the underlying file.
+    pub fn set_permissions(&self, perm: Permissions) -> Result<()> {
+        self.inner.set_attr(&VfsSetAttr {
+            perm: Some(perm),
+            ..Default::default()
+        })
+    }
+
+    /// Changes the owner and group of the underlying file, `None` leaves the
+    /// ID unchanged.
+    pub fn set_owner(&self, uid: Option<u32>, gid: Option<u32>) -> Result<()> {
+        self.inner.set_attr(&VfsSetAttr {
+            uid,
+            gid,
+            ..Default::default()
+        })
+    }
+
+    /// Changes the last access and modification times of the underlying file,
+    /// `None` leaves the time unchanged.
+    pub fn set_times(&self, accessed: Option<Duration>, modified: Option<Duration>) -> Result<()> {
+        self.inner.set_attr(&VfsSetAttr {
+            atime: accessed,
+            mtime: modified,
+            ..Default::default()
+        })
+    }
+
+    /// Maps `len` bytes of the file from `offset` into memory, returns the
+    /// start address. The mapping is removed by [`munmap`](super::munmap).
+    ///
+    /// `offset` must be aligned to 4K. Writes to the memory are visible to
+    /// reads of the file, and vice versa.
+    #[cfg(feature = "paging")]
+    pub fn mmap(&self, offset: u64, len: usize, writable: bool) -> Result<usize> {
+        self.inner.mmap(offset, len, writable)
+    }
 }
 
 impl Read for File {
@@ -5,9 +5,14 @@ mod file;
 
 pub use self::dir::{DirBuilder, DirEntry, ReadDir};
 pub use self::file::{File, FileType, Metadata, OpenOptions, Permissions};
+pub use crate::check::Report;
+pub use crate::fs::FsType;
+pub use axfs_vfs::FileSystemInfo;
 
-use alloc::{string::String, vec::Vec};
+use alloc::{string::String, sync::Arc, vec::Vec};
+use axfs_vfs::{VfsOps, VfsSetAttr};
 use axio::{self as io, prelude::*};
+use core::time::Duration;
 
 /// Returns an iterator over the entries within a directory.
 pub fn read_dir(path: &str) -> io::Result<ReadDir> {
@@ -15,9 +20,9 @@ pub fn read_dir(path: &str) -> io::Result<ReadDir> {
 }
 
 /// Returns the canonical, absolute form of a path with all intermediate
-/// components normalized.
+/// components normalized and symbolic links resolved.
 pub fn canonicalize(path: &str) -> io::Result<String> {
-    crate::root::absolute_path(path)
+    crate::root::real_path(path, true)
 }
 
 /// Returns the current working directory as a [`String`].
@@ -59,6 +64,70 @@ pub fn metadata(path: &str) -> io::Result<Metadata> {
     File::open(path)?.metadata()
 }
 
+/// Changes the permissions found on a file or a directory.
+pub fn set_permissions(path: &str, perm: Permissions) -> io::Result<()> {
+    let attr = VfsSetAttr {
+        perm: Some(perm),
+        ..Default::default()
+    };
+    crate::root::lookup(None, path)?.set_attr(&attr)
+}
+
+/// Changes the owner and group of a file or a directory, `None` leaves the
+/// ID unchanged.
+pub fn chown(path: &str, uid: Option<u32>, gid: Option<u32>) -> io::Result<()> {
+    let attr = VfsSetAttr {
+        uid,
+        gid,
+        ..Default::default()
+    };
+    crate::root::lookup(None, path)?.set_attr(&attr)
+}
+
+/// Changes the last access and modification times of a file or a directory,
+/// `None` leaves the time unchanged.
+///
+/// Times are durations since the UNIX epoch.
+pub fn set_times(
+    path: &str,
+    accessed: Option<Duration>,
+    modified: Option<Duration>,
+) -> io::Result<()> {
+    let attr = VfsSetAttr {
+        atime: accessed,
+        mtime: modified,
+        ..Default::default()
+    };
+    crate::root::lookup(None, path)?.set_attr(&attr)
+}
+
+/// Queries the metadata about a file without following symbolic links.
+pub fn symlink_metadata(path: &str) -> io::Result<Metadata> {
+    let node = crate::root::lookup_nofollow(None, path)?;
+    node.get_attr().map(Metadata)
+}
+
+/// Creates a new symbolic link on the filesystem.
+///
+/// The `link` path will be a symbolic link pointing to the `original` path,
+/// which is not required to exist.
+pub fn symlink(original: &str, link: &str) -> io::Result<()> {
+    crate::root::symlink(None, link, original)
+}
+
+/// Reads a symbolic link, returning the path that the link points to.
+pub fn read_link(path: &str) -> io::Result<String> {
+    crate::root::read_link(None, path)
+}
+
+/// Creates a new hard link on the filesystem.
+///
+/// The `link` path will be a link pointing to the `original` path. Both paths
+/// must be in the same filesystem, and `original` cannot be a directory.
+pub fn hard_link(original: &str, link: &str) -> io::Result<()> {
+    crate::root::hard_link(original, link)
+}
+
 /// Creates a new, empty directory at the provided path.
 pub fn create_dir(path: &str) -> io::Result<()> {
     DirBuilder::new().create(path)
@@ -77,5 +146,66 @@ pub fn remove_dir(path: &str) -> io::Result<()> {
 
 /// Removes a file from the filesystem.
 pub fn remove_file(path: &str) -> io::Result<()> {
-    crate::root::remove_file(None, path)
+    let mount = crate::root::mount_point_of(path, false)?;
+    crate::root::remove_file(None, path, mount.as_ref())
+}
+
+/// Rename a file or directory to a new name, replacing the original file if
+/// `to` already exists.
+///
+/// The replacement is atomic on ramfs and ext2, i.e., `to` always exists
+/// during the operation. It is **not** atomic on FAT, where the original `to`
+/// is moved aside to `<to>.<n>~` and removed after the rename. Others may see
+/// `to` missing and the moved aside entry meanwhile, and an interrupted rename
+/// may leave no file at `to`. Both paths must be in the same filesystem.
+pub fn rename(from: &str, to: &str) -> io::Result<()> {
+    crate::root::rename(from, to)
+}
+
+/// Returns the attributes of the filesystem that contains the given path,
+/// e.g. the free space on it.
+pub fn statfs(path: &str) -> io::Result<FileSystemInfo> {
+    crate::root::statfs(path)
+}
+
+/// Removes the memory mapping of a file created by [`File::mmap`], `len`
+/// must be the length of the whole mapping.
+#[cfg(feature = "paging")]
+pub fn munmap(addr: usize, len: usize) -> io::Result<()> {
+    crate::fops::munmap(addr, len)
+}
+
+/// Mounts a filesystem on the directory at the given path.
+///
+/// The directory is created if it does not exist. Mount points can be nested,
+/// e.g. `/mnt` and `/mnt/usb`.
+pub fn mount(path: &str, fs: Arc<dyn VfsOps>) -> io::Result<()> {
+    crate::root::mount(path, fs)
+}
+
+/// Creates an empty filesystem of the given type on a partition, and mounts
+/// it at `/mnt/<dev>`. All data on the partition is lost.
+///
+/// `dev` is the name of the partition, as in `/proc/mounts`, e.g. `virtio-blk1`
+/// for the first partition of a virtio block device. Fails with
+/// [`ResourceBusy`](io::Error::ResourceBusy) if it is mounted.
+pub fn format(dev: &str, fs_type: FsType) -> io::Result<()> {
+    crate::root::format(dev, fs_type)
+}
+
+/// Checks the filesystem on a partition, and repairs it if `repair` is true.
+///
+/// `dev` is the name of the partition as in [`format`]. Fails with
+/// [`ResourceBusy`](io::Error::ResourceBusy) if it is mounted.
+pub fn check(dev: &str, repair: bool) -> io::Result<Report> {
+    crate::root::check(dev, repair)
+}
+
+/// Unmounts the filesystem mounted at the given path.
+///
+/// Fails with [`ResourceBusy`](io::Error::ResourceBusy) if there are files
+/// opened in it, other filesystems mounted under it, or the current directory
+/// is in it.
+pub fn umount(path: &str) -> io::Result<()> {
+    crate::root::umount(path)
 }
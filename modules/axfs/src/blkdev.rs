@@ -0,0 +1,93 @@
+//! Raw block devices exposed in devfs.
+
+use alloc::vec;
+use axdriver::prelude::*;
+use axfs_vfs::{VfsError, VfsNodeAttr, VfsNodeOps, VfsNodePerm, VfsNodeType, VfsResult};
+use axsync::Mutex;
+
+use crate::partition::Partition;
+
+/// A raw block device node in devfs, like `/dev/vda` on Linux.
+///
+/// Reads and writes go to the device directly, bypassing the caches of the
+/// filesystems mounted on it.
+pub(crate) struct BlockDev {
+    part: Mutex<Partition>,
+}
+
+impl BlockDev {
+    pub fn new(part: Partition) -> Self {
+        Self {
+            part: Mutex::new(part),
+        }
+    }
+}
+
+impl VfsNodeOps for BlockDev {
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        let part = self.part.lock();
+        let size = part.num_blocks() * part.block_size() as u64;
+        Ok(VfsNodeAttr::new(
+            VfsNodePerm::default_file(),
+            VfsNodeType::BlockDevice,
+            size,
+            size.div_ceil(512),
+        ))
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        let mut part = self.part.lock();
+        let block_size = part.block_size();
+        let size = part.num_blocks() * block_size as u64;
+        let len = buf.len().min(size.saturating_sub(offset) as usize);
+        let mut block = vec![0; block_size];
+        let mut pos = 0;
+        while pos < len {
+            let block_id = (offset + pos as u64) / block_size as u64;
+            let start = (offset + pos as u64) as usize % block_size;
+            let count = (block_size - start).min(len - pos);
+            part.read_block(block_id, &mut block)
+                .map_err(|_| VfsError::Io)?;
+            buf[pos..pos + count].copy_from_slice(&block[start..start + count]);
+            pos += count;
+        }
+        Ok(len)
+    }
+
+    fn write_at(&self, offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        let mut part = self.part.lock();
+        let block_size = part.block_size();
+        let size = part.num_blocks() * block_size as u64;
+        let len = buf.len().min(size.saturating_sub(offset) as usize);
+        if len == 0 && !buf.is_empty() {
+            return Err(VfsError::StorageFull);
+        }
+        let mut block = vec![0; block_size];
+        let mut pos = 0;
+        while pos < len {
+            let block_id = (offset + pos as u64) / block_size as u64;
+            let start = (offset + pos as u64) as usize % block_size;
+            let count = (block_size - start).min(len - pos);
+            if count < block_size {
+                // partial block, read it first
+                part.read_block(block_id, &mut block)
+                    .map_err(|_| VfsError::Io)?;
+            }
+            block[start..start + count].copy_from_slice(&buf[pos..pos + count]);
+            part.write_block(block_id, &block)
+                .map_err(|_| VfsError::Io)?;
+            pos += count;
+        }
+        Ok(len)
+    }
+
+    fn fsync(&self) -> VfsResult {
+        self.part.lock().flush().map_err(|_| VfsError::Io)
+    }
+
+    fn truncate(&self, _size: u64) -> VfsResult {
+        Ok(()) // the size of the device is fixed
+    }
+
+    axfs_vfs::impl_vfs_non_dir_default! {}
+}
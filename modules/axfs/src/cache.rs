@@ -0,0 +1,232 @@
+//! An LRU write-back cache of disk blocks.
+//!
+//! Blocks are kept in memory until they are evicted or flushed, so repeated
+//! accesses to the filesystem metadata do not reach the device. Dirty blocks
+//! are written back in contiguous runs, and misses on sequential blocks
+//! trigger a growing read-ahead.
+
+use alloc::{boxed::Box, collections::BTreeMap, vec, vec::Vec};
+use axdriver::prelude::*;
+
+use crate::partition::Partition;
+
+/// The default number of blocks in the cache.
+pub const DEFAULT_CAPACITY: usize = 1024;
+
+/// The maximum number of blocks to read ahead, or to write back at once.
+const MAX_BATCH: usize = 32;
+
+const NIL: usize = usize::MAX;
+
+struct Slot {
+    block_id: u64,
+    dirty: bool,
+    prev: usize,
+    next: usize,
+    data: Box<[u8]>,
+}
+
+/// A block cache with LRU replacement over a block device.
+pub struct BlockCache {
+    dev: Partition,
+    block_size: usize,
+    capacity: usize,
+    slots: Vec<Slot>,
+    /// Block ID to the index in `slots`.
+    map: BTreeMap<u64, usize>,
+    /// The most recently used slot.
+    head: usize,
+    /// The least recently used slot, evicted first.
+    tail: usize,
+    /// The block after the last miss, a miss on it means sequential access.
+    next_seq: u64,
+    /// The number of blocks to read ahead on the next sequential miss.
+    read_ahead: usize,
+}
+
+impl BlockCache {
+    /// Creates a cache that holds at most `capacity` blocks of the device.
+    pub fn new(dev: Partition, capacity: usize) -> Self {
+        Self {
+            block_size: dev.block_size(),
+            dev,
+            capacity: capacity.max(1),
+            slots: Vec::new(),
+            map: BTreeMap::new(),
+            head: NIL,
+            tail: NIL,
+            next_seq: u64::MAX,
+            read_ahead: 0,
+        }
+    }
+
+    /// The number of blocks in the device.
+    pub fn num_blocks(&self) -> u64 {
+        self.dev.num_blocks()
+    }
+
+    /// Reads `buf.len()` bytes at `offset` within the block.
+    pub fn read(&mut self, block_id: u64, offset: usize, buf: &mut [u8]) -> DevResult {
+        let idx = self.get(block_id, true)?;
+        buf.copy_from_slice(&self.slots[idx].data[offset..offset + buf.len()]);
+        Ok(())
+    }
+
+    /// Writes `buf` at `offset` within the block. The block is only written to
+    /// the device when evicted or flushed.
+    pub fn write(&mut self, block_id: u64, offset: usize, buf: &[u8]) -> DevResult {
+        // no need to read the block if it is overwritten entirely
+        let whole = offset == 0 && buf.len() == self.block_size;
+        let idx = self.get(block_id, !whole)?;
+        let slot = &mut self.slots[idx];
+        slot.data[offset..offset + buf.len()].copy_from_slice(buf);
+        slot.dirty = true;
+        Ok(())
+    }
+
+    /// Writes all dirty blocks back to the device, then flushes the device.
+    pub fn flush(&mut self) -> DevResult {
+        let dirty: Vec<usize> = self
+            .map
+            .values()
+            .copied()
+            .filter(|&idx| self.slots[idx].dirty)
+            .collect();
+        let mut start = 0;
+        for i in 1..=dirty.len() {
+            let run_end = i == dirty.len()
+                || i - start == MAX_BATCH
+                || self.slots[dirty[i]].block_id != self.slots[dirty[i - 1]].block_id + 1;
+            if run_end {
+                self.write_run(&dirty[start..i])?;
+                start = i;
+            }
+        }
+        self.dev.flush()
+    }
+
+    /// Returns the slot of the block, reads it from the device if it is not
+    /// cached and `fetch` is true.
+    fn get(&mut self, block_id: u64, fetch: bool) -> DevResult<usize> {
+        if let Some(&idx) = self.map.get(&block_id) {
+            self.unlink(idx);
+            self.push_front(idx);
+            return Ok(idx);
+        }
+        if !fetch {
+            return self.alloc_slot(block_id);
+        }
+
+        self.read_ahead = if block_id == self.next_seq {
+            (self.read_ahead * 2).clamp(2, MAX_BATCH - 1)
+        } else {
+            0
+        };
+        let limit = (self.num_blocks().saturating_sub(block_id) as usize)
+            .min(1 + self.read_ahead)
+            .min(self.capacity.div_ceil(2));
+        let count = (1..limit)
+            .find(|&i| self.map.contains_key(&(block_id + i as u64)))
+            .unwrap_or(limit)
+            .max(1);
+        self.next_seq = block_id + count as u64;
+
+        let bs = self.block_size;
+        let mut buf = vec![0; count * bs];
+        self.dev.read_block(block_id, &mut buf)?;
+        // insert in reverse order so that the requested block is the most
+        // recently used one
+        let mut idx = NIL;
+        for (i, data) in buf.chunks_exact(bs).enumerate().rev() {
+            idx = self.alloc_slot(block_id + i as u64)?;
+            self.slots[idx].data.copy_from_slice(data);
+        }
+        Ok(idx)
+    }
+
+    /// Allocates a clean slot for the block, evicts the least recently used
+    /// one if the cache is full. The content of the slot is undefined.
+    fn alloc_slot(&mut self, block_id: u64) -> DevResult<usize> {
+        let idx = if self.slots.len() < self.capacity {
+            self.slots.push(Slot {
+                block_id,
+                dirty: false,
+                prev: NIL,
+                next: NIL,
+                data: vec![0; self.block_size].into_boxed_slice(),
+            });
+            self.slots.len() - 1
+        } else {
+            let idx = self.tail;
+            if self.slots[idx].dirty {
+                self.write_back(idx)?;
+            }
+            self.unlink(idx);
+            self.map.remove(&self.slots[idx].block_id);
+            self.slots[idx].block_id = block_id;
+            idx
+        };
+        self.push_front(idx);
+        self.map.insert(block_id, idx);
+        Ok(idx)
+    }
+
+    /// Writes back the dirty block, along with the dirty blocks following it.
+    fn write_back(&mut self, idx: usize) -> DevResult {
+        let first = self.slots[idx].block_id;
+        let mut run = vec![idx];
+        while run.len() < MAX_BATCH {
+            match self.map.get(&(first + run.len() as u64)) {
+                Some(&next) if self.slots[next].dirty => run.push(next),
+                _ => break,
+            }
+        }
+        self.write_run(&run)
+    }
+
+    /// Writes the slots of contiguous blocks to the device with one request.
+    fn write_run(&mut self, run: &[usize]) -> DevResult {
+        let Some(&first) = run.first() else {
+            return Ok(());
+        };
+        let block_id = self.slots[first].block_id;
+        if run.len() == 1 {
+            self.dev.write_block(block_id, &self.slots[first].data)?;
+        } else {
+            let mut buf = Vec::with_capacity(run.len() * self.block_size);
+            for &idx in run {
+                buf.extend_from_slice(&self.slots[idx].data);
+            }
+            self.dev.write_block(block_id, &buf)?;
+        }
+        for &idx in run {
+            self.slots[idx].dirty = false;
+        }
+        Ok(())
+    }
+
+    fn unlink(&mut self, idx: usize) {
+        let (prev, next) = (self.slots[idx].prev, self.slots[idx].next);
+        if prev == NIL {
+            self.head = next;
+        } else {
+            self.slots[prev].next = next;
+        }
+        if next == NIL {
+            self.tail = prev;
+        } else {
+            self.slots[next].prev = prev;
+        }
+    }
+
+    fn push_front(&mut self, idx: usize) {
+        self.slots[idx].prev = NIL;
+        self.slots[idx].next = self.head;
+        if self.head == NIL {
+            self.tail = idx;
+        } else {
+            self.slots[self.head].prev = idx;
+        }
+        self.head = idx;
+    }
+}
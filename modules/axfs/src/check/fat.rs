@@ -0,0 +1,594 @@
+//! Checking FAT filesystems, like `fsck.fat`.
+//!
+//! The FAT is loaded into memory, and the directory tree is walked from the
+//! root to claim the cluster chain of each file. Chains that are broken, or
+//! that run into clusters already claimed, are truncated, and so are the ones
+//! longer than the size of their files. Then the clusters allocated but not
+//! claimed are freed, and the FAT is written to all copies.
+
+use alloc::{string::String, vec, vec::Vec};
+
+use axfs_vfs::{VfsError, VfsResult};
+
+use super::{Problem, Report};
+use crate::dev::Disk;
+
+/// Size of a directory entry.
+const ENTRY_SIZE: usize = 32;
+/// The first byte of the name of a deleted entry.
+const DELETED: u8 = 0xe5;
+/// Attribute of volume labels.
+const ATTR_VOLUME_ID: u8 = 0x08;
+/// Attribute of directories.
+const ATTR_DIRECTORY: u8 = 0x10;
+/// Attributes of long file name entries.
+const ATTR_LONG_NAME: u8 = 0x0f;
+/// Characters that are not allowed in short names.
+const INVALID_CHARS: &[u8] = b"\"*+,./:;<=>?[\\]|";
+/// Signatures of the FSInfo sector of FAT32.
+const FSINFO_SIGNATURES: [(usize, u32); 2] = [(0, 0x4161_5252), (484, 0x6141_7272)];
+/// Offset of the free cluster count in the FSInfo sector.
+const FSINFO_FREE_COUNT: usize = 488;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FatType {
+    Fat12,
+    Fat16,
+    Fat32,
+}
+
+/// The layout of the volume, from the boot sector.
+struct Volume {
+    fat_type: FatType,
+    bytes_per_sector: u64,
+    cluster_size: usize,
+    num_fats: u32,
+    /// Byte offset and size of each copy of the FAT.
+    fat_start: u64,
+    fat_size: u64,
+    /// Byte offset and size of the root directory of FAT12 and FAT16.
+    root_start: u64,
+    root_size: usize,
+    /// Byte offset of cluster 2, the first one.
+    data_start: u64,
+    /// Number of clusters, the last one is `num_clusters + 1`.
+    num_clusters: u32,
+    /// First cluster of the root directory of FAT32.
+    root_cluster: u32,
+    /// Sector of FSInfo of FAT32, 0 if there is none.
+    fsinfo_sector: u16,
+    /// Offset of the status flags in the boot sector.
+    flags_offset: usize,
+}
+
+impl Volume {
+    fn new(disk: &mut Disk) -> VfsResult<Self> {
+        let mut bs = [0; 512];
+        read_disk(disk, 0, &mut bs)?;
+        let bytes_per_sector = u16_at(&bs, 11) as u64;
+        let sectors_per_cluster = bs[13] as u64;
+        let reserved_sectors = u16_at(&bs, 14) as u64;
+        let num_fats = bs[16] as u32;
+        let root_entries = u16_at(&bs, 17) as u64;
+        let total_sectors = match u16_at(&bs, 19) {
+            0 => u32_at(&bs, 32) as u64,
+            n => n as u64,
+        };
+        let fat_sectors = match u16_at(&bs, 22) {
+            0 => u32_at(&bs, 36) as u64,
+            n => n as u64,
+        };
+        if bytes_per_sector == 0 || sectors_per_cluster == 0 || num_fats == 0 || fat_sectors == 0 {
+            return Err(VfsError::InvalidData);
+        }
+
+        let root_sectors = (root_entries * ENTRY_SIZE as u64).div_ceil(bytes_per_sector);
+        let data_sector = reserved_sectors + num_fats as u64 * fat_sectors + root_sectors;
+        let data_sectors = total_sectors
+            .checked_sub(data_sector)
+            .ok_or(VfsError::InvalidData)?;
+        let num_clusters = (data_sectors / sectors_per_cluster) as u32;
+        let fat_type = match num_clusters {
+            0..4085 => FatType::Fat12,
+            4085..65525 => FatType::Fat16,
+            _ => FatType::Fat32,
+        };
+        let volume = Self {
+            fat_type,
+            bytes_per_sector,
+            cluster_size: (sectors_per_cluster * bytes_per_sector) as usize,
+            num_fats,
+            fat_start: reserved_sectors * bytes_per_sector,
+            fat_size: fat_sectors * bytes_per_sector,
+            root_start: (data_sector - root_sectors) * bytes_per_sector,
+            root_size: (root_entries as usize) * ENTRY_SIZE,
+            data_start: data_sector * bytes_per_sector,
+            num_clusters,
+            root_cluster: if fat_type == FatType::Fat32 {
+                u32_at(&bs, 44)
+            } else {
+                0
+            },
+            fsinfo_sector: if fat_type == FatType::Fat32 {
+                u16_at(&bs, 48)
+            } else {
+                0
+            },
+            flags_offset: if fat_type == FatType::Fat32 { 65 } else { 37 },
+        };
+        // the FAT must cover all clusters
+        if volume.fat_size * 8 < (num_clusters as u64 + 2) * volume.entry_bits() as u64 {
+            return Err(VfsError::InvalidData);
+        }
+        Ok(volume)
+    }
+
+    const fn entry_bits(&self) -> u32 {
+        match self.fat_type {
+            FatType::Fat12 => 12,
+            FatType::Fat16 => 16,
+            FatType::Fat32 => 28,
+        }
+    }
+
+    /// The mask of the bits of an entry.
+    const fn entry_mask(&self) -> u32 {
+        (1 << self.entry_bits()) - 1
+    }
+
+    /// The smallest value of the end-of-chain marks.
+    const fn end_of_chain(&self) -> u32 {
+        0x0fff_fff8 & self.entry_mask()
+    }
+
+    /// The value of bad clusters.
+    const fn bad_cluster(&self) -> u32 {
+        0x0fff_fff7 & self.entry_mask()
+    }
+
+    /// The bits in the entry of cluster 1 that are cleared when the volume is
+    /// not cleanly unmounted or has I/O errors, which FAT12 does not have.
+    const fn clean_bits(&self) -> u32 {
+        match self.fat_type {
+            FatType::Fat12 => 0,
+            FatType::Fat16 => 0xc000,
+            FatType::Fat32 => 0x0c00_0000,
+        }
+    }
+
+    const fn is_valid(&self, cluster: u32) -> bool {
+        cluster >= 2 && cluster < self.num_clusters + 2
+    }
+
+    fn cluster_pos(&self, cluster: u32) -> u64 {
+        self.data_start + (cluster - 2) as u64 * self.cluster_size as u64
+    }
+
+    /// Decodes the `i`-th entry of the FAT.
+    fn entry(&self, fat: &[u8], i: usize) -> u32 {
+        match self.fat_type {
+            FatType::Fat12 => {
+                let pair = u16_at(fat, i * 3 / 2);
+                if i % 2 == 0 {
+                    (pair & 0xfff) as u32
+                } else {
+                    (pair >> 4) as u32
+                }
+            }
+            FatType::Fat16 => u16_at(fat, i * 2) as u32,
+            FatType::Fat32 => u32_at(fat, i * 4),
+        }
+    }
+
+    /// Encodes the entries into the FAT, the rest of it is left untouched.
+    fn encode(&self, entries: &[u32], fat: &mut [u8]) {
+        for (i, &entry) in entries.iter().enumerate() {
+            match self.fat_type {
+                FatType::Fat12 => {
+                    let pos = i * 3 / 2;
+                    let pair = u16_at(fat, pos);
+                    let pair = if i % 2 == 0 {
+                        (pair & 0xf000) | entry as u16
+                    } else {
+                        (pair & 0x000f) | (entry as u16) << 4
+                    };
+                    fat[pos..pos + 2].copy_from_slice(&pair.to_le_bytes());
+                }
+                FatType::Fat16 => {
+                    fat[i * 2..i * 2 + 2].copy_from_slice(&(entry as u16).to_le_bytes())
+                }
+                FatType::Fat32 => fat[i * 4..i * 4 + 4].copy_from_slice(&entry.to_le_bytes()),
+            }
+        }
+    }
+}
+
+/// Whether the FAT filesystem on the disk should be checked before it is
+/// mounted: it is marked dirty in the boot sector or in the FAT.
+pub(super) fn needs_check(disk: &mut Disk) -> VfsResult<bool> {
+    let volume = Volume::new(disk)?;
+    let mut flags = [0];
+    read_disk(disk, volume.flags_offset as u64, &mut flags)?;
+    let mut fat = [0; 8];
+    read_disk(disk, volume.fat_start, &mut fat)?;
+    let entry = volume.entry(&fat, 1);
+    let clean_bits = volume.clean_bits();
+    Ok(flags[0] & 1 != 0 || entry & clean_bits != clean_bits)
+}
+
+/// Checks the FAT filesystem on the disk, and repairs it if `repair` is true.
+/// The volume is marked clean after it is repaired.
+pub(super) fn check(mut disk: Disk, repair: bool) -> VfsResult<Report> {
+    let volume = Volume::new(&mut disk)?;
+    let mut fat = vec![0; volume.fat_size as usize];
+    read_disk(&mut disk, volume.fat_start, &mut fat)?;
+    let entries = (0..volume.num_clusters as usize + 2)
+        .map(|i| volume.entry(&fat, i))
+        .collect::<Vec<_>>();
+    let mut fsck = FatCheck {
+        disk,
+        repair,
+        report: Report::default(),
+        claimed: vec![false; entries.len()],
+        entries,
+        volume,
+    };
+    fsck.check_tree()?;
+    fsck.check_lost_clusters();
+    fsck.check_copies(&fat)?;
+    fsck.check_free_count()?;
+    if repair {
+        fsck.entries[1] |= fsck.volume.clean_bits();
+        fsck.volume.encode(&fsck.entries, &mut fat);
+        fsck.write_fats(&fat)?;
+        let flags_offset = fsck.volume.flags_offset as u64;
+        let mut flags = [0];
+        read_disk(&mut fsck.disk, flags_offset, &mut flags)?;
+        flags[0] &= !1;
+        write_disk(&mut fsck.disk, flags_offset, &flags)?;
+        fsck.disk.flush().map_err(|_| VfsError::Io)?;
+        fsck.report.repaired = true;
+    }
+    Ok(fsck.report)
+}
+
+/// The state of checking a FAT filesystem.
+struct FatCheck {
+    disk: Disk,
+    repair: bool,
+    report: Report,
+    volume: Volume,
+    /// The entries of the FAT, which are repaired in memory.
+    entries: Vec<u32>,
+    /// Clusters that belong to a file.
+    claimed: Vec<bool>,
+}
+
+/// A directory to check, with the first clusters of it and its parent, which
+/// are 0 for the root directory.
+struct Dir {
+    cluster: u32,
+    parent: u32,
+    /// Byte offsets of the parts of the directory, and their sizes.
+    extents: Vec<(u64, usize)>,
+}
+
+impl FatCheck {
+    fn problem(&mut self, problem: Problem) {
+        debug!("FAT check: {}", problem);
+        self.report.problems.push(problem);
+    }
+
+    /// Walks the directory tree from the root, claims the clusters of all
+    /// files and directories.
+    fn check_tree(&mut self) -> VfsResult {
+        let volume = &self.volume;
+        let extents = if volume.fat_type == FatType::Fat32 {
+            let root = volume.root_cluster;
+            let (chain, _) = self.claim_chain(root, None);
+            if chain.is_empty() {
+                warn!("FAT: bad root directory cluster {}", root);
+                return Err(VfsError::InvalidData);
+            }
+            self.extents(&chain)
+        } else {
+            vec![(volume.root_start, volume.root_size)]
+        };
+        let mut stack = vec![Dir {
+            cluster: 0,
+            parent: 0,
+            extents,
+        }];
+        while let Some(dir) = stack.pop() {
+            self.check_dir(&dir, &mut stack)?;
+        }
+        Ok(())
+    }
+
+    fn extents(&self, chain: &[u32]) -> Vec<(u64, usize)> {
+        chain
+            .iter()
+            .map(|&cluster| (self.volume.cluster_pos(cluster), self.volume.cluster_size))
+            .collect()
+    }
+
+    /// Checks the entries of a directory, pushes its subdirectories to
+    /// `stack`.
+    fn check_dir(&mut self, dir: &Dir, stack: &mut Vec<Dir>) -> VfsResult {
+        for &(pos, size) in &dir.extents {
+            let mut buf = vec![0; size];
+            read_disk(&mut self.disk, pos, &mut buf)?;
+            let mut changed = false;
+            let mut end = false;
+            for entry in buf.chunks_exact_mut(ENTRY_SIZE) {
+                if entry[0] == 0 {
+                    end = true; // no more entries after this one
+                    break;
+                }
+                if entry[0] != DELETED {
+                    changed |= self.check_entry(dir, entry, stack);
+                }
+            }
+            if changed && self.repair {
+                write_disk(&mut self.disk, pos, &buf)?;
+            }
+            if end {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks an entry of a directory, returns whether it is changed.
+    fn check_entry(&mut self, dir: &Dir, entry: &mut [u8], stack: &mut Vec<Dir>) -> bool {
+        let attr = entry[11];
+        if attr & ATTR_LONG_NAME == ATTR_LONG_NAME || attr & ATTR_VOLUME_ID != 0 {
+            return false;
+        }
+        let mut cluster = u16_at(entry, 26) as u32;
+        if self.volume.fat_type == FatType::Fat32 {
+            cluster |= (u16_at(entry, 20) as u32) << 16;
+        }
+        let size = u32_at(entry, 28);
+        let is_dir = attr & ATTR_DIRECTORY != 0;
+        let dir_name = |entry: &[u8]| String::from_utf8_lossy(&entry[..11]).into_owned();
+        let bad_entry = |entry: &[u8]| Problem::BadDirEntry {
+            dir: dir.cluster as u64,
+            name: dir_name(entry),
+        };
+
+        // `.` and `..` refer to the directory and its parent
+        let dot = match &entry[..11] {
+            b".          " => Some(dir.cluster),
+            b"..         " => Some(dir.parent),
+            _ => None,
+        };
+        if let Some(expected) = dot {
+            if dir.cluster == 0 || !is_dir {
+                self.problem(bad_entry(entry));
+                entry[0] = DELETED;
+                return true;
+            }
+            if cluster != expected {
+                self.problem(bad_entry(entry));
+                set_cluster(entry, expected);
+                return true;
+            }
+            return false;
+        }
+
+        let bad_name = entry[0] == b' '
+            || entry[..11]
+                .iter()
+                .enumerate()
+                .any(|(i, &c)| (c < 0x20 && !(i == 0 && c == 0x05)) || INVALID_CHARS.contains(&c));
+        if bad_name || (is_dir && cluster == 0) {
+            self.problem(bad_entry(entry));
+            entry[0] = DELETED;
+            return true;
+        }
+        if cluster == 0 {
+            if size != 0 {
+                self.problem(Problem::FileSize {
+                    file: 0,
+                    stored: size as u64,
+                    counted: 0,
+                });
+                entry[28..32].fill(0);
+                return true;
+            }
+            return false;
+        }
+
+        let cluster_size = self.volume.cluster_size as u64;
+        let limit = (!is_dir).then(|| (size as u64).div_ceil(cluster_size) as usize);
+        let (chain, cut) = self.claim_chain(cluster, limit);
+        if is_dir {
+            if chain.is_empty() {
+                self.problem(bad_entry(entry));
+                entry[0] = DELETED;
+                return true;
+            }
+            stack.push(Dir {
+                cluster,
+                parent: dir.cluster,
+                extents: self.extents(&chain),
+            });
+            return false;
+        }
+        let counted = chain.len() as u64 * cluster_size;
+        if cut || counted < size as u64 {
+            self.problem(Problem::FileSize {
+                file: cluster as u64,
+                stored: size as u64,
+                counted,
+            });
+            let size = counted.min(size as u64) as u32;
+            entry[28..32].copy_from_slice(&size.to_le_bytes());
+            if chain.is_empty() {
+                set_cluster(entry, 0);
+            }
+            return true;
+        }
+        false
+    }
+
+    /// Claims the cluster chain from `start`, which is truncated at a bad or
+    /// claimed cluster, or after `limit` clusters.
+    ///
+    /// Returns the clusters claimed, and whether the chain is truncated by
+    /// `limit`. The caller must clear the reference to `start` if no cluster
+    /// is claimed.
+    fn claim_chain(&mut self, start: u32, limit: Option<usize>) -> (Vec<u32>, bool) {
+        let (eoc, bad) = (self.volume.end_of_chain(), self.volume.bad_cluster());
+        let file = start as u64;
+        let mut chain: Vec<u32> = Vec::new();
+        let mut cluster = start;
+        loop {
+            let cut = limit.is_some_and(|limit| chain.len() >= limit);
+            if cut || !self.volume.is_valid(cluster) || self.claimed[cluster as usize] {
+                if !cut && !self.volume.is_valid(cluster) {
+                    let block = cluster as u64;
+                    self.problem(Problem::BadBlock { file, block });
+                } else if !cut {
+                    let block = cluster as u64;
+                    self.problem(Problem::CrossLinked { file, block });
+                }
+                if let Some(&last) = chain.last() {
+                    self.set_entry(last, eoc);
+                }
+                return (chain, cut);
+            }
+
+            self.claimed[cluster as usize] = true;
+            chain.push(cluster);
+            let next = self.entries[cluster as usize] & 0x0fff_ffff;
+            if next >= eoc {
+                return (chain, false);
+            }
+            if next == 0 || next == bad {
+                // the chain ends at a free or bad cluster
+                let block = next as u64;
+                self.problem(Problem::BadBlock { file, block });
+                self.set_entry(cluster, eoc);
+                return (chain, false);
+            }
+            cluster = next;
+        }
+    }
+
+    /// Sets the entry of a cluster, keeping the reserved bits of FAT32.
+    fn set_entry(&mut self, cluster: u32, value: u32) {
+        let entry = &mut self.entries[cluster as usize];
+        *entry = (*entry & 0xf000_0000) | value;
+    }
+
+    /// Frees the clusters that are allocated but not claimed.
+    fn check_lost_clusters(&mut self) {
+        let bad = self.volume.bad_cluster();
+        let lost: Vec<u32> = (2..self.entries.len() as u32)
+            .filter(|&c| {
+                let entry = self.entries[c as usize] & 0x0fff_ffff;
+                entry != 0 && entry != bad && !self.claimed[c as usize]
+            })
+            .collect();
+        if !lost.is_empty() {
+            self.problem(Problem::LostClusters {
+                count: lost.len() as u32,
+            });
+            for cluster in lost {
+                self.set_entry(cluster, 0);
+            }
+        }
+    }
+
+    /// Compares the copies of the FAT with the first one.
+    fn check_copies(&mut self, fat: &[u8]) -> VfsResult {
+        let mut copy = vec![0; fat.len()];
+        for i in 1..self.volume.num_fats {
+            let pos = self.volume.fat_start + i as u64 * self.volume.fat_size;
+            read_disk(&mut self.disk, pos, &mut copy)?;
+            if copy != fat {
+                self.problem(Problem::FatMismatch { fat: i });
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the FAT to all copies.
+    fn write_fats(&mut self, fat: &[u8]) -> VfsResult {
+        for i in 0..self.volume.num_fats {
+            let pos = self.volume.fat_start + i as u64 * self.volume.fat_size;
+            write_disk(&mut self.disk, pos, fat)?;
+        }
+        Ok(())
+    }
+
+    /// Compares the free cluster count in FSInfo of FAT32 with the FAT.
+    fn check_free_count(&mut self) -> VfsResult {
+        if self.volume.fsinfo_sector == 0 || self.volume.fsinfo_sector == 0xffff {
+            return Ok(());
+        }
+        let pos = self.volume.fsinfo_sector as u64 * self.volume.bytes_per_sector;
+        let mut sector = [0; 512];
+        read_disk(&mut self.disk, pos, &mut sector)?;
+        if FSINFO_SIGNATURES
+            .iter()
+            .any(|&(offset, sig)| u32_at(&sector, offset) != sig)
+        {
+            return Ok(());
+        }
+        let stored = u32_at(&sector, FSINFO_FREE_COUNT);
+        let counted = self.entries[2..]
+            .iter()
+            .filter(|&&entry| entry & 0x0fff_ffff == 0)
+            .count() as u32;
+        // the count may be unknown
+        if stored != u32::MAX && stored != counted {
+            self.problem(Problem::FreeBlocks {
+                group: None,
+                stored,
+                counted,
+            });
+            if self.repair {
+                let pos = pos + FSINFO_FREE_COUNT as u64;
+                write_disk(&mut self.disk, pos, &counted.to_le_bytes())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Sets the first cluster in a directory entry.
+fn set_cluster(entry: &mut [u8], cluster: u32) {
+    entry[20..22].copy_from_slice(&((cluster >> 16) as u16).to_le_bytes());
+    entry[26..28].copy_from_slice(&(cluster as u16).to_le_bytes());
+}
+
+fn u16_at(buf: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([buf[offset], buf[offset + 1]])
+}
+
+fn u32_at(buf: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(buf[offset..offset + 4].try_into().unwrap())
+}
+
+fn read_disk(disk: &mut Disk, pos: u64, buf: &mut [u8]) -> VfsResult {
+    disk.set_position(pos);
+    let mut buf = buf;
+    while !buf.is_empty() {
+        let n = disk.read_one(buf).map_err(|_| VfsError::Io)?;
+        buf = &mut buf[n..];
+    }
+    Ok(())
+}
+
+fn write_disk(disk: &mut Disk, pos: u64, buf: &[u8]) -> VfsResult {
+    disk.set_position(pos);
+    let mut buf = buf;
+    while !buf.is_empty() {
+        let n = disk.write_one(buf).map_err(|_| VfsError::Io)?;
+        buf = &buf[n..];
+    }
+    Ok(())
+}
@@ -0,0 +1,278 @@
+//! Filesystem consistency checking, like `fsck`.
+//!
+//! The filesystem on a partition is checked before it is mounted if it was
+//! not cleanly unmounted, or if it has been mounted too many times since the
+//! last check. Unmounted partitions can be checked by [`api::check`], and
+//! disk images by [`check_device`].
+//!
+//! Both checkers walk the directory tree from the root, and compare what they
+//! find with the allocation metadata:
+//!
+//! - ext2: inodes in use but unreachable from the root are reconnected to
+//!   `lost+found`, and link counts, bitmaps and free counts are rebuilt from
+//!   the inodes and the directory entries.
+//! - FAT: cross-linked and broken cluster chains are truncated, clusters that
+//!   belong to no file are freed, and the copies of the FAT are made equal.
+//!
+//! In both cases, bad directory entries are removed.
+//!
+//! [`api::check`]: crate::api::check
+
+#[cfg(feature = "fatfs")]
+mod fat;
+
+use alloc::{string::String, vec::Vec};
+use core::fmt;
+
+use axdriver::prelude::*;
+use axerrno::{ax_err, AxResult};
+use axfs_vfs::{VfsError, VfsResult};
+
+use crate::dev::Disk;
+use crate::fs::FsType;
+use crate::partition::Partition;
+
+/// An inconsistency found in a filesystem.
+///
+/// Files are identified by their inode numbers on ext2, and by their first
+/// clusters on FAT, where the root directory is 0.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Problem {
+    /// A directory entry is corrupted, or refers to something invalid.
+    BadDirEntry { dir: u64, name: String },
+    /// A block of a directory is missing, it is filled with an empty one.
+    DirHole { dir: u64, index: u64 },
+    /// A file refers to a block or a cluster out of the data area.
+    BadBlock { file: u64, block: u64 },
+    /// A file refers to a block or a cluster that belongs to another file, or
+    /// to the metadata. The file is truncated before it.
+    CrossLinked { file: u64, block: u64 },
+    /// The number of sectors of an inode differs from its blocks.
+    BlockCount {
+        file: u64,
+        stored: u64,
+        counted: u64,
+    },
+    /// The size of a file does not match the size of its cluster chain.
+    FileSize {
+        file: u64,
+        stored: u64,
+        counted: u64,
+    },
+    /// An inode in use is not reachable from the root directory. Only the
+    /// topmost of the unreachable directories is reported.
+    OrphanedInode { ino: u32 },
+    /// The link count of an inode differs from the number of entries that
+    /// refer to it.
+    LinkCount { ino: u32, stored: u32, counted: u32 },
+    /// Clusters that are allocated in the FAT but belong to no file.
+    LostClusters { count: u32 },
+    /// A copy of the FAT differs from the first one.
+    FatMismatch { fat: u32 },
+    /// The block bitmap of a group differs from the blocks in use.
+    BlockBitmap { group: u32 },
+    /// The inode bitmap of a group differs from the inodes in use.
+    InodeBitmap { group: u32 },
+    /// The number of free blocks in a group descriptor, or in the superblock
+    /// (or the FSInfo sector of FAT32) if `group` is `None`, is wrong.
+    FreeBlocks {
+        group: Option<u32>,
+        stored: u32,
+        counted: u32,
+    },
+    /// The number of free inodes in a group descriptor, or in the superblock
+    /// if `group` is `None`, is wrong.
+    FreeInodes {
+        group: Option<u32>,
+        stored: u32,
+        counted: u32,
+    },
+    /// The number of directories in a group descriptor is wrong.
+    DirsCount {
+        group: u32,
+        stored: u32,
+        counted: u32,
+    },
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let location = |group: &Option<u32>| match group {
+            Some(group) => alloc::format!("group {}", group),
+            None => String::from("superblock"),
+        };
+        match self {
+            Self::BadDirEntry { dir, name } => {
+                write!(f, "bad entry {:?} in directory {}", name, dir)
+            }
+            Self::DirHole { dir, index } => {
+                write!(f, "directory {} has a hole at block {}", dir, index)
+            }
+            Self::BadBlock { file, block } => {
+                write!(f, "file {} refers to bad block {}", file, block)
+            }
+            Self::CrossLinked { file, block } => {
+                write!(f, "file {} refers to block {} in use", file, block)
+            }
+            Self::BlockCount {
+                file,
+                stored,
+                counted,
+            } => {
+                write!(
+                    f,
+                    "file {} has {} sectors, counted {}",
+                    file, stored, counted
+                )
+            }
+            Self::FileSize {
+                file,
+                stored,
+                counted,
+            } => {
+                write!(f, "file {} has size {}, counted {}", file, stored, counted)
+            }
+            Self::OrphanedInode { ino } => write!(f, "inode {} is not in any directory", ino),
+            Self::LinkCount {
+                ino,
+                stored,
+                counted,
+            } => {
+                write!(f, "inode {} has {} links, counted {}", ino, stored, counted)
+            }
+            Self::LostClusters { count } => write!(f, "{} clusters belong to no file", count),
+            Self::FatMismatch { fat } => write!(f, "FAT {} differs from the first one", fat),
+            Self::BlockBitmap { group } => write!(f, "wrong block bitmap of group {}", group),
+            Self::InodeBitmap { group } => write!(f, "wrong inode bitmap of group {}", group),
+            Self::FreeBlocks {
+                group,
+                stored,
+                counted,
+            } => write!(
+                f,
+                "{} has {} free blocks, counted {}",
+                location(group),
+                stored,
+                counted
+            ),
+            Self::FreeInodes {
+                group,
+                stored,
+                counted,
+            } => write!(
+                f,
+                "{} has {} free inodes, counted {}",
+                location(group),
+                stored,
+                counted
+            ),
+            Self::DirsCount {
+                group,
+                stored,
+                counted,
+            } => write!(
+                f,
+                "group {} has {} directories, counted {}",
+                group, stored, counted
+            ),
+        }
+    }
+}
+
+/// The result of checking a filesystem.
+#[derive(Debug, Default)]
+pub struct Report {
+    /// The problems found, in the order they are found.
+    pub problems: Vec<Problem>,
+    /// Whether the problems have been repaired.
+    pub repaired: bool,
+}
+
+impl Report {
+    /// Whether no problem is found.
+    pub fn is_clean(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+/// Checks the filesystem on a whole block device, and repairs it if `repair`
+/// is true. The device is returned along with the report, so that it can be
+/// checked again or used otherwise.
+///
+/// It is meant for checking disk images, e.g. a ramdisk loaded from an image
+/// file in host tests. Partition tables are not scanned.
+pub fn check_device(dev: AxBlockDevice, repair: bool) -> AxResult<(Report, AxBlockDevice)> {
+    let name = String::from(dev.device_name());
+    let mut part = Partition::new(dev, name);
+    let report = match FsType::detect(&mut part) {
+        Ok(Some(ty)) => check(ty, part.clone(), repair)?,
+        Ok(None) => return ax_err!(Unsupported, "unknown filesystem"),
+        Err(_) => return ax_err!(Io),
+    };
+    // the disk of the checker has been dropped, and flushed
+    Ok((report, part.into_device().unwrap()))
+}
+
+/// Checks the filesystem of the given type on the partition, and repairs it
+/// if `repair` is true.
+///
+/// Fails with [`VfsError::Unsupported`] if the support of it is not enabled.
+pub(crate) fn check(ty: FsType, part: Partition, repair: bool) -> VfsResult<Report> {
+    let disk = Disk::new(part);
+    match ty {
+        #[cfg(feature = "fatfs")]
+        FsType::Fat => fat::check(disk, repair),
+        #[cfg(feature = "ext2")]
+        FsType::Ext2 => crate::fs::ext2::Ext2FileSystem::check(disk, repair),
+        #[allow(unreachable_patterns)]
+        _ => {
+            let _ = (disk, repair);
+            Err(VfsError::Unsupported)
+        }
+    }
+}
+
+/// Whether the filesystem on the partition should be checked before it is
+/// mounted.
+fn needs_check(ty: FsType, part: &Partition) -> VfsResult<bool> {
+    let mut disk = Disk::new(part.clone());
+    match ty {
+        #[cfg(feature = "fatfs")]
+        FsType::Fat => fat::needs_check(&mut disk),
+        #[cfg(feature = "ext2")]
+        FsType::Ext2 => crate::fs::ext2::Ext2FileSystem::needs_check(&mut disk),
+        #[allow(unreachable_patterns)]
+        _ => {
+            let _ = &mut disk;
+            Ok(false)
+        }
+    }
+}
+
+/// Checks and repairs the filesystem on the partition before it is mounted,
+/// if it needs to be checked.
+///
+/// Failures are only logged, the filesystem is mounted anyway.
+pub(crate) fn check_before_mount(ty: FsType, part: &Partition) {
+    let name = part.device_name();
+    let report = match needs_check(ty, part) {
+        Ok(true) => {
+            info!("{}: checking the {:?} filesystem", name, ty);
+            check(ty, part.clone(), true)
+        }
+        Ok(false) => return,
+        Err(e) => Err(e),
+    };
+    match report {
+        Ok(report) => {
+            for problem in &report.problems {
+                warn!("{}: {}", name, problem);
+            }
+            if !report.is_clean() {
+                warn!("{}: {} problems repaired", name, report.problems.len());
+            }
+        }
+        Err(e) => warn!("{}: failed to check the filesystem: {:?}", name, e),
+    }
+}
@@ -1,28 +1,36 @@
 use axdriver::prelude::*;
 
+use crate::cache::{BlockCache, DEFAULT_CAPACITY};
+use crate::partition::Partition;
+
 const BLOCK_SIZE: usize = 512;
 
 /// A disk device with a cursor.
+///
+/// All accesses go through a [`BlockCache`], call [`Disk::flush`] to write
+/// the modified blocks to the device.
 pub struct Disk {
     block_id: u64,
     offset: usize,
-    dev: AxBlockDevice,
+    cache: BlockCache,
 }
 
 impl Disk {
-    /// Create a new disk.
-    pub fn new(dev: AxBlockDevice) -> Self {
-        assert_eq!(BLOCK_SIZE, dev.block_size());
+    /// Create a new disk on the partition with the default cache capacity.
+    ///
+    /// The position 0 of the disk is the start of the partition.
+    pub(crate) fn new(part: Partition) -> Self {
+        assert_eq!(BLOCK_SIZE, part.block_size());
         Self {
             block_id: 0,
             offset: 0,
-            dev,
+            cache: BlockCache::new(part, DEFAULT_CAPACITY),
         }
     }
 
     /// Get the size of the disk.
     pub fn size(&self) -> u64 {
-        self.dev.num_blocks() * BLOCK_SIZE as u64
+        self.cache.num_blocks() * BLOCK_SIZE as u64
     }
 
     /// Get the position of the cursor.
@@ -38,55 +46,40 @@ impl Disk {
 
     /// Read within one block, returns the number of bytes read.
     pub fn read_one(&mut self, buf: &mut [u8]) -> DevResult<usize> {
-        let read_size = if self.offset == 0 && buf.len() >= BLOCK_SIZE {
-            // whole block
-            self.dev
-                .read_block(self.block_id, &mut buf[0..BLOCK_SIZE])?;
-            self.block_id += 1;
-            BLOCK_SIZE
-        } else {
-            // partial block
-            let mut data = [0u8; BLOCK_SIZE];
-            let start = self.offset;
-            let count = buf.len().min(BLOCK_SIZE - self.offset);
-
-            self.dev.read_block(self.block_id, &mut data)?;
-            buf[..count].copy_from_slice(&data[start..start + count]);
-
-            self.offset += count;
-            if self.offset >= BLOCK_SIZE {
-                self.block_id += 1;
-                self.offset -= BLOCK_SIZE;
-            }
-            count
-        };
-        Ok(read_size)
+        let count = buf.len().min(BLOCK_SIZE - self.offset);
+        self.cache
+            .read(self.block_id, self.offset, &mut buf[..count])?;
+        self.advance(count);
+        Ok(count)
     }
 
     /// Write within one block, returns the number of bytes written.
     pub fn write_one(&mut self, buf: &[u8]) -> DevResult<usize> {
-        let write_size = if self.offset == 0 && buf.len() >= BLOCK_SIZE {
-            // whole block
-            self.dev.write_block(self.block_id, &buf[0..BLOCK_SIZE])?;
-            self.block_id += 1;
-            BLOCK_SIZE
-        } else {
-            // partial block
-            let mut data = [0u8; BLOCK_SIZE];
-            let start = self.offset;
-            let count = buf.len().min(BLOCK_SIZE - self.offset);
+        let count = buf.len().min(BLOCK_SIZE - self.offset);
+        self.cache
+            .write(self.block_id, self.offset, &buf[..count])?;
+        self.advance(count);
+        Ok(count)
+    }
 
-            self.dev.read_block(self.block_id, &mut data)?;
-            data[start..start + count].copy_from_slice(&buf[..count]);
-            self.dev.write_block(self.block_id, &data)?;
+    /// Write all cached modifications to the device.
+    pub fn flush(&mut self) -> DevResult {
+        self.cache.flush()
+    }
 
-            self.offset += count;
-            if self.offset >= BLOCK_SIZE {
-                self.block_id += 1;
-                self.offset -= BLOCK_SIZE;
-            }
-            count
-        };
-        Ok(write_size)
+    fn advance(&mut self, count: usize) {
+        self.offset += count;
+        if self.offset >= BLOCK_SIZE {
+            self.block_id += 1;
+            self.offset -= BLOCK_SIZE;
+        }
+    }
+}
+
+impl Drop for Disk {
+    fn drop(&mut self) {
+        if let Err(e) = self.flush() {
+            warn!("failed to flush the disk: {:?}", e);
+        }
     }
 }
@@ -1,15 +1,20 @@
 //! Low-level filesystem operations.
 
+use alloc::{borrow::Cow, format, string::String, sync::Arc};
 use axerrno::{ax_err, ax_err_type, AxResult};
-use axfs_vfs::{VfsError, VfsNodeRef};
+use axfs_vfs::{VfsError, VfsNodeRef, VfsSetAttr};
 use axio::SeekFrom;
 use capability::{Cap, WithCap};
 use core::fmt;
 
+use crate::{page_cache::PageCache, root::MountPoint};
+
 #[cfg(feature = "myfs")]
 pub use crate::dev::Disk;
 #[cfg(feature = "myfs")]
 pub use crate::fs::myfs::MyFileSystemIf;
+#[cfg(feature = "paging")]
+pub use crate::mmap::munmap;
 
 /// Alias of [`axfs_vfs::VfsNodeType`].
 pub type FileType = axfs_vfs::VfsNodeType;
@@ -21,10 +26,17 @@ pub type FileAttr = axfs_vfs::VfsNodeAttr;
 pub type FilePerm = axfs_vfs::VfsNodePerm;
 
 /// An opened file object, with open permissions and a cursor.
+///
+/// Reads and writes of regular files on block devices go through the page
+/// cache, which is shared by all opened files of the same node. The modified
+/// data is written back on [`flush`](File::flush) or when the file is closed.
 pub struct File {
     node: WithCap<VfsNodeRef>,
+    cache: Option<Arc<PageCache>>,
     is_append: bool,
     offset: u64,
+    /// Keeps the filesystem mounted while the file is opened.
+    _mount: Option<Arc<MountPoint>>,
 }
 
 /// An opened directory object, with open permissions and a cursor for
@@ -32,6 +44,10 @@ pub struct File {
 pub struct Directory {
     node: WithCap<VfsNodeRef>,
     entry_idx: usize,
+    /// The absolute and canonical path, to find the mount points of the
+    /// paths relative to the directory.
+    path: String,
+    mount: Option<Arc<MountPoint>>,
 }
 
 /// Options and flags which can be used to configure how a file is opened.
@@ -112,11 +128,13 @@ impl OpenOptions {
 }
 
 impl File {
-    fn _open_at(dir: Option<&VfsNodeRef>, path: &str, opts: &OpenOptions) -> AxResult<Self> {
+    fn _open_at(dir: Option<&Directory>, path: &str, opts: &OpenOptions) -> AxResult<Self> {
         debug!("open file: {} {:?}", path, opts);
         if !opts.is_valid() {
             return ax_err!(InvalidInput);
         }
+        let (dir, path, mount) = resolve_at(dir, path, true)?;
+        let path = path.as_ref();
 
         let node_option = crate::root::lookup(dir, path);
         let node = if opts.create || opts.create_new {
@@ -149,13 +167,19 @@ impl File {
         }
 
         node.open()?;
+        let cache = PageCache::get(&node, mount.as_ref())?;
         if opts.truncate {
-            node.truncate(0)?;
+            match &cache {
+                Some(cache) => cache.truncate(0)?,
+                None => node.truncate(0)?,
+            }
         }
         Ok(Self {
             node: WithCap::new(node, access_cap),
+            cache,
             is_append: opts.append,
             offset: 0,
+            _mount: mount,
         })
     }
 
@@ -167,7 +191,11 @@ impl File {
 
     /// Truncates the file to the specified size.
     pub fn truncate(&self, size: u64) -> AxResult {
-        self.node.access(Cap::WRITE)?.truncate(size)?;
+        let node = self.node.access(Cap::WRITE)?;
+        match &self.cache {
+            Some(cache) => cache.truncate(size)?,
+            None => node.truncate(size)?,
+        }
         Ok(())
     }
 
@@ -177,7 +205,10 @@ impl File {
     /// After the read, the cursor will be advanced by the number of bytes read.
     pub fn read(&mut self, buf: &mut [u8]) -> AxResult<usize> {
         let node = self.node.access(Cap::READ)?;
-        let read_len = node.read_at(self.offset, buf)?;
+        let read_len = match &self.cache {
+            Some(cache) => cache.read_at(self.offset, buf)?,
+            None => node.read_at(self.offset, buf)?,
+        };
         self.offset += read_len as u64;
         Ok(read_len)
     }
@@ -192,14 +223,21 @@ impl File {
         if self.is_append {
             self.offset = self.get_attr()?.size();
         };
-        let write_len = node.write_at(self.offset, buf)?;
+        let write_len = match &self.cache {
+            Some(cache) => cache.write_at(self.offset, buf)?,
+            None => node.write_at(self.offset, buf)?,
+        };
         self.offset += write_len as u64;
         Ok(write_len)
     }
 
     /// Flushes the file, writes all buffered data to the underlying device.
     pub fn flush(&self) -> AxResult {
-        self.node.access(Cap::WRITE)?.fsync()?;
+        let node = self.node.access(Cap::WRITE)?;
+        if let Some(cache) = &self.cache {
+            cache.write_back()?;
+        }
+        node.fsync()?;
         Ok(())
     }
 
@@ -217,14 +255,43 @@ impl File {
         Ok(new_offset)
     }
 
+    /// Maps `len` bytes of the file from `offset` into the kernel address
+    /// space. Returns the start address of the mapping.
+    ///
+    /// The mapping shares the pages with the page cache, the modified data is
+    /// written back like the writes to the file. `offset` must be aligned to
+    /// 4K, and the mapped range must not exceed the last page of the file. It
+    /// must be removed by [`munmap`] with the same length.
+    ///
+    /// Returns [`Unsupported`](axerrno::AxError::Unsupported) if the `smp`
+    /// feature is enabled.
+    #[cfg(feature = "paging")]
+    pub fn mmap(&self, offset: u64, len: usize, writable: bool) -> AxResult<usize> {
+        let cap = if writable {
+            Cap::READ | Cap::WRITE
+        } else {
+            Cap::READ
+        };
+        self.node.access(cap)?;
+        match &self.cache {
+            Some(cache) => crate::mmap::mmap(cache, offset, len, writable),
+            None => ax_err!(Unsupported, "the file cannot be mapped"),
+        }
+    }
+
     /// Gets the file attributes.
     pub fn get_attr(&self) -> AxResult<FileAttr> {
         self.node.access(Cap::empty())?.get_attr()
     }
+
+    /// Changes the permission, owner or times of the file.
+    pub fn set_attr(&self, attr: &VfsSetAttr) -> AxResult {
+        self.node.access(Cap::empty())?.set_attr(attr)
+    }
 }
 
 impl Directory {
-    fn _open_dir_at(dir: Option<&VfsNodeRef>, path: &str, opts: &OpenOptions) -> AxResult<Self> {
+    fn _open_dir_at(dir: Option<&Directory>, path: &str, opts: &OpenOptions) -> AxResult<Self> {
         debug!("open dir: {}", path);
         if !opts.read {
             return ax_err!(InvalidInput);
@@ -232,6 +299,9 @@ impl Directory {
         if opts.create || opts.create_new || opts.write || opts.append || opts.truncate {
             return ax_err!(InvalidInput);
         }
+        let abs_path = crate::root::real_path(&path_at(dir, path), true)?;
+        let (dir, path, mount) = resolve_at(dir, path, true)?;
+        let path = path.as_ref();
 
         let node = crate::root::lookup(dir, path)?;
         let attr = node.get_attr()?;
@@ -239,14 +309,20 @@ impl Directory {
             return ax_err!(NotADirectory);
         }
         let access_cap = opts.into();
-        if !perm_to_cap(attr.perm()).contains(access_cap) {
+        let perm_cap = perm_to_cap(attr.perm());
+        if !perm_cap.contains(access_cap) {
             return ax_err!(PermissionDenied);
         }
+        // the paths relative to the directory can be looked up if it is
+        // searchable
+        let access_cap = access_cap | (perm_cap & Cap::EXECUTE);
 
         node.open()?;
         Ok(Self {
             node: WithCap::new(node, access_cap),
             entry_idx: 0,
+            path: abs_path,
+            mount,
         })
     }
 
@@ -267,13 +343,13 @@ impl Directory {
     /// Opens a directory at the path relative to this directory. Returns a
     /// [`Directory`] object.
     pub fn open_dir_at(&self, path: &str, opts: &OpenOptions) -> AxResult<Self> {
-        Self::_open_dir_at(self.access_at(path)?, path, opts)
+        Self::_open_dir_at(Some(self), path, opts)
     }
 
     /// Opens a file at the path relative to this directory. Returns a [`File`]
     /// object.
     pub fn open_file_at(&self, path: &str, opts: &OpenOptions) -> AxResult<File> {
-        File::_open_at(self.access_at(path)?, path, opts)
+        File::_open_at(Some(self), path, opts)
     }
 
     /// Creates an empty file at the path relative to this directory.
@@ -288,7 +364,8 @@ impl Directory {
 
     /// Removes a file at the path relative to this directory.
     pub fn remove_file(&self, path: &str) -> AxResult {
-        crate::root::remove_file(self.access_at(path)?, path)
+        let (dir, path, mount) = resolve_at(Some(self), path, false)?;
+        crate::root::remove_file(dir, &path, mount.as_ref())
     }
 
     /// Removes a directory at the path relative to this directory.
@@ -309,10 +386,33 @@ impl Directory {
         self.entry_idx += n;
         Ok(n)
     }
+
+    /// Returns the index of the entry that the next
+    /// [`read_dir`](Self::read_dir) starts from.
+    pub fn position(&self) -> usize {
+        self.entry_idx
+    }
+
+    /// Moves the cursor to the entry at `entry_idx`, e.g., 0 to read the
+    /// entries from the beginning again.
+    pub fn seek(&mut self, entry_idx: usize) {
+        self.entry_idx = entry_idx;
+    }
+
+    /// Gets the directory attributes.
+    pub fn get_attr(&self) -> AxResult<FileAttr> {
+        self.node.access(Cap::empty())?.get_attr()
+    }
 }
 
 impl Drop for File {
     fn drop(&mut self) {
+        // write back the data modified by this file and others
+        if let (Some(cache), true) = (&self.cache, self.node.can_access(Cap::WRITE)) {
+            if let Err(e) = cache.write_back() {
+                warn!("failed to write back the file: {:?}", e);
+            }
+        }
         unsafe { self.node.access_unchecked().release().ok() };
     }
 }
@@ -361,6 +461,55 @@ impl From<&OpenOptions> for Cap {
     }
 }
 
+/// Returns `path` relative to `dir` as an absolute path, or as is if there is
+/// no such directory.
+fn path_at(dir: Option<&Directory>, path: &str) -> String {
+    match dir {
+        Some(dir) if !path.starts_with('/') => {
+            format!("{}/{}", dir.path.trim_end_matches('/'), path)
+        }
+        _ => path.into(),
+    }
+}
+
+/// The directory node that a path is relative to, the path to look up, and the
+/// mount point that the path belongs to.
+type ResolvedPath<'a, 'b> = (
+    Option<&'a VfsNodeRef>,
+    Cow<'b, str>,
+    Option<Arc<MountPoint>>,
+);
+
+/// Resolves `path` relative to `dir`, see [`ResolvedPath`].
+///
+/// A path relative to `dir` is looked up from the root if it leads out of the
+/// filesystem of `dir`, e.g. into a filesystem mounted under `dir`, since the
+/// lookup from a node does not cross mount points. The symbolic link at the
+/// end of `path` is followed only if `follow` is true.
+fn resolve_at<'a, 'b>(
+    dir: Option<&'a Directory>,
+    path: &'b str,
+    follow: bool,
+) -> AxResult<ResolvedPath<'a, 'b>> {
+    let abs_path = path_at(dir, path);
+    let mount = crate::root::mount_point_of(&abs_path, follow)?;
+    match dir {
+        Some(dir) if !path.starts_with('/') => {
+            let node = dir.node.access(Cap::EXECUTE)?;
+            let same_fs = match (&mount, &dir.mount) {
+                (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+                (a, b) => a.is_none() && b.is_none(),
+            };
+            if same_fs && !path.split('/').any(|s| s == "..") {
+                Ok((Some(node), path.into(), mount))
+            } else {
+                Ok((None, abs_path.into(), mount))
+            }
+        }
+        _ => Ok((None, path.into(), mount)),
+    }
+}
+
 fn perm_to_cap(perm: FilePerm) -> Cap {
     let mut cap = Cap::empty();
     if perm.owner_readable() {
@@ -0,0 +1,152 @@
+//! Block allocation.
+//!
+//! Blocks are allocated as close as possible after a goal block, so that the
+//! data of a file stays contiguous and close to its inode.
+
+use alloc::vec;
+
+use axfs_vfs::{VfsError, VfsResult};
+
+use super::inode::Inode;
+use super::Ext2Inner;
+
+impl Ext2Inner {
+    /// Number of blocks in the given group, the last group may be smaller.
+    pub(super) fn blocks_in_group(&self, group: usize) -> u32 {
+        let sb = &self.superblock;
+        (sb.blocks_count - self.group_first_block(group)).min(sb.blocks_per_group)
+    }
+
+    /// Returns the first block of the given group.
+    pub(super) fn group_first_block(&self, group: usize) -> u32 {
+        self.superblock.first_data_block + group as u32 * self.superblock.blocks_per_group
+    }
+
+    /// Finds a zero bit in the bitmap block, sets it and returns its index.
+    ///
+    /// The search starts at `start`, and wraps around at `limit`. If
+    /// `committed` is set, the bit must also be zero in the bitmap on the disk,
+    /// i.e., it was not freed by the running transaction.
+    pub(super) fn alloc_bit(
+        &mut self,
+        bitmap: u32,
+        start: u32,
+        limit: u32,
+        committed: bool,
+    ) -> VfsResult<Option<u32>> {
+        let mut buf = vec![0; self.block_size];
+        self.read_block(bitmap, &mut buf)?;
+        let on_disk = if committed {
+            self.read_committed_block(bitmap)?
+        } else {
+            None
+        };
+        let is_free = |buf: &[u8], bit: u32| buf[(bit / 8) as usize] & (1 << (bit % 8)) == 0;
+        let start = if start < limit { start } else { 0 };
+        let found = (start..limit).chain(0..start).find(|&bit| {
+            is_free(&buf, bit) && on_disk.as_ref().is_none_or(|disk| is_free(disk, bit))
+        });
+        if let Some(bit) = found {
+            buf[(bit / 8) as usize] |= 1 << (bit % 8);
+            self.write_block(bitmap, &buf)?;
+        }
+        Ok(found)
+    }
+
+    /// Clears the bit in the bitmap block, returns whether it was set.
+    pub(super) fn free_bit(&mut self, bitmap: u32, bit: u32) -> VfsResult<bool> {
+        let pos = self.block_pos(bitmap) + (bit / 8) as u64;
+        let mask = 1 << (bit % 8);
+        let mut byte = [0];
+        self.read_bytes(pos, &mut byte)?;
+        if byte[0] & mask == 0 {
+            return Ok(false);
+        }
+        byte[0] &= !mask;
+        self.write_bytes(pos, &byte)?;
+        Ok(true)
+    }
+
+    /// Returns the preferred location of the `idx`-th data block of an inode:
+    /// right after the previous block, or at the start of the inode's group.
+    pub(super) fn block_goal(&mut self, ino: u32, inode: &Inode, idx: u32) -> VfsResult<u32> {
+        if idx > 0 {
+            let prev = self.bmap(inode, idx - 1)?;
+            if prev != 0 {
+                return Ok(prev + 1);
+            }
+        }
+        Ok(self.group_first_block(self.inode_group(ino)))
+    }
+
+    /// Allocates a zero-filled block, as close as possible after `goal`.
+    ///
+    /// The bitmap, the group descriptor and the superblock are updated
+    /// together. Blocks freed by the running transaction are only reused after
+    /// it commits, which is done here if there is no other free block.
+    pub(super) fn alloc_block(&mut self, goal: u32) -> VfsResult<u32> {
+        let sb = &self.superblock;
+        let goal = if goal >= sb.first_data_block && goal < sb.blocks_count {
+            goal - sb.first_data_block
+        } else {
+            0
+        };
+        let group_count = self.groups.len();
+        let goal_group = (goal / sb.blocks_per_group) as usize;
+        for i in 0..group_count {
+            let group = (goal_group + i) % group_count;
+            if self.groups[group].free_blocks_count == 0 {
+                continue;
+            }
+            let start = if i == 0 {
+                goal % self.superblock.blocks_per_group
+            } else {
+                0
+            };
+            let bitmap = self.groups[group].block_usage_addr;
+            let limit = self.blocks_in_group(group);
+            if let Some(bit) = self.alloc_bit(bitmap, start, limit, true)? {
+                self.groups[group].free_blocks_count -= 1;
+                self.superblock.free_blocks_count -= 1;
+                self.write_group(group)?;
+                self.write_superblock()?;
+                // the block is not in use on the disk, so it can be written
+                // in place whatever it becomes
+                let block = self.group_first_block(group) + bit;
+                self.write_data_bytes(self.block_pos(block), &vec![0; self.block_size])?;
+                return Ok(block);
+            }
+            if self.journal.is_none() {
+                warn!(
+                    "ext2: group {} has no free block but a non-zero count",
+                    group
+                );
+            }
+        }
+        if self.journal.as_ref().is_some_and(|j| j.is_dirty()) {
+            self.commit()?;
+            return self.alloc_block(goal + self.superblock.first_data_block);
+        }
+        Err(VfsError::StorageFull)
+    }
+
+    /// Frees a block, updates the bitmap, the group descriptor and the
+    /// superblock.
+    pub(super) fn free_block(&mut self, block: u32) -> VfsResult {
+        let sb = &self.superblock;
+        if block < sb.first_data_block || block >= sb.blocks_count {
+            return Err(VfsError::InvalidData);
+        }
+        let group = ((block - sb.first_data_block) / sb.blocks_per_group) as usize;
+        let bit = (block - sb.first_data_block) % sb.blocks_per_group;
+        if self.free_bit(self.groups[group].block_usage_addr, bit)? {
+            self.groups[group].free_blocks_count += 1;
+            self.superblock.free_blocks_count += 1;
+            self.write_group(group)?;
+            self.write_superblock()?;
+        } else {
+            warn!("ext2: freeing free block {}", block);
+        }
+        Ok(())
+    }
+}
@@ -30,7 +30,22 @@ pub struct BlockGroupDescriptor {
 }
 
 impl BlockGroupDescriptor {
-    pub fn new() -> Option<Self> {
-        unimplemented!()
+    /// Size of an on-disk block group descriptor, in bytes.
+    pub const SIZE: usize = 32;
+
+    /// Parses a block group descriptor from its on-disk representation.
+    pub fn new_from_buf(buf: &[u8]) -> Self {
+        assert!(buf.len() >= Self::SIZE);
+        // SAFETY: `BlockGroupDescriptor` is `repr(C)`, mirrors the on-disk
+        // layout and consists only of integers.
+        unsafe { core::ptr::read_unaligned(buf.as_ptr() as *const Self) }
+    }
+
+    /// Returns the on-disk representation of the block group descriptor.
+    pub fn as_bytes(&self) -> &[u8] {
+        // SAFETY: `BlockGroupDescriptor` is `repr(C)` without padding.
+        unsafe { core::slice::from_raw_parts(self as *const Self as *const u8, Self::SIZE) }
     }
-}
\ No newline at end of file
+}
+
+const _: () = assert!(core::mem::size_of::<BlockGroupDescriptor>() == BlockGroupDescriptor::SIZE);
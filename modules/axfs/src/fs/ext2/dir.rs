@@ -0,0 +1,524 @@
+use alloc::{sync::Arc, vec, vec::Vec};
+
+use axfs_vfs::{VfsDirEntry, VfsNodeAttr, VfsNodeOps, VfsNodeRef, VfsNodeType};
+use axfs_vfs::{VfsError, VfsResult, VfsSetAttr};
+
+use super::inode::Inode;
+use super::{Ext2Inner, Ext2Ref, FileNode};
+
+/// Size of the fixed part of a directory entry.
+const ENTRY_HEADER_SIZE: usize = 8;
+/// Maximum length of a file name.
+pub(super) const NAME_MAX: usize = 255;
+
+/// Header of an on-disk directory entry, followed by the name.
+pub(super) struct EntryHeader {
+    /// Inode number, 0 if the entry is unused
+    pub inode: u32,
+    /// Total size of this entry, the next entry starts after it
+    pub rec_len: usize,
+    /// Length of the name
+    pub name_len: usize,
+    /// Type indicator, only valid if the filesystem has `filetype`
+    pub file_type: u8,
+}
+
+impl EntryHeader {
+    pub fn parse(block: &[u8], offset: usize) -> VfsResult<Self> {
+        if offset + ENTRY_HEADER_SIZE > block.len() {
+            return Err(VfsError::InvalidData);
+        }
+        let buf = &block[offset..];
+        let header = Self {
+            inode: u32::from_le_bytes(buf[0..4].try_into().unwrap()),
+            rec_len: u16::from_le_bytes(buf[4..6].try_into().unwrap()) as usize,
+            name_len: buf[6] as usize,
+            file_type: buf[7],
+        };
+        if header.rec_len < ENTRY_HEADER_SIZE
+            || header.rec_len % 4 != 0
+            || offset + header.rec_len > block.len()
+            || ENTRY_HEADER_SIZE + header.name_len > header.rec_len
+        {
+            return Err(VfsError::InvalidData);
+        }
+        Ok(header)
+    }
+
+    pub fn write(&self, block: &mut [u8], offset: usize) {
+        let buf = &mut block[offset..];
+        buf[0..4].copy_from_slice(&self.inode.to_le_bytes());
+        buf[4..6].copy_from_slice(&(self.rec_len as u16).to_le_bytes());
+        buf[6] = self.name_len as u8;
+        buf[7] = self.file_type;
+    }
+
+    pub fn name<'a>(&self, block: &'a [u8], offset: usize) -> &'a [u8] {
+        let start = offset + ENTRY_HEADER_SIZE;
+        &block[start..start + self.name_len]
+    }
+}
+
+/// Space taken by an entry with a name of the given length.
+const fn entry_len(name_len: usize) -> usize {
+    (ENTRY_HEADER_SIZE + name_len + 3) & !3
+}
+
+/// Converts a VFS node type to the type indicator of directory entries.
+pub(super) fn type_to_indicator(ty: VfsNodeType) -> u8 {
+    match ty {
+        VfsNodeType::File => 1,
+        VfsNodeType::Dir => 2,
+        VfsNodeType::CharDevice => 3,
+        VfsNodeType::BlockDevice => 4,
+        VfsNodeType::Fifo => 5,
+        VfsNodeType::Socket => 6,
+        VfsNodeType::SymLink => 7,
+    }
+}
+
+/// Converts a type indicator of directory entries to the VFS node type.
+fn indicator_to_type(indicator: u8) -> Option<VfsNodeType> {
+    Some(match indicator {
+        1 => VfsNodeType::File,
+        2 => VfsNodeType::Dir,
+        3 => VfsNodeType::CharDevice,
+        4 => VfsNodeType::BlockDevice,
+        5 => VfsNodeType::Fifo,
+        6 => VfsNodeType::Socket,
+        7 => VfsNodeType::SymLink,
+        _ => return None,
+    })
+}
+
+impl Ext2Inner {
+    /// Number of data blocks of a directory.
+    pub(super) fn dir_blocks(&self, dir: &Inode) -> u32 {
+        (dir.size() / self.block_size as u64) as u32
+    }
+
+    /// Reads the `idx`-th block of a directory, returns its address and
+    /// content.
+    fn read_dir_block(&mut self, dir: &Inode, idx: u32) -> VfsResult<(u32, Vec<u8>)> {
+        let block = self.bmap(dir, idx)?;
+        if block == 0 {
+            return Err(VfsError::InvalidData); // directories have no holes
+        }
+        let mut buf = vec![0; self.block_size];
+        self.read_block(block, &mut buf)?;
+        Ok((block, buf))
+    }
+
+    /// Calls `f` with the inode number, name and type indicator of every used
+    /// entry in the directory, until it returns `true`.
+    pub(super) fn dir_for_each<F>(&mut self, dir: &Inode, mut f: F) -> VfsResult
+    where
+        F: FnMut(u32, &[u8], u8) -> bool,
+    {
+        for idx in 0..self.dir_blocks(dir) {
+            let (_, buf) = self.read_dir_block(dir, idx)?;
+            let mut offset = 0;
+            while offset < buf.len() {
+                let header = EntryHeader::parse(&buf, offset)?;
+                if header.inode != 0 && f(header.inode, header.name(&buf, offset), header.file_type)
+                {
+                    return Ok(());
+                }
+                offset += header.rec_len;
+            }
+        }
+        Ok(())
+    }
+
+    /// Finds the entry with the given name, returns its inode number.
+    pub(super) fn dir_find(&mut self, dir: &Inode, name: &str) -> VfsResult<Option<u32>> {
+        let mut found = None;
+        self.dir_for_each(dir, |ino, entry_name, _| {
+            if entry_name == name.as_bytes() {
+                found = Some(ino);
+                true
+            } else {
+                false
+            }
+        })?;
+        Ok(found)
+    }
+
+    /// Whether the directory contains nothing but `.` and `..`.
+    pub(super) fn dir_is_empty(&mut self, dir: &Inode) -> VfsResult<bool> {
+        let mut empty = true;
+        self.dir_for_each(dir, |_, name, _| {
+            empty = name == b"." || name == b"..";
+            !empty
+        })?;
+        Ok(empty)
+    }
+
+    /// Reads directory entries into `dirents`, starting from `start_idx`.
+    fn dir_read(
+        &mut self,
+        dir: &Inode,
+        start_idx: usize,
+        dirents: &mut [VfsDirEntry],
+    ) -> VfsResult<usize> {
+        let mut entries = Vec::new();
+        let mut idx = 0;
+        self.dir_for_each(dir, |ino, name, file_type| {
+            if idx >= start_idx {
+                entries.push((ino, Vec::from(name), file_type));
+            }
+            idx += 1;
+            entries.len() >= dirents.len()
+        })?;
+        let has_filetype = self.superblock.has_filetype();
+        for (out_entry, (ino, name, file_type)) in dirents.iter_mut().zip(&entries) {
+            let ty = match indicator_to_type(*file_type).filter(|_| has_filetype) {
+                Some(ty) => ty,
+                None => self.read_inode(*ino)?.file_type(),
+            };
+            let name = core::str::from_utf8(name).map_err(|_| VfsError::InvalidData)?;
+            *out_entry = VfsDirEntry::new(name, ty);
+        }
+        Ok(entries.len())
+    }
+
+    /// Adds an entry pointing to `ino` into the directory.
+    ///
+    /// The caller is responsible for writing back the directory inode.
+    pub(super) fn dir_add(
+        &mut self,
+        dir_ino: u32,
+        dir: &mut Inode,
+        name: &str,
+        ino: u32,
+        ty: VfsNodeType,
+    ) -> VfsResult {
+        if name.len() > NAME_MAX {
+            return Err(VfsError::InvalidInput);
+        }
+        dir.flags &= !Inode::FLAG_INDEX;
+        dir.touch_modify(super::now());
+        let file_type = if self.superblock.has_filetype() {
+            type_to_indicator(ty)
+        } else {
+            0
+        };
+        let needed = entry_len(name.len());
+        let new_entry = |rec_len| EntryHeader {
+            inode: ino,
+            rec_len,
+            name_len: name.len(),
+            file_type,
+        };
+        let write_name = |buf: &mut [u8], offset: usize| {
+            let start = offset + ENTRY_HEADER_SIZE;
+            buf[start..start + name.len()].copy_from_slice(name.as_bytes());
+        };
+
+        for idx in 0..self.dir_blocks(dir) {
+            let (block, mut buf) = self.read_dir_block(dir, idx)?;
+            let mut offset = 0;
+            while offset < buf.len() {
+                let mut header = EntryHeader::parse(&buf, offset)?;
+                if header.inode == 0 && header.rec_len >= needed {
+                    // reuse an unused entry
+                    new_entry(header.rec_len).write(&mut buf, offset);
+                    write_name(&mut buf, offset);
+                    return self.write_block(block, &buf);
+                }
+                let used = entry_len(header.name_len);
+                if header.inode != 0 && header.rec_len >= used + needed {
+                    // split the free space at the end of this entry
+                    let new_offset = offset + used;
+                    new_entry(header.rec_len - used).write(&mut buf, new_offset);
+                    write_name(&mut buf, new_offset);
+                    header.rec_len = used;
+                    header.write(&mut buf, offset);
+                    return self.write_block(block, &buf);
+                }
+                offset += header.rec_len;
+            }
+        }
+
+        // no space left, append a new block
+        let idx = self.dir_blocks(dir);
+        let block = self.bmap_alloc(dir_ino, dir, idx)?;
+        let mut buf = vec![0; self.block_size];
+        new_entry(self.block_size).write(&mut buf, 0);
+        write_name(&mut buf, 0);
+        self.write_block(block, &buf)?;
+        dir.set_size(dir.size() + self.block_size as u64);
+        Ok(())
+    }
+
+    /// Removes the entry with the given name from the directory, returns the
+    /// inode number it pointed to.
+    pub(super) fn dir_remove(&mut self, dir: &mut Inode, name: &str) -> VfsResult<u32> {
+        for idx in 0..self.dir_blocks(dir) {
+            let (block, mut buf) = self.read_dir_block(dir, idx)?;
+            let mut prev: Option<(usize, EntryHeader)> = None;
+            let mut offset = 0;
+            while offset < buf.len() {
+                let mut header = EntryHeader::parse(&buf, offset)?;
+                if header.inode != 0 && header.name(&buf, offset) == name.as_bytes() {
+                    let ino = header.inode;
+                    if let Some((prev_offset, mut prev)) = prev {
+                        // merge into the previous entry
+                        prev.rec_len += header.rec_len;
+                        prev.write(&mut buf, prev_offset);
+                    } else {
+                        header.inode = 0;
+                        header.write(&mut buf, offset);
+                    }
+                    dir.flags &= !Inode::FLAG_INDEX;
+                    dir.touch_modify(super::now());
+                    self.write_block(block, &buf)?;
+                    return Ok(ino);
+                }
+                let rec_len = header.rec_len;
+                prev = Some((offset, header));
+                offset += rec_len;
+            }
+        }
+        Err(VfsError::NotFound)
+    }
+
+    /// Points the entry with the given name in the directory to `ino`, which
+    /// has the type `ty`.
+    pub(super) fn dir_set(
+        &mut self,
+        dir: &Inode,
+        name: &str,
+        ino: u32,
+        ty: VfsNodeType,
+    ) -> VfsResult {
+        let file_type = if self.superblock.has_filetype() {
+            type_to_indicator(ty)
+        } else {
+            0
+        };
+        for idx in 0..self.dir_blocks(dir) {
+            let (block, mut buf) = self.read_dir_block(dir, idx)?;
+            let mut offset = 0;
+            while offset < buf.len() {
+                let mut header = EntryHeader::parse(&buf, offset)?;
+                if header.inode != 0 && header.name(&buf, offset) == name.as_bytes() {
+                    header.inode = ino;
+                    header.file_type = file_type;
+                    header.write(&mut buf, offset);
+                    return self.write_block(block, &buf);
+                }
+                offset += header.rec_len;
+            }
+        }
+        Err(VfsError::NotFound)
+    }
+
+    /// Writes the first block of a new directory, with the `.` and `..`
+    /// entries.
+    pub(super) fn dir_init(&mut self, dir: &mut Inode, ino: u32, parent_ino: u32) -> VfsResult {
+        let block = self.bmap_alloc(ino, dir, 0)?;
+        let file_type = if self.superblock.has_filetype() {
+            type_to_indicator(VfsNodeType::Dir)
+        } else {
+            0
+        };
+        let mut buf = vec![0; self.block_size];
+        let dot_len = entry_len(1);
+        EntryHeader {
+            inode: ino,
+            rec_len: dot_len,
+            name_len: 1,
+            file_type,
+        }
+        .write(&mut buf, 0);
+        buf[ENTRY_HEADER_SIZE] = b'.';
+        EntryHeader {
+            inode: parent_ino,
+            rec_len: self.block_size - dot_len,
+            name_len: 2,
+            file_type,
+        }
+        .write(&mut buf, dot_len);
+        buf[dot_len + ENTRY_HEADER_SIZE..dot_len + ENTRY_HEADER_SIZE + 2].copy_from_slice(b"..");
+        self.write_block(block, &buf)?;
+        dir.set_size(self.block_size as u64);
+        Ok(())
+    }
+}
+
+/// The directory node in the ext2 filesystem.
+///
+/// It implements [`axfs_vfs::VfsNodeOps`].
+pub struct DirNode {
+    fs: Ext2Ref,
+    ino: u32,
+}
+
+impl DirNode {
+    pub(super) fn new(fs: Ext2Ref, ino: u32) -> Arc<Self> {
+        Arc::new(Self { fs, ino })
+    }
+
+    fn lookup_child(&self, name: &str) -> VfsResult<VfsNodeRef> {
+        let mut fs = self.fs.lock();
+        let dir = fs.read_inode(self.ino)?;
+        let ino = fs.dir_find(&dir, name)?.ok_or(VfsError::NotFound)?;
+        let child = fs.read_inode(ino)?;
+        drop(fs);
+        Ok(super::new_node(&self.fs, ino, &child))
+    }
+
+    /// Looks up the directory containing the last component of `path`,
+    /// returns its inode number along with the last component.
+    fn lookup_parent<'a>(&self, path: &'a str) -> VfsResult<(u32, &'a str)> {
+        let path = path.trim_end_matches('/');
+        let (parent, name) = path.rsplit_once('/').unwrap_or(("", path));
+        if name.is_empty() || name == "." || name == ".." {
+            return Err(VfsError::InvalidInput);
+        }
+        let parent = Self::new(self.fs.clone(), self.ino).lookup(parent)?;
+        match parent.as_any().downcast_ref::<Self>() {
+            Some(dir) if Arc::ptr_eq(&dir.fs, &self.fs) => Ok((dir.ino, name)),
+            Some(_) => Err(VfsError::CrossesDevices),
+            None if parent.get_attr()?.is_dir() => Err(VfsError::CrossesDevices),
+            None => Err(VfsError::NotADirectory),
+        }
+    }
+}
+
+impl VfsNodeOps for DirNode {
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        self.fs.lock().get_attr(self.ino)
+    }
+
+    fn set_attr(&self, attr: &VfsSetAttr) -> VfsResult {
+        self.fs.lock().set_attr(self.ino, attr)
+    }
+
+    fn parent(&self) -> Option<VfsNodeRef> {
+        let mut fs = self.fs.lock();
+        if self.ino == Inode::ROOT_INO {
+            return fs.mount_parent.clone();
+        }
+        let dir = fs.read_inode(self.ino).ok()?;
+        let parent_ino = fs.dir_find(&dir, "..").ok()??;
+        drop(fs);
+        Some(Self::new(self.fs.clone(), parent_ino))
+    }
+
+    fn lookup(self: Arc<Self>, path: &str) -> VfsResult<VfsNodeRef> {
+        let (name, rest) = split_path(path);
+        let node = match name {
+            "" | "." => Ok(self.clone() as VfsNodeRef),
+            ".." => self.parent().ok_or(VfsError::NotFound),
+            _ => self.lookup_child(name),
+        }?;
+
+        if let Some(rest) = rest {
+            node.lookup(rest)
+        } else {
+            Ok(node)
+        }
+    }
+
+    fn read_dir(&self, start_idx: usize, dirents: &mut [VfsDirEntry]) -> VfsResult<usize> {
+        let mut fs = self.fs.lock();
+        let dir = fs.read_inode(self.ino)?;
+        fs.dir_read(&dir, start_idx, dirents)
+    }
+
+    fn create(&self, path: &str, ty: VfsNodeType) -> VfsResult {
+        log::debug!("create {:?} at ext2: {}", ty, path);
+        let (name, rest) = split_path(path);
+        if let Some(rest) = rest {
+            match name {
+                "" | "." => self.create(rest, ty),
+                ".." => self.parent().ok_or(VfsError::NotFound)?.create(rest, ty),
+                _ => self.lookup_child(name)?.create(rest, ty),
+            }
+        } else if name.is_empty() || name == "." || name == ".." {
+            Ok(()) // already exists
+        } else {
+            self.fs.lock().create_node(self.ino, name, ty)
+        }
+    }
+
+    fn remove(&self, path: &str) -> VfsResult {
+        log::debug!("remove at ext2: {}", path);
+        let (name, rest) = split_path(path);
+        if let Some(rest) = rest {
+            match name {
+                "" | "." => self.remove(rest),
+                ".." => self.parent().ok_or(VfsError::NotFound)?.remove(rest),
+                _ => self.lookup_child(name)?.remove(rest),
+            }
+        } else if name.is_empty() || name == "." || name == ".." {
+            Err(VfsError::InvalidInput) // remove '.' or '..
+        } else {
+            self.fs.lock().remove_node(self.ino, name)
+        }
+    }
+
+    fn symlink(&self, path: &str, target: &str) -> VfsResult {
+        log::debug!("symlink {} -> {} at ext2", path, target);
+        let (name, rest) = split_path(path);
+        if let Some(rest) = rest {
+            match name {
+                "" | "." => self.symlink(rest, target),
+                ".." => self
+                    .parent()
+                    .ok_or(VfsError::NotFound)?
+                    .symlink(rest, target),
+                _ => self.lookup_child(name)?.symlink(rest, target),
+            }
+        } else if name.is_empty() || name == "." || name == ".." {
+            Err(VfsError::AlreadyExists)
+        } else {
+            self.fs.lock().create_symlink(self.ino, name, target)
+        }
+    }
+
+    fn link(&self, path: &str, node: &VfsNodeRef) -> VfsResult {
+        log::debug!("link at ext2: {}", path);
+        let (name, rest) = split_path(path);
+        if let Some(rest) = rest {
+            match name {
+                "" | "." => self.link(rest, node),
+                ".." => self.parent().ok_or(VfsError::NotFound)?.link(rest, node),
+                _ => self.lookup_child(name)?.link(rest, node),
+            }
+        } else if name.is_empty() || name == "." || name == ".." {
+            Err(VfsError::AlreadyExists)
+        } else {
+            let any = node.as_any();
+            if any.is::<DirNode>() {
+                return Err(VfsError::PermissionDenied);
+            }
+            match any.downcast_ref::<FileNode>() {
+                Some(file) if Arc::ptr_eq(&file.fs, &self.fs) => {
+                    self.fs.lock().link_node(self.ino, name, file.ino)
+                }
+                _ => Err(VfsError::CrossesDevices),
+            }
+        }
+    }
+
+    fn rename(&self, src_path: &str, dst_path: &str) -> VfsResult {
+        log::debug!("rename at ext2: {} -> {}", src_path, dst_path);
+        let (src_dir, src_name) = self.lookup_parent(src_path)?;
+        let (dst_dir, dst_name) = self.lookup_parent(dst_path)?;
+        self.fs
+            .lock()
+            .rename_node(src_dir, src_name, dst_dir, dst_name)
+    }
+
+    axfs_vfs::impl_vfs_dir_default! {}
+}
+
+fn split_path(path: &str) -> (&str, Option<&str>) {
+    let trimmed_path = path.trim_start_matches('/');
+    trimmed_path.find('/').map_or((trimmed_path, None), |n| {
+        (&trimmed_path[..n], Some(&trimmed_path[n + 1..]))
+    })
+}
@@ -0,0 +1,66 @@
+use alloc::string::String;
+use axfs_vfs::{impl_vfs_non_dir_default, VfsNodeAttr, VfsNodeOps, VfsResult, VfsSetAttr};
+
+use super::{now, Ext2Ref};
+
+/// The file node in the ext2 filesystem, it also represents other non-directory
+/// nodes like symbolic links and device files.
+///
+/// It implements [`axfs_vfs::VfsNodeOps`].
+pub struct FileNode {
+    pub(super) fs: Ext2Ref,
+    pub(super) ino: u32,
+}
+
+impl FileNode {
+    pub(super) const fn new(fs: Ext2Ref, ino: u32) -> Self {
+        Self { fs, ino }
+    }
+}
+
+impl VfsNodeOps for FileNode {
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        self.fs.lock().get_attr(self.ino)
+    }
+
+    fn set_attr(&self, attr: &VfsSetAttr) -> VfsResult {
+        self.fs.lock().set_attr(self.ino, attr)
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        let mut fs = self.fs.lock();
+        let inode = fs.read_inode(self.ino)?;
+        fs.read_data(&inode, offset, buf)
+    }
+
+    fn write_at(&self, offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        let mut fs = self.fs.lock();
+        fs.start_op()?;
+        let mut inode = fs.read_inode(self.ino)?;
+        let res = fs.write_data(self.ino, &mut inode, offset, buf);
+        inode.touch_modify(now());
+        // blocks may have been allocated even if the write failed
+        fs.write_inode(self.ino, &inode)?;
+        res
+    }
+
+    fn fsync(&self) -> VfsResult {
+        self.fs.lock().sync()
+    }
+
+    fn truncate(&self, size: u64) -> VfsResult {
+        let mut fs = self.fs.lock();
+        fs.start_op()?;
+        let mut inode = fs.read_inode(self.ino)?;
+        let res = fs.truncate_data(&mut inode, size);
+        inode.touch_modify(now());
+        fs.write_inode(self.ino, &inode)?;
+        res
+    }
+
+    fn readlink(&self) -> VfsResult<String> {
+        self.fs.lock().read_symlink(self.ino)
+    }
+
+    impl_vfs_non_dir_default! {}
+}
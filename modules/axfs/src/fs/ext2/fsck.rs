@@ -0,0 +1,732 @@
+//! Consistency checking, like `e2fsck`.
+//!
+//! The check runs in the following passes:
+//!
+//! 1. All inodes in use are scanned, and the blocks they refer to are
+//!    recorded. Pointers out of the filesystem, or to blocks that are already
+//!    in use, are cleared.
+//! 2. The directory tree is walked from the root. Bad entries are removed,
+//!    and the entries that refer to each inode are counted.
+//! 3. The bitmaps and the free counts are rebuilt from what is in use.
+//! 4. Holes in directories are filled, and the inodes in use that are not
+//!    reached are reconnected to `lost+found`, both by the usual allocators.
+//!    The tree is walked again to count the new entries.
+//! 5. The link counts are fixed.
+//!
+//! The journal, if any, has been replayed when the filesystem is loaded, and
+//! the repairs are journaled like other updates.
+
+use alloc::{format, string::String, vec, vec::Vec};
+
+use axfs_vfs::{VfsError, VfsNodeType, VfsResult};
+
+use super::block_group::BlockGroupDescriptor;
+use super::dir::{type_to_indicator, EntryHeader};
+use super::inode::Inode;
+use super::superblock::Superblock;
+use super::{now, read_disk, Ext2FileSystem, Ext2Inner};
+use crate::check::{Problem, Report};
+use crate::dev::Disk;
+
+/// The directory where unreachable inodes are reconnected, as `#<ino>`.
+const LOST_FOUND: &str = "lost+found";
+/// Number of block pointers in an inode: 12 direct ones, a singly, a doubly
+/// and a triply indirect one.
+const BLOCK_PTRS: usize = 15;
+/// Type indicator of directories in [`Fsck::types`].
+const DIR_TYPE: u8 = 2;
+
+/// A bitmap of blocks or inodes in memory.
+struct Bitmap(Vec<u8>);
+
+impl Bitmap {
+    fn new(bits: u32) -> Self {
+        Self(vec![0; (bits as usize).div_ceil(8)])
+    }
+
+    fn get(&self, bit: u32) -> bool {
+        self.0[(bit / 8) as usize] & (1 << (bit % 8)) != 0
+    }
+
+    fn set(&mut self, bit: u32) {
+        self.0[(bit / 8) as usize] |= 1 << (bit % 8);
+    }
+}
+
+/// What is found on the disk so far.
+struct Fsck {
+    repair: bool,
+    report: Report,
+    /// Blocks in use by the metadata or by inodes, indexed by block number.
+    blocks: Bitmap,
+    /// Extended attribute blocks, which can be shared by several inodes.
+    ea_blocks: Bitmap,
+    /// Type indicators of the inodes in use as in directory entries, or 0 if
+    /// an inode is not in use.
+    types: Vec<u8>,
+    /// Inodes reached from the root directory.
+    reached: Bitmap,
+    /// Number of directory entries that refer to each inode.
+    links: Vec<u16>,
+    /// Holes in directories, as the inode number and the block index.
+    holes: Vec<(u32, u32)>,
+}
+
+impl Fsck {
+    fn problem(&mut self, problem: Problem) {
+        debug!("ext2 check: {}", problem);
+        self.report.problems.push(problem);
+    }
+
+    /// Records that `block` is used by the inode `ino`, returns `false` if it
+    /// cannot be: it is out of the filesystem or already in use.
+    fn claim(&mut self, sb: &Superblock, ino: u32, block: u32) -> bool {
+        let (file, block_u64) = (ino as u64, block as u64);
+        if block < sb.first_data_block || block >= sb.blocks_count {
+            self.problem(Problem::BadBlock {
+                file,
+                block: block_u64,
+            });
+            false
+        } else if self.blocks.get(block) {
+            self.problem(Problem::CrossLinked {
+                file,
+                block: block_u64,
+            });
+            false
+        } else {
+            self.blocks.set(block);
+            true
+        }
+    }
+
+    /// Forgets the inodes reached by a previous walk of the tree.
+    fn reset_walk(&mut self) {
+        self.reached = Bitmap::new(self.types.len() as u32);
+        self.links.fill(0);
+    }
+}
+
+/// How to repair a directory entry.
+enum Repair {
+    /// Remove the entry.
+    Remove,
+    /// Point the entry to the given inode, with the given type indicator.
+    Set(u32, u8),
+}
+
+impl Ext2FileSystem {
+    /// Whether the filesystem on the disk should be checked before it is
+    /// mounted: it is not cleanly unmounted, has errors, or has been mounted
+    /// too many times or for too long since the last check.
+    ///
+    /// A journaled filesystem is consistent once its journal is replayed, so
+    /// it is not checked only because it is not cleanly unmounted.
+    pub fn needs_check(disk: &mut Disk) -> VfsResult<bool> {
+        let mut buf = [0; Superblock::SIZE];
+        read_disk(disk, Superblock::OFFSET, &mut buf)?;
+        let sb = Superblock::new_from_buf(&buf);
+        if sb.magic != Superblock::MAGIC {
+            return Err(VfsError::InvalidData);
+        }
+        let unclean = sb.state & Superblock::FS_CLEAN == 0 && !sb.has_journal();
+        let has_errors = sb.state & Superblock::FS_ERR != 0;
+        let mounts_due = sb.max_mnt_count > 0 && sb.mnt_count >= sb.max_mnt_count as u16;
+        let time_due =
+            sb.checkinterval != 0 && now() >= sb.lastcheck.saturating_add(sb.checkinterval);
+        Ok(unclean || has_errors || mounts_due || time_due)
+    }
+
+    /// Checks the ext2 filesystem on the disk, and repairs it if `repair` is
+    /// true. The filesystem is marked clean after it is repaired.
+    ///
+    /// The journal is replayed first if needed, even if `repair` is false.
+    pub fn check(disk: Disk, repair: bool) -> VfsResult<Report> {
+        let mut inner = Ext2Inner::new(disk)?;
+        if repair && inner.read_only {
+            return Err(VfsError::PermissionDenied);
+        }
+        inner.check(repair)
+    }
+}
+
+impl Ext2Inner {
+    fn check(&mut self, repair: bool) -> VfsResult<Report> {
+        let sb = &self.superblock;
+        let mut fsck = Fsck {
+            repair,
+            report: Report::default(),
+            blocks: Bitmap::new(sb.blocks_count),
+            ea_blocks: Bitmap::new(sb.blocks_count),
+            types: vec![0; sb.inodes_count as usize + 1],
+            reached: Bitmap::new(sb.inodes_count + 1),
+            links: vec![0; sb.inodes_count as usize + 1],
+            holes: Vec::new(),
+        };
+        self.check_groups(&mut fsck)?;
+        self.check_inodes(&mut fsck)?;
+        self.check_tree(&mut fsck)?;
+        let orphans = self.find_orphans(&mut fsck);
+        self.check_bitmaps(&mut fsck)?;
+        if repair {
+            self.fill_holes(&mut fsck)?;
+            if !orphans.is_empty() {
+                self.reconnect(&mut fsck, &orphans)?;
+                fsck.reset_walk();
+                self.check_tree(&mut fsck)?;
+                self.fill_holes(&mut fsck)?;
+            }
+        }
+        self.check_links(&mut fsck)?;
+
+        if repair {
+            let sb = &mut self.superblock;
+            sb.state = (sb.state | Superblock::FS_CLEAN) & !Superblock::FS_ERR;
+            sb.mnt_count = 0;
+            sb.lastcheck = now();
+            self.write_superblock()?;
+            self.sync()?;
+            fsck.report.repaired = true;
+        }
+        Ok(fsck.report)
+    }
+
+    /// Marks the metadata blocks of all groups in use.
+    fn check_groups(&mut self, fsck: &mut Fsck) -> VfsResult {
+        let sb = self.superblock;
+        let gdt_blocks =
+            (self.groups.len() * BlockGroupDescriptor::SIZE).div_ceil(self.block_size) as u32;
+        let itable_blocks =
+            (sb.inodes_per_group as usize * sb.inode_size()).div_ceil(self.block_size) as u32;
+        for (group, desc) in self.groups.iter().enumerate() {
+            let first = self.group_first_block(group);
+            if sb.group_has_super(group as u32) {
+                (first..first + 1 + gdt_blocks).for_each(|block| fsck.blocks.set(block));
+            }
+            let itable = desc.inode_table_block;
+            let metadata = [desc.block_usage_addr, desc.inode_usage_addr]
+                .into_iter()
+                .chain(itable..itable.saturating_add(itable_blocks));
+            for block in metadata {
+                if block < sb.first_data_block || block >= sb.blocks_count {
+                    warn!("ext2: the metadata of group {} is out of range", group);
+                    return Err(VfsError::InvalidData);
+                }
+                fsck.blocks.set(block);
+            }
+        }
+        Ok(())
+    }
+
+    /// Pass 1: scans all inodes in use, and claims their blocks.
+    fn check_inodes(&mut self, fsck: &mut Fsck) -> VfsResult {
+        let first_ino = self.superblock.first_ino();
+        let sectors = (self.block_size / 512) as u32;
+        for ino in 1..=self.superblock.inodes_count {
+            let mut inode = self.read_inode(ino)?;
+            let reserved = ino < first_ino && ino != Inode::ROOT_INO;
+            if !reserved && (inode.links_count == 0 || inode.mode == 0) {
+                if ino == Inode::ROOT_INO {
+                    warn!("ext2: the root directory is not in use");
+                    return Err(VfsError::InvalidData);
+                }
+                continue;
+            }
+            if ino == Inode::ROOT_INO && !inode.is_dir() {
+                warn!("ext2: the root inode is not a directory");
+                return Err(VfsError::InvalidData);
+            }
+            fsck.types[ino as usize] = type_to_indicator(inode.file_type());
+
+            // the bad blocks inode has no type, but has data blocks
+            let has_blocks =
+                (reserved && inode.mode == 0) || inode.has_data_blocks(self.block_size);
+            let mut counted = 0;
+            let mut changed = false;
+            if has_blocks {
+                for (slot, ptr) in inode.block.iter_mut().enumerate() {
+                    let depth = slot.saturating_sub(BLOCK_PTRS - 4);
+                    if *ptr != 0 && !self.check_block_tree(fsck, ino, *ptr, depth, &mut counted)? {
+                        *ptr = 0;
+                        changed = true;
+                    }
+                }
+            }
+            let acl = inode.file_acl;
+            if acl != 0 {
+                let shared = acl < self.superblock.blocks_count && fsck.ea_blocks.get(acl);
+                if shared || fsck.claim(&self.superblock, ino, acl) {
+                    fsck.ea_blocks.set(acl);
+                    counted += sectors;
+                } else {
+                    inode.file_acl = 0;
+                    changed = true;
+                }
+            }
+            if inode.blocks != counted {
+                fsck.problem(Problem::BlockCount {
+                    file: ino as u64,
+                    stored: inode.blocks as u64,
+                    counted: counted as u64,
+                });
+                inode.blocks = counted;
+                changed = true;
+            }
+            if changed && fsck.repair {
+                self.write_inode(ino, &inode)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Claims the block `block` of the inode `ino`, which is an indirect block
+    /// of the given depth, or a data block if `depth` is 0. The sectors of the
+    /// blocks claimed are added to `sectors`.
+    ///
+    /// Returns whether the pointer to the block should be kept.
+    fn check_block_tree(
+        &mut self,
+        fsck: &mut Fsck,
+        ino: u32,
+        block: u32,
+        depth: usize,
+        sectors: &mut u32,
+    ) -> VfsResult<bool> {
+        if !fsck.claim(&self.superblock, ino, block) {
+            return Ok(false);
+        }
+        *sectors += (self.block_size / 512) as u32;
+        if depth > 0 {
+            let mut ptrs = self.read_ptrs(block)?;
+            let mut changed = false;
+            for ptr in ptrs.iter_mut().filter(|ptr| **ptr != 0) {
+                if !self.check_block_tree(fsck, ino, *ptr, depth - 1, sectors)? {
+                    *ptr = 0;
+                    changed = true;
+                }
+            }
+            if changed && fsck.repair {
+                self.write_ptrs(block, &ptrs)?;
+            }
+        }
+        Ok(true)
+    }
+
+    /// Pass 2: walks the directory tree from the root.
+    fn check_tree(&mut self, fsck: &mut Fsck) -> VfsResult {
+        fsck.reached.set(Inode::ROOT_INO);
+        let mut stack = vec![(Inode::ROOT_INO, Inode::ROOT_INO)];
+        while let Some((ino, parent)) = stack.pop() {
+            self.check_dir(fsck, ino, parent, &mut stack)?;
+        }
+        Ok(())
+    }
+
+    /// Checks the entries of the directory `ino` reached from `parent`, pushes
+    /// the subdirectories reached for the first time to `stack`.
+    fn check_dir(
+        &mut self,
+        fsck: &mut Fsck,
+        ino: u32,
+        parent: u32,
+        stack: &mut Vec<(u32, u32)>,
+    ) -> VfsResult {
+        let mut dir = self.read_inode(ino)?;
+        let mut dir_changed = false;
+        for idx in 0..self.dir_blocks(&dir) {
+            // bad pointers are only cleared when repairing, so they are taken
+            // as holes as well
+            let block = match self.bmap(&dir, idx) {
+                Ok(block) if block != 0 && block < self.superblock.blocks_count => block,
+                _ => {
+                    fsck.problem(Problem::DirHole {
+                        dir: ino as u64,
+                        index: idx as u64,
+                    });
+                    fsck.holes.push((ino, idx));
+                    continue;
+                }
+            };
+            let mut buf = vec![0; self.block_size];
+            self.read_block(block, &mut buf)?;
+            if self.check_dir_block(fsck, ino, parent, &mut buf, stack) {
+                dir.flags &= !Inode::FLAG_INDEX;
+                dir_changed = true;
+                if fsck.repair {
+                    self.write_block(block, &buf)?;
+                }
+            }
+        }
+        if dir_changed && fsck.repair {
+            self.write_inode(ino, &dir)?;
+        }
+        Ok(())
+    }
+
+    /// Checks the entries in a block of the directory `ino`, returns whether
+    /// the block is changed.
+    fn check_dir_block(
+        &mut self,
+        fsck: &mut Fsck,
+        ino: u32,
+        parent: u32,
+        buf: &mut [u8],
+        stack: &mut Vec<(u32, u32)>,
+    ) -> bool {
+        let mut changed = false;
+        let mut prev: Option<usize> = None;
+        let mut offset = 0;
+        while offset < buf.len() {
+            let Ok(mut header) = EntryHeader::parse(buf, offset) else {
+                // the rest of the block is lost
+                fsck.problem(Problem::BadDirEntry {
+                    dir: ino as u64,
+                    name: String::new(),
+                });
+                match prev {
+                    Some(prev) => {
+                        let mut prev_header = EntryHeader::parse(buf, prev).unwrap();
+                        prev_header.rec_len = buf.len() - prev;
+                        prev_header.write(buf, prev);
+                    }
+                    None => EntryHeader {
+                        inode: 0,
+                        rec_len: buf.len(),
+                        name_len: 0,
+                        file_type: 0,
+                    }
+                    .write(buf, 0),
+                }
+                return true;
+            };
+            if header.inode == 0 {
+                prev = Some(offset);
+                offset += header.rec_len;
+                continue;
+            }
+
+            let repair = self.check_entry(fsck, ino, parent, &header, header.name(buf, offset));
+            if let Some(repair) = repair {
+                let name = String::from_utf8_lossy(header.name(buf, offset)).into_owned();
+                fsck.problem(Problem::BadDirEntry {
+                    dir: ino as u64,
+                    name,
+                });
+                changed = true;
+                match repair {
+                    Repair::Remove => {
+                        match prev {
+                            Some(prev) => {
+                                // merge into the previous entry
+                                let mut prev_header = EntryHeader::parse(buf, prev).unwrap();
+                                prev_header.rec_len += header.rec_len;
+                                prev_header.write(buf, prev);
+                            }
+                            None => {
+                                header.inode = 0;
+                                header.write(buf, offset);
+                                prev = Some(offset);
+                            }
+                        }
+                        offset += header.rec_len;
+                        continue;
+                    }
+                    Repair::Set(target, file_type) => {
+                        header.inode = target;
+                        header.file_type = file_type;
+                        header.write(buf, offset);
+                    }
+                }
+            }
+
+            // the entry is valid now
+            let target = header.inode;
+            fsck.links[target as usize] = fsck.links[target as usize].saturating_add(1);
+            let name = header.name(buf, offset);
+            if name != b"." && name != b".." {
+                fsck.reached.set(target);
+                if fsck.types[target as usize] == DIR_TYPE {
+                    stack.push((target, ino));
+                }
+            }
+            prev = Some(offset);
+            offset += header.rec_len;
+        }
+        changed
+    }
+
+    /// Checks a used entry in the directory `ino` reached from `parent`,
+    /// returns how to repair it if it is bad.
+    fn check_entry(
+        &self,
+        fsck: &Fsck,
+        ino: u32,
+        parent: u32,
+        header: &EntryHeader,
+        name: &[u8],
+    ) -> Option<Repair> {
+        let has_filetype = self.superblock.has_filetype();
+        let target = header.inode;
+        let expected = match name {
+            b"." => Some(ino),
+            b".." => Some(parent),
+            _ => None,
+        };
+        if let Some(expected) = expected {
+            let file_type = if has_filetype { DIR_TYPE } else { 0 };
+            return (target != expected || header.file_type != file_type)
+                .then_some(Repair::Set(expected, file_type));
+        }
+
+        let bad_name = name.is_empty() || name.contains(&b'/') || name.contains(&0);
+        let in_use = target >= self.superblock.first_ino()
+            && target <= self.superblock.inodes_count
+            && fsck.types[target as usize] != 0;
+        let file_type = if in_use {
+            fsck.types[target as usize]
+        } else {
+            0
+        };
+        // a directory can only be in one directory
+        let linked_dir = file_type == DIR_TYPE && fsck.reached.get(target);
+        if bad_name || !in_use || linked_dir {
+            Some(Repair::Remove)
+        } else if has_filetype && header.file_type != file_type {
+            Some(Repair::Set(target, file_type))
+        } else {
+            None
+        }
+    }
+
+    /// Finds the inodes in use that are not reached from the root, reports and
+    /// returns the ones that are not in other unreached directories either.
+    fn find_orphans(&mut self, fsck: &mut Fsck) -> Vec<u32> {
+        let orphans: Vec<u32> = (self.superblock.first_ino()..=self.superblock.inodes_count)
+            .filter(|&ino| fsck.types[ino as usize] != 0 && !fsck.reached.get(ino))
+            .collect();
+        let mut children = Bitmap::new(fsck.types.len() as u32);
+        for &ino in &orphans {
+            if fsck.types[ino as usize] != DIR_TYPE {
+                continue;
+            }
+            let Ok(dir) = self.read_inode(ino) else {
+                continue;
+            };
+            // a bad directory only hides some of its children
+            let _ = self.dir_for_each(&dir, |child, name, _| {
+                if name != b"." && name != b".." && (child as usize) < fsck.types.len() {
+                    children.set(child);
+                }
+                false
+            });
+        }
+        let tops: Vec<u32> = orphans
+            .iter()
+            .copied()
+            .filter(|&ino| !children.get(ino))
+            .collect();
+        let tops = match orphans.first() {
+            // directories in a loop are all children
+            Some(&first) if tops.is_empty() => vec![first],
+            _ => tops,
+        };
+        for &ino in &tops {
+            fsck.problem(Problem::OrphanedInode { ino });
+        }
+        tops
+    }
+
+    /// Pass 3: compares the bitmaps and the free counts with what is in use.
+    fn check_bitmaps(&mut self, fsck: &mut Fsck) -> VfsResult {
+        let sb = self.superblock;
+        let ipg = sb.inodes_per_group;
+        let (mut free_blocks, mut free_inodes) = (0, 0);
+        for group in 0..self.groups.len() {
+            let group_u32 = group as u32;
+            let mut desc = self.groups[group];
+
+            // the bits past the end of the group are set
+            let first = self.group_first_block(group);
+            let mut bitmap = vec![0xff; self.block_size];
+            let mut free = 0;
+            for bit in 0..self.blocks_in_group(group) {
+                if !fsck.blocks.get(first + bit) {
+                    bitmap[(bit / 8) as usize] &= !(1 << (bit % 8));
+                    free += 1;
+                }
+            }
+            self.check_bitmap(fsck, desc.block_usage_addr, &bitmap, || {
+                Problem::BlockBitmap { group: group_u32 }
+            })?;
+            free_blocks += free;
+            if desc.free_blocks_count as u32 != free {
+                fsck.problem(Problem::FreeBlocks {
+                    group: Some(group_u32),
+                    stored: desc.free_blocks_count as u32,
+                    counted: free,
+                });
+                desc.free_blocks_count = free as u16;
+            }
+
+            let mut bitmap = vec![0xff; self.block_size];
+            let (mut free, mut dirs) = (0, 0);
+            for bit in 0..ipg {
+                let ino = group_u32 * ipg + bit + 1;
+                let file_type = fsck.types[ino as usize];
+                if ino >= sb.first_ino() && file_type == 0 {
+                    bitmap[(bit / 8) as usize] &= !(1 << (bit % 8));
+                    free += 1;
+                } else if file_type == DIR_TYPE {
+                    dirs += 1;
+                }
+            }
+            self.check_bitmap(fsck, desc.inode_usage_addr, &bitmap, || {
+                Problem::InodeBitmap { group: group_u32 }
+            })?;
+            free_inodes += free;
+            if desc.free_inodes_count as u32 != free {
+                fsck.problem(Problem::FreeInodes {
+                    group: Some(group_u32),
+                    stored: desc.free_inodes_count as u32,
+                    counted: free,
+                });
+                desc.free_inodes_count = free as u16;
+            }
+            if desc.dirs_count as u32 != dirs {
+                fsck.problem(Problem::DirsCount {
+                    group: group_u32,
+                    stored: desc.dirs_count as u32,
+                    counted: dirs,
+                });
+                desc.dirs_count = dirs as u16;
+            }
+
+            if desc.as_bytes() != self.groups[group].as_bytes() && fsck.repair {
+                self.groups[group] = desc;
+                self.write_group(group)?;
+            }
+        }
+
+        let sb = &mut self.superblock;
+        let mut changed = false;
+        if sb.free_blocks_count != free_blocks {
+            fsck.problem(Problem::FreeBlocks {
+                group: None,
+                stored: sb.free_blocks_count,
+                counted: free_blocks,
+            });
+            sb.free_blocks_count = free_blocks;
+            changed = true;
+        }
+        if sb.free_inodes_count != free_inodes {
+            fsck.problem(Problem::FreeInodes {
+                group: None,
+                stored: sb.free_inodes_count,
+                counted: free_inodes,
+            });
+            sb.free_inodes_count = free_inodes;
+            changed = true;
+        }
+        if changed && fsck.repair {
+            self.write_superblock()?;
+        }
+        Ok(())
+    }
+
+    /// Compares the bitmap block with the expected content.
+    fn check_bitmap<F>(
+        &mut self,
+        fsck: &mut Fsck,
+        block: u32,
+        expected: &[u8],
+        problem: F,
+    ) -> VfsResult
+    where
+        F: FnOnce() -> Problem,
+    {
+        let mut buf = vec![0; self.block_size];
+        self.read_block(block, &mut buf)?;
+        if buf != expected {
+            fsck.problem(problem());
+            if fsck.repair {
+                self.write_block(block, expected)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Fills the holes in directories with empty blocks.
+    fn fill_holes(&mut self, fsck: &mut Fsck) -> VfsResult {
+        for (ino, idx) in core::mem::take(&mut fsck.holes) {
+            let mut dir = self.read_inode(ino)?;
+            // the pointer has been cleared if it is bad
+            let block = self.bmap_alloc(ino, &mut dir, idx)?;
+            let mut buf = vec![0; self.block_size];
+            EntryHeader {
+                inode: 0,
+                rec_len: self.block_size,
+                name_len: 0,
+                file_type: 0,
+            }
+            .write(&mut buf, 0);
+            self.write_block(block, &buf)?;
+            self.write_inode(ino, &dir)?;
+        }
+        Ok(())
+    }
+
+    /// Adds entries for the orphaned inodes in `lost+found`, which is created
+    /// if missing.
+    fn reconnect(&mut self, fsck: &mut Fsck, orphans: &[u32]) -> VfsResult {
+        let root = self.read_inode(Inode::ROOT_INO)?;
+        if self.dir_find(&root, LOST_FOUND)?.is_none() {
+            self.create_node(Inode::ROOT_INO, LOST_FOUND, VfsNodeType::Dir)?;
+        }
+        let root = self.read_inode(Inode::ROOT_INO)?;
+        let lost_found = self
+            .dir_find(&root, LOST_FOUND)?
+            .ok_or(VfsError::NotFound)?;
+        let mut dir = self.read_inode(lost_found)?;
+        if !dir.is_dir() {
+            return Err(VfsError::NotADirectory);
+        }
+        // for the new directory, if it is created
+        fsck.types[lost_found as usize] = DIR_TYPE;
+
+        for &ino in orphans {
+            let inode = self.read_inode(ino)?;
+            let name = format!("#{}", ino);
+            self.dir_add(lost_found, &mut dir, &name, ino, inode.file_type())?;
+            if inode.is_dir() {
+                // the walk of the tree fixes it otherwise
+                self.dir_set(&inode, "..", lost_found, VfsNodeType::Dir)
+                    .ok();
+            }
+        }
+        self.write_inode(lost_found, &dir)
+    }
+
+    /// Pass 5: compares the link count of each inode reached with the number
+    /// of entries that refer to it.
+    fn check_links(&mut self, fsck: &mut Fsck) -> VfsResult {
+        for ino in 1..=self.superblock.inodes_count {
+            if fsck.types[ino as usize] == 0 || !fsck.reached.get(ino) {
+                continue;
+            }
+            let mut inode = self.read_inode(ino)?;
+            let counted = fsck.links[ino as usize];
+            if inode.links_count != counted {
+                fsck.problem(Problem::LinkCount {
+                    ino,
+                    stored: inode.links_count as u32,
+                    counted: counted as u32,
+                });
+                if fsck.repair {
+                    inode.links_count = counted;
+                    self.write_inode(ino, &inode)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
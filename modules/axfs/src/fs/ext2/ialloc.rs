@@ -0,0 +1,111 @@
+//! Inode allocation.
+//!
+//! Regular files are placed in the group of their parent directory, while new
+//! directories are spread across the groups with the most free space, like
+//! the original Linux ext2 allocator does.
+
+use axfs_vfs::{VfsError, VfsResult};
+
+use super::Ext2Inner;
+
+impl Ext2Inner {
+    /// Returns the group that contains the given inode.
+    pub(super) fn inode_group(&self, ino: u32) -> usize {
+        ((ino - 1) / self.superblock.inodes_per_group) as usize
+    }
+
+    /// Picks a group for a new directory: among the groups with at least the
+    /// average number of free inodes, the one with the most free blocks.
+    fn find_group_dir(&self) -> Option<usize> {
+        let avg_free_inodes = self.superblock.free_inodes_count / self.groups.len() as u32;
+        self.groups
+            .iter()
+            .enumerate()
+            .filter(|(_, desc)| {
+                desc.free_inodes_count > 0 && desc.free_inodes_count as u32 >= avg_free_inodes
+            })
+            .max_by_key(|(_, desc)| desc.free_blocks_count)
+            .map(|(group, _)| group)
+    }
+
+    /// Picks a group for a new non-directory inode: the group of the parent
+    /// directory if it still has room, then the next ones.
+    fn find_group_other(&self, parent_group: usize) -> Option<usize> {
+        let group_count = self.groups.len();
+        let groups = (0..group_count).map(|i| (parent_group + i) % group_count);
+        groups
+            .clone()
+            .find(|&g| self.groups[g].free_inodes_count > 0 && self.groups[g].free_blocks_count > 0)
+            .or_else(|| {
+                groups
+                    .clone()
+                    .find(|&g| self.groups[g].free_inodes_count > 0)
+            })
+    }
+
+    /// Allocates an inode for a new child of the directory `parent_ino`.
+    ///
+    /// The bitmap, the group descriptor and the superblock are updated
+    /// together.
+    pub(super) fn alloc_inode(&mut self, parent_ino: u32, is_dir: bool) -> VfsResult<u32> {
+        let parent_group = self.inode_group(parent_ino);
+        let preferred = if is_dir {
+            self.find_group_dir()
+        } else {
+            self.find_group_other(parent_group)
+        };
+        let first = preferred.ok_or(VfsError::StorageFull)?;
+
+        let ipg = self.superblock.inodes_per_group;
+        let group_count = self.groups.len();
+        for i in 0..group_count {
+            let group = (first + i) % group_count;
+            while self.groups[group].free_inodes_count > 0 {
+                let bitmap = self.groups[group].inode_usage_addr;
+                let Some(bit) = self.alloc_bit(bitmap, 0, ipg, false)? else {
+                    warn!(
+                        "ext2: group {} has no free inode but a non-zero count",
+                        group
+                    );
+                    break;
+                };
+                self.groups[group].free_inodes_count -= 1;
+                self.superblock.free_inodes_count -= 1;
+                let ino = group as u32 * ipg + bit + 1;
+                if ino < self.superblock.first_ino() {
+                    // keep it marked in use, and try the next one
+                    warn!("ext2: reserved inode {} is not marked in use", ino);
+                    self.write_group(group)?;
+                    self.write_superblock()?;
+                    continue;
+                }
+                if is_dir {
+                    self.groups[group].dirs_count += 1;
+                }
+                self.write_group(group)?;
+                self.write_superblock()?;
+                return Ok(ino);
+            }
+        }
+        Err(VfsError::StorageFull)
+    }
+
+    /// Frees an inode, updates the bitmap, the group descriptor and the
+    /// superblock.
+    pub(super) fn free_inode(&mut self, ino: u32, is_dir: bool) -> VfsResult {
+        let group = self.inode_group(ino);
+        let bit = (ino - 1) % self.superblock.inodes_per_group;
+        if self.free_bit(self.groups[group].inode_usage_addr, bit)? {
+            self.groups[group].free_inodes_count += 1;
+            if is_dir {
+                self.groups[group].dirs_count -= 1;
+            }
+            self.superblock.free_inodes_count += 1;
+            self.write_group(group)?;
+            self.write_superblock()?;
+        } else {
+            warn!("ext2: freeing free inode {}", ino);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,577 @@
+use axfs_vfs::{VfsError, VfsNodePerm, VfsNodeType, VfsResult};
+
+use super::superblock::Superblock;
+use super::Ext2Inner;
+
+/// Number of direct block pointers in an inode.
+const DIRECT_BLOCKS: u32 = 12;
+/// Index of the singly indirect block pointer in [`Inode::block`].
+const INDIRECT_SLOT: usize = 12;
+
+/// An inode represents a file, a directory, a symbolic link or a special
+/// file. It stores the metadata of the object and the pointers to its data
+/// blocks, but not its name (names live in directory entries).
+///
+/// Only the first 128 bytes (the size of inodes in revision 0) are
+/// interpreted, the extra space of larger inodes is left untouched.
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct Inode {
+    /// Type and permissions
+    pub mode: u16,
+    /// Lower 16 bits of user ID
+    pub uid: u16,
+    /// Lower 32 bits of size in bytes
+    pub size: u32,
+    /// Last access time (in POSIX time)
+    pub atime: u32,
+    /// Last inode change time (in POSIX time)
+    pub ctime: u32,
+    /// Last modification time (in POSIX time)
+    pub mtime: u32,
+    /// Deletion time (in POSIX time)
+    pub dtime: u32,
+    /// Lower 16 bits of group ID
+    pub gid: u16,
+    /// Count of hard links (directory entries) to this inode
+    pub links_count: u16,
+    /// Count of 512-byte disk sectors in use by this inode
+    pub blocks: u32,
+    /// Flags (see `FLAG_INDEX`)
+    pub flags: u32,
+    #[doc(hidden)]
+    pub osd1: u32,
+    /// 12 direct block pointers, followed by a singly, a doubly and a triply
+    /// indirect block pointer
+    pub block: [u32; 15],
+    /// Generation number (used by NFS)
+    pub generation: u32,
+    /// Block address of the extended attribute block
+    pub file_acl: u32,
+    /// Upper 32 bits of size in bytes (for regular files)
+    pub size_high: u32,
+    /// Block address of fragment
+    pub faddr: u32,
+    /// OS dependent, the upper 16 bits of user ID and group ID are at
+    /// offset 4 and 6 on Linux
+    pub osd2: [u8; 12],
+}
+
+impl Inode {
+    /// Size of the interpreted part of an on-disk inode, in bytes.
+    pub const SIZE: usize = 128;
+    /// Inode number of the root directory.
+    pub const ROOT_INO: u32 = 2;
+
+    /// Mask of the file type bits in [`Inode::mode`].
+    pub const TYPE_MASK: u16 = 0xf000;
+    /// FIFO
+    pub const TYPE_FIFO: u16 = 0x1000;
+    /// Character device
+    pub const TYPE_CHAR_DEV: u16 = 0x2000;
+    /// Directory
+    pub const TYPE_DIR: u16 = 0x4000;
+    /// Block device
+    pub const TYPE_BLOCK_DEV: u16 = 0x6000;
+    /// Regular file
+    pub const TYPE_FILE: u16 = 0x8000;
+    /// Symbolic link
+    pub const TYPE_SYMLINK: u16 = 0xa000;
+    /// Unix socket
+    pub const TYPE_SOCKET: u16 = 0xc000;
+
+    /// Size of [`Inode::block`] in bytes. Symbolic links with shorter targets
+    /// store them inline.
+    pub const INLINE_SIZE: usize = 60;
+    /// Maximum number of hard links to an inode.
+    pub const LINK_MAX: u16 = 32000;
+
+    /// The directory uses hashed indexes. The index must be dropped whenever
+    /// the directory is modified, as we only maintain the linear format.
+    pub const FLAG_INDEX: u32 = 0x1000;
+
+    /// Parses an inode from its on-disk representation.
+    pub fn new_from_buf(buf: &[u8]) -> Self {
+        assert!(buf.len() >= Self::SIZE);
+        // SAFETY: `Inode` is `repr(C)`, mirrors the on-disk layout and
+        // consists only of integers.
+        unsafe { core::ptr::read_unaligned(buf.as_ptr() as *const Self) }
+    }
+
+    /// Returns the on-disk representation of the inode.
+    pub fn as_bytes(&self) -> &[u8] {
+        // SAFETY: `Inode` is `repr(C)` without padding.
+        unsafe { core::slice::from_raw_parts(self as *const Self as *const u8, Self::SIZE) }
+    }
+
+    /// Creates an empty inode of the given type and permission, all times
+    /// are set to `now`.
+    pub fn new(ty: VfsNodeType, perm: VfsNodePerm, now: u32) -> Self {
+        Self {
+            mode: type_to_mode(ty) | perm.bits(),
+            links_count: 1,
+            atime: now,
+            ctime: now,
+            mtime: now,
+            ..Default::default()
+        }
+    }
+
+    /// Returns the type of the inode.
+    pub fn file_type(&self) -> VfsNodeType {
+        match self.mode & Self::TYPE_MASK {
+            Self::TYPE_FIFO => VfsNodeType::Fifo,
+            Self::TYPE_CHAR_DEV => VfsNodeType::CharDevice,
+            Self::TYPE_DIR => VfsNodeType::Dir,
+            Self::TYPE_BLOCK_DEV => VfsNodeType::BlockDevice,
+            Self::TYPE_SYMLINK => VfsNodeType::SymLink,
+            Self::TYPE_SOCKET => VfsNodeType::Socket,
+            _ => VfsNodeType::File,
+        }
+    }
+
+    /// Returns the permission bits of the inode.
+    pub fn perm(&self) -> VfsNodePerm {
+        VfsNodePerm::from_bits_truncate(self.mode & 0o777)
+    }
+
+    /// Sets the permission bits of the inode.
+    pub fn set_perm(&mut self, perm: VfsNodePerm) {
+        self.mode = (self.mode & !0o777) | perm.bits();
+    }
+
+    /// Returns the user ID of the owner.
+    pub fn uid(&self) -> u32 {
+        self.uid as u32 | (u16::from_le_bytes([self.osd2[4], self.osd2[5]]) as u32) << 16
+    }
+
+    /// Returns the group ID of the owner.
+    pub fn gid(&self) -> u32 {
+        self.gid as u32 | (u16::from_le_bytes([self.osd2[6], self.osd2[7]]) as u32) << 16
+    }
+
+    /// Sets the user ID of the owner.
+    pub fn set_uid(&mut self, uid: u32) {
+        self.uid = uid as u16;
+        self.osd2[4..6].copy_from_slice(&((uid >> 16) as u16).to_le_bytes());
+    }
+
+    /// Sets the group ID of the owner.
+    pub fn set_gid(&mut self, gid: u32) {
+        self.gid = gid as u16;
+        self.osd2[6..8].copy_from_slice(&((gid >> 16) as u16).to_le_bytes());
+    }
+
+    /// Updates the times of last modification and last inode change.
+    pub fn touch_modify(&mut self, now: u32) {
+        self.mtime = now;
+        self.ctime = now;
+    }
+
+    /// Whether the inode is a directory.
+    pub fn is_dir(&self) -> bool {
+        self.mode & Self::TYPE_MASK == Self::TYPE_DIR
+    }
+
+    /// Returns the size of the inode, in bytes.
+    pub fn size(&self) -> u64 {
+        if self.mode & Self::TYPE_MASK == Self::TYPE_FILE {
+            self.size as u64 | (self.size_high as u64) << 32
+        } else {
+            self.size as u64
+        }
+    }
+
+    /// Sets the size of the inode, in bytes.
+    pub fn set_size(&mut self, size: u64) {
+        self.size = size as u32;
+        if self.mode & Self::TYPE_MASK == Self::TYPE_FILE {
+            self.size_high = (size >> 32) as u32;
+        }
+    }
+
+    /// Returns the space of the block pointers, where fast symbolic links
+    /// store the target path.
+    pub fn inline_data(&self) -> &[u8] {
+        // SAFETY: the block pointers are plain integers in the on-disk byte
+        // order, any bytes are valid.
+        unsafe { core::slice::from_raw_parts(self.block.as_ptr() as *const u8, Self::INLINE_SIZE) }
+    }
+
+    /// Mutable version of [`Inode::inline_data`].
+    pub fn inline_data_mut(&mut self) -> &mut [u8] {
+        // SAFETY: same as `inline_data`.
+        unsafe {
+            core::slice::from_raw_parts_mut(self.block.as_mut_ptr() as *mut u8, Self::INLINE_SIZE)
+        }
+    }
+
+    /// Whether the block pointers of this inode refer to data blocks.
+    ///
+    /// Device files keep their device number there, and fast symbolic links
+    /// store the target path inline.
+    pub fn has_data_blocks(&self, block_size: usize) -> bool {
+        match self.mode & Self::TYPE_MASK {
+            Self::TYPE_FILE | Self::TYPE_DIR => true,
+            Self::TYPE_SYMLINK => {
+                let ea_blocks = if self.file_acl != 0 {
+                    block_size as u32 / 512
+                } else {
+                    0
+                };
+                self.blocks != ea_blocks
+            }
+            _ => false,
+        }
+    }
+}
+
+const _: () = assert!(core::mem::size_of::<Inode>() == Inode::SIZE);
+
+/// Converts a VFS node type to the type bits of [`Inode::mode`].
+pub fn type_to_mode(ty: VfsNodeType) -> u16 {
+    match ty {
+        VfsNodeType::Fifo => Inode::TYPE_FIFO,
+        VfsNodeType::CharDevice => Inode::TYPE_CHAR_DEV,
+        VfsNodeType::Dir => Inode::TYPE_DIR,
+        VfsNodeType::BlockDevice => Inode::TYPE_BLOCK_DEV,
+        VfsNodeType::File => Inode::TYPE_FILE,
+        VfsNodeType::SymLink => Inode::TYPE_SYMLINK,
+        VfsNodeType::Socket => Inode::TYPE_SOCKET,
+    }
+}
+
+/// Position of a data block in the block tree of an inode: the slot in
+/// [`Inode::block`], followed by the indexes in each level of indirect blocks.
+struct BlockPath {
+    slot: usize,
+    offsets: [u32; 3],
+    depth: usize,
+}
+
+impl Ext2Inner {
+    /// Number of block pointers in an indirect block.
+    fn ptrs_per_block(&self) -> u32 {
+        self.block_size as u32 / 4
+    }
+
+    fn block_path(&self, idx: u32) -> VfsResult<BlockPath> {
+        let ppb = self.ptrs_per_block() as u64;
+        let mut idx = idx as u64;
+        if idx < DIRECT_BLOCKS as u64 {
+            return Ok(BlockPath {
+                slot: idx as usize,
+                offsets: [0; 3],
+                depth: 0,
+            });
+        }
+        idx -= DIRECT_BLOCKS as u64;
+        let mut span = ppb;
+        for depth in 1..=3 {
+            if idx < span {
+                let mut offsets = [0; 3];
+                for level in (0..depth).rev() {
+                    offsets[level] = (idx % ppb) as u32;
+                    idx /= ppb;
+                }
+                return Ok(BlockPath {
+                    slot: INDIRECT_SLOT + depth - 1,
+                    offsets,
+                    depth,
+                });
+            }
+            idx -= span;
+            span *= ppb;
+        }
+        Err(VfsError::InvalidInput) // beyond the maximum file size
+    }
+
+    fn read_ptr(&mut self, block: u32, index: u32) -> VfsResult<u32> {
+        let mut buf = [0; 4];
+        self.read_bytes(self.block_pos(block) + index as u64 * 4, &mut buf)?;
+        Ok(u32::from_le_bytes(buf))
+    }
+
+    fn write_ptr(&mut self, block: u32, index: u32, ptr: u32) -> VfsResult {
+        self.write_bytes(self.block_pos(block) + index as u64 * 4, &ptr.to_le_bytes())
+    }
+
+    /// Returns the address of the `idx`-th data block of the inode, or 0 if
+    /// the block is a hole.
+    pub(super) fn bmap(&mut self, inode: &Inode, idx: u32) -> VfsResult<u32> {
+        let path = self.block_path(idx)?;
+        let mut ptr = inode.block[path.slot];
+        for &offset in &path.offsets[..path.depth] {
+            if ptr == 0 {
+                break;
+            }
+            ptr = self.read_ptr(ptr, offset)?;
+        }
+        Ok(ptr)
+    }
+
+    /// Returns the address of the `idx`-th data block of the inode, allocates
+    /// it (and the indirect blocks leading to it) if it is a hole.
+    ///
+    /// Newly allocated blocks are zero-filled.
+    pub(super) fn bmap_alloc(&mut self, ino: u32, inode: &mut Inode, idx: u32) -> VfsResult<u32> {
+        let path = self.block_path(idx)?;
+        let sectors = (self.block_size / 512) as u32;
+        let mut goal = None;
+        let mut ptr = inode.block[path.slot];
+        if ptr == 0 {
+            let block_goal = self.block_goal(ino, inode, idx)?;
+            ptr = self.alloc_block(block_goal)?;
+            goal = Some(ptr + 1);
+            inode.block[path.slot] = ptr;
+            inode.blocks += sectors;
+        }
+        for &offset in &path.offsets[..path.depth] {
+            let parent = ptr;
+            ptr = self.read_ptr(parent, offset)?;
+            if ptr == 0 {
+                let block_goal = match goal {
+                    Some(goal) => goal,
+                    None => self.block_goal(ino, inode, idx)?,
+                };
+                ptr = self.alloc_block(block_goal)?;
+                goal = Some(ptr + 1);
+                self.write_ptr(parent, offset, ptr)?;
+                inode.blocks += sectors;
+            }
+        }
+        Ok(ptr)
+    }
+
+    /// Frees all data blocks of the inode starting from the `start`-th one.
+    fn free_blocks_from(&mut self, inode: &mut Inode, start: u32) -> VfsResult {
+        for slot in (start.min(DIRECT_BLOCKS) as usize)..DIRECT_BLOCKS as usize {
+            if inode.block[slot] != 0 {
+                self.free_data_block(inode, inode.block[slot])?;
+                inode.block[slot] = 0;
+            }
+        }
+
+        let ppb = self.ptrs_per_block() as u64;
+        let mut base = DIRECT_BLOCKS as u64;
+        let mut span = ppb;
+        for depth in 1..=3 {
+            let slot = INDIRECT_SLOT + depth - 1;
+            let root = inode.block[slot];
+            if root != 0 {
+                if start as u64 <= base {
+                    self.free_tree(inode, root, depth)?;
+                    inode.block[slot] = 0;
+                } else if (start as u64) < base + span {
+                    self.free_tree_from(inode, root, depth, start as u64 - base)?;
+                }
+            }
+            base += span;
+            span *= ppb;
+        }
+        Ok(())
+    }
+
+    /// Frees an indirect block of the given depth and everything below it.
+    fn free_tree(&mut self, inode: &mut Inode, block: u32, depth: usize) -> VfsResult {
+        if depth > 0 {
+            for ptr in self.read_ptrs(block)? {
+                if ptr != 0 {
+                    self.free_tree(inode, ptr, depth - 1)?;
+                }
+            }
+        }
+        self.free_data_block(inode, block)
+    }
+
+    /// Frees the data blocks below an indirect block of the given depth,
+    /// starting from the `start`-th one (relative to this indirect block).
+    fn free_tree_from(
+        &mut self,
+        inode: &mut Inode,
+        block: u32,
+        depth: usize,
+        start: u64,
+    ) -> VfsResult {
+        let child_span = (self.ptrs_per_block() as u64).pow(depth as u32 - 1);
+        let mut ptrs = self.read_ptrs(block)?;
+        for (i, ptr) in ptrs.iter_mut().enumerate() {
+            let child_start = i as u64 * child_span;
+            if *ptr == 0 || child_start + child_span <= start {
+                continue;
+            }
+            if child_start >= start {
+                self.free_tree(inode, *ptr, depth - 1)?;
+                *ptr = 0;
+            } else {
+                self.free_tree_from(inode, *ptr, depth - 1, start - child_start)?;
+            }
+        }
+        self.write_ptrs(block, &ptrs)
+    }
+
+    /// Reads all block pointers in an indirect block.
+    pub(super) fn read_ptrs(&mut self, block: u32) -> VfsResult<alloc::vec::Vec<u32>> {
+        let mut buf = alloc::vec![0; self.block_size];
+        self.read_block(block, &mut buf)?;
+        Ok(buf
+            .chunks_exact(4)
+            .map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+            .collect())
+    }
+
+    /// Writes all block pointers in an indirect block.
+    pub(super) fn write_ptrs(&mut self, block: u32, ptrs: &[u32]) -> VfsResult {
+        let mut buf = alloc::vec![0; self.block_size];
+        for (chunk, ptr) in buf.chunks_exact_mut(4).zip(ptrs) {
+            chunk.copy_from_slice(&ptr.to_le_bytes());
+        }
+        self.write_block(block, &buf)
+    }
+
+    fn free_data_block(&mut self, inode: &mut Inode, block: u32) -> VfsResult {
+        self.free_block(block)?;
+        inode.blocks -= (self.block_size / 512) as u32;
+        Ok(())
+    }
+
+    /// Reads the content of the inode at `offset`, returns the number of bytes
+    /// read.
+    pub(super) fn read_data(
+        &mut self,
+        inode: &Inode,
+        offset: u64,
+        buf: &mut [u8],
+    ) -> VfsResult<usize> {
+        let size = inode.size();
+        if offset >= size {
+            return Ok(0);
+        }
+        let len = buf.len().min((size - offset) as usize);
+        let bs = self.block_size as u64;
+        let mut pos = offset;
+        let mut read_len = 0;
+        while read_len < len {
+            let block_off = (pos % bs) as usize;
+            let count = (len - read_len).min(self.block_size - block_off);
+            let dst = &mut buf[read_len..read_len + count];
+            match self.bmap(inode, (pos / bs) as u32)? {
+                0 => dst.fill(0), // hole
+                block => self.read_bytes(self.block_pos(block) + block_off as u64, dst)?,
+            }
+            pos += count as u64;
+            read_len += count;
+        }
+        Ok(len)
+    }
+
+    /// Writes `buf` to the inode at `offset`, allocating blocks as needed and
+    /// extending the size of the inode. Returns the number of bytes written,
+    /// which is less than `buf.len()` if the disk fills up halfway.
+    ///
+    /// The caller is responsible for writing back the inode.
+    pub(super) fn write_data(
+        &mut self,
+        ino: u32,
+        inode: &mut Inode,
+        offset: u64,
+        buf: &[u8],
+    ) -> VfsResult<usize> {
+        let end = offset
+            .checked_add(buf.len() as u64)
+            .ok_or(VfsError::InvalidInput)?;
+        if end.div_ceil(self.block_size as u64) > u32::MAX as u64 {
+            return Err(VfsError::InvalidInput);
+        }
+        if end > i32::MAX as u64 && inode.mode & Inode::TYPE_MASK == Inode::TYPE_FILE {
+            self.set_large_file()?;
+        }
+        let bs = self.block_size as u64;
+        let mut pos = offset;
+        let mut written = 0;
+        while written < buf.len() {
+            let block_off = (pos % bs) as usize;
+            let count = (buf.len() - written).min(self.block_size - block_off);
+            let src = &buf[written..written + count];
+            let res = self
+                .bmap_alloc(ino, inode, (pos / bs) as u32)
+                .and_then(|block| {
+                    self.write_data_bytes(self.block_pos(block) + block_off as u64, src)
+                });
+            if let Err(e) = res {
+                if written == 0 {
+                    return Err(e);
+                }
+                break;
+            }
+            pos += count as u64;
+            written += count;
+        }
+        if pos > inode.size() {
+            inode.set_size(pos);
+        }
+        Ok(written)
+    }
+
+    /// Truncates or extends the inode to `size` bytes, freeing the blocks past
+    /// the end. Extending leaves a hole.
+    ///
+    /// The caller is responsible for writing back the inode.
+    pub(super) fn truncate_data(&mut self, inode: &mut Inode, size: u64) -> VfsResult {
+        let bs = self.block_size as u64;
+        if size < inode.size() {
+            let keep = size.div_ceil(bs);
+            let start = u32::try_from(keep).map_err(|_| VfsError::InvalidInput)?;
+            self.free_blocks_from(inode, start)?;
+            // zero the tail of the last block, in case the file grows again
+            let tail = (size % bs) as usize;
+            if tail != 0 {
+                let block = self.bmap(inode, (size / bs) as u32)?;
+                if block != 0 {
+                    let zeros = alloc::vec![0; self.block_size - tail];
+                    self.write_data_bytes(self.block_pos(block) + tail as u64, &zeros)?;
+                }
+            }
+        } else if size > i32::MAX as u64 && inode.mode & Inode::TYPE_MASK == Inode::TYPE_FILE {
+            self.set_large_file()?;
+        }
+        inode.set_size(size);
+        Ok(())
+    }
+
+    /// Releases everything the inode refers to: data blocks and the extended
+    /// attribute block.
+    pub(super) fn release_inode_data(&mut self, inode: &mut Inode) -> VfsResult {
+        if inode.has_data_blocks(self.block_size) {
+            self.free_blocks_from(inode, 0)?;
+        }
+        if inode.file_acl != 0 {
+            // the extended attribute block may be shared, check its refcount
+            let pos = self.block_pos(inode.file_acl) + 4;
+            let mut buf = [0; 4];
+            self.read_bytes(pos, &mut buf)?;
+            let refcount = u32::from_le_bytes(buf);
+            if refcount > 1 {
+                self.write_bytes(pos, &(refcount - 1).to_le_bytes())?;
+            } else {
+                self.free_block(inode.file_acl)?;
+            }
+            inode.blocks = inode.blocks.saturating_sub((self.block_size / 512) as u32);
+            inode.file_acl = 0;
+        }
+        inode.set_size(0);
+        Ok(())
+    }
+
+    fn set_large_file(&mut self) -> VfsResult {
+        let flag = Superblock::FEATURE_RO_COMPAT_LARGE_FILE;
+        if self.superblock.features_ronly & flag == 0 {
+            if self.superblock.rev_major == 0 {
+                return Err(VfsError::InvalidInput); // no feature flags in revision 0
+            }
+            self.superblock.features_ronly |= flag;
+            self.write_superblock()?;
+        }
+        Ok(())
+    }
+}
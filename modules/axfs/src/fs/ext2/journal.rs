@@ -0,0 +1,528 @@
+//! Journaling of metadata updates, in the format of the ext3 journal (JBD).
+//!
+//! Metadata blocks modified by operations are collected in a running
+//! transaction in memory, and reach their home locations only through the
+//! journal: on commit, they are written to the log followed by a commit block,
+//! then to their home locations, after which the log is emptied again. A crash
+//! loses the updates since the last commit but leaves the filesystem
+//! consistent, as committed transactions are replayed at the next mount.
+//!
+//! File data is written in place and flushed before the transaction commits
+//! ("ordered" mode), so the metadata never refers to stale data. Blocks freed
+//! by the running transaction are not reused until it commits, otherwise new
+//! data could overwrite metadata still in use on the disk.
+//!
+//! Transactions are committed on `fsync`, on unmount, and when they grow too
+//! large for the journal.
+
+use alloc::{collections::BTreeMap, vec, vec::Vec};
+use core::mem;
+
+use axfs_vfs::{VfsError, VfsResult};
+
+use super::superblock::Superblock;
+use super::{read_disk, read_metadata, write_disk, Ext2Inner};
+use crate::dev::Disk;
+
+/// Magic number in the header of the journal metadata blocks.
+const MAGIC: u32 = 0xc03b_3998;
+/// Size of the header of the journal metadata blocks: the magic number, the
+/// block type and the sequence number of the transaction.
+const HEADER_SIZE: usize = 12;
+
+// types of the journal metadata blocks
+const DESCRIPTOR_BLOCK: u32 = 1;
+const COMMIT_BLOCK: u32 = 2;
+const SUPERBLOCK_V1: u32 = 3;
+const SUPERBLOCK_V2: u32 = 4;
+const REVOKE_BLOCK: u32 = 5;
+
+// offsets of the fields in the journal superblock
+const SB_BLOCK_SIZE: usize = 12;
+const SB_MAXLEN: usize = 16;
+const SB_FIRST: usize = 20;
+const SB_SEQUENCE: usize = 24;
+const SB_START: usize = 28;
+const SB_FEATURE_COMPAT: usize = 36;
+const SB_FEATURE_INCOMPAT: usize = 40;
+const SB_UUID: usize = 48;
+
+/// Revoke blocks may be present in the log.
+const FEATURE_INCOMPAT_REVOKE: u32 = 0x1;
+
+/// Size of a tag in the descriptor blocks, which gives the home location of a
+/// block in the log. The first tag is followed by the UUID of the journal.
+const TAG_SIZE: usize = 8;
+const UUID_SIZE: usize = 16;
+/// The block started with the magic number, which is replaced with zeros in
+/// the log.
+const TAG_ESCAPE: u16 = 0x1;
+/// The tag is not followed by a UUID.
+const TAG_SAME_UUID: u16 = 0x2;
+/// The last tag in the descriptor block.
+const TAG_LAST: u16 = 0x8;
+
+/// Number of blocks that a single operation is expected to modify at most.
+/// The running transaction is committed before an operation if it has less
+/// room left, so that operations are not split across transactions.
+const OP_BLOCKS: usize = 64;
+/// Maximum number of blocks in a transaction, which are all kept in memory.
+const MAX_TRANSACTION_BLOCKS: usize = 512;
+
+/// The journal of a mounted filesystem, along with the running transaction.
+pub(super) struct Journal {
+    /// Disk blocks of the journal inode, indexed by the block number in the
+    /// journal.
+    blocks: Vec<u32>,
+    /// The journal superblock, in its on-disk representation.
+    sb: Vec<u8>,
+    block_size: usize,
+    /// Range of the log blocks in the journal.
+    first: u32,
+    maxlen: u32,
+    /// Sequence number of the next transaction to commit.
+    sequence: u32,
+    /// Maximum number of blocks in the running transaction.
+    limit: usize,
+    /// Modified copies of the metadata blocks in the running transaction,
+    /// indexed by their home locations.
+    running: BTreeMap<u32, Vec<u8>>,
+}
+
+/// A block logged by a committed transaction, found by replay.
+struct LoggedBlock {
+    home: u32,
+    log_block: u32,
+    escaped: bool,
+}
+
+impl Journal {
+    /// Loads the journal superblock stored in the given blocks.
+    fn new(disk: &mut Disk, blocks: Vec<u32>, block_size: usize) -> VfsResult<Self> {
+        let mut sb = vec![0; block_size];
+        read_disk(disk, blocks[0] as u64 * block_size as u64, &mut sb)?;
+        let ty = be32(&sb, 4);
+        if be32(&sb, 0) != MAGIC || (ty != SUPERBLOCK_V1 && ty != SUPERBLOCK_V2) {
+            warn!("ext2: invalid journal superblock");
+            return Err(VfsError::InvalidData);
+        }
+        if ty == SUPERBLOCK_V1 {
+            sb[SB_FEATURE_COMPAT..SB_UUID].fill(0); // no features in version 1
+        }
+        let (maxlen, first) = (be32(&sb, SB_MAXLEN), be32(&sb, SB_FIRST));
+        if be32(&sb, SB_BLOCK_SIZE) as usize != block_size
+            || maxlen as usize > blocks.len()
+            || first == 0
+            || first >= maxlen
+        {
+            warn!("ext2: invalid journal geometry");
+            return Err(VfsError::InvalidData);
+        }
+        let limit = ((maxlen - first) as usize / 2).min(MAX_TRANSACTION_BLOCKS);
+        Ok(Self {
+            blocks,
+            sequence: be32(&sb, SB_SEQUENCE),
+            sb,
+            block_size,
+            first,
+            maxlen,
+            limit,
+            running: BTreeMap::new(),
+        })
+    }
+
+    /// Whether the log may contain transactions to replay.
+    fn needs_replay(&self) -> bool {
+        be32(&self.sb, SB_START) != 0
+    }
+
+    /// Whether the log can be replayed, i.e., it has no incompatible features
+    /// other than revoke blocks.
+    fn can_replay(&self) -> bool {
+        be32(&self.sb, SB_FEATURE_INCOMPAT) & !FEATURE_INCOMPAT_REVOKE == 0
+    }
+
+    /// Whether transactions can be written in the format of the journal, which
+    /// has no checksums, and whether the journal is large enough for them.
+    fn can_write(&self) -> bool {
+        self.can_replay() && be32(&self.sb, SB_FEATURE_COMPAT) == 0 && self.limit > 2 * OP_BLOCKS
+    }
+
+    /// Whether there are updates that have not been committed yet.
+    pub(super) fn is_dirty(&self) -> bool {
+        !self.running.is_empty()
+    }
+
+    /// Returns the next log block after `log_block`, the log wraps around.
+    fn next(&self, log_block: u32) -> u32 {
+        if log_block + 1 >= self.maxlen {
+            self.first
+        } else {
+            log_block + 1
+        }
+    }
+
+    fn read_log(&self, disk: &mut Disk, log_block: u32, buf: &mut [u8]) -> VfsResult {
+        let pos = self.blocks[log_block as usize] as u64 * self.block_size as u64;
+        read_disk(disk, pos, buf)
+    }
+
+    fn write_log(&self, disk: &mut Disk, log_block: u32, buf: &[u8]) -> VfsResult {
+        let pos = self.blocks[log_block as usize] as u64 * self.block_size as u64;
+        write_disk(disk, pos, buf)
+    }
+
+    /// Writes the journal superblock with the given start of the log, and the
+    /// sequence number of the next transaction.
+    fn write_sb(&mut self, disk: &mut Disk, start: u32) -> VfsResult {
+        put_be32(&mut self.sb, SB_START, start);
+        put_be32(&mut self.sb, SB_SEQUENCE, self.sequence);
+        self.write_log(disk, 0, &self.sb)?;
+        flush(disk)
+    }
+
+    /// Returns a journal metadata block with only the header.
+    fn metadata_block(&self, ty: u32) -> Vec<u8> {
+        let mut buf = vec![0; self.block_size];
+        put_be32(&mut buf, 0, MAGIC);
+        put_be32(&mut buf, 4, ty);
+        put_be32(&mut buf, 8, self.sequence);
+        buf
+    }
+
+    /// Replays the committed transactions in the log to their home locations if
+    /// `replay` is set, and empties the log.
+    ///
+    /// The log is scanned in any case, the next transaction must have a larger
+    /// sequence number than the ones left in the log.
+    fn recover(&mut self, disk: &mut Disk, replay: bool) -> VfsResult {
+        let mut log_block = be32(&self.sb, SB_START);
+        let mut buf = vec![0; self.block_size];
+        let mut committed = Vec::new();
+        // the latest transaction that revoked each block
+        let mut revoked = BTreeMap::new();
+        let mut logged = Vec::new();
+        let mut revoking = Vec::new();
+        // the log is scanned until a block that is not from the next transaction
+        let mut scanned = 0;
+        while scanned < self.maxlen - self.first {
+            self.read_log(disk, log_block, &mut buf)?;
+            if be32(&buf, 0) != MAGIC || be32(&buf, 8) != self.sequence {
+                break;
+            }
+            log_block = self.next(log_block);
+            scanned += 1;
+            match be32(&buf, 4) {
+                DESCRIPTOR_BLOCK => {
+                    for (home, flags) in tags(&buf) {
+                        logged.push(LoggedBlock {
+                            home,
+                            log_block,
+                            escaped: flags & TAG_ESCAPE != 0,
+                        });
+                        log_block = self.next(log_block);
+                        scanned += 1;
+                    }
+                }
+                REVOKE_BLOCK => {
+                    let len = (be32(&buf, HEADER_SIZE) as usize).min(self.block_size);
+                    for pos in (HEADER_SIZE + 4..len).step_by(4) {
+                        revoking.push(be32(&buf, pos));
+                    }
+                }
+                COMMIT_BLOCK => {
+                    for block in revoking.drain(..) {
+                        revoked.insert(block, self.sequence);
+                    }
+                    committed.push((self.sequence, mem::take(&mut logged)));
+                    self.sequence = self.sequence.wrapping_add(1);
+                }
+                _ => break,
+            }
+        }
+        if replay {
+            info!(
+                "ext2: replaying {} transactions in the journal",
+                committed.len()
+            );
+        } else {
+            committed.clear();
+        }
+
+        for (sequence, logged) in committed {
+            for block in logged {
+                // revoked by the same or a later transaction
+                if revoked
+                    .get(&block.home)
+                    .is_some_and(|&r: &u32| r.wrapping_sub(sequence) as i32 >= 0)
+                {
+                    continue;
+                }
+                self.read_log(disk, block.log_block, &mut buf)?;
+                if block.escaped {
+                    put_be32(&mut buf, 0, MAGIC);
+                }
+                write_disk(disk, block.home as u64 * self.block_size as u64, &buf)?;
+            }
+        }
+        flush(disk)?;
+        // skip the sequence number of an uncommitted transaction in the log
+        self.sequence = self.sequence.wrapping_add(1);
+        self.write_sb(disk, 0)
+    }
+
+    /// Reads `buf.len()` bytes at `pos` as updated by the running transaction.
+    pub(super) fn read(&self, disk: &mut Disk, mut pos: u64, mut buf: &mut [u8]) -> VfsResult {
+        if self.running.is_empty() {
+            return read_disk(disk, pos, buf);
+        }
+        let bs = self.block_size as u64;
+        while !buf.is_empty() {
+            let (block, offset) = ((pos / bs) as u32, (pos % bs) as usize);
+            let len = buf.len().min(self.block_size - offset);
+            match self.running.get(&block) {
+                Some(data) => buf[..len].copy_from_slice(&data[offset..offset + len]),
+                None => read_disk(disk, pos, &mut buf[..len])?,
+            }
+            pos += len as u64;
+            buf = &mut buf[len..];
+        }
+        Ok(())
+    }
+
+    /// Writes `buf` at `pos` in the running transaction, commits it if it
+    /// becomes full.
+    pub(super) fn write(&mut self, disk: &mut Disk, mut pos: u64, mut buf: &[u8]) -> VfsResult {
+        let bs = self.block_size as u64;
+        while !buf.is_empty() {
+            let (block, offset) = ((pos / bs) as u32, (pos % bs) as usize);
+            let len = buf.len().min(self.block_size - offset);
+            if !self.running.contains_key(&block) {
+                let mut data = vec![0; self.block_size];
+                if len < self.block_size {
+                    read_disk(disk, block as u64 * bs, &mut data)?;
+                }
+                self.running.insert(block, data);
+            }
+            self.running.get_mut(&block).unwrap()[offset..offset + len]
+                .copy_from_slice(&buf[..len]);
+            pos += len as u64;
+            buf = &buf[len..];
+        }
+        if self.running.len() >= self.limit {
+            debug!("ext2: the transaction is full, commit it halfway");
+            self.commit(disk)?;
+        }
+        Ok(())
+    }
+
+    /// Drops the blocks in the given range from the running transaction, as
+    /// they are about to be written in place.
+    pub(super) fn forget(&mut self, pos: u64, len: usize) {
+        if self.running.is_empty() || len == 0 {
+            return;
+        }
+        let bs = self.block_size as u64;
+        let first = (pos / bs) as u32;
+        let last = ((pos + len as u64 - 1) / bs) as u32;
+        self.running
+            .retain(|&block, _| block < first || block > last);
+    }
+
+    /// Whether an operation may not fit in the running transaction.
+    pub(super) fn is_nearly_full(&self) -> bool {
+        self.running.len() + OP_BLOCKS > self.limit
+    }
+
+    /// Commits the running transaction: writes it to the log, then to the home
+    /// locations, and empties the log.
+    pub(super) fn commit(&mut self, disk: &mut Disk) -> VfsResult {
+        if self.running.is_empty() {
+            return Ok(());
+        }
+        let running = mem::take(&mut self.running);
+        // file data goes to the disk before the metadata referring to it
+        flush(disk)?;
+
+        let uuid = self.sb[SB_UUID..SB_UUID + UUID_SIZE].to_vec();
+        let tags_per_block = (self.block_size - HEADER_SIZE - UUID_SIZE) / TAG_SIZE;
+        let blocks = running.iter().collect::<Vec<_>>();
+        let mut log_block = self.first;
+        for chunk in blocks.chunks(tags_per_block) {
+            let mut desc = self.metadata_block(DESCRIPTOR_BLOCK);
+            let mut pos = HEADER_SIZE;
+            for (i, &(&home, data)) in chunk.iter().enumerate() {
+                let mut flags = if i == 0 { 0 } else { TAG_SAME_UUID };
+                if i == chunk.len() - 1 {
+                    flags |= TAG_LAST;
+                }
+                if be32(data, 0) == MAGIC {
+                    flags |= TAG_ESCAPE;
+                }
+                put_be32(&mut desc, pos, home);
+                put_be32(&mut desc, pos + 4, flags as u32);
+                pos += TAG_SIZE;
+                if i == 0 {
+                    desc[pos..pos + UUID_SIZE].copy_from_slice(&uuid);
+                    pos += UUID_SIZE;
+                }
+            }
+            self.write_log(disk, log_block, &desc)?;
+            log_block += 1;
+            for &(_, data) in chunk {
+                if be32(data, 0) == MAGIC {
+                    let mut escaped = data.clone();
+                    escaped[..4].fill(0);
+                    self.write_log(disk, log_block, &escaped)?;
+                } else {
+                    self.write_log(disk, log_block, data)?;
+                }
+                log_block += 1;
+            }
+        }
+        // the transaction is complete once the commit block is on the disk
+        self.write_sb(disk, self.first)?;
+        let mut commit = self.metadata_block(COMMIT_BLOCK);
+        let now = axfs_vfs::time::current_time();
+        commit[48..56].copy_from_slice(&now.as_secs().to_be_bytes());
+        commit[56..60].copy_from_slice(&now.subsec_nanos().to_be_bytes());
+        self.write_log(disk, log_block, &commit)?;
+        flush(disk)?;
+
+        // checkpoint
+        for (&home, data) in &running {
+            write_disk(disk, home as u64 * self.block_size as u64, data)?;
+        }
+        flush(disk)?;
+        self.sequence = self.sequence.wrapping_add(1);
+        self.write_sb(disk, 0)
+    }
+}
+
+impl Ext2Inner {
+    /// Loads the journal, and replays it if the filesystem was not cleanly
+    /// unmounted.
+    ///
+    /// Metadata updates are journaled afterwards, unless the filesystem is
+    /// read-only or the journal has features that we cannot write.
+    pub(super) fn load_journal(&mut self) -> VfsResult {
+        let sb = self.superblock;
+        let recover = sb.features_req & Superblock::FEATURE_INCOMPAT_RECOVER != 0;
+        if !sb.has_journal() {
+            if recover {
+                warn!("ext2: the journal needs recovery, but there is no journal");
+                return Err(VfsError::InvalidData);
+            }
+            return Ok(());
+        }
+        if sb.features_req & Superblock::FEATURE_INCOMPAT_JOURNAL_DEV != 0 || sb.journal_inode == 0
+        {
+            if recover {
+                warn!("ext2: cannot replay an external journal");
+                return Err(VfsError::Unsupported);
+            }
+            warn!("ext2: external journals are not supported, mount read-only");
+            self.read_only = true;
+            return Ok(());
+        }
+
+        let inode = self.read_inode(sb.journal_inode)?;
+        let count = inode.size() / self.block_size as u64;
+        if count == 0 || count > u32::MAX as u64 {
+            return Err(VfsError::InvalidData);
+        }
+        let mut blocks = Vec::with_capacity(count as usize);
+        for idx in 0..count as u32 {
+            match self.bmap(&inode, idx)? {
+                0 => return Err(VfsError::InvalidData),
+                block => blocks.push(block),
+            }
+        }
+        let mut journal = Journal::new(&mut self.disk, blocks, self.block_size)?;
+        if recover && journal.needs_replay() {
+            if !journal.can_replay() {
+                warn!("ext2: unsupported journal features, cannot replay it");
+                return Err(VfsError::Unsupported);
+            }
+            journal.recover(&mut self.disk, true)?;
+            let (superblock, groups) = read_metadata(&mut self.disk)?;
+            self.read_only |= super::check_features(&superblock)?;
+            (self.superblock, self.groups) = (superblock, groups);
+        } else if journal.needs_replay() {
+            // the log is ignored if the filesystem does not need recovery
+            warn!("ext2: discard the journal of a clean filesystem");
+            journal.recover(&mut self.disk, false)?;
+        }
+        if recover && !self.read_only {
+            self.superblock.features_req &= !Superblock::FEATURE_INCOMPAT_RECOVER;
+            let sb = self.superblock;
+            write_disk(&mut self.disk, Superblock::OFFSET, sb.as_bytes())?;
+            flush(&mut self.disk)?;
+        }
+
+        if !self.read_only {
+            if journal.can_write() {
+                self.journal = Some(journal);
+            } else {
+                warn!("ext2: unsupported journal features, mount read-only");
+                self.read_only = true;
+            }
+        }
+        Ok(())
+    }
+
+    /// Commits the running transaction, if the filesystem is journaled.
+    pub(super) fn commit(&mut self) -> VfsResult {
+        match &mut self.journal {
+            Some(journal) => journal.commit(&mut self.disk),
+            None => Ok(()),
+        }
+    }
+
+    /// Reads the given block as it is on the disk, if it differs from the one
+    /// modified by the running transaction.
+    pub(super) fn read_committed_block(&mut self, block: u32) -> VfsResult<Option<Vec<u8>>> {
+        match &self.journal {
+            Some(journal) if journal.running.contains_key(&block) => {
+                let mut buf = vec![0; self.block_size];
+                let pos = self.block_pos(block);
+                read_disk(&mut self.disk, pos, &mut buf)?;
+                Ok(Some(buf))
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+/// Parses the tags in a descriptor block, returns the home locations and the
+/// flags of the logged blocks.
+fn tags(buf: &[u8]) -> Vec<(u32, u16)> {
+    let mut tags = Vec::new();
+    let mut pos = HEADER_SIZE;
+    while pos + TAG_SIZE <= buf.len() {
+        let home = be32(buf, pos);
+        let flags = u16::from_be_bytes([buf[pos + 6], buf[pos + 7]]);
+        tags.push((home, flags));
+        pos += TAG_SIZE;
+        if flags & TAG_SAME_UUID == 0 {
+            pos += UUID_SIZE;
+        }
+        if flags & TAG_LAST != 0 {
+            break;
+        }
+    }
+    tags
+}
+
+fn flush(disk: &mut Disk) -> VfsResult {
+    disk.flush().map_err(|_| VfsError::Io)
+}
+
+/// Journal structures are stored in big-endian.
+fn be32(buf: &[u8], pos: usize) -> u32 {
+    u32::from_be_bytes(buf[pos..pos + 4].try_into().unwrap())
+}
+
+fn put_be32(buf: &mut [u8], pos: usize, val: u32) {
+    buf[pos..pos + 4].copy_from_slice(&val.to_be_bytes());
+}
@@ -0,0 +1,275 @@
+//! Creating new filesystems, like `mkfs.ext2`.
+//!
+//! The layout follows the defaults of `mke2fs`: revision 1 with the
+//! `filetype`, `sparse_super` and `large_file` features, 128-byte inodes,
+//! 5% of the blocks reserved for the superuser, and a `lost+found` directory
+//! for `e2fsck`. Small disks use 1 KiB blocks and one inode per 4 KiB, larger
+//! ones use 4 KiB blocks and one inode per 16 KiB.
+
+use alloc::{vec, vec::Vec};
+
+use axfs_vfs::{VfsError, VfsNodePerm, VfsNodeType, VfsResult, VfsSetAttr};
+
+use super::block_group::BlockGroupDescriptor;
+use super::inode::Inode;
+use super::superblock::{is_sparse_group, Superblock};
+use super::{now, write_disk, Ext2Inner};
+use crate::dev::Disk;
+
+/// Disks smaller than this use 1 KiB blocks.
+const SMALL_DISK_SIZE: u64 = 512 << 20;
+/// Groups with fewer free blocks than this are not worth creating, the space
+/// at the end of the disk is left unused.
+const MIN_GROUP_DATA_BLOCKS: u32 = 50;
+/// Number of reserved inodes, the first usable one is [`FIRST_INO`].
+const FIRST_INO: u32 = 11;
+
+/// Layout of the new filesystem.
+struct Layout {
+    block_size: usize,
+    blocks_count: u32,
+    first_data_block: u32,
+    blocks_per_group: u32,
+    inodes_per_group: u32,
+    group_count: u32,
+    /// Number of blocks of the group descriptor table.
+    gdt_blocks: u32,
+    /// Number of blocks of the inode table in each group.
+    itable_blocks: u32,
+}
+
+impl Layout {
+    fn new(disk_size: u64) -> VfsResult<Self> {
+        let (block_size, bytes_per_inode) = if disk_size < SMALL_DISK_SIZE {
+            (1024, 4096)
+        } else {
+            (4096, 16384)
+        };
+        let first_data_block = (block_size == 1024) as u32;
+        // a bitmap block covers the blocks and the inodes of a group
+        let blocks_per_group = 8 * block_size as u32;
+        let inodes_per_block = (block_size / Inode::SIZE) as u32;
+
+        let mut layout = Self {
+            block_size,
+            blocks_count: (disk_size / block_size as u64).min(u32::MAX as u64) as u32,
+            first_data_block,
+            blocks_per_group,
+            inodes_per_group: 0,
+            group_count: 0,
+            gdt_blocks: 0,
+            itable_blocks: 0,
+        };
+        loop {
+            if layout.blocks_count <= first_data_block {
+                return Err(VfsError::InvalidInput); // the disk is too small
+            }
+            let data_blocks = layout.blocks_count - first_data_block;
+            layout.group_count = data_blocks.div_ceil(blocks_per_group);
+            layout.gdt_blocks = (layout.group_count as usize * BlockGroupDescriptor::SIZE)
+                .div_ceil(block_size) as u32;
+            let inodes = layout.blocks_count as u64 * block_size as u64 / bytes_per_inode;
+            layout.inodes_per_group = (inodes.div_ceil(layout.group_count as u64) as u32)
+                .max(2 * FIRST_INO)
+                .next_multiple_of(inodes_per_block)
+                .min(blocks_per_group);
+            layout.itable_blocks = layout.inodes_per_group / inodes_per_block;
+
+            let last = layout.group_count - 1;
+            if layout.blocks_in_group(last) >= layout.overhead(last) + MIN_GROUP_DATA_BLOCKS {
+                return Ok(layout);
+            }
+            // drop the last group, it is too small to hold its metadata
+            layout.blocks_count = layout.group_first_block(last);
+        }
+    }
+
+    fn group_first_block(&self, group: u32) -> u32 {
+        self.first_data_block + group * self.blocks_per_group
+    }
+
+    fn blocks_in_group(&self, group: u32) -> u32 {
+        (self.blocks_count - self.group_first_block(group)).min(self.blocks_per_group)
+    }
+
+    /// Number of metadata blocks at the start of the group.
+    fn overhead(&self, group: u32) -> u32 {
+        let super_blocks = if is_sparse_group(group) {
+            1 + self.gdt_blocks
+        } else {
+            0
+        };
+        super_blocks + 2 + self.itable_blocks
+    }
+
+    fn superblock(&self, free_blocks: u32, free_inodes: u32) -> Superblock {
+        let now = now();
+        let mut sb = Superblock::new_from_buf(&[0; Superblock::SIZE]);
+        sb.inodes_count = self.inodes_per_group * self.group_count;
+        sb.blocks_count = self.blocks_count;
+        sb.r_blocks_count = self.blocks_count / 20;
+        sb.free_blocks_count = free_blocks;
+        sb.free_inodes_count = free_inodes;
+        sb.first_data_block = self.first_data_block;
+        sb.log_block_size = self.block_size.trailing_zeros() - 10;
+        sb.log_frag_size = sb.log_block_size as i32;
+        sb.blocks_per_group = self.blocks_per_group;
+        sb.frags_per_group = self.blocks_per_group;
+        sb.inodes_per_group = self.inodes_per_group;
+        sb.wtime = now;
+        sb.max_mnt_count = -1;
+        sb.magic = Superblock::MAGIC;
+        sb.state = Superblock::FS_CLEAN;
+        sb.errors = 1; // continue
+        sb.lastcheck = now;
+        sb.rev_major = 1;
+        sb.first_inode = FIRST_INO;
+        sb.inode_size = Inode::SIZE as u16;
+        sb.features_req = Superblock::FEATURE_INCOMPAT_FILETYPE;
+        sb.features_ronly = Superblock::SUPPORTED_RO_COMPAT;
+        sb.fs_id = fs_id(now);
+        sb
+    }
+}
+
+impl Ext2Inner {
+    /// Creates an empty ext2 filesystem on the disk, with the root directory
+    /// and `lost+found` in it.
+    ///
+    /// All data on the disk is lost.
+    pub(super) fn format(mut disk: Disk) -> VfsResult<Self> {
+        let layout = Layout::new(disk.size())?;
+        info!(
+            "ext2: format {} blocks of {} bytes, {} groups",
+            layout.blocks_count, layout.block_size, layout.group_count
+        );
+        let groups = write_groups(&mut disk, &layout)?;
+        let free_blocks = groups.iter().map(|g| g.free_blocks_count as u32).sum();
+        let free_inodes = groups.iter().map(|g| g.free_inodes_count as u32).sum();
+        let mut sb = layout.superblock(free_blocks, free_inodes);
+        for group in (0..layout.group_count).filter(|&g| is_sparse_group(g)) {
+            sb.block_group = group as u16;
+            write_super(&mut disk, &layout, group, &sb, &groups)?;
+        }
+        disk.flush().map_err(|_| VfsError::Io)?;
+
+        let mut fs = Self::new(disk)?;
+        fs.init_root()?;
+        fs.sync()?;
+        Ok(fs)
+    }
+
+    /// Creates the root directory and `lost+found` in it.
+    fn init_root(&mut self) -> VfsResult {
+        let ino = Inode::ROOT_INO;
+        let mut root = Inode::new(VfsNodeType::Dir, VfsNodePerm::default_dir(), now());
+        root.links_count = 2;
+        self.dir_init(&mut root, ino, ino)?;
+        self.groups[0].dirs_count += 1;
+        self.write_group(0)?;
+        self.write_inode_full(ino, &root)?;
+
+        self.create_node(ino, "lost+found", VfsNodeType::Dir)?;
+        let root = self.read_inode(ino)?;
+        let lost_found = self.dir_find(&root, "lost+found")?;
+        let attr = VfsSetAttr {
+            perm: Some(VfsNodePerm::from_bits_truncate(0o700)),
+            ..Default::default()
+        };
+        self.set_attr(lost_found.ok_or(VfsError::NotFound)?, &attr)?;
+        self.write_superblock()
+    }
+}
+
+/// Writes the bitmaps and the inode table of each group, returns the group
+/// descriptors.
+fn write_groups(disk: &mut Disk, layout: &Layout) -> VfsResult<Vec<BlockGroupDescriptor>> {
+    let block_size = layout.block_size;
+    let zeros = vec![0; block_size];
+    let mut groups = Vec::with_capacity(layout.group_count as usize);
+    for group in 0..layout.group_count {
+        let first = layout.group_first_block(group);
+        let blocks = layout.blocks_in_group(group);
+        let overhead = layout.overhead(group);
+        let bitmap = first + overhead - layout.itable_blocks - 2;
+        let mut desc = BlockGroupDescriptor::new_from_buf(&[0; BlockGroupDescriptor::SIZE]);
+        desc.block_usage_addr = bitmap;
+        desc.inode_usage_addr = bitmap + 1;
+        desc.inode_table_block = bitmap + 2;
+        desc.free_blocks_count = (blocks - overhead) as u16;
+        desc.free_inodes_count = layout.inodes_per_group as u16;
+
+        // the metadata blocks are in use, and so are the bits past the end of
+        // the group
+        let mut buf = vec![0; block_size];
+        set_bits(&mut buf, 0..overhead);
+        set_bits(&mut buf, blocks..8 * block_size as u32);
+        write_block(disk, layout, desc.block_usage_addr, &buf)?;
+
+        let mut buf = vec![0; block_size];
+        if group == 0 {
+            set_bits(&mut buf, 0..FIRST_INO - 1);
+            desc.free_inodes_count -= (FIRST_INO - 1) as u16;
+        }
+        set_bits(&mut buf, layout.inodes_per_group..8 * block_size as u32);
+        write_block(disk, layout, desc.inode_usage_addr, &buf)?;
+
+        for i in 0..layout.itable_blocks {
+            write_block(disk, layout, desc.inode_table_block + i, &zeros)?;
+        }
+        groups.push(desc);
+    }
+    Ok(groups)
+}
+
+/// Writes a copy of the superblock and the group descriptor table in the
+/// group.
+fn write_super(
+    disk: &mut Disk,
+    layout: &Layout,
+    group: u32,
+    sb: &Superblock,
+    groups: &[BlockGroupDescriptor],
+) -> VfsResult {
+    let first = layout.group_first_block(group);
+    if group == 0 {
+        // the primary superblock is always at byte 1024, the space before it
+        // is left for the boot loader
+        write_disk(disk, Superblock::OFFSET, sb.as_bytes())?;
+    } else {
+        write_block(disk, layout, first, sb.as_bytes())?;
+    }
+    let mut gdt = vec![0; layout.gdt_blocks as usize * layout.block_size];
+    for (desc, buf) in groups
+        .iter()
+        .zip(gdt.chunks_exact_mut(BlockGroupDescriptor::SIZE))
+    {
+        buf.copy_from_slice(desc.as_bytes());
+    }
+    write_block(disk, layout, first + 1, &gdt)
+}
+
+/// Sets the bits in the range of the bitmap.
+fn set_bits(bitmap: &mut [u8], range: core::ops::Range<u32>) {
+    for bit in range {
+        bitmap[(bit / 8) as usize] |= 1 << (bit % 8);
+    }
+}
+
+fn write_block(disk: &mut Disk, layout: &Layout, block: u32, buf: &[u8]) -> VfsResult {
+    write_disk(disk, block as u64 * layout.block_size as u64, buf)
+}
+
+/// Generates a filesystem ID from the time, as there is no source of random
+/// numbers.
+fn fs_id(now: u32) -> [u8; 16] {
+    let mut seed = now as u64 ^ 0x2545_f491_4f6c_dd1d;
+    let mut id = [0; 16];
+    for chunk in id.chunks_exact_mut(8) {
+        seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1);
+        chunk.copy_from_slice(&seed.to_le_bytes());
+    }
+    id[6] = (id[6] & 0x0f) | 0x40; // UUID version 4
+    id[8] = (id[8] & 0x3f) | 0x80;
+    id
+}
@@ -1,52 +1,535 @@
-mod superblock;
+//! The [ext2] filesystem.
+//!
+//! Supports revision 0 and 1 filesystems with the `filetype`, `sparse_super`
+//! and `large_file` features, which are what `mkfs.ext2` produces by default.
+//! Other read-only compatible features make the filesystem read-only.
+//!
+//! The ext3 journal is supported as well: metadata updates are journaled, and
+//! replayed when mounting a filesystem that was not cleanly unmounted. See the
+//! `journal` module for details.
+//!
+//! Filesystems can also be checked and repaired like `e2fsck`, see the `fsck`
+//! module.
+//!
+//! [ext2]: https://www.nongnu.org/ext2-doc/ext2.html
+
+mod balloc;
 mod block_group;
+mod dir;
+mod file;
+mod fsck;
+mod ialloc;
+mod inode;
+mod journal;
+mod mkfs;
+mod superblock;
+mod symlink;
+
+use alloc::{sync::Arc, vec, vec::Vec};
+use core::time::Duration;
 
-use alloc::boxed::Box;
-use alloc::sync::Arc;
+use axfs_vfs::{FileSystemInfo, VfsError, VfsNodeAttr, VfsNodePerm, VfsNodeRef, VfsNodeType};
+use axfs_vfs::{VfsOps, VfsResult, VfsSetAttr};
+use axsync::Mutex;
 
-use axfs_vfs::{VfsOps, VfsNodeRef};
-use superblock::Superblock;
-// use block_group::BlockGroupDescriptor;
+use self::block_group::BlockGroupDescriptor;
+use self::inode::Inode;
+use self::journal::Journal;
+use self::superblock::Superblock;
 use crate::dev::Disk;
 
+pub use self::dir::DirNode;
+pub use self::file::FileNode;
+
+/// Shared state of a mounted ext2 filesystem, referenced by all its nodes.
+type Ext2Ref = Arc<Mutex<Ext2Inner>>;
+
+/// An ext2 filesystem that implements [`axfs_vfs::VfsOps`].
 pub struct Ext2FileSystem {
-    pub superblock: Box<Superblock>,
-    // bgdt: Box<BlockGroupDescriptor>,
-    pub dev: Arc<Disk>,
+    inner: Ext2Ref,
+}
+
+/// The in-memory copy of the filesystem metadata, along with the disk.
+pub(crate) struct Ext2Inner {
+    disk: Disk,
+    superblock: Superblock,
+    groups: Vec<BlockGroupDescriptor>,
+    block_size: usize,
+    read_only: bool,
+    /// The journal, if metadata updates are journaled.
+    journal: Option<Journal>,
+    /// The parent of the mount point, returned as the parent of the root.
+    mount_parent: Option<VfsNodeRef>,
 }
 
 impl Ext2FileSystem {
-    #[cfg(feature = "use-ramdisk")]
-    pub fn new(mut disk: Disk) -> Self {
-        unimplemented!();
+    /// Loads the ext2 filesystem on the disk.
+    pub fn new(disk: Disk) -> VfsResult<Self> {
+        Self::load(Ext2Inner::new(disk)?)
     }
 
-    #[cfg(not(feature = "use-ramdisk"))]
-    pub fn new(mut disk: Disk) -> Option<Ext2FileSystem> {
-        let mut superblock = [0 as u8; 1024];
-        disk.set_position(1024);
-        disk.read_one(&mut superblock);
-        let superblock = Superblock::new_from_buf(&mut superblock);
-        let superblock = Box::new(superblock);
-        if superblock.magic != Superblock::MAGIC {
-            return None;
+    /// Creates an empty ext2 filesystem on the disk and loads it.
+    ///
+    /// All data on the disk is lost.
+    pub fn format(disk: Disk) -> VfsResult<Self> {
+        Self::load(Ext2Inner::format(disk)?)
+    }
+
+    fn load(mut inner: Ext2Inner) -> VfsResult<Self> {
+        if !inner.read_only {
+            // the filesystem is not clean until unmounted
+            inner.superblock.mnt_count = inner.superblock.mnt_count.wrapping_add(1);
+            inner.superblock.mtime = now();
+            inner.superblock.state &= !Superblock::FS_CLEAN;
+            if inner.journal.is_some() {
+                // the journal is replayed if the filesystem is not unmounted
+                inner.superblock.features_req |= Superblock::FEATURE_INCOMPAT_RECOVER;
+            }
+            inner.write_superblock()?;
+            inner.commit()?;
         }
+        Ok(Self {
+            inner: Arc::new(Mutex::new(inner)),
+        })
+    }
+}
 
+impl VfsOps for Ext2FileSystem {
+    fn mount(&self, _path: &str, mount_point: VfsNodeRef) -> VfsResult {
+        self.inner.lock().mount_parent = mount_point.parent();
+        Ok(())
+    }
 
-        Some(Self {
-            superblock,
-            // bgdt: Box::new(BlockGroupDescriptor::new()),
-            dev: Arc::new(disk),
+    fn umount(&self) -> VfsResult {
+        let mut inner = self.inner.lock();
+        inner.mount_parent = None;
+        if !inner.read_only {
+            inner.superblock.wtime = now();
+            inner.superblock.state |= Superblock::FS_CLEAN;
+            inner.superblock.features_req &= !Superblock::FEATURE_INCOMPAT_RECOVER;
+            inner.write_superblock()?;
+        }
+        inner.sync()
+    }
+
+    fn statfs(&self) -> VfsResult<FileSystemInfo> {
+        let inner = self.inner.lock();
+        let sb = &inner.superblock;
+        Ok(FileSystemInfo {
+            fs_type: Superblock::MAGIC as u64,
+            block_size: inner.block_size as u64,
+            blocks: sb.blocks_count as u64,
+            blocks_free: sb.free_blocks_count as u64,
+            blocks_avail: sb.free_blocks_count.saturating_sub(sb.r_blocks_count) as u64,
+            files: sb.inodes_count as u64,
+            files_free: sb.free_inodes_count as u64,
+            name_max: dir::NAME_MAX as u64,
         })
     }
 
-    pub fn init(&'static self) {
-        unimplemented!();
+    fn root_dir(&self) -> VfsNodeRef {
+        DirNode::new(self.inner.clone(), Inode::ROOT_INO)
     }
 }
 
-impl VfsOps for Ext2FileSystem {
-    fn root_dir(&self) -> VfsNodeRef {
-        unimplemented!();
+/// Returns the current time in POSIX time, as stored in inodes.
+fn now() -> u32 {
+    axfs_vfs::time::current_time().as_secs() as u32
+}
+
+/// Creates the node of the given inode.
+fn new_node(fs: &Ext2Ref, ino: u32, inode: &Inode) -> VfsNodeRef {
+    if inode.is_dir() {
+        DirNode::new(fs.clone(), ino)
+    } else {
+        Arc::new(FileNode::new(fs.clone(), ino))
+    }
+}
+
+impl Ext2Inner {
+    fn new(mut disk: Disk) -> VfsResult<Self> {
+        let (superblock, groups) = read_metadata(&mut disk)?;
+        let read_only = check_features(&superblock)?;
+        let mut inner = Self {
+            disk,
+            block_size: superblock.block_size(),
+            superblock,
+            groups,
+            read_only,
+            journal: None,
+            mount_parent: None,
+        };
+        inner.load_journal()?;
+        Ok(inner)
+    }
+
+    /// Checks that the filesystem is writable before an operation that
+    /// modifies it, and makes room for it in the running transaction.
+    fn start_op(&mut self) -> VfsResult {
+        if self.read_only {
+            return Err(VfsError::PermissionDenied);
+        }
+        match &mut self.journal {
+            Some(journal) if journal.is_nearly_full() => journal.commit(&mut self.disk),
+            _ => Ok(()),
+        }
+    }
+
+    /// Commits the running transaction, and writes the cached blocks back to
+    /// the disk.
+    fn sync(&mut self) -> VfsResult {
+        self.commit()?;
+        self.disk.flush().map_err(|_| VfsError::Io)
+    }
+
+    /// Reads metadata, including the updates in the running transaction.
+    fn read_bytes(&mut self, pos: u64, buf: &mut [u8]) -> VfsResult {
+        match &self.journal {
+            Some(journal) => journal.read(&mut self.disk, pos, buf),
+            None => read_disk(&mut self.disk, pos, buf),
+        }
+    }
+
+    /// Writes metadata, which is journaled if the filesystem has a journal.
+    fn write_bytes(&mut self, pos: u64, buf: &[u8]) -> VfsResult {
+        match &mut self.journal {
+            Some(journal) => journal.write(&mut self.disk, pos, buf),
+            None => write_disk(&mut self.disk, pos, buf),
+        }
+    }
+
+    /// Writes file data in place, bypassing the journal.
+    fn write_data_bytes(&mut self, pos: u64, buf: &[u8]) -> VfsResult {
+        if let Some(journal) = &mut self.journal {
+            journal.forget(pos, buf.len());
+        }
+        write_disk(&mut self.disk, pos, buf)
+    }
+
+    /// Returns the byte offset of the given block on the disk.
+    const fn block_pos(&self, block: u32) -> u64 {
+        block as u64 * self.block_size as u64
+    }
+
+    fn read_block(&mut self, block: u32, buf: &mut [u8]) -> VfsResult {
+        self.read_bytes(self.block_pos(block), buf)
+    }
+
+    fn write_block(&mut self, block: u32, buf: &[u8]) -> VfsResult {
+        self.write_bytes(self.block_pos(block), buf)
+    }
+
+    fn write_superblock(&mut self) -> VfsResult {
+        let sb = self.superblock;
+        self.write_bytes(Superblock::OFFSET, sb.as_bytes())
+    }
+
+    fn write_group(&mut self, group: usize) -> VfsResult {
+        let gdt_pos = self.block_pos(self.superblock.first_data_block + 1);
+        let desc = self.groups[group];
+        let pos = gdt_pos + (group * BlockGroupDescriptor::SIZE) as u64;
+        self.write_bytes(pos, desc.as_bytes())
+    }
+
+    /// Returns the byte offset of the given inode on the disk.
+    fn inode_pos(&self, ino: u32) -> VfsResult<u64> {
+        if ino == 0 || ino > self.superblock.inodes_count {
+            return Err(VfsError::InvalidData);
+        }
+        let group = ((ino - 1) / self.superblock.inodes_per_group) as usize;
+        let index = (ino - 1) % self.superblock.inodes_per_group;
+        let table = self.groups[group].inode_table_block;
+        Ok(self.block_pos(table) + index as u64 * self.superblock.inode_size() as u64)
+    }
+
+    fn read_inode(&mut self, ino: u32) -> VfsResult<Inode> {
+        let mut buf = [0; Inode::SIZE];
+        self.read_bytes(self.inode_pos(ino)?, &mut buf)?;
+        Ok(Inode::new_from_buf(&buf))
     }
-}
\ No newline at end of file
+
+    fn write_inode(&mut self, ino: u32, inode: &Inode) -> VfsResult {
+        self.write_bytes(self.inode_pos(ino)?, inode.as_bytes())
+    }
+
+    fn get_attr(&mut self, ino: u32) -> VfsResult<VfsNodeAttr> {
+        let inode = self.read_inode(ino)?;
+        let mut attr = VfsNodeAttr::new(
+            inode.perm(),
+            inode.file_type(),
+            inode.size(),
+            inode.blocks as u64,
+        );
+        attr.set_ino(ino as u64);
+        attr.set_nlink(inode.links_count as u64);
+        attr.set_owner(inode.uid(), inode.gid());
+        let secs = |t: u32| Duration::from_secs(t as u64);
+        attr.set_times(secs(inode.atime), secs(inode.mtime), secs(inode.ctime));
+        Ok(attr)
+    }
+
+    /// Changes the permission, owner or times of the inode `ino`.
+    fn set_attr(&mut self, ino: u32, attr: &VfsSetAttr) -> VfsResult {
+        self.start_op()?;
+        let mut inode = self.read_inode(ino)?;
+        if let Some(perm) = attr.perm {
+            inode.set_perm(perm);
+        }
+        if let Some(uid) = attr.uid {
+            inode.set_uid(uid);
+        }
+        if let Some(gid) = attr.gid {
+            inode.set_gid(gid);
+        }
+        if let Some(atime) = attr.atime {
+            inode.atime = atime.as_secs() as u32;
+        }
+        if let Some(mtime) = attr.mtime {
+            inode.mtime = mtime.as_secs() as u32;
+        }
+        inode.ctime = now();
+        self.write_inode(ino, &inode)
+    }
+
+    /// Creates a node with the given name and type in the directory `dir_ino`.
+    ///
+    /// Returns [`Ok(())`](Ok) if it already exists.
+    fn create_node(&mut self, dir_ino: u32, name: &str, ty: VfsNodeType) -> VfsResult {
+        self.start_op()?;
+        let mut dir = self.read_inode(dir_ino)?;
+        if self.dir_find(&dir, name)?.is_some() {
+            return Ok(());
+        }
+        let perm = match ty {
+            VfsNodeType::Dir => VfsNodePerm::default_dir(),
+            VfsNodeType::File => VfsNodePerm::default_file(),
+            _ => return Err(VfsError::Unsupported),
+        };
+
+        let is_dir = ty.is_dir();
+        let ino = self.alloc_inode(dir_ino, is_dir)?;
+        let mut inode = Inode::new(ty, perm, now());
+        if is_dir {
+            inode.links_count = 2;
+            if let Err(e) = self.dir_init(&mut inode, ino, dir_ino) {
+                self.release_inode_data(&mut inode)?;
+                self.free_inode(ino, is_dir)?;
+                return Err(e);
+            }
+        }
+        self.write_inode_full(ino, &inode)?;
+
+        if let Err(e) = self.dir_add(dir_ino, &mut dir, name, ino, ty) {
+            self.release_inode_data(&mut inode)?;
+            self.free_inode(ino, is_dir)?;
+            self.write_inode(dir_ino, &dir)?;
+            return Err(e);
+        }
+        if is_dir {
+            dir.links_count += 1;
+        }
+        self.write_inode(dir_ino, &dir)
+    }
+
+    /// Writes a newly allocated inode, clears the extra space of large inodes.
+    fn write_inode_full(&mut self, ino: u32, inode: &Inode) -> VfsResult {
+        let mut buf = vec![0; self.superblock.inode_size()];
+        buf[..Inode::SIZE].copy_from_slice(inode.as_bytes());
+        self.write_bytes(self.inode_pos(ino)?, &buf)
+    }
+
+    /// Removes the entry with the given name in the directory `dir_ino`,
+    /// releases the inode if it has no more links.
+    fn remove_node(&mut self, dir_ino: u32, name: &str) -> VfsResult {
+        self.start_op()?;
+        let mut dir = self.read_inode(dir_ino)?;
+        let ino = self.dir_find(&dir, name)?.ok_or(VfsError::NotFound)?;
+        let inode = self.read_inode(ino)?;
+        let is_dir = inode.is_dir();
+        if is_dir && !self.dir_is_empty(&inode)? {
+            return Err(VfsError::DirectoryNotEmpty);
+        }
+
+        self.dir_remove(&mut dir, name)?;
+        if is_dir {
+            dir.links_count -= 1; // the `..` entry of the removed directory
+        }
+        self.write_inode(dir_ino, &dir)?;
+        self.unlink_inode(ino, inode)
+    }
+
+    /// Drops a link to the inode whose entry has been removed, releases it if
+    /// it has no more links or is a directory.
+    fn unlink_inode(&mut self, ino: u32, mut inode: Inode) -> VfsResult {
+        let is_dir = inode.is_dir();
+        inode.links_count = inode.links_count.saturating_sub(1);
+        if is_dir || inode.links_count == 0 {
+            self.release_inode_data(&mut inode)?;
+            // clear the inode, only the deletion time is recorded
+            let deleted = Inode {
+                dtime: now(),
+                ..Default::default()
+            };
+            self.write_inode(ino, &deleted)?;
+            self.free_inode(ino, is_dir)
+        } else {
+            inode.ctime = now();
+            self.write_inode(ino, &inode)
+        }
+    }
+
+    /// Moves the entry `src_name` in the directory `src_ino` to `dst_name` in
+    /// the directory `dst_ino`.
+    ///
+    /// An existing destination is replaced by pointing its entry to the moved
+    /// inode, so the name is never missing.
+    fn rename_node(
+        &mut self,
+        src_ino: u32,
+        src_name: &str,
+        dst_ino: u32,
+        dst_name: &str,
+    ) -> VfsResult {
+        self.start_op()?;
+        let src = self.read_inode(src_ino)?;
+        let ino = self.dir_find(&src, src_name)?.ok_or(VfsError::NotFound)?;
+        let inode = self.read_inode(ino)?;
+        let is_dir = inode.is_dir();
+        let moves_dir = is_dir && src_ino != dst_ino;
+        if src_ino == dst_ino && src_name == dst_name {
+            return Ok(());
+        }
+        if moves_dir {
+            // a directory cannot be moved into itself
+            let mut ancestor = dst_ino;
+            while ancestor != Inode::ROOT_INO {
+                if ancestor == ino {
+                    return Err(VfsError::InvalidInput);
+                }
+                let dir = self.read_inode(ancestor)?;
+                ancestor = self.dir_find(&dir, "..")?.ok_or(VfsError::InvalidData)?;
+            }
+        }
+
+        let mut dst = self.read_inode(dst_ino)?;
+        match self.dir_find(&dst, dst_name)? {
+            Some(old_ino) if old_ino == ino => return Ok(()), // hard links to the same inode
+            Some(old_ino) => {
+                let old = self.read_inode(old_ino)?;
+                if old.is_dir() {
+                    if !is_dir {
+                        return Err(VfsError::IsADirectory);
+                    } else if !self.dir_is_empty(&old)? {
+                        return Err(VfsError::DirectoryNotEmpty);
+                    }
+                    dst.links_count -= 1; // the `..` entry of the replaced directory
+                } else if is_dir {
+                    return Err(VfsError::NotADirectory);
+                }
+                self.dir_set(&dst, dst_name, ino, inode.file_type())?;
+                dst.touch_modify(now());
+                self.unlink_inode(old_ino, old)?;
+            }
+            None => {
+                let res = self.dir_add(dst_ino, &mut dst, dst_name, ino, inode.file_type());
+                if res.is_err() {
+                    self.write_inode(dst_ino, &dst)?;
+                    return res;
+                }
+            }
+        }
+        if moves_dir {
+            dst.links_count += 1;
+        }
+        self.write_inode(dst_ino, &dst)?;
+
+        // the source directory may have been changed if it is the same
+        let mut src = self.read_inode(src_ino)?;
+        self.dir_remove(&mut src, src_name)?;
+        if moves_dir {
+            src.links_count -= 1;
+            self.dir_set(&inode, "..", dst_ino, VfsNodeType::Dir)?;
+        }
+        self.write_inode(src_ino, &src)?;
+
+        let mut inode = self.read_inode(ino)?;
+        inode.ctime = now();
+        self.write_inode(ino, &inode)
+    }
+}
+
+/// Reads and validates the superblock and the group descriptors.
+fn read_metadata(disk: &mut Disk) -> VfsResult<(Superblock, Vec<BlockGroupDescriptor>)> {
+    let mut buf = [0; Superblock::SIZE];
+    read_disk(disk, Superblock::OFFSET, &mut buf)?;
+    let superblock = Superblock::new_from_buf(&buf);
+    if superblock.magic != Superblock::MAGIC {
+        return Err(VfsError::InvalidData);
+    }
+    debug!("ext2 superblock: {:#?}", superblock);
+    if superblock.log_block_size > 6
+        || superblock.blocks_per_group == 0
+        || superblock.inodes_per_group == 0
+        || superblock.inode_size() < Inode::SIZE
+        || superblock.first_data_block >= superblock.blocks_count
+    {
+        return Err(VfsError::InvalidData);
+    }
+
+    if superblock.state & Superblock::FS_ERR != 0 {
+        warn!("ext2: the filesystem has errors, run e2fsck on it");
+    }
+
+    let block_size = superblock.block_size();
+    let group_count = superblock.group_count() as usize;
+    let mut buf = vec![0; group_count * BlockGroupDescriptor::SIZE];
+    let gdt_pos = (superblock.first_data_block as u64 + 1) * block_size as u64;
+    read_disk(disk, gdt_pos, &mut buf)?;
+    let groups = buf
+        .chunks_exact(BlockGroupDescriptor::SIZE)
+        .map(BlockGroupDescriptor::new_from_buf)
+        .collect();
+    Ok((superblock, groups))
+}
+
+/// Checks the features of the filesystem, returns whether it can only be
+/// mounted read-only.
+fn check_features(superblock: &Superblock) -> VfsResult<bool> {
+    if superblock.rev_major == 0 {
+        return Ok(false);
+    }
+    let incompat = superblock.features_req & !Superblock::SUPPORTED_INCOMPAT;
+    if incompat != 0 {
+        warn!("ext2: unsupported required features {:#x}", incompat);
+        return Err(VfsError::Unsupported);
+    }
+    let ro_compat = superblock.features_ronly & !Superblock::SUPPORTED_RO_COMPAT;
+    if ro_compat != 0 {
+        warn!(
+            "ext2: unsupported features {:#x}, mount read-only",
+            ro_compat
+        );
+        return Ok(true);
+    }
+    Ok(false)
+}
+
+fn read_disk(disk: &mut Disk, pos: u64, buf: &mut [u8]) -> VfsResult {
+    disk.set_position(pos);
+    let mut buf = buf;
+    while !buf.is_empty() {
+        let n = disk.read_one(buf).map_err(|_| VfsError::Io)?;
+        buf = &mut buf[n..];
+    }
+    Ok(())
+}
+
+fn write_disk(disk: &mut Disk, pos: u64, buf: &[u8]) -> VfsResult {
+    disk.set_position(pos);
+    let mut buf = buf;
+    while !buf.is_empty() {
+        let n = disk.write_one(buf).map_err(|_| VfsError::Io)?;
+        buf = &buf[n..];
+    }
+    Ok(())
+}
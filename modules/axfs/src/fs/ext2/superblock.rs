@@ -162,54 +162,112 @@ impl Debug for Superblock {
     }
 }
 
-impl Superblock{
+impl Superblock {
+    /// Ext2 signature.
     pub const MAGIC: u16 = 0xef53;
+    /// Byte offset of the superblock from the beginning of the volume.
+    pub const OFFSET: u64 = 1024;
+    /// Size of the on-disk superblock, in bytes.
+    pub const SIZE: usize = 1024;
 
-    pub fn new_from_buf(buf: &mut[u8]) -> Self {
-        Self {
-            inodes_count: buf[0..3].as_ptr() as u32,
-            blocks_count: buf[4..7].as_ptr() as u32,
-            r_blocks_count: buf[8..0xb].as_ptr() as u32,
-            free_blocks_count: buf[0xc..0xf].as_ptr() as u32,
-            free_inodes_count: buf[0x10..0x13].as_ptr() as u32,
-            first_data_block: buf[0x14..0x17].as_ptr() as u32,
-            log_block_size: buf[0x18..0x1b].as_ptr() as u32,
-            log_frag_size: buf[0x1c..0x1f].as_ptr() as i32,
-            blocks_per_group: buf[0x20..0x23].as_ptr() as u32,
-            frags_per_group: buf[0x24..0x27].as_ptr() as u32,
-            inodes_per_group: buf[0x28..0x2b].as_ptr() as u32,
-            mtime: buf[0x2c..0x2f].as_ptr() as u32,
-            wtime: buf[0x30..0x33].as_ptr() as u32,
-            mnt_count: buf[0x34..0x35].as_ptr() as u16,
-            max_mnt_count: buf[0x36..0x37].as_ptr() as i16,
-            magic: buf[0x38..0x39].as_ptr() as u16,
-            state: buf[0x3a..0x3b].as_ptr() as u16,
-            errors: buf[0x3c..0x3d].as_ptr() as u16,
-            rev_minor: buf[0x3e..0x3f].as_ptr() as u16,
-            lastcheck: buf[0x40..0x43].as_ptr() as u32,
-            checkinterval: buf[0x44..0x47].as_ptr() as u32,
-            creator_os: buf[0x48..0x4b].as_ptr() as u32,
-            rev_major: buf[0x4c..0x4f].as_ptr() as u32,
-            block_uid: buf[0x50..0x51].as_ptr() as u16,
-            block_gid: buf[0x52..0x53].as_ptr() as u16,
-            first_inode: buf[0x54..0x57].as_ptr() as u32,
-            inode_size: buf[0x58..0x59].as_ptr() as u16,
-            block_group: buf[0x5a..0x5b].as_ptr() as u16,
-            features_opt: buf[0x5c..0x5f].as_ptr() as u32,
-            features_req: buf[0x60..].as_ptr() as u32,
-            features_ronly: buf[0x64..0x67].as_ptr() as u32,
-            fs_id: buf[0x68..0x77].try_into().unwrap(),
-            volume_name: buf[0x78..0x87].try_into().unwrap(),
-            last_mnt_path: buf[0x88..0xc7].try_into().unwrap(),
-            compression: buf[0xc8..0xcb].as_ptr() as u32,
-            prealloc_blocks_files: buf[0xcc],
-            prealloc_blocks_dirs: buf[0xcd],
-            _unused: buf[0xce..0xcf].try_into().unwrap(),
-            journal_id: buf[0xd0..0xef].try_into().unwrap(),
-            journal_inode: buf[0xe0..0xe3].as_ptr() as u32,
-            journal_dev: buf[0xe4..0xe7].as_ptr() as u32,
-            journal_orphan_head: buf[0xe8..0xeb].as_ptr() as u32,
-            _reserved: buf[0xec..0x3ff].try_into().unwrap(),
+    /// The filesystem was cleanly unmounted.
+    pub const FS_CLEAN: u16 = 1;
+    /// The filesystem has errors.
+    pub const FS_ERR: u16 = 2;
+
+    /// The filesystem has a journal (ext3).
+    pub const FEATURE_COMPAT_HAS_JOURNAL: u32 = 0x0004;
+    /// Directory entries record the file type.
+    pub const FEATURE_INCOMPAT_FILETYPE: u32 = 0x0002;
+    /// The journal needs to be replayed, set while the filesystem is mounted.
+    pub const FEATURE_INCOMPAT_RECOVER: u32 = 0x0004;
+    /// The journal is on an external device.
+    pub const FEATURE_INCOMPAT_JOURNAL_DEV: u32 = 0x0008;
+    /// Backup superblocks and group descriptors only in some groups.
+    pub const FEATURE_RO_COMPAT_SPARSE_SUPER: u32 = 0x0001;
+    /// Files can be larger than 2 GiB.
+    pub const FEATURE_RO_COMPAT_LARGE_FILE: u32 = 0x0002;
+
+    /// Required features that this implementation can handle.
+    pub const SUPPORTED_INCOMPAT: u32 =
+        Self::FEATURE_INCOMPAT_FILETYPE | Self::FEATURE_INCOMPAT_RECOVER;
+    /// Read-only compatible features that this implementation can write.
+    pub const SUPPORTED_RO_COMPAT: u32 =
+        Self::FEATURE_RO_COMPAT_SPARSE_SUPER | Self::FEATURE_RO_COMPAT_LARGE_FILE;
+
+    /// Parses the superblock from its on-disk representation.
+    ///
+    /// `buf` must hold at least [`Superblock::SIZE`] bytes. All fields are
+    /// stored in little-endian, like all the targets supported by ArceOS.
+    pub fn new_from_buf(buf: &[u8]) -> Self {
+        assert!(buf.len() >= Self::SIZE);
+        // SAFETY: `Superblock` is `repr(C)`, mirrors the on-disk layout and
+        // consists only of integers, so any bit pattern is valid.
+        unsafe { core::ptr::read_unaligned(buf.as_ptr() as *const Self) }
+    }
+
+    /// Returns the on-disk representation of the superblock.
+    pub fn as_bytes(&self) -> &[u8] {
+        // SAFETY: `Superblock` is `repr(C)` without padding.
+        unsafe { core::slice::from_raw_parts(self as *const Self as *const u8, Self::SIZE) }
+    }
+
+    /// Size of a block, in bytes.
+    pub const fn block_size(&self) -> usize {
+        1024 << self.log_block_size
+    }
+
+    /// Number of block groups in the filesystem.
+    pub const fn group_count(&self) -> u32 {
+        (self.blocks_count - self.first_data_block).div_ceil(self.blocks_per_group)
+    }
+
+    /// Size of an on-disk inode structure, in bytes.
+    pub const fn inode_size(&self) -> usize {
+        if self.rev_major == 0 {
+            128
+        } else {
+            self.inode_size as usize
+        }
+    }
+
+    /// First inode number that is not reserved.
+    pub const fn first_ino(&self) -> u32 {
+        if self.rev_major == 0 {
+            11
+        } else {
+            self.first_inode
         }
     }
-}
\ No newline at end of file
+
+    /// Whether the filesystem has a journal.
+    pub const fn has_journal(&self) -> bool {
+        self.rev_major > 0 && self.features_opt & Self::FEATURE_COMPAT_HAS_JOURNAL != 0
+    }
+
+    /// Whether the directory entries record the file type.
+    pub const fn has_filetype(&self) -> bool {
+        self.rev_major > 0 && self.features_req & Self::FEATURE_INCOMPAT_FILETYPE != 0
+    }
+
+    /// Whether the group has a copy of the superblock and the group
+    /// descriptor table, which is in every group without `sparse_super`.
+    pub const fn group_has_super(&self, group: u32) -> bool {
+        self.features_ronly & Self::FEATURE_RO_COMPAT_SPARSE_SUPER == 0 || is_sparse_group(group)
+    }
+}
+
+/// Whether the group has a copy of the superblock with `sparse_super`: groups
+/// 0, 1 and powers of 3, 5 and 7.
+pub const fn is_sparse_group(group: u32) -> bool {
+    const fn is_power_of(group: u32, base: u32) -> bool {
+        let mut n = group;
+        while n % base == 0 {
+            n /= base;
+        }
+        n == 1
+    }
+    group <= 1 || is_power_of(group, 3) || is_power_of(group, 5) || is_power_of(group, 7)
+}
+
+const _: () = assert!(core::mem::size_of::<Superblock>() == Superblock::SIZE);
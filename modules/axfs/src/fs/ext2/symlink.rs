@@ -0,0 +1,93 @@
+//! Symbolic links and hard links.
+//!
+//! Targets shorter than [`Inode::INLINE_SIZE`] are stored in the block
+//! pointers of the inode ("fast" symbolic links), longer ones in a data block.
+
+use alloc::{string::String, vec};
+
+use axfs_vfs::{VfsError, VfsNodePerm, VfsNodeType, VfsResult};
+
+use super::inode::Inode;
+use super::Ext2Inner;
+
+impl Ext2Inner {
+    /// Reads the target path of the symbolic link `ino`.
+    pub(super) fn read_symlink(&mut self, ino: u32) -> VfsResult<String> {
+        let inode = self.read_inode(ino)?;
+        if inode.file_type() != VfsNodeType::SymLink {
+            return Err(VfsError::InvalidInput);
+        }
+        let size = inode.size() as usize;
+        let target = if inode.has_data_blocks(self.block_size) {
+            if size > self.block_size {
+                return Err(VfsError::InvalidData);
+            }
+            let mut buf = vec![0; size];
+            self.read_data(&inode, 0, &mut buf)?;
+            buf
+        } else {
+            let data = inode.inline_data();
+            data.get(..size).ok_or(VfsError::InvalidData)?.to_vec()
+        };
+        String::from_utf8(target).map_err(|_| VfsError::InvalidData)
+    }
+
+    /// Creates a symbolic link with the given name and target in the
+    /// directory `dir_ino`.
+    pub(super) fn create_symlink(&mut self, dir_ino: u32, name: &str, target: &str) -> VfsResult {
+        self.start_op()?;
+        if target.is_empty() || target.len() >= self.block_size {
+            return Err(VfsError::InvalidInput);
+        }
+        let mut dir = self.read_inode(dir_ino)?;
+        if self.dir_find(&dir, name)?.is_some() {
+            return Err(VfsError::AlreadyExists);
+        }
+
+        let ino = self.alloc_inode(dir_ino, false)?;
+        let perm = VfsNodePerm::from_bits_truncate(0o777);
+        let mut inode = Inode::new(VfsNodeType::SymLink, perm, super::now());
+        if target.len() < Inode::INLINE_SIZE {
+            inode.inline_data_mut()[..target.len()].copy_from_slice(target.as_bytes());
+            inode.set_size(target.len() as u64);
+        } else if let Err(e) = self.write_data(ino, &mut inode, 0, target.as_bytes()) {
+            self.release_inode_data(&mut inode)?;
+            self.free_inode(ino, false)?;
+            return Err(e);
+        }
+        self.write_inode_full(ino, &inode)?;
+
+        if let Err(e) = self.dir_add(dir_ino, &mut dir, name, ino, VfsNodeType::SymLink) {
+            self.release_inode_data(&mut inode)?;
+            self.free_inode(ino, false)?;
+            self.write_inode(dir_ino, &dir)?;
+            return Err(e);
+        }
+        self.write_inode(dir_ino, &dir)
+    }
+
+    /// Adds an entry with the given name pointing to the existing inode `ino`
+    /// into the directory `dir_ino`.
+    pub(super) fn link_node(&mut self, dir_ino: u32, name: &str, ino: u32) -> VfsResult {
+        self.start_op()?;
+        let mut inode = self.read_inode(ino)?;
+        if inode.is_dir() {
+            return Err(VfsError::PermissionDenied);
+        } else if inode.links_count == 0 {
+            return Err(VfsError::NotFound); // already removed
+        } else if inode.links_count >= Inode::LINK_MAX {
+            return Err(VfsError::TooManyLinks);
+        }
+        let mut dir = self.read_inode(dir_ino)?;
+        if self.dir_find(&dir, name)?.is_some() {
+            return Err(VfsError::AlreadyExists);
+        }
+
+        let res = self.dir_add(dir_ino, &mut dir, name, ino, inode.file_type());
+        self.write_inode(dir_ino, &dir)?;
+        res?;
+        inode.links_count += 1;
+        inode.ctime = super::now();
+        self.write_inode(ino, &inode)
+    }
+}
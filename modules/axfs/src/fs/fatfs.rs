@@ -1,22 +1,41 @@
-use alloc::sync::Arc;
+use alloc::{format, string::String, sync::Arc};
 use core::cell::UnsafeCell;
+use core::time::Duration;
 
-use axfs_vfs::{VfsDirEntry, VfsError, VfsNodePerm, VfsResult};
+use axfs_vfs::{FileSystemInfo, VfsDirEntry, VfsError, VfsNodePerm, VfsResult, VfsSetAttr};
 use axfs_vfs::{VfsNodeAttr, VfsNodeOps, VfsNodeRef, VfsNodeType, VfsOps};
 use axsync::Mutex;
-use fatfs::{Dir, File, LossyOemCpConverter, NullTimeProvider, Read, Seek, SeekFrom, Write};
+use fatfs::{Date, DateTime, Dir, DirEntry, File, LossyOemCpConverter, Time, TimeProvider};
+use fatfs::{Read, Seek, SeekFrom, Write};
 
 use crate::dev::Disk;
 
 const BLOCK_SIZE: usize = 512;
 
 pub struct FatFileSystem {
-    inner: fatfs::FileSystem<Disk, NullTimeProvider, LossyOemCpConverter>,
+    inner: fatfs::FileSystem<Disk, AxTimeProvider, LossyOemCpConverter>,
     root_dir: UnsafeCell<Option<VfsNodeRef>>,
 }
 
-pub struct FileWrapper<'a>(Mutex<File<'a, Disk, NullTimeProvider, LossyOemCpConverter>>);
-pub struct DirWrapper<'a>(Dir<'a, Disk, NullTimeProvider, LossyOemCpConverter>);
+pub struct FileWrapper<'a>(
+    Mutex<File<'a, Disk, AxTimeProvider, LossyOemCpConverter>>,
+    Mutex<FatTimes>,
+);
+pub struct DirWrapper<'a>(Dir<'a, Disk, AxTimeProvider, LossyOemCpConverter>, FatTimes);
+
+/// Times of a FAT directory entry, as durations since the UNIX epoch.
+///
+/// `fatfs` does not expose the times of opened files, they are taken from the
+/// directory entry on lookup, and kept up to date by the node.
+#[derive(Clone, Copy, Default)]
+struct FatTimes {
+    accessed: Duration,
+    modified: Duration,
+}
+
+/// Provides the current time to `fatfs` from [`axfs_vfs::time`].
+#[derive(Debug, Clone, Copy, Default)]
+struct AxTimeProvider;
 
 unsafe impl Sync for FatFileSystem {}
 unsafe impl Send for FatFileSystem {}
@@ -26,39 +45,73 @@ unsafe impl<'a> Send for DirWrapper<'a> {}
 unsafe impl<'a> Sync for DirWrapper<'a> {}
 
 impl FatFileSystem {
-    #[cfg(feature = "use-ramdisk")]
-    pub fn new(mut disk: Disk) -> Self {
-        let opts = fatfs::FormatVolumeOptions::new();
-        fatfs::format_volume(&mut disk, opts).expect("failed to format volume");
-        let inner = fatfs::FileSystem::new(disk, fatfs::FsOptions::new())
-            .expect("failed to initialize FAT filesystem");
-        Self {
+    pub fn new(disk: Disk) -> VfsResult<Self> {
+        let inner = fatfs::FileSystem::new(disk, fs_options()).map_err(as_vfs_err)?;
+        Ok(Self {
             inner,
             root_dir: UnsafeCell::new(None),
-        }
+        })
     }
 
-    #[cfg(not(feature = "use-ramdisk"))]
-    pub fn new(disk: Disk) -> Self {
-        let inner = fatfs::FileSystem::new(disk, fatfs::FsOptions::new())
-            .expect("failed to initialize FAT filesystem");
-        Self {
-            inner,
-            root_dir: UnsafeCell::new(None),
-        }
+    /// Loads the FAT filesystem on the disk and initializes it.
+    ///
+    /// The nodes borrow the filesystem for `'static`, so it is never freed.
+    pub fn new_static(disk: Disk) -> VfsResult<Arc<Self>> {
+        let fs = Arc::new(Self::new(disk)?);
+        // leak one reference to keep the filesystem alive
+        let fs_ref: &'static Self = unsafe { &*Arc::into_raw(fs.clone()) };
+        fs_ref.init();
+        Ok(fs)
+    }
+
+    /// Creates an empty FAT filesystem on the disk and loads it. The FAT type
+    /// is chosen by the size of the disk like `mkfs.fat`, FAT32 is used for
+    /// disks larger than 512 MiB.
+    ///
+    /// All data on the disk is lost.
+    pub fn format(mut disk: Disk) -> VfsResult<Arc<Self>> {
+        let opts = fatfs::FormatVolumeOptions::new();
+        fatfs::format_volume(&mut disk, opts).map_err(as_vfs_err)?;
+        Self::new_static(disk)
     }
 
     pub fn init(&'static self) {
         // must be called before later operations
-        unsafe { *self.root_dir.get() = Some(Self::new_dir(self.inner.root_dir())) }
+        let root_dir = Self::new_dir(self.inner.root_dir(), FatTimes::default());
+        unsafe { *self.root_dir.get() = Some(root_dir) }
+    }
+
+    fn new_file(
+        file: File<'_, Disk, AxTimeProvider, LossyOemCpConverter>,
+        times: FatTimes,
+    ) -> Arc<FileWrapper> {
+        Arc::new(FileWrapper(Mutex::new(file), Mutex::new(times)))
     }
 
-    fn new_file(file: File<'_, Disk, NullTimeProvider, LossyOemCpConverter>) -> Arc<FileWrapper> {
-        Arc::new(FileWrapper(Mutex::new(file)))
+    fn new_dir(
+        dir: Dir<'_, Disk, AxTimeProvider, LossyOemCpConverter>,
+        times: FatTimes,
+    ) -> Arc<DirWrapper> {
+        Arc::new(DirWrapper(dir, times))
+    }
+}
+
+fn fs_options() -> fatfs::FsOptions<AxTimeProvider, LossyOemCpConverter> {
+    fatfs::FsOptions::new().time_provider(AxTimeProvider)
+}
+
+impl FatTimes {
+    fn from_entry(entry: &DirEntry<'_, Disk, AxTimeProvider, LossyOemCpConverter>) -> Self {
+        Self {
+            accessed: from_fat_date(entry.accessed()),
+            modified: from_fat_mtime(entry.modified()),
+        }
     }
 
-    fn new_dir(dir: Dir<'_, Disk, NullTimeProvider, LossyOemCpConverter>) -> Arc<DirWrapper> {
-        Arc::new(DirWrapper(dir))
+    fn fill_attr(&self, mut attr: VfsNodeAttr) -> VfsNodeAttr {
+        // FAT has no status change time, use the modification time instead
+        attr.set_times(self.accessed, self.modified, self.modified);
+        attr
     }
 }
 
@@ -70,7 +123,31 @@ impl VfsNodeOps for FileWrapper<'static> {
         let blocks = (size + BLOCK_SIZE as u64 - 1) / BLOCK_SIZE as u64;
         // FAT fs doesn't support permissions, we just set everything to 755
         let perm = VfsNodePerm::from_bits_truncate(0o755);
-        Ok(VfsNodeAttr::new(perm, VfsNodeType::File, size, blocks))
+        let attr = VfsNodeAttr::new(perm, VfsNodeType::File, size, blocks);
+        Ok(self.1.lock().fill_attr(attr))
+    }
+
+    /// Changes the times of the file, FAT does not support permissions and
+    /// owners.
+    #[allow(deprecated)] // no other way to set the times of an existing file
+    fn set_attr(&self, attr: &VfsSetAttr) -> VfsResult {
+        if attr.perm.is_some() || attr.uid.is_some() || attr.gid.is_some() {
+            return Err(VfsError::Unsupported);
+        }
+        let mut file = self.0.lock();
+        let mut times = self.1.lock();
+        if let Some(atime) = attr.atime {
+            // only the date of last access is recorded
+            let accessed = to_fat_time(atime).date;
+            file.set_accessed(accessed);
+            times.accessed = from_fat_date(accessed);
+        }
+        if let Some(mtime) = attr.mtime {
+            let modified = to_fat_time(mtime);
+            file.set_modified(modified);
+            times.modified = from_fat_mtime(modified);
+        }
+        file.flush().map_err(as_vfs_err)
     }
 
     fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
@@ -82,13 +159,48 @@ impl VfsNodeOps for FileWrapper<'static> {
     fn write_at(&self, offset: u64, buf: &[u8]) -> VfsResult<usize> {
         let mut file = self.0.lock();
         file.seek(SeekFrom::Start(offset)).map_err(as_vfs_err)?; // TODO: more efficient
-        file.write(buf).map_err(as_vfs_err)
+        let len = file.write(buf).map_err(as_vfs_err)?;
+        // `fatfs` stamps the directory entry with the same time
+        self.1.lock().modified = from_fat_mtime(AxTimeProvider.get_current_date_time());
+        Ok(len)
+    }
+
+    fn fsync(&self) -> VfsResult {
+        self.0.lock().flush().map_err(as_vfs_err)
     }
 
     fn truncate(&self, size: u64) -> VfsResult {
         let mut file = self.0.lock();
         file.seek(SeekFrom::Start(size)).map_err(as_vfs_err)?; // TODO: more efficient
-        file.truncate().map_err(as_vfs_err)
+        file.truncate().map_err(as_vfs_err)?;
+        self.1.lock().modified = from_fat_mtime(AxTimeProvider.get_current_date_time());
+        Ok(())
+    }
+}
+
+impl DirWrapper<'static> {
+    /// Returns the times in the directory entry at `path`, or zeros if it is
+    /// not found (e.g., the root directory has no entry).
+    fn find_times(&self, path: &str) -> FatTimes {
+        let (dir, name) = match path.rsplit_once('/') {
+            Some((parent, name)) => match self.0.open_dir(parent) {
+                Ok(dir) => (dir, name),
+                Err(_) => return FatTimes::default(),
+            },
+            None => (self.0.clone(), path),
+        };
+        dir.iter()
+            .filter_map(Result::ok)
+            .find(|entry| entry.file_name().eq_ignore_ascii_case(name))
+            .map_or_else(FatTimes::default, |entry| FatTimes::from_entry(&entry))
+    }
+
+    /// Returns a name `<path>.<n>~` that no entry has, to move an entry aside.
+    fn unused_name(&self, path: &str) -> String {
+        (0..)
+            .map(|i| format!("{}.{}~", path, i))
+            .find(|name| self.0.open_file(name).is_err() && self.0.open_dir(name).is_err())
+            .unwrap()
     }
 }
 
@@ -97,18 +209,20 @@ impl VfsNodeOps for DirWrapper<'static> {
 
     fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
         // FAT fs doesn't support permissions, we just set everything to 755
-        Ok(VfsNodeAttr::new(
+        let attr = VfsNodeAttr::new(
             VfsNodePerm::from_bits_truncate(0o755),
             VfsNodeType::Dir,
             BLOCK_SIZE as u64,
             1,
-        ))
+        );
+        Ok(self.1.fill_attr(attr))
     }
 
     fn parent(&self) -> Option<VfsNodeRef> {
-        self.0
-            .open_dir("..")
-            .map_or(None, |dir| Some(FatFileSystem::new_dir(dir)))
+        self.0.open_dir("..").map_or(None, |dir| {
+            let times = self.find_times("..");
+            Some(FatFileSystem::new_dir(dir, times))
+        })
     }
 
     fn lookup(self: Arc<Self>, path: &str) -> VfsResult<VfsNodeRef> {
@@ -123,9 +237,9 @@ impl VfsNodeOps for DirWrapper<'static> {
 
         // TODO: use `fatfs::Dir::find_entry`, but it's not public.
         if let Ok(file) = self.0.open_file(path) {
-            Ok(FatFileSystem::new_file(file))
+            Ok(FatFileSystem::new_file(file, self.find_times(path)))
         } else if let Ok(dir) = self.0.open_dir(path) {
-            Ok(FatFileSystem::new_dir(dir))
+            Ok(FatFileSystem::new_dir(dir, self.find_times(path)))
         } else {
             Err(VfsError::NotFound)
         }
@@ -164,6 +278,92 @@ impl VfsNodeOps for DirWrapper<'static> {
         self.0.remove(path).map_err(as_vfs_err)
     }
 
+    /// Renames a file or directory.
+    ///
+    /// FAT cannot replace an entry atomically. The existing destination is
+    /// moved aside to `<dst_path>.<n>~` before the source is renamed, and
+    /// moved back if that fails. So `dst_path` is missing for a while, and
+    /// only the moved aside entry is left if the operation is interrupted.
+    fn rename(&self, src_path: &str, dst_path: &str) -> VfsResult {
+        debug!("rename at fatfs: {} -> {}", src_path, dst_path);
+        let src_path = src_path.trim_matches('/');
+        let dst_path = dst_path.trim_matches('/');
+        let src_is_dir = if self.0.open_file(src_path).is_ok() {
+            false
+        } else if self.0.open_dir(src_path).is_ok() {
+            true
+        } else {
+            return Err(VfsError::NotFound);
+        };
+        // names are case-insensitive in FAT, so a change of case is done
+        // through another name
+        if src_path.eq_ignore_ascii_case(dst_path) {
+            let src_name = src_path.rsplit('/').next().unwrap();
+            let dst_name = dst_path.rsplit('/').next().unwrap();
+            if src_name == dst_name {
+                return Ok(());
+            }
+            let tmp = self.unused_name(src_path);
+            self.0.rename(src_path, &self.0, &tmp).map_err(as_vfs_err)?;
+            if let Err(e) = self.0.rename(&tmp, &self.0, dst_path) {
+                if self.0.rename(&tmp, &self.0, src_path).is_err() {
+                    warn!("failed to restore {} from {}", src_path, tmp);
+                }
+                return Err(as_vfs_err(e));
+            }
+            return Ok(());
+        }
+        // a directory cannot be moved into itself
+        if src_is_dir
+            && dst_path.len() > src_path.len()
+            && dst_path.as_bytes()[src_path.len()] == b'/'
+            && dst_path[..src_path.len()].eq_ignore_ascii_case(src_path)
+        {
+            return Err(VfsError::InvalidInput);
+        }
+
+        let dst_exists = if self.0.open_file(dst_path).is_ok() {
+            if src_is_dir {
+                return Err(VfsError::NotADirectory);
+            }
+            true
+        } else if let Ok(dir) = self.0.open_dir(dst_path) {
+            if !src_is_dir {
+                return Err(VfsError::IsADirectory);
+            }
+            let is_empty = dir
+                .iter()
+                .all(|e| e.is_ok_and(|e| matches!(e.file_name().as_str(), "." | "..")));
+            if !is_empty {
+                return Err(VfsError::DirectoryNotEmpty);
+            }
+            true
+        } else {
+            false
+        };
+        if !dst_exists {
+            return self
+                .0
+                .rename(src_path, &self.0, dst_path)
+                .map_err(as_vfs_err);
+        }
+
+        let backup = self.unused_name(dst_path);
+        self.0
+            .rename(dst_path, &self.0, &backup)
+            .map_err(as_vfs_err)?;
+        if let Err(e) = self.0.rename(src_path, &self.0, dst_path) {
+            if self.0.rename(&backup, &self.0, dst_path).is_err() {
+                warn!("failed to restore {} from {}", dst_path, backup);
+            }
+            return Err(as_vfs_err(e));
+        }
+        if self.0.remove(&backup).is_err() {
+            warn!("failed to remove the replaced {}", backup);
+        }
+        Ok(())
+    }
+
     fn read_dir(&self, start_idx: usize, dirents: &mut [VfsDirEntry]) -> VfsResult<usize> {
         let mut iter = self.0.iter().skip(start_idx);
         for (i, out_entry) in dirents.iter_mut().enumerate() {
@@ -187,6 +387,21 @@ impl VfsNodeOps for DirWrapper<'static> {
 }
 
 impl VfsOps for FatFileSystem {
+    fn statfs(&self) -> VfsResult<FileSystemInfo> {
+        let stats = self.inner.stats().map_err(as_vfs_err)?;
+        Ok(FileSystemInfo {
+            fs_type: 0x4d44, // `MSDOS_SUPER_MAGIC` on Linux
+            block_size: stats.cluster_size() as u64,
+            blocks: stats.total_clusters() as u64,
+            blocks_free: stats.free_clusters() as u64,
+            blocks_avail: stats.free_clusters() as u64,
+            // FAT has no inodes
+            files: 0,
+            files_free: 0,
+            name_max: 255,
+        })
+    }
+
     fn root_dir(&self) -> VfsNodeRef {
         let root_dir = unsafe { (*self.root_dir.get()).as_ref().unwrap() };
         root_dir.clone()
@@ -231,7 +446,7 @@ impl Write for Disk {
         Ok(write_len)
     }
     fn flush(&mut self) -> Result<(), Self::Error> {
-        Ok(())
+        Disk::flush(self).map_err(|_| ())
     }
 }
 
@@ -269,3 +484,77 @@ const fn as_vfs_err(err: fatfs::Error<()>) -> VfsError {
         _ => VfsError::Io,
     }
 }
+
+impl TimeProvider for AxTimeProvider {
+    fn get_current_date(&self) -> Date {
+        self.get_current_date_time().date
+    }
+
+    fn get_current_date_time(&self) -> DateTime {
+        to_fat_time(axfs_vfs::time::current_time())
+    }
+}
+
+/// Seconds from the UNIX epoch to 1980-01-01, the earliest FAT date.
+const FAT_EPOCH_SECS: u64 = 315_532_800;
+/// Seconds from the UNIX epoch to 2107-12-31 23:59:58, the latest FAT date.
+const FAT_MAX_SECS: u64 = 4_354_819_198;
+
+/// Converts a FAT date and time to the duration since the UNIX epoch.
+fn from_fat_time(dt: DateTime) -> Duration {
+    // days from civil, see http://howardhinnant.github.io/date_algorithms.html
+    // invalid dates may be read from corrupted entries
+    let month = (dt.date.month as u64).clamp(1, 12);
+    let day = (dt.date.day as u64).max(1);
+    let year = dt.date.year as u64 - (month <= 2) as u64;
+    let era = year / 400;
+    let yoe = year - era * 400;
+    let doy = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146097 + doe - 719468;
+    let secs =
+        days * 86400 + dt.time.hour as u64 * 3600 + dt.time.min as u64 * 60 + dt.time.sec as u64;
+    Duration::from_secs(secs)
+}
+
+/// Converts a FAT date and time to the duration since the UNIX epoch, in the
+/// two-second precision of modification times.
+fn from_fat_mtime(dt: DateTime) -> Duration {
+    Duration::from_secs(from_fat_time(dt).as_secs() & !1)
+}
+
+/// Converts a FAT date to the duration since the UNIX epoch.
+fn from_fat_date(date: Date) -> Duration {
+    from_fat_time(DateTime::new(date, Time::new(0, 0, 0, 0)))
+}
+
+/// Converts the duration since the UNIX epoch to a FAT date and time, which
+/// is clamped to the range FAT can represent.
+fn to_fat_time(time: Duration) -> DateTime {
+    let secs = time.as_secs().clamp(FAT_EPOCH_SECS, FAT_MAX_SECS);
+    let millis = if secs == time.as_secs() {
+        time.subsec_millis() as u16
+    } else {
+        0
+    };
+    // civil from days, see http://howardhinnant.github.io/date_algorithms.html
+    let days = secs / 86400 + 719468;
+    let era = days / 146097;
+    let doe = days - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as u64;
+
+    let rem = secs % 86400;
+    let date = Date::new(year as u16, month as u16, day as u16);
+    let time = Time::new(
+        (rem / 3600) as u16,
+        (rem % 3600 / 60) as u16,
+        (rem % 60) as u16,
+        millis,
+    );
+    DateTime::new(date, time)
+}
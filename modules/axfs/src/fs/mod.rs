@@ -1,15 +1,102 @@
-cfg_if::cfg_if! {
-    if #[cfg(feature = "myfs")] {
-        pub mod myfs;
-    } else if #[cfg(feature = "ext2")] {
-        pub mod ext2;
-    } else if #[cfg(feature = "fatfs")] {
-        pub mod fatfs;
-    }
-}
+#[cfg(feature = "myfs")]
+pub mod myfs;
+
+#[cfg(feature = "fatfs")]
+pub mod fatfs;
+
+#[cfg(feature = "ext2")]
+pub mod ext2;
 
 #[cfg(feature = "devfs")]
 pub use axfs_devfs as devfs;
 
 #[cfg(feature = "ramfs")]
 pub use axfs_ramfs as ramfs;
+
+#[cfg(feature = "procfs")]
+pub use axfs_procfs as procfs;
+
+#[cfg(feature = "overlayfs")]
+pub use axfs_overlayfs as overlayfs;
+
+#[cfg(feature = "initramfs")]
+pub use axfs_initramfs as initramfs;
+
+/// The archive of the initramfs embedded in the kernel image, which is given by
+/// the `AX_INITRAMFS` environment variable at build time.
+#[cfg(feature = "initramfs")]
+pub static INITRAMFS_ARCHIVE: &[u8] = include_bytes!(env!("AX_INITRAMFS_PATH"));
+
+use alloc::{sync::Arc, vec};
+use axdriver::prelude::*;
+use axfs_vfs::{VfsError, VfsOps, VfsResult};
+
+use crate::dev::Disk;
+use crate::partition::{is_fat_boot_sector, Partition};
+
+/// Filesystems that can be detected on partitions, or created by
+/// [`api::format`](crate::api::format).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FsType {
+    /// FAT12, FAT16 or FAT32, which is chosen by the size of the partition.
+    Fat,
+    /// The second extended filesystem.
+    Ext2,
+}
+
+impl FsType {
+    /// The offset and value of the magic number in the ext2 superblock.
+    const EXT2_MAGIC_OFFSET: usize = 1024 + 56;
+    const EXT2_MAGIC: [u8; 2] = [0x53, 0xef];
+
+    /// Detects the filesystem on the partition by its signature, returns
+    /// `None` if it is unknown.
+    pub fn detect(part: &mut Partition) -> DevResult<Option<Self>> {
+        let bs = part.block_size();
+        // the boot sector of FAT, and the superblock of ext2
+        let mut buf = vec![0; (Self::EXT2_MAGIC_OFFSET + 2).div_ceil(bs) * bs];
+        if part.num_blocks() < (buf.len() / bs) as u64 {
+            return Ok(None);
+        }
+        part.read_block(0, &mut buf)?;
+        if is_fat_boot_sector(&buf) {
+            Ok(Some(Self::Fat))
+        } else if buf[Self::EXT2_MAGIC_OFFSET..Self::EXT2_MAGIC_OFFSET + 2] == Self::EXT2_MAGIC {
+            Ok(Some(Self::Ext2))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+/// Loads the filesystem of the given type on the disk, fails with
+/// [`VfsError::Unsupported`] if the support of it is not enabled.
+pub fn new_fs(ty: FsType, disk: Disk) -> VfsResult<Arc<dyn VfsOps>> {
+    match ty {
+        #[cfg(feature = "fatfs")]
+        FsType::Fat => Ok(fatfs::FatFileSystem::new_static(disk)?),
+        #[cfg(feature = "ext2")]
+        FsType::Ext2 => Ok(Arc::new(ext2::Ext2FileSystem::new(disk)?)),
+        #[allow(unreachable_patterns)]
+        _ => {
+            let _ = disk;
+            Err(VfsError::Unsupported)
+        }
+    }
+}
+
+/// Creates an empty filesystem of the given type on the disk and loads it,
+/// fails with [`VfsError::Unsupported`] if the support of it is not enabled.
+pub fn format(ty: FsType, disk: Disk) -> VfsResult<Arc<dyn VfsOps>> {
+    match ty {
+        #[cfg(feature = "fatfs")]
+        FsType::Fat => Ok(fatfs::FatFileSystem::format(disk)?),
+        #[cfg(feature = "ext2")]
+        FsType::Ext2 => Ok(Arc::new(ext2::Ext2FileSystem::format(disk)?)),
+        #[allow(unreachable_patterns)]
+        _ => {
+            let _ = disk;
+            Err(VfsError::Unsupported)
+        }
+    }
+}
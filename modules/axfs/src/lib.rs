@@ -4,20 +4,48 @@
 //!
 //! # Cargo Features
 //!
-//! - `fatfs`: Use [FAT] as the main filesystem and mount it on `/`. This feature
-//!    is **enabled** by default.
-//! - `devfs`: Mount [`axfs_devfs::DeviceFileSystem`] on `/dev`. This feature is
-//!    **enabled** by default.
-//! - `ramfs`: Mount [`axfs_ramfs::RamFileSystem`] on `/tmp`. This feature is
+//! - `ext2`: Support [ext2] filesystems on block devices, and ext3 ones whose
+//!    journal makes metadata updates crash-consistent. This feature is
 //!    **enabled** by default.
+//! - `fatfs`: Support [FAT] filesystems on block devices. This feature is
+//!    **disabled** by default.
+//! - `devfs`: Mount [`axfs_devfs::DeviceFileSystem`] on `/dev`, with raw block
+//!    devices as `/dev/vda`, `/dev/vdb`, etc. Other modules can add devices to
+//!    it by [`add_device`]. This feature is **enabled** by default.
+//! - `ramfs`: Mount [`axfs_ramfs::RamFileSystem`] on `/tmp`, which can take at
+//!    most half of the memory. This feature is **enabled** by default.
+//! - `procfs`: Mount [`axfs_procfs::ProcFileSystem`] on `/proc`. Other modules
+//!    can add files to it by [`add_proc_file`]. This feature is **enabled** by
+//!    default.
+//! - `overlayfs`: Stack a [`axfs_ramfs::RamFileSystem`] over the root
+//!    filesystem by [`axfs_overlayfs::OverlayFileSystem`], so that the root
+//!    filesystem can be an immutable image but still writable. The changes
+//!    are kept in memory and discarded on reboot. This feature is **disabled**
+//!    by default.
+//! - `initramfs`: Embed a `cpio` or `tar` archive given by the `AX_INITRAMFS`
+//!    environment variable at build time in the kernel image, and load it by
+//!    [`axfs_initramfs::InitRamFileSystem`]. It is mounted on `/` if no
+//!    filesystem is found on the block devices, otherwise on `/initrd`. This
+//!    feature is **disabled** by default.
+//! - `paging`: Support memory-mapped files by [`File::mmap`], which maps the
+//!    page cache of the file into the kernel address space. This feature is
+//!    **disabled** by default.
+//! - `smp`: Set when multiple CPUs are enabled. Memory-mapped files are not
+//!    supported in this case, as unmapping pages does not flush the TLBs of
+//!    other CPUs yet. This feature is **disabled** by default.
+//! - `multitask`: Each task has its own current directory, which is copied to
+//!    the tasks it spawns. Otherwise, there is only one current directory for
+//!    all tasks. This feature is **disabled** by default.
 //! - `myfs`: Allow users to define their custom filesystems to override the
 //!    default. In this case, [`MyFileSystemIf`] is required to be implemented
 //!    to create and initialize other filesystems. This feature is **disabled** by
 //!    by default, but it will override other filesystem selection features if
 //!    both are enabled.
 //!
+//! [ext2]: https://en.wikipedia.org/wiki/Ext2
 //! [FAT]: https://en.wikipedia.org/wiki/File_Allocation_Table
 //! [`MyFileSystemIf`]: fops::MyFileSystemIf
+//! [`File::mmap`]: fops::File::mmap
 
 #![cfg_attr(all(not(test), not(doc)), no_std)]
 #![feature(doc_auto_cfg)]
@@ -26,20 +54,123 @@
 extern crate log;
 extern crate alloc;
 
+#[cfg(feature = "devfs")]
+mod blkdev;
+mod cache;
 mod dev;
 mod fs;
+#[cfg(feature = "paging")]
+mod mmap;
+mod page_cache;
+mod partition;
 mod root;
 
 pub mod api;
+pub mod check;
 pub mod fops;
 
+pub use axfs_vfs::time::set_clock;
+
+use alloc::{format, string::String, vec::Vec};
 use axdriver::{prelude::*, AxDeviceContainer};
+#[cfg(feature = "devfs")]
+use axfs_vfs::VfsNodeRef;
+
+use self::partition::Partition;
 
 /// Initializes filesystems by block devices.
+///
+/// Partition tables on all devices are scanned, and the filesystem on each
+/// partition is detected. The first one is mounted on `/`, and the others are
+/// mounted on `/mnt/<devname><n>`, where `n` is the partition number.
+/// Partitions without a supported filesystem can be formatted later by
+/// [`api::format`]. If there are none and the `initramfs` feature is enabled,
+/// the initramfs is mounted on `/` instead.
 pub fn init_filesystems(mut blk_devs: AxDeviceContainer<AxBlockDevice>) {
     info!("Initialize filesystems...");
 
-    let dev = blk_devs.take_one().expect("No block device found!");
-    info!("  use block device 0: {:?}", dev.device_name());
-    self::root::init_rootfs(self::dev::Disk::new(dev));
+    let mut disks = Vec::new();
+    let mut parts = Vec::new();
+    let mut names: Vec<String> = Vec::new();
+    let mut idx = 0;
+    while let Some(dev) = blk_devs.take_one() {
+        info!("  use block device {}: {:?}", idx, dev.device_name());
+        // devices of the same driver are distinguished by their index
+        let mut name = String::from(dev.device_name());
+        if names.contains(&name) {
+            name = format!("{}-{}", name, idx);
+        }
+        let disk = Partition::new(dev, name.clone());
+        parts.extend(self::partition::scan_partitions(&disk));
+        disks.push(disk);
+        names.push(name);
+        idx += 1;
+    }
+    self::root::init_rootfs(parts);
+
+    #[cfg(feature = "devfs")]
+    for (idx, disk) in disks.into_iter().enumerate() {
+        let name = disk_name(idx);
+        info!("  add block device {}: /dev/{}", idx, name);
+        let dev = alloc::sync::Arc::new(self::blkdev::BlockDev::new(disk));
+        add_device(&name, dev);
+    }
+}
+
+/// Returns the name of the `idx`-th raw block device in devfs, which is
+/// `vda`, ..., `vdz`, `vdaa`, `vdab`, etc. like Linux.
+#[cfg(feature = "devfs")]
+fn disk_name(mut idx: usize) -> String {
+    let mut suffix = Vec::new();
+    loop {
+        suffix.push(b'a' + (idx % 26) as u8);
+        if idx < 26 {
+            break;
+        }
+        idx = idx / 26 - 1;
+    }
+    suffix.reverse();
+    format!("vd{}", core::str::from_utf8(&suffix).unwrap())
+}
+
+/// Adds a device node to the devfs mounted on `/dev`.
+///
+/// `path` is relative to `/dev`, and missing directories in it are created.
+/// A device with the same path is replaced. It must be called after
+/// [`init_filesystems`].
+#[cfg(feature = "devfs")]
+pub fn add_device(path: &str, node: VfsNodeRef) {
+    self::root::DEV_FS.add(path, node);
+}
+
+/// Removes the device node at `path` relative to `/dev`.
+///
+/// Opened files of the device are still usable until they are closed.
+#[cfg(feature = "devfs")]
+pub fn remove_device(path: &str) -> axerrno::AxResult {
+    match self::root::DEV_FS.remove(path) {
+        Some(_) => Ok(()),
+        None => axerrno::ax_err!(NotFound),
+    }
+}
+
+/// Adds a file to the procfs mounted on `/proc`, whose content is generated
+/// by `read` each time it is read.
+///
+/// `path` is relative to `/proc`, and missing directories in it are created.
+/// It must be called after [`init_filesystems`].
+#[cfg(feature = "procfs")]
+pub fn add_proc_file<F>(path: &str, read: F)
+where
+    F: Fn() -> String + Send + Sync + 'static,
+{
+    self::root::PROC_FS.add_file(path, read);
+}
+
+/// Unmounts all filesystems, writes the cached data back to block devices.
+///
+/// It should be called before the system shuts down.
+pub fn uninit_filesystems() {
+    info!("Uninitialize filesystems...");
+    self::root::uninit_rootfs();
 }
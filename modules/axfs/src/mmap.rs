@@ -0,0 +1,161 @@
+//! Memory-mapped files.
+//!
+//! Pages in the page cache are mapped into a dedicated region of the kernel
+//! address space, i.e., `[MMAP_VADDR_BASE, MMAP_VADDR_BASE + MMAP_SIZE)` in
+//! the platform configuration. So reads and writes through the mappings and
+//! through the opened files always see each other.
+//!
+//! The TLB is only flushed on the current CPU after a mapping is removed, so
+//! other CPUs could still access the pages after they are released to the page
+//! cache and reused. Mappings are not supported with the `smp` feature until
+//! the TLBs of all CPUs can be flushed.
+
+use alloc::{collections::BTreeMap, sync::Arc, vec::Vec};
+use axerrno::{ax_err, ax_err_type, AxError, AxResult};
+use axhal::mem::{virt_to_phys, PhysAddr, VirtAddr};
+use axhal::paging::{kernel_page_table, MappingFlags, PageSize, PageTable, PagingError};
+use axsync::Mutex;
+
+use crate::page_cache::{PageCache, PAGE_SIZE};
+
+struct Mapping {
+    cache: Arc<PageCache>,
+    first_page: u64,
+    num_pages: usize,
+    writable: bool,
+}
+
+/// Current mappings indexed by their start addresses.
+static MAPPINGS: Mutex<BTreeMap<usize, Mapping>> = Mutex::new(BTreeMap::new());
+
+/// Maps `len` bytes of the cached file from `offset`, returns the start
+/// address of the mapping.
+pub(crate) fn mmap(
+    cache: &Arc<PageCache>,
+    offset: u64,
+    len: usize,
+    writable: bool,
+) -> AxResult<usize> {
+    if cfg!(feature = "smp") {
+        return ax_err!(Unsupported, "mmap is not supported on multiple CPUs");
+    }
+    if len == 0 || offset % PAGE_SIZE as u64 != 0 {
+        return ax_err!(InvalidInput);
+    }
+    let first_page = offset / PAGE_SIZE as u64;
+    let num_pages = len.div_ceil(PAGE_SIZE);
+    if first_page + num_pages as u64 > cache.size()?.div_ceil(PAGE_SIZE as u64) {
+        return ax_err!(InvalidInput, "mapping beyond the end of file");
+    }
+
+    // load the pages first, as it may sleep for I/O
+    let mut paddrs = Vec::with_capacity(num_pages);
+    for i in 0..num_pages {
+        match cache.map_page(first_page + i as u64, writable) {
+            Ok(vaddr) => paddrs.push(virt_to_phys(vaddr.into())),
+            Err(e) => {
+                release_pages(cache, first_page, i, writable);
+                return Err(e);
+            }
+        }
+    }
+
+    let mut mappings = MAPPINGS.lock();
+    let res = find_free_area(&mappings, num_pages * PAGE_SIZE)
+        .ok_or_else(|| ax_err_type!(NoMemory, "no free address space to map"))
+        .and_then(|vaddr| {
+            map_pages(&mut kernel_page_table().lock(), vaddr, &paddrs, writable)?;
+            Ok(vaddr)
+        });
+    match res {
+        Ok(vaddr) => {
+            let mapping = Mapping {
+                cache: cache.clone(),
+                first_page,
+                num_pages,
+                writable,
+            };
+            mappings.insert(vaddr, mapping);
+            Ok(vaddr)
+        }
+        Err(e) => {
+            drop(mappings);
+            release_pages(cache, first_page, num_pages, writable);
+            Err(e)
+        }
+    }
+}
+
+/// Removes the mapping at `addr` created by [`File::mmap`], `len` must be
+/// the length of the whole mapping.
+///
+/// [`File::mmap`]: crate::fops::File::mmap
+pub fn munmap(addr: usize, len: usize) -> AxResult {
+    let mut mappings = MAPPINGS.lock();
+    if mappings.get(&addr).map(|m| m.num_pages) != Some(len.div_ceil(PAGE_SIZE)) {
+        return ax_err!(InvalidInput, "not a mapping");
+    }
+    let mapping = mappings.remove(&addr).unwrap();
+    unmap_pages(&mut kernel_page_table().lock(), addr, mapping.num_pages);
+    drop(mappings);
+    release_pages(
+        &mapping.cache,
+        mapping.first_page,
+        mapping.num_pages,
+        mapping.writable,
+    );
+    Ok(())
+}
+
+/// Finds a free range of `size` bytes in the region for mappings.
+fn find_free_area(mappings: &BTreeMap<usize, Mapping>, size: usize) -> Option<usize> {
+    let mut start = axconfig::MMAP_VADDR_BASE;
+    for (&addr, mapping) in mappings {
+        if addr - start >= size {
+            break;
+        }
+        start = addr + mapping.num_pages * PAGE_SIZE;
+    }
+    let end = axconfig::MMAP_VADDR_BASE + axconfig::MMAP_SIZE;
+    (end - start >= size).then_some(start)
+}
+
+fn map_pages(pt: &mut PageTable, vaddr: usize, paddrs: &[PhysAddr], writable: bool) -> AxResult {
+    let flags = if writable {
+        MappingFlags::READ | MappingFlags::WRITE
+    } else {
+        MappingFlags::READ
+    };
+    for (i, &paddr) in paddrs.iter().enumerate() {
+        let page_vaddr = VirtAddr::from(vaddr + i * PAGE_SIZE);
+        if let Err(e) = pt.map(page_vaddr, paddr, PageSize::Size4K, flags) {
+            unmap_pages(pt, vaddr, i);
+            return Err(paging_err_to_ax_err(e));
+        }
+        axhal::arch::flush_tlb(Some(page_vaddr));
+    }
+    Ok(())
+}
+
+fn unmap_pages(pt: &mut PageTable, vaddr: usize, num_pages: usize) {
+    for i in 0..num_pages {
+        let page_vaddr = VirtAddr::from(vaddr + i * PAGE_SIZE);
+        pt.unmap(page_vaddr).ok();
+        axhal::arch::flush_tlb(Some(page_vaddr));
+    }
+}
+
+/// Releases the pages pinned for a mapping.
+fn release_pages(cache: &PageCache, first_page: u64, num_pages: usize, writable: bool) {
+    for i in 0..num_pages {
+        cache.unmap_page(first_page + i as u64, writable);
+    }
+}
+
+fn paging_err_to_ax_err(err: PagingError) -> AxError {
+    warn!("failed to map a file: {:?}", err);
+    match err {
+        PagingError::NoMemory => AxError::NoMemory,
+        _ => AxError::BadState,
+    }
+}
@@ -0,0 +1,368 @@
+//! Page cache of regular files.
+//!
+//! The contents of files on block devices are cached in 4K pages, which are
+//! shared by all opened files and memory mappings of the same node. Writes
+//! only modify the cached pages, they are written back to the filesystem when
+//! the file is flushed or closed. When the free memory is low, the least
+//! recently used pages that are not mapped are evicted.
+
+use alloc::collections::{btree_map::Entry, BTreeMap};
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use axalloc::GlobalPage;
+use axerrno::{ax_err, ax_err_type};
+use axfs_vfs::{VfsNodeRef, VfsResult};
+use axsync::Mutex;
+use core::cmp::Ordering;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering::Relaxed};
+
+use crate::root::MountPoint;
+
+/// Size of the cached pages.
+pub(crate) const PAGE_SIZE: usize = 0x1000;
+
+/// Cached pages are evicted before new ones are allocated if there are fewer
+/// free pages than this.
+const LOW_WATERMARK: usize = 256; // 1M
+
+/// The number of pages evicted each time the free memory is low.
+const EVICT_BATCH: usize = 64;
+
+/// Identifies a node by the address of its mount point (0 for the root
+/// filesystem) and its inode number.
+type CacheKey = (usize, u64);
+
+/// Caches of the nodes in use.
+static CACHES: Mutex<BTreeMap<CacheKey, Weak<PageCache>>> = Mutex::new(BTreeMap::new());
+
+/// Logical clock to find the least recently used pages.
+static TICKS: AtomicU64 = AtomicU64::new(0);
+
+struct CachedPage {
+    page: GlobalPage,
+    dirty: bool,
+    /// Number of memory mappings of the page, it cannot be evicted while
+    /// mapped.
+    maps: usize,
+    /// Number of writable memory mappings, the page may be modified at any
+    /// time by them.
+    writable_maps: usize,
+    last_access: u64,
+}
+
+/// Cached pages of a regular file, indexed by the page number in the file.
+pub(crate) struct PageCache {
+    key: CacheKey,
+    node: VfsNodeRef,
+    pages: Mutex<BTreeMap<u64, CachedPage>>,
+    /// The node has been removed, its pages are no longer written back.
+    detached: AtomicBool,
+    /// Keeps the filesystem mounted while the cache is in use.
+    _mount: Option<Arc<MountPoint>>,
+}
+
+impl CachedPage {
+    fn new(page: GlobalPage) -> Self {
+        Self {
+            page,
+            dirty: false,
+            maps: 0,
+            writable_maps: 0,
+            last_access: TICKS.fetch_add(1, Relaxed),
+        }
+    }
+
+    const fn is_dirty(&self) -> bool {
+        self.dirty || self.writable_maps > 0
+    }
+}
+
+impl PageCache {
+    /// Returns the page cache of `node`, which is shared by all users of the
+    /// node, or `None` if the node is not cached.
+    ///
+    /// Only regular files with inode numbers in the root filesystem and the
+    /// partitions mounted from block devices are cached.
+    pub fn get(node: &VfsNodeRef, mount: Option<&Arc<MountPoint>>) -> VfsResult<Option<Arc<Self>>> {
+        let attr = node.get_attr()?;
+        if !attr.is_file() || attr.ino() == 0 || mount.is_some_and(|mp| !mp.is_page_cached()) {
+            return Ok(None);
+        }
+        let key = cache_key(mount, attr.ino());
+        let mut caches = CACHES.lock();
+        if let Some(cache) = caches.get(&key).and_then(Weak::upgrade) {
+            return Ok(Some(cache));
+        }
+        let cache = Arc::new(Self {
+            key,
+            node: node.clone(),
+            pages: Mutex::new(BTreeMap::new()),
+            detached: AtomicBool::new(false),
+            _mount: mount.cloned(),
+        });
+        caches.insert(key, Arc::downgrade(&cache));
+        Ok(Some(cache))
+    }
+
+    /// Drops the cache of a removed node, so that the node that reuses the
+    /// inode number does not see its pages. Dirty pages are discarded.
+    pub fn invalidate(mount: Option<&Arc<MountPoint>>, ino: u64) {
+        let cache = CACHES.lock().remove(&cache_key(mount, ino));
+        if let Some(cache) = cache.and_then(|c| c.upgrade()) {
+            cache.detached.store(true, Relaxed);
+            cache.pages.lock().retain(|_, page| page.maps > 0);
+        }
+    }
+
+    /// Returns the size of the file.
+    pub fn size(&self) -> VfsResult<u64> {
+        Ok(self.node.get_attr()?.size())
+    }
+
+    /// Reads the file at `offset` through the cache. Returns the number of
+    /// bytes read.
+    pub fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        let size = self.size()?;
+        let end = size.min(offset.saturating_add(buf.len() as u64));
+        let mut pos = offset;
+        while pos < end {
+            let (idx, start) = split_offset(pos);
+            let len = (PAGE_SIZE - start).min((end - pos) as usize);
+            let dst = &mut buf[(pos - offset) as usize..][..len];
+            self.with_page(idx, size, true, |page| {
+                dst.copy_from_slice(&page.page.as_slice()[start..start + len]);
+            })?;
+            pos += len as u64;
+        }
+        Ok(end.saturating_sub(offset) as usize)
+    }
+
+    /// Writes the file at `offset` through the cache, the file is extended if
+    /// the write goes beyond its end. Returns the number of bytes written.
+    pub fn write_at(&self, offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        let size = self.size()?;
+        let end = offset
+            .checked_add(buf.len() as u64)
+            .ok_or_else(|| ax_err_type!(InvalidInput))?;
+        if end > size {
+            self.node.truncate(end)?;
+        }
+        let mut pos = offset;
+        while pos < end {
+            let (idx, start) = split_offset(pos);
+            let len = (PAGE_SIZE - start).min((end - pos) as usize);
+            let src = &buf[(pos - offset) as usize..][..len];
+            // no need to read the pages to be overwritten entirely
+            let load = len < PAGE_SIZE;
+            self.with_page(idx, size, load, |page| {
+                page.page.as_slice_mut()[start..start + len].copy_from_slice(src);
+                page.dirty = true;
+            })?;
+            pos += len as u64;
+        }
+        Ok(buf.len())
+    }
+
+    /// Truncates the file to `size`, and drops the cached pages beyond it.
+    ///
+    /// Mapped pages are kept, but the part beyond the end of the file is
+    /// cleared.
+    pub fn truncate(&self, size: u64) -> VfsResult {
+        let mut pages = self.pages.lock();
+        self.node.truncate(size)?;
+        let (last_idx, last_len) = split_offset(size);
+        pages.retain(|&idx, page| {
+            let start = match idx.cmp(&last_idx) {
+                Ordering::Less => return true,
+                Ordering::Equal => last_len,
+                Ordering::Greater => 0,
+            };
+            if start == 0 && page.maps == 0 {
+                return false;
+            }
+            page.page.as_slice_mut()[start..].fill(0);
+            true
+        });
+        Ok(())
+    }
+
+    /// Writes the dirty pages back to the filesystem.
+    pub fn write_back(&self) -> VfsResult {
+        let mut pages = self.pages.lock();
+        if self.detached.load(Relaxed) {
+            return Ok(());
+        }
+        let size = self.size()?;
+        for (&idx, page) in pages.iter_mut() {
+            if page.is_dirty() {
+                self.write_page(idx, &page.page, size)?;
+                page.dirty = false;
+            }
+        }
+        Ok(())
+    }
+
+    /// Loads the page `idx` and keeps it in the cache until
+    /// [`unmap_page`](Self::unmap_page) is called. Returns the virtual address
+    /// of the page.
+    #[cfg(feature = "paging")]
+    pub fn map_page(&self, idx: u64, writable: bool) -> VfsResult<usize> {
+        self.with_page(idx, self.size()?, true, |page| {
+            page.maps += 1;
+            if writable {
+                page.writable_maps += 1;
+            }
+            page.page.start_vaddr().as_usize()
+        })
+    }
+
+    /// Releases the page `idx` pinned by [`map_page`](Self::map_page).
+    #[cfg(feature = "paging")]
+    pub fn unmap_page(&self, idx: u64, writable: bool) {
+        if let Some(page) = self.pages.lock().get_mut(&idx) {
+            page.maps -= 1;
+            if writable {
+                page.writable_maps -= 1;
+                page.dirty = true;
+            }
+        }
+    }
+
+    /// Calls `f` with the page `idx`, which is allocated if it is not cached,
+    /// and read from the file if `load` is true.
+    fn with_page<F, T>(&self, idx: u64, size: u64, load: bool, f: F) -> VfsResult<T>
+    where
+        F: FnOnce(&mut CachedPage) -> T,
+    {
+        if let Some(page) = self.pages.lock().get_mut(&idx) {
+            page.last_access = TICKS.fetch_add(1, Relaxed);
+            return Ok(f(page));
+        }
+        // allocate without the lock, as pages of this file may be evicted
+        let mut new_page = alloc_page()?;
+        let mut pages = self.pages.lock();
+        let page = match pages.entry(idx) {
+            Entry::Occupied(entry) => entry.into_mut(), // loaded by others
+            Entry::Vacant(entry) => {
+                if load {
+                    self.read_page(idx, &mut new_page, size)?;
+                }
+                entry.insert(CachedPage::new(new_page))
+            }
+        };
+        page.last_access = TICKS.fetch_add(1, Relaxed);
+        Ok(f(page))
+    }
+
+    fn read_page(&self, idx: u64, page: &mut GlobalPage, size: u64) -> VfsResult {
+        let pos = idx * PAGE_SIZE as u64;
+        let len = size.saturating_sub(pos).min(PAGE_SIZE as u64) as usize;
+        let buf = &mut page.as_slice_mut()[..len];
+        let mut read_len = 0;
+        while read_len < len {
+            let off = pos + read_len as u64;
+            match self.node.read_at(off, &mut buf[read_len..])? {
+                0 => break, // the rest is zero
+                n => read_len += n,
+            }
+        }
+        Ok(())
+    }
+
+    fn write_page(&self, idx: u64, page: &GlobalPage, size: u64) -> VfsResult {
+        let pos = idx * PAGE_SIZE as u64;
+        let len = size.saturating_sub(pos).min(PAGE_SIZE as u64) as usize;
+        let buf = &page.as_slice()[..len];
+        let mut write_len = 0;
+        while write_len < len {
+            let off = pos + write_len as u64;
+            match self.node.write_at(off, &buf[write_len..])? {
+                0 => return ax_err!(StorageFull),
+                n => write_len += n,
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes back and drops the page `idx` if it is not in use.
+    fn evict(&self, idx: u64) {
+        let Some(mut pages) = self.pages.try_lock() else {
+            return;
+        };
+        let Some(page) = pages.get(&idx).filter(|page| page.maps == 0) else {
+            return;
+        };
+        if page.dirty && !self.detached.load(Relaxed) {
+            let res = self
+                .size()
+                .and_then(|size| self.write_page(idx, &page.page, size));
+            if let Err(e) = res {
+                warn!("failed to write back page {} of a file: {:?}", idx, e);
+                return;
+            }
+        }
+        pages.remove(&idx);
+    }
+}
+
+impl Drop for PageCache {
+    fn drop(&mut self) {
+        if let Err(e) = self.write_back() {
+            warn!("failed to write back cached pages: {:?}", e);
+        }
+        let mut caches = CACHES.lock();
+        // the cache of a removed node may have been replaced
+        if caches
+            .get(&self.key)
+            .is_some_and(|cache| core::ptr::eq(cache.as_ptr(), self))
+        {
+            caches.remove(&self.key);
+        }
+    }
+}
+
+fn cache_key(mount: Option<&Arc<MountPoint>>, ino: u64) -> CacheKey {
+    (mount.map_or(0, |mp| Arc::as_ptr(mp) as usize), ino)
+}
+
+/// Splits the file offset into the page number and the offset in the page.
+const fn split_offset(pos: u64) -> (u64, usize) {
+    (pos / PAGE_SIZE as u64, (pos % PAGE_SIZE as u64) as usize)
+}
+
+/// Allocates a page for the cache, cached pages are evicted first if the free
+/// memory is low.
+fn alloc_page() -> VfsResult<GlobalPage> {
+    if axalloc::global_allocator().available_pages() < LOW_WATERMARK {
+        shrink(EVICT_BATCH);
+    }
+    GlobalPage::alloc_zero().or_else(|_| {
+        shrink(EVICT_BATCH);
+        GlobalPage::alloc_zero()
+    })
+}
+
+/// Evicts at most `count` pages that are not mapped from all caches, the least
+/// recently used first. Caches in use by others are skipped.
+fn shrink(count: usize) {
+    let caches = CACHES
+        .lock()
+        .values()
+        .filter_map(Weak::upgrade)
+        .collect::<Vec<_>>();
+    let mut victims = Vec::new();
+    for (i, cache) in caches.iter().enumerate() {
+        if let Some(pages) = cache.pages.try_lock() {
+            victims.extend(
+                pages
+                    .iter()
+                    .filter(|(_, page)| page.maps == 0)
+                    .map(|(&idx, page)| (page.last_access, i, idx)),
+            );
+        }
+    }
+    victims.sort_unstable();
+    for &(_, i, idx) in victims.iter().take(count) {
+        caches[i].evict(idx);
+    }
+}
@@ -0,0 +1,383 @@
+//! Partition tables on block devices.
+//!
+//! Both the [MBR] (with logical partitions in extended partitions) and the
+//! [GPT] partition tables are supported. Each partition is wrapped as a
+//! [`Partition`], a block device that only covers the blocks of it. A device
+//! without a partition table is used as a whole.
+//!
+//! [MBR]: https://en.wikipedia.org/wiki/Master_boot_record
+//! [GPT]: https://en.wikipedia.org/wiki/GUID_Partition_Table
+
+use alloc::{format, string::String, sync::Arc, vec, vec::Vec};
+use axdriver::prelude::*;
+use axsync::Mutex;
+
+/// The maximum number of logical partitions in an extended partition, to stop
+/// at loops in the EBR chain.
+const MAX_LOGICAL_PARTITIONS: usize = 64;
+
+/// A range of contiguous blocks on a block device, which can be shared by
+/// other partitions.
+#[derive(Clone)]
+pub struct Partition {
+    dev: Arc<Mutex<AxBlockDevice>>,
+    name: String,
+    block_size: usize,
+    start_block: u64,
+    num_blocks: u64,
+}
+
+impl Partition {
+    /// Returns the partition covering the whole device.
+    pub fn new(dev: AxBlockDevice, name: String) -> Self {
+        let (block_size, num_blocks) = (dev.block_size(), dev.num_blocks());
+        Self {
+            dev: Arc::new(Mutex::new(dev)),
+            name,
+            block_size,
+            start_block: 0,
+            num_blocks,
+        }
+    }
+
+    /// Returns the device, if there is no other partition on it.
+    pub fn into_device(self) -> Option<AxBlockDevice> {
+        Arc::try_unwrap(self.dev).ok().map(Mutex::into_inner)
+    }
+
+    /// Returns the partition of `num_blocks` blocks from `start_block` on the
+    /// same device, or `None` if it is out of the range of this partition.
+    fn slice(&self, name: String, start_block: u64, num_blocks: u64) -> Option<Self> {
+        if num_blocks == 0 || start_block.checked_add(num_blocks)? > self.num_blocks {
+            return None;
+        }
+        Some(Self {
+            dev: self.dev.clone(),
+            name,
+            block_size: self.block_size,
+            start_block: self.start_block + start_block,
+            num_blocks,
+        })
+    }
+
+    /// Checks that `len` bytes from the block are in the partition.
+    fn check_range(&self, block_id: u64, len: usize) -> DevResult {
+        let count = len.div_ceil(self.block_size) as u64;
+        match block_id.checked_add(count) {
+            Some(end) if end <= self.num_blocks => Ok(()),
+            _ => Err(DevError::InvalidParam),
+        }
+    }
+}
+
+impl BaseDriverOps for Partition {
+    fn device_name(&self) -> &str {
+        &self.name
+    }
+
+    fn device_type(&self) -> DeviceType {
+        DeviceType::Block
+    }
+}
+
+impl BlockDriverOps for Partition {
+    fn num_blocks(&self) -> u64 {
+        self.num_blocks
+    }
+
+    fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    fn read_block(&mut self, block_id: u64, buf: &mut [u8]) -> DevResult {
+        self.check_range(block_id, buf.len())?;
+        self.dev.lock().read_block(self.start_block + block_id, buf)
+    }
+
+    fn write_block(&mut self, block_id: u64, buf: &[u8]) -> DevResult {
+        self.check_range(block_id, buf.len())?;
+        self.dev
+            .lock()
+            .write_block(self.start_block + block_id, buf)
+    }
+
+    fn flush(&mut self) -> DevResult {
+        self.dev.lock().flush()
+    }
+}
+
+/// Scans the partition table on the whole device `disk`, returns all
+/// partitions on it.
+///
+/// The partitions are named as the name of `disk` followed by the partition
+/// number (with a `p` in between if the name ends with a digit), which starts
+/// from 1 in the order of the table, and logical partitions of MBR start from
+/// 5. If there is no partition table, `disk` itself is returned.
+pub fn scan_partitions(disk: &Partition) -> Vec<Partition> {
+    let mut disk = disk.clone();
+    let name = disk.name.clone();
+    let parts = match read_mbr(&mut disk) {
+        Ok(Some(entries)) if entries.iter().any(|e| e.ty == MbrEntry::TYPE_GPT) => {
+            match read_gpt(&mut disk) {
+                Ok(Some(parts)) => parts,
+                Ok(None) => {
+                    warn!("{}: invalid GPT, use the whole device", name);
+                    Vec::new()
+                }
+                Err(e) => {
+                    warn!("{}: failed to read GPT: {:?}", name, e);
+                    Vec::new()
+                }
+            }
+        }
+        Ok(Some(entries)) => mbr_partitions(&mut disk, &entries),
+        Ok(None) => Vec::new(),
+        Err(e) => {
+            warn!("{}: failed to read MBR: {:?}", name, e);
+            Vec::new()
+        }
+    };
+
+    let sep = if name.ends_with(|c: char| c.is_ascii_digit()) {
+        "p"
+    } else {
+        ""
+    };
+    let mut parts = parts
+        .into_iter()
+        .filter_map(|(num, start, count)| {
+            let part = disk.slice(format!("{}{}{}", name, sep, num), start, count);
+            if part.is_none() {
+                warn!("{}: partition {} is out of the device", name, num);
+            }
+            part
+        })
+        .collect::<Vec<_>>();
+    if parts.is_empty() {
+        parts.push(disk);
+    } else {
+        for part in &parts {
+            debug!(
+                "{}: blocks {:#x}..{:#x}",
+                part.name,
+                part.start_block,
+                part.start_block + part.num_blocks
+            );
+        }
+    }
+    parts
+}
+
+/// A partition entry in the MBR or an EBR.
+#[derive(Debug, Clone, Copy)]
+struct MbrEntry {
+    status: u8,
+    ty: u8,
+    start_lba: u32,
+    num_sectors: u32,
+}
+
+impl MbrEntry {
+    const OFFSET: usize = 446;
+    const SIZE: usize = 16;
+    const TYPE_EMPTY: u8 = 0x00;
+    const TYPE_GPT: u8 = 0xee;
+    const TYPES_EXTENDED: [u8; 3] = [0x05, 0x0f, 0x85];
+
+    fn new_from_buf(buf: &[u8]) -> Self {
+        Self {
+            status: buf[0],
+            ty: buf[4],
+            start_lba: read_u32(buf, 8),
+            num_sectors: read_u32(buf, 12),
+        }
+    }
+
+    fn is_used(&self) -> bool {
+        self.ty != Self::TYPE_EMPTY && self.num_sectors != 0
+    }
+
+    fn is_extended(&self) -> bool {
+        Self::TYPES_EXTENDED.contains(&self.ty)
+    }
+}
+
+/// Reads the 4 entries of the MBR or an EBR at the block, returns `None` if
+/// it is not a valid one.
+fn read_mbr_at(disk: &mut Partition, lba: u64) -> DevResult<Option<[MbrEntry; 4]>> {
+    let mut buf = vec![0; disk.block_size];
+    disk.read_block(lba, &mut buf)?;
+    if buf[510..512] != [0x55, 0xaa] || is_fat_boot_sector(&buf) {
+        return Ok(None);
+    }
+    let entries = core::array::from_fn(|i| {
+        let off = MbrEntry::OFFSET + i * MbrEntry::SIZE;
+        MbrEntry::new_from_buf(&buf[off..off + MbrEntry::SIZE])
+    });
+    Ok(Some(entries))
+}
+
+/// Reads the MBR, returns `None` if there is no partition in it.
+fn read_mbr(disk: &mut Partition) -> DevResult<Option<[MbrEntry; 4]>> {
+    if disk.num_blocks == 0 {
+        return Ok(None);
+    }
+    Ok(read_mbr_at(disk, 0)?.filter(|entries| {
+        // the boot code of a volume boot record may look like an MBR
+        entries.iter().all(|e| e.status & 0x7f == 0) && entries.iter().any(|e| e.is_used())
+    }))
+}
+
+/// Returns the partition number, first block, and number of blocks of the
+/// primary and logical partitions in the MBR.
+fn mbr_partitions(disk: &mut Partition, entries: &[MbrEntry; 4]) -> Vec<(u32, u64, u64)> {
+    let mut parts = Vec::new();
+    let mut logical_num = 5;
+    for (i, e) in entries.iter().enumerate() {
+        if !e.is_used() {
+            continue;
+        } else if !e.is_extended() {
+            parts.push((i as u32 + 1, e.start_lba as u64, e.num_sectors as u64));
+            continue;
+        }
+
+        // follow the EBR chain, the first entry of an EBR is a logical
+        // partition relative to the EBR, the second one is the next EBR
+        // relative to the extended partition
+        let ext_start = e.start_lba as u64;
+        let mut ebr_lba = ext_start;
+        for _ in 0..MAX_LOGICAL_PARTITIONS {
+            let ebr = match read_mbr_at(disk, ebr_lba) {
+                Ok(Some(ebr)) => ebr,
+                Ok(None) => {
+                    warn!("{}: invalid EBR at block {:#x}", disk.name, ebr_lba);
+                    break;
+                }
+                Err(err) => {
+                    warn!("{}: failed to read EBR: {:?}", disk.name, err);
+                    break;
+                }
+            };
+            if ebr[0].is_used() {
+                let start = ebr_lba + ebr[0].start_lba as u64;
+                parts.push((logical_num, start, ebr[0].num_sectors as u64));
+                logical_num += 1;
+            }
+            if !ebr[1].is_used() || !ebr[1].is_extended() {
+                break;
+            }
+            ebr_lba = ext_start + ebr[1].start_lba as u64;
+        }
+    }
+    parts
+}
+
+/// The header of GPT, at the second block of the device.
+struct GptHeader {
+    entries_lba: u64,
+    num_entries: u32,
+    entry_size: u32,
+    entries_crc32: u32,
+}
+
+impl GptHeader {
+    const LBA: u64 = 1;
+    const SIGNATURE: &'static [u8] = b"EFI PART";
+    const MIN_SIZE: usize = 92;
+
+    /// Parses the header, returns `None` if the signature or checksum does
+    /// not match.
+    fn new_from_buf(buf: &[u8]) -> Option<Self> {
+        if &buf[0..8] != Self::SIGNATURE {
+            return None;
+        }
+        let header_size = read_u32(buf, 12) as usize;
+        if !(Self::MIN_SIZE..=buf.len()).contains(&header_size) {
+            return None;
+        }
+        let mut header = buf[..header_size].to_vec();
+        header[16..20].fill(0); // the checksum is calculated with itself zeroed
+        if crc32(&header) != read_u32(buf, 16) {
+            return None;
+        }
+        Some(Self {
+            entries_lba: read_u64(buf, 72),
+            num_entries: read_u32(buf, 80),
+            entry_size: read_u32(buf, 84),
+            entries_crc32: read_u32(buf, 88),
+        })
+    }
+}
+
+/// Reads the GPT, returns `None` if it is invalid.
+fn read_gpt(disk: &mut Partition) -> DevResult<Option<Vec<(u32, u64, u64)>>> {
+    let bs = disk.block_size;
+    let mut buf = vec![0; bs];
+    disk.read_block(GptHeader::LBA, &mut buf)?;
+    let Some(header) = GptHeader::new_from_buf(&buf) else {
+        return Ok(None);
+    };
+    let entry_size = header.entry_size as usize;
+    if entry_size < 128 || !entry_size.is_power_of_two() {
+        return Ok(None);
+    }
+    let table_size = header.num_entries as usize * entry_size;
+    if disk.check_range(header.entries_lba, table_size).is_err() {
+        return Ok(None);
+    }
+
+    let mut buf = vec![0; table_size.div_ceil(bs) * bs];
+    disk.read_block(header.entries_lba, &mut buf)?;
+    if crc32(&buf[..table_size]) != header.entries_crc32 {
+        return Ok(None);
+    }
+    let parts = buf[..table_size]
+        .chunks_exact(entry_size)
+        .enumerate()
+        // an entry of the zero type GUID is unused
+        .filter(|(_, entry)| entry[..16].iter().any(|&b| b != 0))
+        .filter_map(|(i, entry)| {
+            let first_lba = read_u64(entry, 32);
+            let last_lba = read_u64(entry, 40);
+            let count = last_lba.checked_sub(first_lba)? + 1;
+            Some((i as u32 + 1, first_lba, count))
+        })
+        .collect();
+    Ok(Some(parts))
+}
+
+/// Whether the block is the boot sector of a FAT volume, which also ends with
+/// the signature `0x55 0xAA` like an MBR.
+pub(crate) fn is_fat_boot_sector(buf: &[u8]) -> bool {
+    let bytes_per_sector = u16::from_le_bytes([buf[11], buf[12]]);
+    let sectors_per_cluster = buf[13];
+    let reserved_sectors = u16::from_le_bytes([buf[14], buf[15]]);
+    let num_fats = buf[16];
+    buf[510..512] == [0x55, 0xaa]
+        && (buf[0] == 0xeb || buf[0] == 0xe9)
+        && (512..=4096).contains(&bytes_per_sector)
+        && bytes_per_sector.is_power_of_two()
+        && sectors_per_cluster.is_power_of_two()
+        && reserved_sectors != 0
+        && num_fats != 0
+}
+
+fn read_u32(buf: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(buf[offset..offset + 4].try_into().unwrap())
+}
+
+fn read_u64(buf: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(buf[offset..offset + 8].try_into().unwrap())
+}
+
+/// The CRC-32 (IEEE 802.3) checksum used by GPT.
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &b in data {
+        crc ^= b as u32;
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xedb8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
@@ -1,106 +1,306 @@
 //! Root directory of the filesystem
-//!
-//! TODO: it doesn't work very well if the mount points have containment relationships.
 
-use alloc::{string::String, sync::Arc, vec::Vec};
-use axerrno::{ax_err, AxError, AxResult};
+use alloc::{format, string::String, sync::Arc, vec::Vec};
+use axdriver::prelude::*;
+use axerrno::{ax_err, ax_err_type, AxError, AxResult};
+use axfs_vfs::{FileSystemInfo, VfsSetAttr};
 use axfs_vfs::{VfsNodeAttr, VfsNodeOps, VfsNodeRef, VfsNodeType, VfsOps, VfsResult};
 use axsync::Mutex;
 use lazy_init::LazyInit;
 
-use crate::{api::FileType, fs};
+use crate::partition::Partition;
+use crate::{api::FileType, check::Report, dev::Disk, fs, page_cache::PageCache};
+
+/// The maximum number of symbolic links followed in one path resolution.
+const MAX_SYMLINKS: usize = 40;
+
+/// The current directory before the scheduler is initialized, or of the only
+/// task if multitasking is disabled.
+static INIT_CURRENT_DIR: Mutex<CurrentDir> = Mutex::new(CurrentDir::root());
+
+/// The current directory of a task, which is the root directory by default.
+///
+/// It is stored in each task if multitasking is enabled, and copied to the
+/// tasks it spawns.
+#[derive(Clone, Default)]
+struct CurrentDir {
+    /// The absolute and canonical path ending with `/`, or empty for the root
+    /// directory.
+    path: String,
+    /// The directory node, or `None` for the root directory.
+    node: Option<VfsNodeRef>,
+}
+
+impl CurrentDir {
+    const fn root() -> Self {
+        Self {
+            path: String::new(),
+            node: None,
+        }
+    }
+
+    fn path(&self) -> &str {
+        if self.path.is_empty() {
+            "/"
+        } else {
+            &self.path
+        }
+    }
+}
 
-static CURRENT_DIR_PATH: Mutex<String> = Mutex::new(String::new());
-static CURRENT_DIR: LazyInit<Mutex<VfsNodeRef>> = LazyInit::new();
+/// Calls `f` with the current directory of the current task.
+fn with_current_dir<R>(f: impl FnOnce(&mut CurrentDir) -> R) -> R {
+    #[cfg(feature = "multitask")]
+    if let Some(curr) = axtask::current_may_uninit() {
+        return curr.with_local(f);
+    }
+    f(&mut INIT_CURRENT_DIR.lock())
+}
 
-struct MountPoint {
-    path: &'static str,
+/// Calls `f` with the current directories of all tasks that have not exited.
+fn for_each_current_dir(mut f: impl FnMut(&mut CurrentDir)) {
+    #[cfg(feature = "multitask")]
+    for task in axtask::all_tasks() {
+        if task.state() != axtask::TaskState::Exited {
+            task.with_local(&mut f);
+        }
+    }
+    f(&mut INIT_CURRENT_DIR.lock());
+}
+
+/// A filesystem mounted on a directory.
+///
+/// Opened files and directories hold a reference to the mount point they
+/// belong to, so that it cannot be unmounted while they are in use.
+pub(crate) struct MountPoint {
+    path: String,
+    /// The device or the kind of the filesystem, shown in `/proc/mounts`.
+    source: String,
     fs: Arc<dyn VfsOps>,
+    /// Whether the files are cached in the page cache, which is only enabled
+    /// for filesystems on block devices.
+    page_cached: bool,
 }
 
 struct RootDirectory {
     main_fs: Arc<dyn VfsOps>,
-    mounts: Vec<MountPoint>,
+    main_source: String,
+    mounts: Mutex<Vec<Arc<MountPoint>>>,
 }
 
 static ROOT_DIR: LazyInit<Arc<RootDirectory>> = LazyInit::new();
 
+/// All partitions found on the block devices, including the ones without a
+/// supported filesystem, which can be formatted.
+static PARTITIONS: LazyInit<Vec<Partition>> = LazyInit::new();
+
+#[cfg(feature = "devfs")]
+pub(crate) static DEV_FS: LazyInit<Arc<fs::devfs::DeviceFileSystem>> = LazyInit::new();
+
+#[cfg(feature = "procfs")]
+pub(crate) static PROC_FS: LazyInit<Arc<fs::procfs::ProcFileSystem>> = LazyInit::new();
+
 impl MountPoint {
-    pub fn new(path: &'static str, fs: Arc<dyn VfsOps>) -> Self {
-        Self { path, fs }
+    pub fn new(path: String, source: String, fs: Arc<dyn VfsOps>, page_cached: bool) -> Self {
+        Self {
+            path,
+            source,
+            fs,
+            page_cached,
+        }
     }
-}
 
-impl Drop for MountPoint {
-    fn drop(&mut self) {
-        self.fs.umount().ok();
+    /// Whether the files in the filesystem are cached in the page cache.
+    pub const fn is_page_cached(&self) -> bool {
+        self.page_cached
+    }
+
+    /// Whether the absolute `path` is the mount point or under it.
+    fn contains(&self, path: &str) -> bool {
+        is_prefix(&self.path, path)
     }
 }
 
 impl RootDirectory {
-    pub const fn new(main_fs: Arc<dyn VfsOps>) -> Self {
+    pub const fn new(main_source: String, main_fs: Arc<dyn VfsOps>) -> Self {
         Self {
             main_fs,
-            mounts: Vec::new(),
+            main_source,
+            mounts: Mutex::new(Vec::new()),
         }
     }
 
-    pub fn mount(&mut self, path: &'static str, fs: Arc<dyn VfsOps>) -> AxResult {
+    /// Mounts `fs` on the absolute and canonical `path`. `source` names the
+    /// device or the kind of the filesystem, and `page_cached` is true if it is
+    /// on a block device.
+    pub fn mount(
+        &self,
+        path: String,
+        source: &str,
+        fs: Arc<dyn VfsOps>,
+        page_cached: bool,
+    ) -> AxResult {
         if path == "/" {
             return ax_err!(InvalidInput, "cannot mount root filesystem");
         }
         if !path.starts_with('/') {
             return ax_err!(InvalidInput, "mount path must start with '/'");
         }
-        if self.mounts.iter().any(|mp| mp.path == path) {
+        let mut mounts = self.mounts.lock();
+        if mounts.iter().any(|mp| mp.path == path) {
             return ax_err!(InvalidInput, "mount point already exists");
         }
-        // create the mount point in the main filesystem if it does not exist
-        self.main_fs.root_dir().create(path, FileType::Dir)?;
-        fs.mount(path, self.main_fs.root_dir().lookup(path)?)?;
-        self.mounts.push(MountPoint::new(path, fs));
+        // create the mount point in the parent filesystem if it does not exist
+        let (parent_fs, rest_path) = Self::find_fs(&self.main_fs, &mounts, &path);
+        let parent_root = parent_fs.root_dir();
+        let mount_point = match parent_root.clone().lookup(rest_path) {
+            Err(AxError::NotFound) => {
+                parent_root.create(rest_path, FileType::Dir)?;
+                parent_root.lookup(rest_path)?
+            }
+            res => res?,
+        };
+        if !mount_point.get_attr()?.is_dir() {
+            return ax_err!(NotADirectory, "mount point is not a directory");
+        }
+        fs.mount(&path, mount_point)?;
+        let mount_point = MountPoint::new(path, source.into(), fs, page_cached);
+        mounts.push(Arc::new(mount_point));
         Ok(())
     }
 
-    pub fn _umount(&mut self, path: &str) {
-        self.mounts.retain(|mp| mp.path != path);
+    /// Mounts `fs` on the partition `name` at `/mnt/<name>`, `/mnt` is created
+    /// if it does not exist.
+    fn mount_partition(&self, name: &str, fs: Arc<dyn VfsOps>) -> AxResult {
+        let root = self.main_fs.root_dir();
+        if let Err(AxError::NotFound) = root.clone().lookup("mnt") {
+            root.create("mnt", VfsNodeType::Dir)?;
+        }
+        let path = format!("/mnt/{}", name);
+        info!("  mount {} on {}", name, path);
+        self.mount(path, name, fs, true)
     }
 
-    pub fn contains(&self, path: &str) -> bool {
-        self.mounts.iter().any(|mp| mp.path == path)
+    /// Unmounts the filesystem on the absolute and canonical `path`.
+    pub fn umount(&self, path: &str) -> AxResult {
+        let mut mounts = self.mounts.lock();
+        let idx = mounts
+            .iter()
+            .position(|mp| mp.path == path)
+            .ok_or_else(|| ax_err_type!(InvalidInput, "not a mount point"))?;
+        // the mount point is referenced by opened files, other filesystems
+        // are mounted under it, or it is the current directory of some task
+        let mut in_use = false;
+        for_each_current_dir(|cwd| in_use |= is_prefix(path, cwd.path().trim_end_matches('/')));
+        if Arc::strong_count(&mounts[idx]) > 1
+            || mounts
+                .iter()
+                .any(|mp| mp.path != path && is_prefix(path, &mp.path))
+            || in_use
+        {
+            return ax_err!(ResourceBusy, "mount point is busy");
+        }
+        mounts[idx].fs.umount()?;
+        mounts.remove(idx);
+        Ok(())
     }
 
-    fn lookup_mounted_fs<F, T>(&self, path: &str, f: F) -> AxResult<T>
-    where
-        F: FnOnce(Arc<dyn VfsOps>, &str) -> AxResult<T>,
-    {
-        debug!("lookup at root: {}", path);
-        let path = path.trim_matches('/');
-        if let Some(rest) = path.strip_prefix("./") {
-            return self.lookup_mounted_fs(rest, f);
+    /// Unmounts all filesystems in the reverse order they were mounted.
+    pub fn umount_all(&self) {
+        for mp in self.mounts.lock().drain(..).rev() {
+            if let Err(e) = mp.fs.umount() {
+                warn!("failed to unmount {}: {:?}", mp.path, e);
+            }
         }
+        if let Err(e) = self.main_fs.umount() {
+            warn!("failed to unmount the root filesystem: {:?}", e);
+        }
+    }
 
-        let mut idx = 0;
-        let mut max_len = 0;
+    /// Renames `src_path` to `dst_path`, both are absolute and canonical.
+    pub fn rename(&self, src_path: &str, dst_path: &str) -> AxResult {
+        let mounts = self.mounts.lock();
+        if src_path == "/"
+            || dst_path == "/"
+            || mounts
+                .iter()
+                .any(|mp| mp.path == src_path || mp.path == dst_path)
+        {
+            return ax_err!(ResourceBusy, "cannot rename mount points");
+        }
+        let (src_fs, src_rest) = Self::find_fs(&self.main_fs, &mounts, src_path);
+        let (dst_fs, dst_rest) = Self::find_fs(&self.main_fs, &mounts, dst_path);
+        drop(mounts);
+        if !Arc::ptr_eq(&src_fs, &dst_fs) {
+            return ax_err!(CrossesDevices, "cannot rename across filesystems");
+        }
+        src_fs.root_dir().rename(src_rest, dst_rest)
+    }
+
+    pub fn contains(&self, path: &str) -> bool {
+        self.mounts.lock().iter().any(|mp| mp.path == path)
+    }
 
-        // Find the filesystem that has the longest mounted path match
+    /// Returns the mount point that the absolute `path` belongs to, or `None`
+    /// if it is in the main filesystem.
+    pub fn mount_point_of(&self, path: &str) -> Option<Arc<MountPoint>> {
+        self.mounts
+            .lock()
+            .iter()
+            .filter(|mp| mp.contains(path))
+            .max_by_key(|mp| mp.path.len())
+            .cloned()
+    }
+
+    /// Returns the filesystem that the absolute `path` belongs to.
+    pub fn fs_of(&self, path: &str) -> Arc<dyn VfsOps> {
+        self.mount_point_of(path)
+            .map_or_else(|| self.main_fs.clone(), |mp| mp.fs.clone())
+    }
+
+    /// Finds the filesystem that has the longest mounted path match, returns
+    /// it along with the rest of the path in it.
+    fn find_fs<'a>(
+        main_fs: &Arc<dyn VfsOps>,
+        mounts: &[Arc<MountPoint>],
+        path: &'a str,
+    ) -> (Arc<dyn VfsOps>, &'a str) {
+        let path = path.trim_matches('/');
         // TODO: more efficient, e.g. trie
-        for (i, mp) in self.mounts.iter().enumerate() {
+        mounts
+            .iter()
             // skip the first '/'
-            if path.starts_with(&mp.path[1..]) && mp.path.len() - 1 > max_len {
-                max_len = mp.path.len() - 1;
-                idx = i;
-            }
-        }
+            .filter(|mp| is_prefix(&mp.path[1..], path))
+            .max_by_key(|mp| mp.path.len())
+            .map_or((main_fs.clone(), path), |mp| {
+                (mp.fs.clone(), &path[mp.path.len() - 1..])
+            })
+    }
 
-        if max_len == 0 {
-            f(self.main_fs.clone(), path) // not matched any mount point
-        } else {
-            f(self.mounts[idx].fs.clone(), &path[max_len..]) // matched at `idx`
-        }
+    fn lookup_mounted_fs<F, T>(&self, path: &str, f: F) -> AxResult<T>
+    where
+        F: FnOnce(Arc<dyn VfsOps>, &str) -> AxResult<T>,
+    {
+        debug!("lookup at root: {}", path);
+        // remove empty and `.` components, so that the path can be matched
+        // with mount points, `..` is left to the filesystems
+        let path = path
+            .split('/')
+            .filter(|s| !s.is_empty() && *s != ".")
+            .collect::<Vec<_>>()
+            .join("/");
+        let (fs, rest_path) = Self::find_fs(&self.main_fs, &self.mounts.lock(), &path);
+        f(fs, rest_path)
     }
 }
 
+/// Whether `path` is `prefix` or a path under it, both paths are in the same
+/// form (absolute or not) without trailing slashes.
+fn is_prefix(prefix: &str, path: &str) -> bool {
+    path.strip_prefix(prefix)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
 impl VfsNodeOps for RootDirectory {
     axfs_vfs::impl_vfs_dir_default! {}
 
@@ -108,6 +308,10 @@ impl VfsNodeOps for RootDirectory {
         self.main_fs.root_dir().get_attr()
     }
 
+    fn set_attr(&self, attr: &VfsSetAttr) -> VfsResult {
+        self.main_fs.root_dir().set_attr(attr)
+    }
+
     fn lookup(self: Arc<Self>, path: &str) -> VfsResult<VfsNodeRef> {
         self.lookup_mounted_fs(path, |fs, rest_path| fs.root_dir().lookup(rest_path))
     }
@@ -131,63 +335,219 @@ impl VfsNodeOps for RootDirectory {
             }
         })
     }
+
+    fn symlink(&self, path: &str, target: &str) -> VfsResult {
+        self.lookup_mounted_fs(path, |fs, rest_path| {
+            if rest_path.is_empty() {
+                ax_err!(AlreadyExists)
+            } else {
+                fs.root_dir().symlink(rest_path, target)
+            }
+        })
+    }
+
+    fn link(&self, path: &str, node: &VfsNodeRef) -> VfsResult {
+        self.lookup_mounted_fs(path, |fs, rest_path| {
+            if rest_path.is_empty() {
+                ax_err!(AlreadyExists)
+            } else {
+                fs.root_dir().link(rest_path, node)
+            }
+        })
+    }
 }
 
-pub(crate) fn init_rootfs(disk: crate::dev::Disk) {
+pub(crate) fn init_rootfs(parts: Vec<Partition>) {
+    PARTITIONS.init_by(parts.clone());
+    let mut parts = parts.into_iter();
     cfg_if::cfg_if! {
         if #[cfg(feature = "myfs")] { // override the default filesystem
-            let main_fs = fs::myfs::new_myfs(disk);
-        //} else if #[cfg(feature = "fatfs")] {
-            // static FAT_FS: LazyInit<Arc<fs::fatfs::FatFileSystem>> = LazyInit::new();
-            // FAT_FS.init_by(Arc::new(fs::fatfs::FatFileSystem::new(disk)));
-            // FAT_FS.init();
-            // let main_fs = FAT_FS.clone();
-        } else if #[cfg(feature = "ext2")] {
-            debug!("szx 1");
-            static EXT2_FS: LazyInit<Arc<fs::ext2::Ext2FileSystem>> = LazyInit::new();
-            debug!("szx 2");
-            EXT2_FS.init_by(Arc::new(fs::ext2::Ext2FileSystem::new(disk).unwrap()));
-            debug!("ext2 superblock: \n{:?}", EXT2_FS.superblock);
-            let main_fs = EXT2_FS.clone();
+            let part = parts.next().expect("No block device found!");
+            let name = String::from(part.device_name());
+            let main_fs = fs::myfs::new_myfs(Disk::new(part));
+        } else {
+            let main = parts.by_ref().find_map(load_fs);
+            // boot from the initramfs if there are no disks
+            #[cfg(feature = "initramfs")]
+            let main = main.or_else(|| Some((String::from("initramfs"), new_initramfs(true))));
+            let (name, main_fs) = main.expect("No filesystem found!");
+            info!("  mount {} on /", name);
         }
     }
 
-    let mut root_dir = RootDirectory::new(main_fs);
+    // writes to the root filesystem go to memory, and never reach the disk
+    #[cfg(feature = "overlayfs")]
+    let main_fs = Arc::new(fs::overlayfs::OverlayFileSystem::with_lock::<OverlayLock>(
+        Arc::new(new_ramfs()),
+        main_fs,
+    ));
+
+    let root_dir = RootDirectory::new(name, main_fs);
 
     #[cfg(feature = "devfs")]
     {
-        let null = fs::devfs::NullDev;
-        let zero = fs::devfs::ZeroDev;
-        let bar = fs::devfs::ZeroDev;
-        let devfs = fs::devfs::DeviceFileSystem::new();
-        let foo_dir = devfs.mkdir("foo");
-        devfs.add("null", Arc::new(null));
-        devfs.add("zero", Arc::new(zero));
-        foo_dir.add("bar", Arc::new(bar));
-
+        let devfs = Arc::new(fs::devfs::DeviceFileSystem::new());
+        devfs.add("null", Arc::new(fs::devfs::NullDev));
+        devfs.add("zero", Arc::new(fs::devfs::ZeroDev));
+        let seed = axfs_vfs::time::current_time().as_nanos() as u64;
+        let random = Arc::new(fs::devfs::RandomDev::new(seed));
+        devfs.add("random", random.clone());
+        devfs.add("urandom", random);
         root_dir
-            .mount("/dev", Arc::new(devfs))
+            .mount("/dev".into(), "devfs", devfs.clone(), false)
             .expect("failed to mount devfs at /dev");
+        DEV_FS.init_by(devfs);
     }
 
     #[cfg(feature = "ramfs")]
+    root_dir
+        .mount("/tmp".into(), "ramfs", Arc::new(new_ramfs()), false)
+        .expect("failed to mount ramfs at /tmp");
+
+    #[cfg(feature = "procfs")]
     {
-        let ramfs = fs::ramfs::RamFileSystem::new();
+        let procfs = Arc::new(fs::procfs::ProcFileSystem::new());
+        procfs.add_file("mounts", proc_mounts);
         root_dir
-            .mount("/tmp", Arc::new(ramfs))
-            .expect("failed to mount ramfs at /tmp");
+            .mount("/proc".into(), "proc", procfs.clone(), false)
+            .expect("failed to mount procfs at /proc");
+        PROC_FS.init_by(procfs);
+    }
+
+    #[cfg(feature = "initramfs")]
+    if root_dir.main_source != "initramfs" && !fs::INITRAMFS_ARCHIVE.is_empty() {
+        if let Err(e) = root_dir.mount("/initrd".into(), "initramfs", new_initramfs(false), false) {
+            warn!("failed to mount initramfs at /initrd: {:?}", e);
+        }
+    }
+
+    // mount the other partitions under `/mnt`
+    for (name, fs) in parts.filter_map(load_fs) {
+        if let Err(e) = root_dir.mount_partition(&name, fs) {
+            warn!("failed to mount {}: {:?}", name, e);
+        }
     }
 
     ROOT_DIR.init_by(Arc::new(root_dir));
-    CURRENT_DIR.init_by(Mutex::new(ROOT_DIR.clone()));
-    *CURRENT_DIR_PATH.lock() = "/".into();
+}
+
+/// Creates a RAM filesystem that, like tmpfs on Linux, can take at most half
+/// of the memory, and as many inodes as pages in it.
+/// The lock of the overlay on the root filesystem, which lets other tasks run
+/// while it is contended, as the disk under the overlay may block.
+#[cfg(feature = "overlayfs")]
+#[derive(Default)]
+struct OverlayLock(Mutex<()>);
+
+#[cfg(feature = "overlayfs")]
+impl fs::overlayfs::OverlayLock for OverlayLock {
+    fn with_lock(&self, f: &mut dyn FnMut()) {
+        let _guard = self.0.lock();
+        f();
+    }
+}
+
+#[cfg(feature = "ramfs")]
+fn new_ramfs() -> fs::ramfs::RamFileSystem {
+    let allocator = axalloc::global_allocator();
+    let pages = (allocator.used_pages() + allocator.available_pages()) as u64 / 2;
+    let max_pages = (pages > 0).then_some(pages);
+    let max_bytes = max_pages.map(|n| n * crate::page_cache::PAGE_SIZE as u64);
+    fs::ramfs::RamFileSystem::new(max_bytes, max_pages)
+}
+
+/// Loads the initramfs embedded in the kernel image. If it is the root
+/// filesystem, the mount points of the other filesystems are created in it.
+#[cfg(feature = "initramfs")]
+fn new_initramfs(is_root: bool) -> Arc<dyn VfsOps> {
+    let initramfs = fs::initramfs::InitRamFileSystem::new(fs::INITRAMFS_ARCHIVE)
+        .expect("failed to load initramfs");
+    if is_root {
+        let mut mount_points = alloc::vec![
+            #[cfg(feature = "devfs")]
+            String::from("/dev"),
+            #[cfg(feature = "ramfs")]
+            String::from("/tmp"),
+            #[cfg(feature = "procfs")]
+            String::from("/proc"),
+        ];
+        // partitions without filesystems may be formatted and mounted later
+        mount_points.extend(
+            PARTITIONS
+                .iter()
+                .map(|p| format!("/mnt/{}", p.device_name())),
+        );
+        for path in mount_points {
+            if let Err(e) = initramfs.add_mount_point(&path) {
+                warn!("failed to create {} in initramfs: {:?}", path, e);
+            }
+        }
+    }
+    Arc::new(initramfs)
+}
+
+/// Detects and loads the filesystem on the partition, returns it along with
+/// the name of the partition, or `None` if there is no supported one.
+fn load_fs(mut part: Partition) -> Option<(String, Arc<dyn VfsOps>)> {
+    let name = String::from(part.device_name());
+    let ty = match fs::FsType::detect(&mut part) {
+        Ok(Some(ty)) => ty,
+        Ok(None) if cfg!(feature = "use-ramdisk") => {
+            // the ramdisk is empty, format it with the first supported one
+            let ty = if cfg!(feature = "fatfs") {
+                fs::FsType::Fat
+            } else {
+                fs::FsType::Ext2
+            };
+            return match fs::format(ty, Disk::new(part)) {
+                Ok(fs) => Some((name, fs)),
+                Err(e) => {
+                    warn!("{}: failed to format as {:?}: {:?}", name, ty, e);
+                    None
+                }
+            };
+        }
+        Ok(None) => {
+            warn!("{}: unknown filesystem", name);
+            return None;
+        }
+        Err(e) => {
+            warn!("{}: failed to detect filesystem: {:?}", name, e);
+            return None;
+        }
+    };
+    crate::check::check_before_mount(ty, &part);
+    match fs::new_fs(ty, Disk::new(part)) {
+        Ok(fs) => Some((name, fs)),
+        Err(e) => {
+            warn!("{}: failed to load {:?} filesystem: {:?}", name, ty, e);
+            None
+        }
+    }
+}
+
+/// Generates the content of `/proc/mounts`, the source and the path of each
+/// mounted filesystem per line.
+#[cfg(feature = "procfs")]
+fn proc_mounts() -> String {
+    let mut content = format!("{} /\n", ROOT_DIR.main_source);
+    for mp in ROOT_DIR.mounts.lock().iter() {
+        content += &format!("{} {}\n", mp.source, mp.path);
+    }
+    content
+}
+
+pub(crate) fn uninit_rootfs() {
+    ROOT_DIR.umount_all();
 }
 
 fn parent_node_of(dir: Option<&VfsNodeRef>, path: &str) -> VfsNodeRef {
     if path.starts_with('/') {
         ROOT_DIR.clone()
     } else {
-        dir.cloned().unwrap_or_else(|| CURRENT_DIR.lock().clone())
+        dir.cloned()
+            .or_else(|| with_current_dir(|cwd| cwd.node.clone()))
+            .unwrap_or_else(|| ROOT_DIR.clone())
     }
 }
 
@@ -195,12 +555,82 @@ pub(crate) fn absolute_path(path: &str) -> AxResult<String> {
     if path.starts_with('/') {
         Ok(axfs_vfs::path::canonicalize(path))
     } else {
-        let path = CURRENT_DIR_PATH.lock().clone() + path;
+        let path = with_current_dir(|cwd| String::from(cwd.path())) + path;
         Ok(axfs_vfs::path::canonicalize(&path))
     }
 }
 
-pub(crate) fn lookup(dir: Option<&VfsNodeRef>, path: &str) -> AxResult<VfsNodeRef> {
+/// Expands the symbolic links in `path`, returns the directory that the result
+/// is relative to (in the same way as `dir`) and the path without links.
+///
+/// The last component is followed only if `follow` is true or `path` ends with
+/// `/`. Resolution stops at the first component that cannot be looked up, and
+/// the rest is kept as is, so that the caller gets the error or creates it.
+fn resolve_path(
+    dir: Option<&VfsNodeRef>,
+    path: &str,
+    follow: bool,
+) -> AxResult<(Option<VfsNodeRef>, String)> {
+    let follow = follow || path.ends_with('/');
+    let mut dir = dir.cloned();
+    let mut resolved = String::from(if path.starts_with('/') { "/" } else { "" });
+    // components to be resolved, in reverse order
+    let mut pending = path.rsplit('/').map(String::from).collect::<Vec<_>>();
+    let mut links = 0;
+    while let Some(name) = pending.pop() {
+        if name.is_empty() {
+            continue;
+        }
+        if !resolved.is_empty() && !resolved.ends_with('/') {
+            resolved.push('/');
+        }
+        let parent_len = resolved.len();
+        resolved += &name;
+        let is_last = pending.iter().all(|s| s.is_empty());
+        if name == "." || name == ".." || (is_last && !follow) {
+            continue;
+        }
+        let node = match parent_node_of(dir.as_ref(), &resolved).lookup(&resolved) {
+            Ok(node) => node,
+            Err(_) => {
+                for rest in pending.drain(..).rev().filter(|s| !s.is_empty()) {
+                    resolved.push('/');
+                    resolved += &rest;
+                }
+                break;
+            }
+        };
+        if !node.get_attr()?.is_symlink() {
+            continue;
+        }
+
+        links += 1;
+        if links > MAX_SYMLINKS {
+            return ax_err!(FilesystemLoop);
+        }
+        let target = node.readlink()?;
+        if target.starts_with('/') {
+            dir = None;
+            resolved = "/".into();
+        } else {
+            resolved.truncate(parent_len);
+        }
+        pending.extend(target.rsplit('/').map(String::from));
+    }
+    if path.ends_with('/') && !resolved.ends_with('/') {
+        resolved.push('/');
+    }
+    Ok((dir, resolved))
+}
+
+/// Returns the canonical, absolute form of `path` with symbolic links
+/// expanded.
+pub(crate) fn real_path(path: &str, follow: bool) -> AxResult<String> {
+    let (_, path) = resolve_path(None, path, follow)?;
+    absolute_path(&path)
+}
+
+fn lookup_resolved(dir: Option<&VfsNodeRef>, path: &str) -> AxResult<VfsNodeRef> {
     if path.is_empty() {
         return ax_err!(NotFound);
     }
@@ -212,34 +642,109 @@ pub(crate) fn lookup(dir: Option<&VfsNodeRef>, path: &str) -> AxResult<VfsNodeRe
     }
 }
 
+pub(crate) fn lookup(dir: Option<&VfsNodeRef>, path: &str) -> AxResult<VfsNodeRef> {
+    let (dir, path) = resolve_path(dir, path, true)?;
+    lookup_resolved(dir.as_ref(), &path)
+}
+
+/// Like [`lookup`], but does not follow the symbolic link at the end of
+/// `path`.
+pub(crate) fn lookup_nofollow(dir: Option<&VfsNodeRef>, path: &str) -> AxResult<VfsNodeRef> {
+    let (dir, path) = resolve_path(dir, path, false)?;
+    lookup_resolved(dir.as_ref(), &path)
+}
+
+/// Creates an empty file at `path`, or the target of the symbolic link at
+/// `path` if it is dangling.
 pub(crate) fn create_file(dir: Option<&VfsNodeRef>, path: &str) -> AxResult<VfsNodeRef> {
     if path.is_empty() {
         return ax_err!(NotFound);
     } else if path.ends_with('/') {
         return ax_err!(NotADirectory);
     }
-    let parent = parent_node_of(dir, path);
-    parent.create(path, VfsNodeType::File)?;
-    parent.lookup(path)
+    let (dir, path) = resolve_path(dir, path, true)?;
+    let parent = parent_node_of(dir.as_ref(), &path);
+    parent.create(&path, VfsNodeType::File)?;
+    parent.lookup(&path)
 }
 
 pub(crate) fn create_dir(dir: Option<&VfsNodeRef>, path: &str) -> AxResult {
-    match lookup(dir, path) {
+    let (dir, path) = resolve_path(dir, path, false)?;
+    match lookup_resolved(dir.as_ref(), &path) {
+        Ok(_) => ax_err!(AlreadyExists),
+        Err(AxError::NotFound) => {
+            parent_node_of(dir.as_ref(), &path).create(&path, VfsNodeType::Dir)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Creates a symbolic link at `path` which points to `target`.
+pub(crate) fn symlink(dir: Option<&VfsNodeRef>, path: &str, target: &str) -> AxResult {
+    if path.is_empty() {
+        return ax_err!(NotFound);
+    } else if target.is_empty() {
+        return ax_err!(InvalidInput);
+    }
+    let (dir, path) = resolve_path(dir, path, false)?;
+    match lookup_resolved(dir.as_ref(), &path) {
+        Ok(_) => ax_err!(AlreadyExists),
+        Err(AxError::NotFound) => parent_node_of(dir.as_ref(), &path).symlink(&path, target),
+        Err(e) => Err(e),
+    }
+}
+
+/// Creates a hard link at `link_path` to the file at `path`. Symbolic links
+/// at the end of `path` are not followed.
+pub(crate) fn hard_link(path: &str, link_path: &str) -> AxResult {
+    let node = lookup_nofollow(None, path)?;
+    if node.get_attr()?.is_dir() {
+        return ax_err!(
+            PermissionDenied,
+            "hard links to directories are not allowed"
+        );
+    }
+    if link_path.is_empty() {
+        return ax_err!(NotFound);
+    }
+    let (dir, link_path) = resolve_path(None, link_path, false)?;
+    match lookup_resolved(dir.as_ref(), &link_path) {
         Ok(_) => ax_err!(AlreadyExists),
-        Err(AxError::NotFound) => parent_node_of(dir, path).create(path, VfsNodeType::Dir),
+        Err(AxError::NotFound) => parent_node_of(dir.as_ref(), &link_path).link(&link_path, &node),
         Err(e) => Err(e),
     }
 }
 
-pub(crate) fn remove_file(dir: Option<&VfsNodeRef>, path: &str) -> AxResult {
-    let node = lookup(dir, path)?;
+/// Returns the target of the symbolic link at `path`.
+pub(crate) fn read_link(dir: Option<&VfsNodeRef>, path: &str) -> AxResult<String> {
+    let node = lookup_nofollow(dir, path)?;
+    if !node.get_attr()?.is_symlink() {
+        return ax_err!(InvalidInput, "not a symbolic link");
+    }
+    node.readlink()
+}
+
+/// Removes the file at `path`, which belongs to the filesystem mounted at
+/// `mount`, or the root filesystem if it is `None`.
+pub(crate) fn remove_file(
+    dir: Option<&VfsNodeRef>,
+    path: &str,
+    mount: Option<&Arc<MountPoint>>,
+) -> AxResult {
+    let (dir, path) = resolve_path(dir, path, false)?;
+    let (dir, path) = (dir.as_ref(), path.as_str());
+    let node = lookup_resolved(dir, path)?;
     let attr = node.get_attr()?;
     if attr.is_dir() {
         ax_err!(IsADirectory)
     } else if !attr.perm().owner_writable() {
         ax_err!(PermissionDenied)
     } else {
-        parent_node_of(dir, path).remove(path)
+        parent_node_of(dir, path).remove(path)?;
+        if attr.is_file() && attr.nlink() <= 1 {
+            PageCache::invalidate(mount, attr.ino());
+        }
+        Ok(())
     }
 }
 
@@ -257,11 +762,13 @@ pub(crate) fn remove_dir(dir: Option<&VfsNodeRef>, path: &str) -> AxResult {
     {
         return ax_err!(InvalidInput);
     }
+    let (dir, path) = resolve_path(dir, path, false)?;
+    let (dir, path) = (dir.as_ref(), path.as_str());
     if ROOT_DIR.contains(&absolute_path(path)?) {
         return ax_err!(PermissionDenied);
     }
 
-    let node = lookup(dir, path)?;
+    let node = lookup_resolved(dir, path)?;
     let attr = node.get_attr()?;
     if !attr.is_dir() {
         ax_err!(NotADirectory)
@@ -272,18 +779,107 @@ pub(crate) fn remove_dir(dir: Option<&VfsNodeRef>, path: &str) -> AxResult {
     }
 }
 
+pub(crate) fn mount(path: &str, fs: Arc<dyn VfsOps>) -> AxResult {
+    ROOT_DIR.mount(real_path(path, true)?, "none", fs, false)
+}
+
+pub(crate) fn umount(path: &str) -> AxResult {
+    ROOT_DIR.umount(&real_path(path, true)?)
+}
+
+/// Held while formatting or checking a partition, so that it is not mounted
+/// by another format in the meantime.
+static FORMAT_LOCK: Mutex<()> = Mutex::new(());
+
+/// Creates an empty filesystem of type `ty` on the partition `dev`, and
+/// mounts it at `/mnt/<dev>`.
+pub(crate) fn format(dev: &str, ty: fs::FsType) -> AxResult {
+    let part = PARTITIONS
+        .iter()
+        .find(|part| part.device_name() == dev)
+        .ok_or_else(|| ax_err_type!(NotFound, "no such partition"))?;
+    let _guard = FORMAT_LOCK.lock();
+    let mounted = ROOT_DIR.mounts.lock().iter().any(|mp| mp.source == dev);
+    if mounted || ROOT_DIR.main_source == dev {
+        return ax_err!(ResourceBusy, "the partition is mounted");
+    }
+    info!("format {} as {:?}", dev, ty);
+    let fs = fs::format(ty, Disk::new(part.clone()))?;
+    ROOT_DIR.mount_partition(dev, fs)
+}
+
+/// Checks the filesystem on the partition named `dev`, which must not be
+/// mounted, and repairs it if `repair` is true.
+pub(crate) fn check(dev: &str, repair: bool) -> AxResult<Report> {
+    let mut part = PARTITIONS
+        .iter()
+        .find(|part| part.device_name() == dev)
+        .cloned()
+        .ok_or_else(|| ax_err_type!(NotFound, "no such partition"))?;
+    let _guard = FORMAT_LOCK.lock();
+    let mounted = ROOT_DIR.mounts.lock().iter().any(|mp| mp.source == dev);
+    if mounted || ROOT_DIR.main_source == dev {
+        return ax_err!(ResourceBusy, "the partition is mounted");
+    }
+    let ty = fs::FsType::detect(&mut part)
+        .map_err(|_| AxError::Io)?
+        .ok_or_else(|| ax_err_type!(Unsupported, "unknown filesystem"))?;
+    info!("check {} as {:?}", dev, ty);
+    crate::check::check(ty, part, repair)
+}
+
+/// Returns the mount point that `path` belongs to. The symbolic link at the end
+/// of `path` is followed only if `follow` is true.
+pub(crate) fn mount_point_of(path: &str, follow: bool) -> AxResult<Option<Arc<MountPoint>>> {
+    Ok(ROOT_DIR.mount_point_of(&real_path(path, follow)?))
+}
+
+/// Returns the attributes of the filesystem that `path` belongs to.
+pub(crate) fn statfs(path: &str) -> AxResult<FileSystemInfo> {
+    let path = real_path(path, true)?;
+    lookup(None, &path)?;
+    ROOT_DIR.fs_of(&path).statfs()
+}
+
+/// Renames the file or directory at `old` to `new`, replacing `new` if it
+/// exists. Symbolic links at the end of both paths are not followed.
+pub(crate) fn rename(old: &str, new: &str) -> AxResult {
+    if old.is_empty() || new.is_empty() {
+        return ax_err!(NotFound);
+    }
+    let old = real_path(old, false)?;
+    let new = real_path(new, false)?;
+    let attr_of = |path| lookup_nofollow(None, path).and_then(|node| node.get_attr());
+    let replaced = attr_of(&new).ok().filter(|attr| {
+        attr.is_file() && attr.nlink() <= 1 && attr_of(&old).is_ok_and(|a| a.ino() != attr.ino())
+    });
+    ROOT_DIR.rename(&old, &new)?;
+    if let Some(attr) = replaced {
+        PageCache::invalidate(ROOT_DIR.mount_point_of(&new).as_ref(), attr.ino());
+    }
+
+    // the current directories may be moved
+    for_each_current_dir(|cwd| {
+        if is_prefix(&old, cwd.path().trim_end_matches('/')) {
+            cwd.path = format!("{}{}", new, &cwd.path[old.len()..]);
+        }
+    });
+    Ok(())
+}
+
 pub(crate) fn current_dir() -> AxResult<String> {
-    Ok(CURRENT_DIR_PATH.lock().clone())
+    Ok(with_current_dir(|cwd| cwd.path().into()))
 }
 
 pub(crate) fn set_current_dir(path: &str) -> AxResult {
-    let mut abs_path = absolute_path(path)?;
+    let mut abs_path = real_path(path, true)?;
     if !abs_path.ends_with('/') {
         abs_path += "/";
     }
     if abs_path == "/" {
-        *CURRENT_DIR.lock() = ROOT_DIR.clone();
-        *CURRENT_DIR_PATH.lock() = "/".into();
+        // the old directory is dropped after the current task is unlocked
+        let old = with_current_dir(core::mem::take);
+        drop(old);
         return Ok(());
     }
 
@@ -294,8 +890,12 @@ pub(crate) fn set_current_dir(path: &str) -> AxResult {
     } else if !attr.perm().owner_executable() {
         ax_err!(PermissionDenied)
     } else {
-        *CURRENT_DIR.lock() = node;
-        *CURRENT_DIR_PATH.lock() = abs_path;
+        let new = CurrentDir {
+            path: abs_path,
+            node: Some(node),
+        };
+        let old = with_current_dir(|cwd| core::mem::replace(cwd, new));
+        drop(old);
         Ok(())
     }
 }
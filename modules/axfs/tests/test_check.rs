@@ -0,0 +1,142 @@
+#![cfg(all(feature = "ext2", not(any(feature = "fatfs", feature = "myfs"))))]
+#![cfg(not(feature = "overlayfs"))]
+
+#[allow(dead_code)]
+mod test_common;
+
+use axdriver::{prelude::*, AxDeviceContainer};
+use axfs::api::{self as fs, Report};
+use axfs::check::{check_device, Problem};
+use axio::Error;
+use driver_block::ramdisk::RamDisk;
+
+const IMG_PATH: &str = "resources/ext2.img";
+const BLOCK_SIZE: usize = 1024;
+const INODE_SIZE: usize = 128;
+
+fn le32(data: &[u8], pos: usize) -> u32 {
+    u32::from_le_bytes(data[pos..pos + 4].try_into().unwrap())
+}
+
+fn set_le32(data: &mut [u8], pos: usize, value: u32) {
+    data[pos..pos + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+/// Returns the offset of an inode in the image, which has only one group.
+fn inode_pos(data: &[u8], ino: usize) -> usize {
+    let inode_table = le32(data, 2 * BLOCK_SIZE + 8) as usize;
+    inode_table * BLOCK_SIZE + (ino - 1) * INODE_SIZE
+}
+
+/// Returns the offset of the entry with the given name in the first block
+/// of the root directory.
+fn root_entry_pos(data: &[u8], name: &str) -> usize {
+    let block = le32(data, inode_pos(data, 2) + 40) as usize * BLOCK_SIZE;
+    let mut pos = block;
+    while pos < block + BLOCK_SIZE {
+        let name_len = data[pos + 6] as usize;
+        if &data[pos + 8..pos + 8 + name_len] == name.as_bytes() {
+            return pos;
+        }
+        pos += u16::from_le_bytes([data[pos + 4], data[pos + 5]]) as usize;
+    }
+    panic!("no entry {:?} in the root directory", name);
+}
+
+/// Loads the image, and corrupts it in a few ways `e2fsck` would repair.
+fn make_corrupted_image() -> std::io::Result<Vec<u8>> {
+    let path = std::env::current_dir()?.join(IMG_PATH);
+    println!("Loading disk image from {:?} ...", path);
+    let mut data = std::fs::read(path)?;
+
+    // the free blocks count in the superblock
+    let free_blocks = le32(&data, BLOCK_SIZE + 12);
+    set_le32(&mut data, BLOCK_SIZE + 12, free_blocks + 5);
+    // /long.txt (inode 12) has one link
+    let long_txt = inode_pos(&data, 12);
+    data[long_txt + 26] = 2;
+    // /short.txt (inode 13) shares the first block of /long.txt
+    let long_block = le32(&data, long_txt + 40);
+    let short_txt = inode_pos(&data, 13);
+    set_le32(&mut data, short_txt + 40, long_block);
+    // /very-long-dir-name (inode 18) is removed from the root directory
+    let entry = root_entry_pos(&data, "very-long-dir-name");
+    set_le32(&mut data, entry, 0);
+    Ok(data)
+}
+
+fn read_image(disk: &mut AxBlockDevice) -> Vec<u8> {
+    let mut data = vec![0; disk.num_blocks() as usize * disk.block_size()];
+    for (id, block) in data.chunks_mut(disk.block_size()).enumerate() {
+        disk.read_block(id as u64, block).unwrap();
+    }
+    data
+}
+
+fn check(data: &[u8], repair: bool) -> (Report, Vec<u8>) {
+    let (report, mut disk) = check_device(RamDisk::from(data), repair).unwrap();
+    for problem in &report.problems {
+        println!("{}", problem);
+    }
+    (report, read_image(&mut disk))
+}
+
+#[test]
+fn test_check() {
+    println!("Testing check with ramdisk ...");
+    axtask::init_scheduler(); // call this to use `axsync::Mutex`.
+    test_common::init_page_allocator();
+
+    let data = make_corrupted_image().expect("failed to load disk image");
+    let (report, checked) = check(&data, false);
+    assert!(!report.repaired);
+    assert_eq!(checked, data);
+    let expected = [
+        Problem::CrossLinked {
+            file: 13,
+            block: 58,
+        },
+        Problem::BlockCount {
+            file: 13,
+            stored: 2,
+            counted: 0,
+        },
+        Problem::OrphanedInode { ino: 18 },
+        Problem::LinkCount {
+            ino: 12,
+            stored: 2,
+            counted: 1,
+        },
+        Problem::FreeBlocks {
+            group: None,
+            stored: 1973,
+            counted: 1969,
+        },
+        Problem::BlockBitmap { group: 0 },
+    ];
+    for problem in &expected {
+        assert!(report.problems.contains(problem), "{:?} not found", problem);
+    }
+
+    let (report, repaired) = check(&data, true);
+    assert!(report.repaired);
+    for problem in &expected {
+        assert!(report.problems.contains(problem), "{:?} not found", problem);
+    }
+    let (report, checked) = check(&repaired, false);
+    assert!(report.is_clean(), "{:?}", report.problems);
+    assert_eq!(checked, repaired);
+
+    // a filesystem not cleanly unmounted is checked before it is mounted, and
+    // the orphaned directory is reconnected to lost+found
+    let mut data = data;
+    data[BLOCK_SIZE + 58] &= !1; // not `EXT2_VALID_FS`
+    axfs::init_filesystems(AxDeviceContainer::from_one(RamDisk::from(&data)));
+    assert!(fs::metadata("/very-long-dir-name").is_err());
+    let file = "/lost+found/#18/very-long-file-name.txt";
+    assert_eq!(fs::read_to_string(file).unwrap(), "Rust is cool!\n");
+    assert_eq!(fs::metadata("/lost+found/#18/..").unwrap().len(), 12288);
+    assert_eq!(fs::metadata("/short.txt").unwrap().len(), 14);
+    assert_eq!(fs::check("ramdisk", false).err(), Some(Error::ResourceBusy));
+    println!("test_check() OK!");
+}
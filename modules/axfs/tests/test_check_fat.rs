@@ -0,0 +1,117 @@
+#![cfg(all(feature = "fatfs", not(feature = "myfs")))]
+
+use axdriver::prelude::*;
+use axfs::check::{check_device, Problem, Report};
+use driver_block::ramdisk::RamDisk;
+
+const IMG_PATH: &str = "resources/fat16.img";
+const SECTOR_SIZE: usize = 512;
+/// The image has one reserved sector, two FATs of 20 sectors, and a root
+/// directory of 32 sectors. Clusters are one sector.
+const FAT_SECTORS: usize = 20;
+const ROOT_POS: usize = (1 + 2 * FAT_SECTORS) * SECTOR_SIZE;
+
+fn le16(data: &[u8], pos: usize) -> u16 {
+    u16::from_le_bytes(data[pos..pos + 2].try_into().unwrap())
+}
+
+fn set_le16(data: &mut [u8], pos: usize, value: u16) {
+    data[pos..pos + 2].copy_from_slice(&value.to_le_bytes());
+}
+
+fn fat_pos(fat: usize, cluster: usize) -> usize {
+    (1 + fat * FAT_SECTORS) * SECTOR_SIZE + cluster * 2
+}
+
+/// Returns the offset of the entry with the given short name in the root
+/// directory.
+fn root_entry_pos(data: &[u8], name: &[u8; 11]) -> usize {
+    (ROOT_POS..ROOT_POS + 32 * SECTOR_SIZE)
+        .step_by(32)
+        .find(|&pos| &data[pos..pos + 11] == name)
+        .expect("no such entry in the root directory")
+}
+
+fn load_image() -> std::io::Result<Vec<u8>> {
+    let path = std::env::current_dir()?.join(IMG_PATH);
+    println!("Loading disk image from {:?} ...", path);
+    std::fs::read(path)
+}
+
+fn read_image(disk: &mut AxBlockDevice) -> Vec<u8> {
+    let mut data = vec![0; disk.num_blocks() as usize * disk.block_size()];
+    for (id, block) in data.chunks_mut(disk.block_size()).enumerate() {
+        disk.read_block(id as u64, block).unwrap();
+    }
+    data
+}
+
+fn check(data: &[u8], repair: bool) -> (Report, Vec<u8>) {
+    let (report, mut disk) = check_device(RamDisk::from(data), repair).unwrap();
+    for problem in &report.problems {
+        println!("{}", problem);
+    }
+    (report, read_image(&mut disk))
+}
+
+#[test]
+fn test_check_fat() {
+    println!("Testing check of FAT with ramdisk ...");
+    axtask::init_scheduler(); // call this to use `axsync::Mutex`.
+
+    let mut data = load_image().expect("failed to load disk image");
+    let (report, checked) = check(&data, false);
+    assert!(report.is_clean(), "{:?}", report.problems);
+    assert_eq!(checked, data);
+
+    // /long.txt (clusters 3 to 30) is shorter than its chain
+    let long_txt = root_entry_pos(&data, b"LONG    TXT");
+    assert_eq!(le16(&data, long_txt + 26), 3);
+    data[long_txt + 28..long_txt + 32].copy_from_slice(&5000u32.to_le_bytes());
+    // /short.txt (cluster 31) starts in the chain of /long.txt
+    let short_txt = root_entry_pos(&data, b"SHORT   TXT");
+    assert_eq!(le16(&data, short_txt + 26), 31);
+    set_le16(&mut data, short_txt + 26, 5);
+    // the second FAT differs
+    set_le16(&mut data, fat_pos(1, 2), 0xffff);
+    // the volume is dirty
+    set_le16(&mut data, fat_pos(0, 1), 0x7fff);
+
+    let (report, checked) = check(&data, false);
+    assert!(!report.repaired);
+    assert_eq!(checked, data);
+    let expected = [
+        Problem::FileSize {
+            file: 3,
+            stored: 5000,
+            counted: 5120,
+        },
+        Problem::CrossLinked { file: 5, block: 5 },
+        Problem::FileSize {
+            file: 5,
+            stored: 14,
+            counted: 0,
+        },
+        Problem::LostClusters { count: 19 },
+        Problem::FatMismatch { fat: 1 },
+    ];
+    assert_eq!(report.problems, expected);
+
+    let (report, repaired) = check(&data, true);
+    assert!(report.repaired);
+    assert_eq!(report.problems, expected);
+    let (report, checked) = check(&repaired, false);
+    assert!(report.is_clean(), "{:?}", report.problems);
+    assert_eq!(checked, repaired);
+
+    // /long.txt ends at cluster 12, and /short.txt is empty
+    assert_eq!(le16(&repaired, fat_pos(0, 12)), 0xfff8);
+    assert_eq!(le16(&repaired, fat_pos(0, 13)), 0);
+    assert_eq!(le16(&repaired, fat_pos(0, 31)), 0);
+    assert_eq!(le16(&repaired, short_txt + 26), 0);
+    assert_eq!(le16(&repaired, short_txt + 28), 0);
+    assert_eq!(le16(&repaired, fat_pos(0, 1)), 0xffff);
+    let fats = [fat_pos(0, 0), fat_pos(1, 0), fat_pos(2, 0)];
+    assert_eq!(repaired[fats[0]..fats[1]], repaired[fats[1]..fats[2]]);
+    println!("test_check_fat() OK!");
+}
@@ -1,4 +1,7 @@
+use std::sync::Arc;
+
 use axfs::api as fs;
+use axfs::fops;
 use axio as io;
 
 use fs::{File, FileType, OpenOptions};
@@ -168,6 +171,9 @@ fn test_devfs_ramfs() -> Result<()> {
     const N: usize = 32;
     let mut buf = [1; N];
 
+    // add a device in a subdirectory of /dev
+    axfs::add_device("foo/bar", Arc::new(axfs_devfs::ZeroDev));
+
     // list '/' and check if /dev and /tmp exist
     let dirents = fs::read_dir("././//.//")?
         .map(|e| e.unwrap().file_name())
@@ -198,6 +204,10 @@ fn test_devfs_ramfs() -> Result<()> {
         .collect::<Vec<_>>();
     assert!(dirents.contains(&"null".into()));
     assert!(dirents.contains(&"zero".into()));
+    assert!(dirents.contains(&"urandom".into()));
+    assert!(dirents.contains(&"vda".into()));
+    assert_eq!(fs::metadata("/dev/vda")?.file_type(), FileType::BlockDevice);
+    assert_eq!(fs::statfs("/dev/null")?.fs_type, 0x1373);
 
     // stat /dev
     let dname = "/dev";
@@ -236,6 +246,11 @@ fn test_devfs_ramfs() -> Result<()> {
     assert_eq!(fs::remove_dir("dev//foo/../foo/../.././/233"), Ok(()));
     assert_err!(fs::remove_dir("very/../dev//"), PermissionDenied);
 
+    // remove the added device
+    assert_eq!(axfs::remove_device("foo/bar"), Ok(()));
+    assert_err!(axfs::remove_device("foo/bar"), NotFound);
+    assert_err!(fs::metadata("/dev/foo/bar"), NotFound);
+
     // tests in /tmp
     assert_eq!(fs::metadata("tmp")?.file_type(), FileType::Dir);
     assert_eq!(fs::create_dir(".///tmp///././dir"), Ok(()));
@@ -252,6 +267,317 @@ fn test_devfs_ramfs() -> Result<()> {
     Ok(())
 }
 
+fn test_rename() -> Result<()> {
+    // rename in the same directory, replacing the existing file
+    assert_eq!(fs::write("/rename-a.txt", "a"), Ok(()));
+    assert_eq!(fs::write("/rename-b.txt", "b"), Ok(()));
+    assert_eq!(fs::rename("/rename-a.txt", ".//rename-b.txt"), Ok(()));
+    assert_err!(fs::metadata("/rename-a.txt"), NotFound);
+    assert_eq!(fs::read_to_string("/rename-b.txt")?, "a");
+
+    // move files and directories to other directories
+    assert_eq!(fs::create_dir("/rename-dir"), Ok(()));
+    assert_eq!(fs::rename("rename-b.txt", "/rename-dir/b.txt"), Ok(()));
+    assert_eq!(fs::rename("/rename-dir", "very/long/rename-dir"), Ok(()));
+    assert_err!(fs::metadata("/rename-dir"), NotFound);
+    assert_eq!(fs::read_to_string("/very/long/rename-dir/b.txt")?, "a");
+
+    // the current directory is moved
+    assert_eq!(fs::set_current_dir("/very/long/rename-dir"), Ok(()));
+    assert_eq!(fs::rename("/very/long/rename-dir", "/rename-dir"), Ok(()));
+    assert_eq!(fs::current_dir()?, "/rename-dir/");
+    assert_eq!(fs::read_to_string("b.txt")?, "a");
+    assert_eq!(fs::set_current_dir("/"), Ok(()));
+
+    // replace directories
+    assert_eq!(fs::create_dir("/rename-dir2"), Ok(()));
+    assert_err!(
+        fs::rename("/rename-dir/b.txt", "/rename-dir2"),
+        IsADirectory
+    );
+    assert_err!(
+        fs::rename("/rename-dir2", "/rename-dir/b.txt"),
+        NotADirectory
+    );
+    assert_err!(fs::rename("/rename-dir2", "/rename-dir"), DirectoryNotEmpty);
+    assert_eq!(fs::rename("/rename-dir", "/rename-dir2"), Ok(()));
+    assert_eq!(fs::read_to_string("/rename-dir2/b.txt")?, "a");
+
+    // error cases
+    assert_err!(fs::rename("/rename-dir2", "/rename-dir2/sub"), InvalidInput);
+    assert_err!(fs::rename("/rename-none", "/rename-dir"), NotFound);
+    assert_err!(
+        fs::rename("/rename-dir2/b.txt", "/tmp/b.txt"),
+        CrossesDevices
+    );
+    assert_err!(fs::rename("/dev", "/dev2"), ResourceBusy);
+    assert_err!(fs::rename("/dev/null", "/dev/null2"), PermissionDenied);
+
+    // rename in /tmp
+    assert_eq!(fs::write("/tmp/a.txt", "tmp"), Ok(()));
+    assert_eq!(fs::rename("/tmp/a.txt", "/tmp/b.txt"), Ok(()));
+    assert_eq!(fs::read_to_string("/tmp/b.txt")?, "tmp");
+    assert_eq!(fs::remove_file("/tmp/b.txt"), Ok(()));
+
+    assert_eq!(fs::remove_file("/rename-dir2/b.txt"), Ok(()));
+    assert_eq!(fs::remove_dir("/rename-dir2"), Ok(()));
+    println!("test_rename() OK!");
+    Ok(())
+}
+
+#[cfg(feature = "ramfs")]
+fn test_mount() -> Result<()> {
+    use axfs_ramfs::RamFileSystem;
+    use std::sync::Arc;
+
+    // nested mount points
+    assert_eq!(
+        fs::mount("/mnt", Arc::new(RamFileSystem::new(None, None))),
+        Ok(())
+    );
+    assert_eq!(
+        fs::mount("mnt//usb/", Arc::new(RamFileSystem::new(None, None))),
+        Ok(())
+    );
+    assert_err!(
+        fs::mount("/mnt/", Arc::new(RamFileSystem::new(None, None))),
+        InvalidInput
+    );
+    assert_err!(
+        fs::mount("/", Arc::new(RamFileSystem::new(None, None))),
+        InvalidInput
+    );
+    assert_eq!(fs::write("/mnt/a.txt", "mnt"), Ok(()));
+    assert_eq!(fs::write("/mnt/usb/b.txt", "usb"), Ok(()));
+    assert_eq!(fs::read_to_string("/mnt/usb/../a.txt")?, "mnt");
+    assert_eq!(fs::read_to_string("mnt/./usb/b.txt")?, "usb");
+    assert_eq!(fs::statfs("/mnt/usb/b.txt")?.fs_type, 0x0102_1994);
+    assert_err!(fs::metadata("/mnt/b.txt"), NotFound);
+    assert_err!(fs::metadata("/mnt/usb/a.txt"), NotFound);
+    let dirents = fs::read_dir("/mnt")?
+        .map(|e| e.unwrap().file_name())
+        .collect::<Vec<_>>();
+    assert!(dirents.contains(&"a.txt".into()));
+    assert!(dirents.contains(&"usb".into()));
+
+    // paths that share a prefix with a mount point
+    assert_eq!(fs::create_dir("/mntx"), Ok(()));
+    assert_err!(fs::metadata("/mntx/a.txt"), NotFound);
+    assert_eq!(fs::remove_dir("/mntx"), Ok(()));
+
+    // busy mount points
+    assert_err!(fs::umount("/mnt"), ResourceBusy);
+    let file = File::open("/mnt/usb/b.txt")?;
+    assert_err!(fs::umount("/mnt/usb"), ResourceBusy);
+    drop(file);
+    let dir = fs::read_dir("/mnt/usb")?;
+    assert_err!(fs::umount("/mnt/usb"), ResourceBusy);
+    drop(dir);
+    // opened relative to a directory on the parent filesystem
+    let mut opts = fops::OpenOptions::new();
+    opts.read(true);
+    let root = fops::Directory::open_dir("/", &opts)?;
+    let file = root.open_file_at("mnt/usb/b.txt", &opts)?;
+    assert_err!(fs::umount("/mnt/usb"), ResourceBusy);
+    drop(file);
+    let dir = root.open_dir_at("mnt/usb", &opts)?;
+    assert_err!(fs::umount("/mnt/usb"), ResourceBusy);
+    drop((dir, root));
+    assert_eq!(fs::umount("/mnt/usb"), Ok(()));
+    assert_err!(fs::metadata("/mnt/usb/b.txt"), NotFound);
+    assert_eq!(
+        fs::mount("/mnt/usb", Arc::new(RamFileSystem::new(None, None))),
+        Ok(())
+    );
+    assert_eq!(fs::umount("/mnt/usb"), Ok(()));
+    assert_eq!(fs::set_current_dir("/mnt"), Ok(()));
+    assert_err!(fs::umount("/mnt"), ResourceBusy);
+    assert_eq!(fs::set_current_dir("/"), Ok(()));
+
+    assert_eq!(fs::umount("/mnt"), Ok(()));
+    assert_err!(fs::umount("/mnt"), InvalidInput);
+    assert_err!(fs::metadata("/mnt/a.txt"), NotFound);
+    assert_eq!(fs::remove_dir("/mnt"), Ok(()));
+
+    println!("test_mount() OK!");
+    Ok(())
+}
+
+#[cfg(feature = "ramfs")]
+fn test_links() -> Result<()> {
+    // symbolic links in /tmp
+    assert_eq!(fs::create_dir("/tmp/v1.2"), Ok(()));
+    assert_eq!(fs::write("/tmp/v1.2/data.txt", "v1.2"), Ok(()));
+    assert_eq!(fs::symlink("v1.2", "/tmp/current"), Ok(()));
+    assert_eq!(fs::read_link("/tmp//current")?, "v1.2");
+    assert_eq!(fs::read_to_string("/tmp/current/data.txt")?, "v1.2");
+    assert!(fs::metadata("/tmp/current")?.is_dir());
+    let md = fs::symlink_metadata("/tmp/current")?;
+    assert_eq!(md.file_type(), FileType::SymLink);
+    assert_eq!(md.len(), 4);
+    assert_eq!(
+        fs::canonicalize("tmp/./current//data.txt")?,
+        "/tmp/v1.2/data.txt"
+    );
+    let entry = fs::read_dir("/tmp")?
+        .map(|e| e.unwrap())
+        .find(|e| e.file_name() == "current")
+        .unwrap();
+    assert_eq!(entry.file_type(), FileType::SymLink);
+
+    // relative targets start from the directory of the link, absolute ones
+    // may cross mount points
+    assert_eq!(fs::symlink("../current/", "/tmp/v1.2/up"), Ok(()));
+    assert_eq!(fs::read_to_string("/tmp/v1.2/up/up/data.txt")?, "v1.2");
+    assert_eq!(fs::symlink("/dev/./null", "/tmp/null"), Ok(()));
+    assert_eq!(fs::metadata("/tmp/null")?.file_type(), FileType::CharDevice);
+    assert_eq!(fs::write("/tmp/null", "test"), Ok(()));
+
+    // the current directory is resolved
+    assert_eq!(fs::set_current_dir("/tmp/current"), Ok(()));
+    assert_eq!(fs::current_dir()?, "/tmp/v1.2/");
+    assert_eq!(fs::read_to_string("data.txt")?, "v1.2");
+    assert_eq!(fs::set_current_dir("/"), Ok(()));
+
+    // dangling links and loops
+    assert_eq!(fs::symlink("new.txt", "/tmp/dangling"), Ok(()));
+    assert_err!(fs::metadata("/tmp/dangling"), NotFound);
+    assert_eq!(fs::write("/tmp/dangling", "new"), Ok(()));
+    assert_eq!(fs::read_to_string("/tmp/new.txt")?, "new");
+    assert_eq!(fs::symlink("loop2", "/tmp/loop1"), Ok(()));
+    assert_eq!(fs::symlink("loop1", "/tmp/loop2"), Ok(()));
+    assert_err!(fs::metadata("/tmp/loop1"), FilesystemLoop);
+    assert_err!(fs::read_to_string("/tmp/loop2/a.txt"), FilesystemLoop);
+
+    // error cases
+    assert_err!(fs::symlink("v1.2", "/tmp/current"), AlreadyExists);
+    assert_err!(fs::symlink("", "/tmp/empty"), InvalidInput);
+    assert_err!(fs::create_dir("/tmp/current"), AlreadyExists);
+    assert_err!(fs::read_link("/tmp/v1.2"), InvalidInput);
+    assert_err!(fs::remove_dir("/tmp/current"), NotADirectory);
+
+    // hard links
+    assert_eq!(
+        fs::hard_link("/tmp/current/data.txt", "/tmp/data2.txt"),
+        Ok(())
+    );
+    assert_eq!(fs::write("/tmp/data2.txt", "v1.3"), Ok(()));
+    assert_eq!(fs::read_to_string("/tmp/v1.2/data.txt")?, "v1.3");
+    assert_eq!(fs::hard_link("/tmp/null", "/tmp/null2"), Ok(()));
+    assert_eq!(fs::read_link("/tmp/null2")?, "/dev/./null");
+    assert_err!(fs::hard_link("/tmp/v1.2", "/tmp/v1.3"), PermissionDenied);
+    assert_err!(fs::hard_link("/dev/zero", "/tmp/zero"), CrossesDevices);
+    assert_err!(
+        fs::hard_link("/tmp/new.txt", "/tmp/data2.txt"),
+        AlreadyExists
+    );
+    assert_err!(fs::hard_link("/tmp/none.txt", "/tmp/none2.txt"), NotFound);
+
+    // removing a link does not affect its target
+    assert_eq!(fs::remove_file("/tmp/current"), Ok(()));
+    assert!(fs::metadata("/tmp/v1.2")?.is_dir());
+    assert_eq!(fs::remove_file("/tmp/v1.2/data.txt"), Ok(()));
+    assert_eq!(fs::read_to_string("/tmp/data2.txt")?, "v1.3");
+    for name in [
+        "v1.2/up",
+        "null",
+        "null2",
+        "dangling",
+        "new.txt",
+        "loop1",
+        "loop2",
+        "data2.txt",
+    ] {
+        fs::remove_file(&format!("/tmp/{}", name))?;
+    }
+    assert_eq!(fs::remove_dir("/tmp/v1.2"), Ok(()));
+    assert_eq!(fs::read_dir("tmp").unwrap().count(), 0);
+
+    println!("test_links() OK!");
+    Ok(())
+}
+
+#[cfg(feature = "ramfs")]
+fn test_attrs() -> Result<()> {
+    use core::time::Duration;
+
+    assert_eq!(fs::write("/tmp/attr.txt", "attr"), Ok(()));
+    let md = fs::metadata("/tmp/attr.txt")?;
+    assert_eq!((md.nlink(), md.uid(), md.gid()), (1, 0, 0));
+    assert_ne!(md.ino(), fs::metadata("/tmp")?.ino());
+
+    // chmod, chown and utimes
+    let perm = fs::Permissions::from_bits_truncate(0o640);
+    assert_eq!(fs::set_permissions("/tmp/attr.txt", perm), Ok(()));
+    assert_eq!(fs::chown("/tmp/attr.txt", Some(1000), None), Ok(()));
+    let file = File::open("/tmp/attr.txt")?;
+    assert_eq!(file.set_owner(None, Some(100)), Ok(()));
+    let (atime, mtime) = (Duration::from_secs(1000), Duration::from_secs(2000));
+    assert_eq!(file.set_times(Some(atime), Some(mtime)), Ok(()));
+    let md = file.metadata()?;
+    assert_eq!(md.permissions().bits(), 0o640);
+    assert_eq!((md.uid(), md.gid()), (1000, 100));
+    assert_eq!((md.accessed(), md.modified()), (atime, mtime));
+    drop(file);
+
+    // symbolic links are followed, hard links share the attributes
+    assert_eq!(fs::symlink("attr.txt", "/tmp/attr-link"), Ok(()));
+    let perm = fs::Permissions::from_bits_truncate(0o600);
+    assert_eq!(fs::set_permissions("/tmp/attr-link", perm), Ok(()));
+    assert_eq!(fs::metadata("/tmp/attr.txt")?.permissions().bits(), 0o600);
+    assert_eq!(fs::hard_link("/tmp/attr.txt", "/tmp/attr2.txt"), Ok(()));
+    let md = fs::metadata("/tmp/attr2.txt")?;
+    assert_eq!((md.nlink(), md.uid()), (2, 1000));
+    assert_err!(fs::set_permissions("/tmp/none.txt", perm), NotFound);
+
+    for name in ["attr.txt", "attr-link", "attr2.txt"] {
+        fs::remove_file(&format!("/tmp/{}", name))?;
+    }
+    println!("test_attrs() OK!");
+    Ok(())
+}
+
+#[cfg(feature = "procfs")]
+fn test_procfs() -> Result<()> {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static VALUE: AtomicUsize = AtomicUsize::new(0);
+
+    let mounts = fs::read_to_string("/proc/mounts")?;
+    assert!(mounts.lines().any(|l| l.ends_with(" /")));
+    assert!(mounts.lines().any(|l| l == "devfs /dev"));
+    assert!(mounts.lines().any(|l| l == "proc /proc"));
+    assert!(!mounts.contains("/proc/test"));
+
+    // generated files in nested directories
+    axfs::add_proc_file("test/value", || {
+        format!("{}\n", VALUE.load(Ordering::Relaxed))
+    });
+    assert_eq!(fs::read_to_string("/proc/test/value")?, "0\n");
+    VALUE.store(42, Ordering::Relaxed);
+    assert_eq!(fs::read_to_string("/proc/./test/../test/value")?, "42\n");
+    let md = fs::metadata("/proc/test/value")?;
+    assert_eq!((md.len(), md.permissions().bits()), (0, 0o444));
+    assert!(fs::metadata("/proc/test")?.is_dir());
+
+    // read-only
+    assert_err!(fs::write("/proc/test/value", "1"), PermissionDenied);
+    assert_err!(fs::write("/proc/foo", "foo"), PermissionDenied);
+    assert_err!(fs::remove_file("/proc/mounts"), PermissionDenied);
+    assert_err!(fs::create_dir("/proc/foo"), PermissionDenied);
+
+    println!("test_procfs() OK!");
+    Ok(())
+}
+
+/// Gives the page cache some memory, which is allocated from `axalloc`.
+#[allow(dead_code)] // the FAT tests do not need it
+pub fn init_page_allocator() {
+    const SIZE: usize = 0x10_0000; // 1M
+    let layout = std::alloc::Layout::from_size_align(SIZE, 0x1000).unwrap();
+    axalloc::global_init(unsafe { std::alloc::alloc(layout) } as usize, SIZE);
+}
+
 pub fn test_all() {
     test_read_write_file().expect("test_read_write_file() failed");
     test_read_dir().expect("test_read_dir() failed");
@@ -259,4 +585,13 @@ pub fn test_all() {
     test_create_file_dir().expect("test_create_file_dir() failed");
     test_remove_file_dir().expect("test_remove_file_dir() failed");
     test_devfs_ramfs().expect("test_devfs_ramfs() failed");
+    #[cfg(feature = "procfs")]
+    test_procfs().expect("test_procfs() failed");
+    test_rename().expect("test_rename() failed");
+    #[cfg(feature = "ramfs")]
+    test_mount().expect("test_mount() failed");
+    #[cfg(feature = "ramfs")]
+    test_links().expect("test_links() failed");
+    #[cfg(feature = "ramfs")]
+    test_attrs().expect("test_attrs() failed");
 }
@@ -0,0 +1,63 @@
+#![cfg(all(feature = "multitask", feature = "ext2", feature = "ramfs"))]
+#![cfg(not(any(feature = "fatfs", feature = "myfs")))]
+
+use axdriver::AxDeviceContainer;
+use axfs::api as fs;
+use axfs_ramfs::RamFileSystem;
+use axio::Error;
+use driver_block::ramdisk::RamDisk;
+use std::sync::Arc;
+
+const IMG_PATH: &str = "resources/ext2.img";
+
+fn make_disk() -> std::io::Result<RamDisk> {
+    let path = std::env::current_dir()?.join(IMG_PATH);
+    println!("Loading disk image from {:?} ...", path);
+    let data = std::fs::read(path)?;
+    Ok(RamDisk::from(&data))
+}
+
+#[test]
+fn test_cwd() {
+    println!("Testing per-task current directories ...");
+
+    let disk = make_disk().expect("failed to load disk image");
+    axtask::init_scheduler(); // call this to use `axsync::Mutex`.
+    axfs::init_filesystems(AxDeviceContainer::from_one(disk));
+
+    fs::create_dir("/tmp/job1").unwrap();
+    fs::create_dir("/tmp/job2").unwrap();
+    fs::set_current_dir("/tmp/job1").unwrap();
+
+    // the child starts in the directory of the parent, and changes its own
+    let child = axtask::spawn(|| {
+        assert_eq!(fs::current_dir().unwrap(), "/tmp/job1/");
+        fs::set_current_dir("../job2").unwrap();
+        fs::write("out.txt", "job2").unwrap();
+        assert_eq!(fs::current_dir().unwrap(), "/tmp/job2/");
+    });
+    child.join();
+    assert_eq!(fs::current_dir().unwrap(), "/tmp/job1/");
+    assert_eq!(fs::read_to_string("../job2/out.txt").unwrap(), "job2");
+    assert!(fs::metadata("out.txt").is_err());
+
+    // the current directory of any task keeps its filesystem mounted
+    fs::mount("/tmp/job2", Arc::new(RamFileSystem::new(None, None))).unwrap();
+    let child = axtask::spawn(|| {
+        fs::set_current_dir("/tmp/job2").unwrap();
+        axtask::exit(fs::umount("/tmp/job2").is_ok() as _);
+    });
+    assert_eq!(child.join(), Some(0));
+    let child = axtask::spawn(|| {
+        fs::set_current_dir("/").unwrap();
+        axtask::exit(fs::umount("/tmp/job2").is_ok() as _);
+    });
+    assert_eq!(child.join(), Some(1));
+    assert_eq!(fs::umount("/tmp/job2"), Err(Error::InvalidInput));
+
+    // renaming the directory moves the current directory
+    fs::rename("/tmp/job1", "/tmp/job3").unwrap();
+    assert_eq!(fs::current_dir().unwrap(), "/tmp/job3/");
+    fs::set_current_dir("/").unwrap();
+    println!("test_cwd() OK!");
+}
@@ -0,0 +1,232 @@
+#![cfg(all(feature = "ext2", not(any(feature = "fatfs", feature = "myfs"))))]
+#![cfg(not(feature = "overlayfs"))]
+
+mod test_common;
+
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::time::Duration;
+
+use axdriver::AxDeviceContainer;
+use axfs::api::{self as fs, File, FileType, Permissions};
+use axio::{prelude::*, Error, Result};
+use driver_block::ramdisk::RamDisk;
+
+const IMG_PATH: &str = "resources/ext2.img";
+
+static TIME: AtomicU64 = AtomicU64::new(1_700_000_000);
+
+fn fake_clock() -> Duration {
+    Duration::from_secs(TIME.load(Ordering::Relaxed))
+}
+
+fn make_disk() -> std::io::Result<RamDisk> {
+    let path = std::env::current_dir()?.join(IMG_PATH);
+    println!("Loading disk image from {:?} ...", path);
+    let data = std::fs::read(path)?;
+    println!("size = {} bytes", data.len());
+    Ok(RamDisk::from(&data))
+}
+
+fn test_ext2_links() -> Result<()> {
+    let test_txt = fs::read_to_string("/very/long/path/test.txt")?;
+    let short_txt = fs::read_to_string("/short.txt")?;
+
+    // fast symbolic links are stored in the inode, slow ones in a data block
+    let long_target = format!("{}very/long/path/test.txt", "./".repeat(40));
+    assert_eq!(fs::symlink("very/long/path/test.txt", "/fast"), Ok(()));
+    assert_eq!(fs::symlink(&long_target, "/slow"), Ok(()));
+    assert_eq!(fs::read_link("/fast")?, "very/long/path/test.txt");
+    assert_eq!(fs::read_link("/slow")?, long_target);
+    let md = fs::symlink_metadata("/slow")?;
+    assert_eq!(md.file_type(), FileType::SymLink);
+    assert_eq!(md.len(), long_target.len() as u64);
+    assert_eq!(fs::read_to_string("/fast")?, test_txt);
+    assert_eq!(fs::read_to_string("/slow")?, test_txt);
+
+    // hard links share the inode
+    assert_eq!(fs::hard_link("/short.txt", "/very/short.txt"), Ok(()));
+    assert_eq!(fs::hard_link("/fast", "/fast2"), Ok(()));
+    assert_eq!(fs::read_link("/fast2")?, "very/long/path/test.txt");
+    assert_eq!(fs::remove_file("/short.txt"), Ok(()));
+    assert_eq!(fs::read_to_string("/very/short.txt")?, short_txt);
+    assert_eq!(fs::hard_link("/very/short.txt", "/short.txt"), Ok(()));
+    assert_eq!(fs::remove_file("/very/short.txt"), Ok(()));
+    assert_eq!(
+        fs::hard_link("/short.txt", "/tmp/short.txt").err(),
+        Some(Error::CrossesDevices)
+    );
+    assert_eq!(
+        fs::hard_link("/very", "/very2").err(),
+        Some(Error::PermissionDenied)
+    );
+
+    for path in ["/fast", "/fast2", "/slow"] {
+        fs::remove_file(path)?;
+    }
+    assert_eq!(fs::read_to_string("/short.txt")?, short_txt);
+    println!("test_ext2_links() OK!");
+    Ok(())
+}
+
+fn test_ext2_rename() -> Result<()> {
+    // `..` of a moved directory points to the new parent
+    assert_eq!(fs::create_dir("/rename-dir"), Ok(()));
+    assert_eq!(fs::create_dir("/rename-dir/sub"), Ok(()));
+    assert_eq!(fs::rename("/rename-dir/sub", "/very/sub"), Ok(()));
+    assert!(fs::metadata("/very/sub/../long")?.is_dir());
+    assert_eq!(fs::remove_dir("/rename-dir"), Ok(()));
+
+    // the replaced file is released, hard links to it are kept
+    assert_eq!(fs::write("/rename-a.txt", "a"), Ok(()));
+    assert_eq!(fs::write("/rename-b.txt", "b"), Ok(()));
+    assert_eq!(fs::hard_link("/rename-b.txt", "/very/sub/b.txt"), Ok(()));
+    assert_eq!(fs::rename("/rename-a.txt", "/rename-b.txt"), Ok(()));
+    assert_eq!(fs::read_to_string("/rename-b.txt")?, "a");
+    assert_eq!(fs::read_to_string("/very/sub/b.txt")?, "b");
+
+    // renaming a hard link to another link of the same file does nothing
+    assert_eq!(fs::hard_link("/rename-b.txt", "/rename-c.txt"), Ok(()));
+    assert_eq!(fs::rename("/rename-b.txt", "/rename-c.txt"), Ok(()));
+    assert_eq!(fs::read_to_string("/rename-b.txt")?, "a");
+
+    for path in ["/rename-b.txt", "/rename-c.txt", "/very/sub/b.txt"] {
+        fs::remove_file(path)?;
+    }
+    assert_eq!(fs::remove_dir("/very/sub"), Ok(()));
+    println!("test_ext2_rename() OK!");
+    Ok(())
+}
+
+fn test_ext2_attrs() -> Result<()> {
+    let start = TIME.fetch_add(100, Ordering::Relaxed) + 100;
+    let secs = Duration::from_secs;
+
+    // new inodes and the changed directory get the current time
+    assert_eq!(fs::write("/attr.txt", "attr"), Ok(()));
+    let md = fs::metadata("/attr.txt")?;
+    assert!(md.ino() > 2);
+    assert_eq!((md.nlink(), md.uid(), md.gid()), (1, 0, 0));
+    assert_eq!((md.accessed(), md.modified()), (secs(start), secs(start)));
+    assert_eq!(fs::symlink_metadata("/")?.modified(), secs(start));
+    assert_eq!(fs::symlink_metadata("/")?.ino(), 2);
+    assert!(fs::symlink_metadata("/very")?.nlink() >= 2);
+
+    TIME.store(start + 10, Ordering::Relaxed);
+    assert_eq!(fs::write("/attr.txt", "attr2"), Ok(()));
+    let md = fs::metadata("/attr.txt")?;
+    assert_eq!(
+        (md.accessed(), md.modified()),
+        (secs(start), secs(start + 10))
+    );
+    assert_eq!(md.changed(), secs(start + 10));
+
+    // owners above 16 bits are stored in the high halves
+    TIME.store(start + 20, Ordering::Relaxed);
+    let perm = Permissions::from_bits_truncate(0o604);
+    assert_eq!(fs::set_permissions("/attr.txt", perm), Ok(()));
+    assert_eq!(fs::chown("/attr.txt", Some(70000), Some(100)), Ok(()));
+    assert_eq!(
+        fs::set_times("/attr.txt", Some(secs(1)), Some(secs(2))),
+        Ok(())
+    );
+    let md = fs::metadata("/attr.txt")?;
+    assert_eq!(md.permissions().bits(), 0o604);
+    assert_eq!(md.file_type(), FileType::File);
+    assert_eq!((md.uid(), md.gid()), (70000, 100));
+    assert_eq!((md.accessed(), md.modified()), (secs(1), secs(2)));
+    assert_eq!(md.changed(), secs(start + 20));
+
+    assert_eq!(fs::hard_link("/attr.txt", "/very/attr.txt"), Ok(()));
+    assert_eq!(fs::metadata("/attr.txt")?.nlink(), 2);
+    assert_eq!(fs::remove_file("/very/attr.txt"), Ok(()));
+    assert_eq!(fs::metadata("/attr.txt")?.nlink(), 1);
+    assert_eq!(fs::remove_file("/attr.txt"), Ok(()));
+    println!("test_ext2_attrs() OK!");
+    Ok(())
+}
+
+fn test_ext2_page_cache() -> Result<()> {
+    // opened files of the same node share the cached pages
+    let mut writer = File::create("/cache.txt")?;
+    let mut reader = File::open("/cache.txt")?;
+    writer.write_all(b"cached")?;
+    let mut contents = String::new();
+    reader.read_to_string(&mut contents)?;
+    assert_eq!(contents, "cached");
+    drop((writer, reader));
+
+    // unaligned writes across pages, shrinking and extending
+    let data = (0..3 * 4096 + 100).map(|i| i as u8).collect::<Vec<_>>();
+    let mut file = File::create("/cache.bin")?;
+    file.write_all(&data[..1000])?;
+    file.write_all(&data[1000..])?;
+    file.set_len(5000)?;
+    file.set_len(6000)?;
+    file.flush()?;
+    drop(file);
+    let contents = fs::read("/cache.bin")?;
+    assert_eq!(contents[..5000], data[..5000]);
+    assert_eq!(contents[5000..], [0; 1000]);
+
+    // the cache of a removed file is not written back, even if its inode is
+    // reused by a new file
+    let mut removed = File::create("/cache.txt")?;
+    removed.write_all(b"removed")?;
+    assert_eq!(fs::remove_file("/cache.txt"), Ok(()));
+    assert_eq!(fs::write("/cache.txt", "new"), Ok(()));
+    drop(removed);
+    assert_eq!(fs::read_to_string("/cache.txt")?, "new");
+
+    // the file is larger than the memory for the page cache, pages are written
+    // back and evicted
+    let big = (0..0x14_0000).map(|i| (i / 4096) as u8).collect::<Vec<_>>();
+    assert_eq!(fs::write("/cache-big.bin", &big), Ok(()));
+    assert!(fs::read("/cache-big.bin")? == big);
+
+    for path in ["/cache.txt", "/cache.bin", "/cache-big.bin"] {
+        fs::remove_file(path)?;
+    }
+    println!("test_ext2_page_cache() OK!");
+    Ok(())
+}
+
+fn test_ext2_statfs() -> Result<()> {
+    let info = fs::statfs("/very/long/path")?;
+    assert_eq!(info.fs_type, 0xef53);
+    assert_eq!(info.name_max, 255);
+    assert!(info.blocks_avail <= info.blocks_free && info.blocks_free < info.blocks);
+    assert!(info.files_free < info.files);
+    assert_eq!(fs::statfs("/not-exist").err(), Some(Error::NotFound));
+
+    // blocks and inodes are taken by new files, and returned after removal
+    let data = vec![0xa5; 0x10000];
+    assert_eq!(fs::write("/statfs.bin", &data), Ok(()));
+    let used = fs::statfs("/")?;
+    let data_blocks = data.len() as u64 / info.block_size;
+    assert!(used.blocks_free <= info.blocks_free - data_blocks);
+    assert_eq!(used.files_free, info.files_free - 1);
+    assert_eq!(fs::remove_file("/statfs.bin"), Ok(()));
+    let freed = fs::statfs("/")?;
+    assert_eq!(freed.blocks_free, info.blocks_free);
+    assert_eq!(freed.files_free, info.files_free);
+    println!("test_ext2_statfs() OK!");
+    Ok(())
+}
+
+#[test]
+fn test_ext2() {
+    println!("Testing ext2 with ramdisk ...");
+
+    let disk = make_disk().expect("failed to load disk image");
+    axtask::init_scheduler(); // call this to use `axsync::Mutex`.
+    test_common::init_page_allocator();
+    axfs::set_clock(fake_clock);
+    axfs::init_filesystems(AxDeviceContainer::from_one(disk));
+
+    test_common::test_all();
+    test_ext2_links().expect("test_ext2_links() failed");
+    test_ext2_rename().expect("test_ext2_rename() failed");
+    test_ext2_attrs().expect("test_ext2_attrs() failed");
+    test_ext2_page_cache().expect("test_ext2_page_cache() failed");
+    test_ext2_statfs().expect("test_ext2_statfs() failed");
+}
@@ -0,0 +1,130 @@
+#![cfg(all(feature = "ext2", not(any(feature = "fatfs", feature = "myfs"))))]
+#![cfg(not(feature = "overlayfs"))]
+
+mod test_common;
+
+use axdriver::AxDeviceContainer;
+use axfs::api::{self as fs, File};
+use axio::{prelude::*, Result, SeekFrom};
+use driver_block::ramdisk::RamDisk;
+
+const IMG_PATH: &str = "resources/ext3.img";
+const BLOCK_SIZE: usize = 1024;
+
+fn make_disk() -> std::io::Result<RamDisk> {
+    let path = std::env::current_dir()?.join(IMG_PATH);
+    println!("Loading disk image from {:?} ...", path);
+    let data = std::fs::read(path)?;
+    println!("size = {} bytes", data.len());
+    Ok(RamDisk::from(&data))
+}
+
+/// Reads what is on the disk, bypassing the caches of the filesystem, i.e.,
+/// what would be left if the power was cut now.
+fn read_disk() -> Result<Vec<u8>> {
+    let mut data = Vec::new();
+    File::open("/dev/vda")?.read_to_end(&mut data)?;
+    Ok(data)
+}
+
+fn le32(data: &[u8], pos: usize) -> u32 {
+    u32::from_le_bytes(data[pos..pos + 4].try_into().unwrap())
+}
+
+fn be32(data: &[u8], pos: usize) -> u32 {
+    u32::from_be_bytes(data[pos..pos + 4].try_into().unwrap())
+}
+
+fn block(data: &[u8], block: u32) -> &[u8] {
+    &data[block as usize * BLOCK_SIZE..][..BLOCK_SIZE]
+}
+
+/// Returns the block bitmap of the only group.
+fn block_bitmap(data: &[u8]) -> &[u8] {
+    block(data, le32(data, 2 * BLOCK_SIZE))
+}
+
+/// Returns the superblock of the journal, in the first block of inode 8.
+fn journal_superblock(data: &[u8]) -> &[u8] {
+    let inode_table = le32(data, 2 * BLOCK_SIZE + 8) as usize;
+    let inode = inode_table * BLOCK_SIZE + 7 * 128;
+    block(data, le32(data, inode + 40))
+}
+
+fn test_ext3_replay() -> Result<()> {
+    // the image has two committed transactions in the journal, the second one
+    // revokes a block logged by the first one
+    assert_eq!(fs::read_to_string("/journal.txt")?, "Replayed! :)\n");
+    assert_eq!(fs::read_to_string("/revoked.txt")?, "Not replayed\n");
+
+    // the log is emptied, and the filesystem needs recovery until unmounted
+    let data = read_disk()?;
+    let journal_sb = journal_superblock(&data);
+    assert_eq!(be32(journal_sb, 0), 0xc03b_3998);
+    assert_eq!(be32(journal_sb, 28), 0);
+    assert_ne!(le32(&data, 1024 + 96) & 0x4, 0);
+    println!("test_ext3_replay() OK!");
+    Ok(())
+}
+
+fn test_ext3_power_cut() -> Result<()> {
+    fs::write("/power-cut-removed.txt", "removed")?;
+    let mut file = File::create("/power-cut.txt")?;
+    file.write_all(b"committed")?;
+    file.flush()?; // commits the transaction
+    let committed = read_disk()?;
+    assert_eq!(be32(journal_superblock(&committed), 28), 0);
+
+    // uncommitted updates only write blocks that are free on the disk, even
+    // the ones freed by themselves
+    file.set_len(0)?;
+    file.seek(SeekFrom::Start(0))?;
+    file.write_all(b"uncommitted")?;
+    fs::create_dir("/power-cut")?;
+    fs::rename("/short.txt", "/power-cut/short.txt")?;
+    fs::remove_file("/power-cut-removed.txt")?;
+    // enough data to evict everything else from the cache of the disk
+    fs::write("/power-cut/new.bin", vec![0x5a; 0x10_0000])?;
+    let uncommitted = read_disk()?;
+    let bitmap = block_bitmap(&committed);
+    let blocks = committed
+        .chunks(BLOCK_SIZE)
+        .zip(uncommitted.chunks(BLOCK_SIZE));
+    for (i, (old, new)) in blocks.enumerate() {
+        if old != new {
+            // block 0 is not in the bitmap
+            let bit = i.checked_sub(1).expect("block 0 is modified");
+            assert_eq!(bitmap[bit / 8] & (1 << (bit % 8)), 0, "block {}", i);
+        }
+    }
+
+    // the metadata reaches the disk when committed
+    file.flush()?;
+    drop(file);
+    let synced = read_disk()?;
+    assert_eq!(be32(journal_superblock(&synced), 28), 0);
+    assert!(block_bitmap(&synced) != block_bitmap(&committed));
+    assert_eq!(fs::read_to_string("/power-cut.txt")?, "uncommitted");
+
+    fs::rename("/power-cut/short.txt", "/short.txt")?;
+    for path in ["/power-cut/new.bin", "/power-cut.txt"] {
+        fs::remove_file(path)?;
+    }
+    fs::remove_dir("/power-cut")?;
+    println!("test_ext3_power_cut() OK!");
+    Ok(())
+}
+
+#[test]
+fn test_ext3() {
+    println!("Testing ext3 with ramdisk ...");
+
+    let disk = make_disk().expect("failed to load disk image");
+    axtask::init_scheduler(); // call this to use `axsync::Mutex`.
+    test_common::init_page_allocator();
+    axfs::init_filesystems(AxDeviceContainer::from_one(disk));
+
+    test_ext3_replay().expect("test_ext3_replay() failed");
+    test_common::test_all();
+    test_ext3_power_cut().expect("test_ext3_power_cut() failed");
+}
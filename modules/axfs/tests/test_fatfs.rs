@@ -1,8 +1,10 @@
-#![cfg(not(feature = "myfs"))]
+#![cfg(all(feature = "fatfs", not(feature = "myfs")))]
 
 mod test_common;
 
 use axdriver::AxDeviceContainer;
+use axfs::api as fs;
+use axio::{Error, Result};
 use driver_block::ramdisk::RamDisk;
 
 const IMG_PATH: &str = "resources/fat16.img";
@@ -15,6 +17,55 @@ fn make_disk() -> std::io::Result<RamDisk> {
     Ok(RamDisk::from(&data))
 }
 
+fn entry_names(path: &str) -> Result<Vec<String>> {
+    fs::read_dir(path)?
+        .map(|e| e.map(|e| e.file_name()))
+        .collect()
+}
+
+/// FAT replaces the destination of a rename in several steps, check that no
+/// backup of the replaced entry is left behind.
+fn test_fatfs_rename() -> Result<()> {
+    fs::create_dir("/fat-rename")?;
+    fs::write("/fat-rename/a.txt", "a")?;
+    fs::write("/fat-rename/b.txt", "b")?;
+    fs::rename("/fat-rename/a.txt", "/fat-rename/b.txt")?;
+    assert_eq!(fs::read_to_string("/fat-rename/b.txt")?, "a");
+    assert_eq!(entry_names("/fat-rename")?, ["b.txt"]);
+
+    // names differing only in case refer to the same entry, whose case can
+    // still be changed
+    fs::rename("/fat-rename/b.txt", "/fat-rename/B.TXT")?;
+    assert_eq!(entry_names("/fat-rename")?, ["B.TXT"]);
+    fs::rename("/FAT-RENAME/B.TXT", "/fat-rename/b.txt")?;
+    assert_eq!(entry_names("/fat-rename")?, ["b.txt"]);
+    fs::rename("/FAT-RENAME/b.txt", "/fat-rename/b.txt")?;
+    assert_eq!(fs::read_to_string("/fat-rename/B.txt")?, "a");
+
+    // an empty directory is replaced, a non-empty one is kept
+    fs::create_dir("/fat-rename/d1")?;
+    fs::create_dir("/fat-rename/d2")?;
+    fs::write("/fat-rename/d1/c.txt", "c")?;
+    fs::rename("/fat-rename/d1", "/fat-rename/d2")?;
+    assert_eq!(fs::read_to_string("/fat-rename/d2/c.txt")?, "c");
+    fs::create_dir("/fat-rename/d3")?;
+    assert_eq!(
+        fs::rename("/fat-rename/d3", "/fat-rename/d2").err(),
+        Some(Error::DirectoryNotEmpty)
+    );
+    assert_eq!(fs::read_to_string("/fat-rename/d2/c.txt")?, "c");
+
+    let mut names = entry_names("/fat-rename")?;
+    names.sort();
+    assert_eq!(names, ["b.txt", "d2", "d3"]);
+
+    fs::remove_file("/fat-rename/d2/c.txt")?;
+    fs::remove_dir("/fat-rename/d2")?;
+    fs::remove_dir("/fat-rename/d3")?;
+    fs::remove_file("/fat-rename/b.txt")?;
+    fs::remove_dir("/fat-rename")
+}
+
 #[test]
 fn test_fatfs() {
     println!("Testing fatfs with ramdisk ...");
@@ -24,4 +75,5 @@ fn test_fatfs() {
     axfs::init_filesystems(AxDeviceContainer::from_one(disk));
 
     test_common::test_all();
+    test_fatfs_rename().expect("test_fatfs_rename() failed");
 }
@@ -0,0 +1,123 @@
+#![cfg(all(feature = "ext2", not(any(feature = "fatfs", feature = "myfs"))))]
+
+#[allow(dead_code)]
+mod test_common;
+
+use axdriver::AxDeviceContainer;
+use axfs::api::{self as fs, FsType};
+use axio::Error;
+use driver_block::ramdisk::RamDisk;
+
+const IMG_PATH: &str = "resources/ext2.img";
+const SECTOR_SIZE: usize = 512;
+const GAP_SECTORS: usize = 2048;
+const BLANK_SECTORS: usize = 8 << 11; // 8M
+
+fn write_mbr_entry(sector: &mut [u8], idx: usize, ty: u8, start: usize, count: usize) {
+    let entry = &mut sector[446 + idx * 16..446 + (idx + 1) * 16];
+    entry[4] = ty;
+    entry[8..12].copy_from_slice(&(start as u32).to_le_bytes());
+    entry[12..16].copy_from_slice(&(count as u32).to_le_bytes());
+    sector[510..512].copy_from_slice(&[0x55, 0xaa]);
+}
+
+/// Puts the ext2 image in the first partition, and leaves the second one
+/// blank.
+fn make_disk() -> std::io::Result<RamDisk> {
+    let path = std::env::current_dir()?.join(IMG_PATH);
+    println!("Loading disk image from {:?} ...", path);
+    let fs_img = std::fs::read(path)?;
+    let fs_sectors = fs_img.len() / SECTOR_SIZE;
+
+    let part1_start = GAP_SECTORS;
+    let part2_start = part1_start + fs_sectors;
+    let mut data = vec![0; (part2_start + BLANK_SECTORS) * SECTOR_SIZE];
+    write_mbr_entry(&mut data, 0, 0x83, part1_start, fs_sectors);
+    write_mbr_entry(&mut data, 1, 0x83, part2_start, BLANK_SECTORS);
+    data[part1_start * SECTOR_SIZE..][..fs_img.len()].copy_from_slice(&fs_img);
+    // garbage left on the blank partition
+    data[part2_start * SECTOR_SIZE..].fill(0xa5);
+    Ok(RamDisk::from(&data))
+}
+
+/// Files of the same inode number on the root filesystem and the one mounted at
+/// `/mnt/ramdisk2` have their own pages, even if one is opened relative to a
+/// directory on the other.
+#[cfg(not(feature = "overlayfs"))]
+fn test_page_cache_keys() {
+    use axfs::fops;
+
+    fs::write("/same-ino.txt", "root").unwrap();
+    let root_file = fs::File::open("/same-ino.txt").unwrap();
+    let ino = root_file.metadata().unwrap().ino();
+    let mut opts = fops::OpenOptions::new();
+    opts.read(true);
+    let root = fops::Directory::open_dir("/", &opts).unwrap();
+    opts.write(true);
+    opts.create(true);
+    let mut file = (0..)
+        .map(|i| root.open_file_at(&format!("mnt/ramdisk2/f{}", i), &opts))
+        .find(|f| f.as_ref().unwrap().get_attr().unwrap().ino() >= ino)
+        .unwrap()
+        .unwrap();
+    assert_eq!(file.get_attr().unwrap().ino(), ino);
+    assert_eq!(file.read(&mut [0; 4]), Ok(0));
+    assert_eq!(fs::umount("/mnt/ramdisk2"), Err(Error::ResourceBusy));
+    drop((file, root, root_file));
+    fs::remove_file("/same-ino.txt").unwrap();
+}
+
+#[test]
+fn test_format() {
+    println!("Testing format with ramdisk ...");
+
+    let disk = make_disk().expect("failed to load disk image");
+    axtask::init_scheduler(); // call this to use `axsync::Mutex`.
+    test_common::init_page_allocator();
+    axfs::init_filesystems(AxDeviceContainer::from_one(disk));
+
+    // the blank partition is not mounted, and mounted ones cannot be formatted
+    let ext2 = FsType::Ext2;
+    assert!(fs::metadata("/mnt/ramdisk2").is_err());
+    assert_eq!(fs::format("ramdisk1", ext2), Err(Error::ResourceBusy));
+    assert_eq!(fs::format("ramdisk3", ext2), Err(Error::NotFound));
+
+    assert_eq!(fs::format("ramdisk2", ext2), Ok(()));
+    assert_eq!(fs::format("ramdisk2", ext2), Err(Error::ResourceBusy));
+    let dirents = fs::read_dir("/mnt/ramdisk2")
+        .unwrap()
+        .map(|e| e.unwrap().file_name())
+        .collect::<Vec<_>>();
+    assert_eq!(dirents, ["lost+found"]);
+    let lost_found = fs::metadata("/mnt/ramdisk2/lost+found").unwrap();
+    assert!(lost_found.is_dir());
+    assert_eq!(lost_found.permissions().bits(), 0o700);
+
+    let info = fs::statfs("/mnt/ramdisk2").unwrap();
+    assert_eq!(info.fs_type, 0xef53);
+    assert_eq!(info.block_size, 1024);
+    assert_eq!(info.blocks, (BLANK_SECTORS * SECTOR_SIZE / 1024) as u64);
+    assert_eq!(info.blocks_avail, info.blocks_free - info.blocks / 20);
+    assert_eq!(info.files, 2048);
+    assert_eq!(info.files_free, info.files - 11);
+
+    // the new filesystem is usable
+    fs::create_dir("/mnt/ramdisk2/a").unwrap();
+    fs::create_dir("/mnt/ramdisk2/a/b").unwrap();
+    let data = (0..0x4_0000).map(|i| i as u8).collect::<Vec<_>>();
+    fs::write("/mnt/ramdisk2/a/b/data.bin", &data).unwrap();
+    assert!(fs::read("/mnt/ramdisk2/a/b/data.bin").unwrap() == data);
+    let used = fs::statfs("/mnt/ramdisk2").unwrap();
+    assert!(used.blocks_free <= info.blocks_free - data.len() as u64 / 1024);
+    assert_eq!(used.files_free, info.files_free - 3);
+
+    #[cfg(not(feature = "overlayfs"))]
+    test_page_cache_keys();
+
+    // formatted again after unmounted
+    assert_eq!(fs::umount("/mnt/ramdisk2"), Ok(()));
+    assert_eq!(fs::format("ramdisk2", ext2), Ok(()));
+    assert!(fs::metadata("/mnt/ramdisk2/a").is_err());
+    assert_eq!(fs::umount("/mnt/ramdisk2"), Ok(()));
+    println!("test_format() OK!");
+}
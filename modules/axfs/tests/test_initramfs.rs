@@ -0,0 +1,42 @@
+#![cfg(all(feature = "initramfs", feature = "devfs", feature = "ramfs"))]
+#![cfg(not(any(feature = "myfs", feature = "overlayfs")))]
+
+#[allow(dead_code)]
+mod test_common;
+
+use axdriver::AxDeviceContainer;
+use axfs::api as fs;
+use axio::Error;
+
+#[test]
+fn test_initramfs() {
+    println!("Testing initramfs without disks ...");
+
+    axtask::init_scheduler(); // call this to use `axsync::Mutex`.
+    test_common::init_page_allocator();
+    axfs::init_filesystems(AxDeviceContainer::default());
+
+    // the initramfs is the root, with the mount points of other filesystems
+    assert_eq!(fs::statfs("/").unwrap().fs_type, 0x8584_58f6);
+    let mut names = fs::read_dir("/")
+        .unwrap()
+        .map(|e| e.unwrap().file_name())
+        .collect::<Vec<_>>();
+    names.retain(|name| ["dev", "proc", "tmp"].contains(&name.as_str()));
+    names.sort();
+    assert_eq!(names, ["dev", "proc", "tmp"]);
+    assert!(fs::read_to_string("/proc/mounts")
+        .unwrap()
+        .starts_with("initramfs /\n"));
+
+    // it is read-only, but the filesystems mounted on it are not
+    assert_eq!(fs::create_dir("/new").err(), Some(Error::PermissionDenied));
+    assert_eq!(
+        fs::write("/new.txt", "x").err(),
+        Some(Error::PermissionDenied)
+    );
+    fs::write("/tmp/new.txt", "initramfs").unwrap();
+    assert_eq!(fs::read_to_string("/tmp/new.txt").unwrap(), "initramfs");
+    assert!(fs::metadata("/dev/null").is_ok());
+    println!("test_initramfs() OK!");
+}
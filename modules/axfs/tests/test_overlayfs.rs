@@ -0,0 +1,55 @@
+#![cfg(all(feature = "overlayfs", feature = "ext2", feature = "devfs"))]
+#![cfg(not(any(feature = "fatfs", feature = "myfs")))]
+
+mod test_common;
+
+use axdriver::AxDeviceContainer;
+use axfs::api as fs;
+use axio::{Error, Read};
+use driver_block::ramdisk::RamDisk;
+
+const IMG_PATH: &str = "resources/ext2.img";
+
+fn load_image() -> std::io::Result<Vec<u8>> {
+    let path = std::env::current_dir()?.join(IMG_PATH);
+    println!("Loading disk image from {:?} ...", path);
+    std::fs::read(path)
+}
+
+#[test]
+fn test_overlayfs() {
+    println!("Testing overlayfs on ext2 with ramdisk ...");
+
+    let image = load_image().expect("failed to load disk image");
+    axtask::init_scheduler(); // call this to use `axsync::Mutex`.
+    test_common::init_page_allocator();
+    axfs::init_filesystems(AxDeviceContainer::from_one(RamDisk::from(&image)));
+
+    test_common::test_all();
+    assert_eq!(fs::statfs("/").unwrap().fs_type, 0x794c_7630);
+
+    // files in the image can be changed and removed
+    fs::write("/short.txt", "Overlay is cool!\n").unwrap();
+    assert_eq!(
+        fs::read_to_string("/short.txt").unwrap(),
+        "Overlay is cool!\n"
+    );
+    fs::remove_file("/very/long/path/test.txt").unwrap();
+    assert_eq!(
+        fs::metadata("/very/long/path/test.txt").err(),
+        Some(Error::NotFound)
+    );
+    assert_eq!(
+        fs::rename("/very", "/very2").err(),
+        Some(Error::CrossesDevices)
+    );
+
+    // but the image itself is never written, except that ext2 marks the
+    // superblock as mounted
+    let mut disk = fs::File::open("/dev/vda").unwrap();
+    let mut data = Vec::new();
+    disk.read_to_end(&mut data).unwrap();
+    assert_eq!(data.len(), image.len());
+    assert!(data[..1024] == image[..1024] && data[2048..] == image[2048..]);
+    println!("test_overlayfs() OK!");
+}
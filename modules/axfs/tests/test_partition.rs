@@ -0,0 +1,106 @@
+#![cfg(all(feature = "ext2", not(any(feature = "fatfs", feature = "myfs"))))]
+
+#[allow(dead_code)]
+mod test_common;
+
+use axdriver::AxDeviceContainer;
+use axfs::api as fs;
+use axio::{Read, Seek, SeekFrom, Write};
+use driver_block::ramdisk::RamDisk;
+
+const IMG_PATH: &str = "resources/ext2.img";
+const SECTOR_SIZE: usize = 512;
+const GAP_SECTORS: usize = 2048;
+
+fn write_mbr_entry(sector: &mut [u8], idx: usize, status: u8, ty: u8, start: usize, count: usize) {
+    let entry = &mut sector[446 + idx * 16..446 + (idx + 1) * 16];
+    entry[0] = status;
+    entry[4] = ty;
+    entry[8..12].copy_from_slice(&(start as u32).to_le_bytes());
+    entry[12..16].copy_from_slice(&(count as u32).to_le_bytes());
+    sector[510..512].copy_from_slice(&[0x55, 0xaa]);
+}
+
+/// Puts the ext2 image in a primary partition, and a logical partition in an
+/// extended partition after it.
+fn make_disk() -> std::io::Result<RamDisk> {
+    let path = std::env::current_dir()?.join(IMG_PATH);
+    println!("Loading disk image from {:?} ...", path);
+    let fs_img = std::fs::read(path)?;
+    let fs_sectors = fs_img.len() / SECTOR_SIZE;
+
+    let part1_start = GAP_SECTORS;
+    let ext_start = part1_start + fs_sectors;
+    let part5_start = ext_start + GAP_SECTORS;
+    let mut data = vec![0; (part5_start + fs_sectors) * SECTOR_SIZE];
+
+    let mbr = &mut data[..SECTOR_SIZE];
+    write_mbr_entry(mbr, 0, 0x80, 0x83, part1_start, fs_sectors);
+    write_mbr_entry(mbr, 1, 0x00, 0x05, ext_start, GAP_SECTORS + fs_sectors);
+    let ebr = &mut data[ext_start * SECTOR_SIZE..(ext_start + 1) * SECTOR_SIZE];
+    write_mbr_entry(ebr, 0, 0x00, 0x83, GAP_SECTORS, fs_sectors);
+
+    data[part1_start * SECTOR_SIZE..][..fs_img.len()].copy_from_slice(&fs_img);
+    data[part5_start * SECTOR_SIZE..][..fs_img.len()].copy_from_slice(&fs_img);
+    println!("size = {} bytes", data.len());
+    Ok(RamDisk::from(&data))
+}
+
+#[test]
+fn test_partition() {
+    println!("Testing MBR partitions with ramdisk ...");
+
+    let disk = make_disk().expect("failed to load disk image");
+    axtask::init_scheduler(); // call this to use `axsync::Mutex`.
+    test_common::init_page_allocator();
+    axfs::init_filesystems(AxDeviceContainer::from_one(disk));
+
+    // the first partition is mounted on `/`, the logical one on `/mnt`
+    let dirents = fs::read_dir("/mnt")
+        .unwrap()
+        .map(|e| e.unwrap().file_name())
+        .collect::<Vec<_>>();
+    assert_eq!(dirents, ["ramdisk5"]);
+    assert_eq!(fs::read_to_string("/short.txt").unwrap(), "Rust is cool!\n");
+    assert_eq!(
+        fs::read_to_string("/mnt/ramdisk5/very/long/path/test.txt").unwrap(),
+        "Rust is cool!\n"
+    );
+
+    // partitions do not overlap
+    fs::write("/mnt/ramdisk5/short.txt", "Hello, world!\n").unwrap();
+    assert_eq!(fs::read_to_string("/short.txt").unwrap(), "Rust is cool!\n");
+    assert_eq!(
+        fs::read_to_string("/mnt/ramdisk5/short.txt").unwrap(),
+        "Hello, world!\n"
+    );
+
+    // the raw device covers the partition table and all partitions
+    let mut disk = fs::File::options()
+        .read(true)
+        .write(true)
+        .open("/dev/vda")
+        .unwrap();
+    let mut mbr = [0; SECTOR_SIZE];
+    assert_eq!(disk.read(&mut mbr).unwrap(), SECTOR_SIZE);
+    assert_eq!(mbr[510..], [0x55, 0xaa]);
+    assert_eq!(mbr[446 + 4], 0x83);
+
+    // unaligned writes to the gap before the first partition
+    let offset = (SECTOR_SIZE * 3 - 5) as u64;
+    assert_eq!(disk.seek(SeekFrom::Start(offset)).unwrap(), offset);
+    assert_eq!(disk.write(b"Hello, world!").unwrap(), 13);
+    assert_eq!(disk.seek(SeekFrom::Start(offset - 1)).unwrap(), offset - 1);
+    let mut buf = [0xff; 15];
+    assert_eq!(disk.read(&mut buf).unwrap(), 15);
+    assert_eq!(&buf, b"\0Hello, world!\0");
+    let size = disk.metadata().unwrap().len();
+    assert_eq!(disk.seek(SeekFrom::Start(size - 4)).unwrap(), size - 4);
+    assert_eq!(disk.read(&mut buf).unwrap(), 4);
+    assert_eq!(disk.write(b"full").unwrap_err(), axio::Error::StorageFull);
+    drop(disk);
+
+    assert_eq!(fs::umount("/mnt/ramdisk5"), Ok(()));
+    assert!(fs::metadata("/mnt/ramdisk5/short.txt").is_err());
+    println!("test_partition() OK!");
+}
@@ -17,7 +17,7 @@ struct MyFileSystemIfImpl;
 #[crate_interface::impl_interface]
 impl MyFileSystemIf for MyFileSystemIfImpl {
     fn new_myfs(_disk: Disk) -> Arc<dyn VfsOps> {
-        Arc::new(RamFileSystem::new())
+        Arc::new(RamFileSystem::new(None, None))
     }
 }
 
@@ -46,6 +46,7 @@ fn test_ramfs() {
     println!("Testing ramfs ...");
 
     axtask::init_scheduler(); // call this to use `axsync::Mutex`.
+    test_common::init_page_allocator();
     axfs::init_filesystems(AxDeviceContainer::from_one(RamDisk::default())); // dummy disk, actually not used.
 
     if let Err(e) = create_init_files() {
@@ -1,5 +1,7 @@
 //! Interrupt management.
 
+use core::sync::atomic::{AtomicUsize, Ordering};
+
 use handler_table::HandlerTable;
 
 use crate::platform::irq::MAX_IRQ_COUNT;
@@ -11,10 +13,33 @@ pub type IrqHandler = handler_table::Handler;
 
 static IRQ_HANDLER_TABLE: HandlerTable<MAX_IRQ_COUNT> = HandlerTable::new();
 
+#[allow(clippy::declare_interior_mutable_const)]
+const ZERO: AtomicUsize = AtomicUsize::new(0);
+static IRQ_COUNTS: [AtomicUsize; MAX_IRQ_COUNT] = [ZERO; MAX_IRQ_COUNT];
+
+/// Returns the IRQ numbers that have occurred, along with how many times each
+/// of them occurred since boot.
+pub fn irq_counts() -> impl Iterator<Item = (usize, usize)> {
+    IRQ_COUNTS
+        .iter()
+        .enumerate()
+        .map(|(irq_num, count)| (irq_num, count.load(Ordering::Relaxed)))
+        .filter(|&(_, count)| count > 0)
+}
+
+/// Counts an occurrence of the IRQ, it is done in [`dispatch_irq_common`]
+/// unless the platform handles the IRQ by itself.
+pub(crate) fn count_irq(irq_num: usize) {
+    if let Some(count) = IRQ_COUNTS.get(irq_num) {
+        count.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
 /// Platform-independent IRQ dispatching.
 #[allow(dead_code)]
 pub(crate) fn dispatch_irq_common(irq_num: usize) {
     trace!("IRQ {}", irq_num);
+    count_irq(irq_num);
     if !IRQ_HANDLER_TABLE.handle(irq_num) {
         warn!("Unhandled IRQ {}", irq_num);
     }
@@ -1,7 +1,9 @@
 //! Page table manipulation.
 
 use axalloc::global_allocator;
+use lazy_init::LazyInit;
 use page_table::PagingIf;
+use spinlock::SpinNoIrq;
 
 use crate::mem::{phys_to_virt, virt_to_phys, MemRegionFlags, PhysAddr, VirtAddr, PAGE_SIZE_4K};
 
@@ -61,3 +63,22 @@ cfg_if::cfg_if! {
         pub type PageTable = page_table::aarch64::A64PageTable<PagingIfImpl>;
     }
 }
+
+static KERNEL_PAGE_TABLE: LazyInit<SpinNoIrq<PageTable>> = LazyInit::new();
+
+/// Sets the page table of the kernel address space, which is shared by all
+/// CPUs.
+///
+/// It can only be called once, by the primary CPU.
+pub fn init_kernel_page_table(page_table: PageTable) {
+    KERNEL_PAGE_TABLE.init_by(SpinNoIrq::new(page_table));
+}
+
+/// Returns the page table of the kernel address space, to add or remove
+/// mappings after it is initialized by [`init_kernel_page_table`].
+///
+/// The TLB of the current CPU should be flushed after the mappings are
+/// changed, see [`flush_tlb`](crate::arch::flush_tlb).
+pub fn kernel_page_table() -> &'static SpinNoIrq<PageTable> {
+    &KERNEL_PAGE_TABLE
+}
@@ -11,6 +11,7 @@
 //! - [`IpAddr`], [`Ipv4Addr`]: IP addresses (either v4 or v6) and IPv4 addresses.
 //! - [`SocketAddr`]: IP address with a port number.
 //! - [`resolve_socket_addr`]: Function for DNS query.
+//! - [`sockets`]: Function for listing all sockets.
 //!
 //! # Cargo Features
 //!
@@ -26,6 +27,8 @@
 extern crate log;
 extern crate alloc;
 
+use alloc::string::String;
+
 cfg_if::cfg_if! {
     if #[cfg(feature = "smoltcp")] {
         mod smoltcp_impl;
@@ -34,12 +37,26 @@ cfg_if::cfg_if! {
 }
 
 pub use self::net_impl::resolve_socket_addr;
+pub use self::net_impl::sockets;
 pub use self::net_impl::TcpSocket;
 pub use self::net_impl::UdpSocket;
 pub use smoltcp::wire::{IpAddress as IpAddr, IpEndpoint as SocketAddr, Ipv4Address as Ipv4Addr};
 
 use axdriver::{prelude::*, AxDeviceContainer};
 
+/// Brief information of a socket, returned by [`sockets`].
+#[derive(Debug, Clone)]
+pub struct SocketInfo {
+    /// The transport protocol, `"tcp"` or `"udp"`.
+    pub protocol: &'static str,
+    /// The local address, or `None` if the socket is not bound.
+    pub local_addr: Option<SocketAddr>,
+    /// The remote address, or `None` if the socket is not connected.
+    pub peer_addr: Option<SocketAddr>,
+    /// The state of the socket, e.g., `LISTEN` or `ESTABLISHED` for TCP.
+    pub state: String,
+}
+
 /// Initializes the network subsystem by NIC devices.
 pub fn init_network(mut net_devs: AxDeviceContainer<AxNetDevice>) {
     info!("Initialize network subsystem...");
@@ -1,4 +1,4 @@
-use alloc::{boxed::Box, collections::VecDeque};
+use alloc::{boxed::Box, collections::VecDeque, vec::Vec};
 use core::ops::{Deref, DerefMut};
 
 use axerrno::{ax_err, AxError, AxResult};
@@ -64,6 +64,14 @@ impl ListenTable {
         }
     }
 
+    /// Returns the ports being listened on, in ascending order.
+    pub fn listening_ports(&self) -> Vec<u16> {
+        (0..PORT_NUM)
+            .filter(|&port| self.tcp[port].lock().is_some())
+            .map(|port| port as u16)
+            .collect()
+    }
+
     pub fn unlisten(&self, port: u16) {
         debug!("socket unlisten on {}", port);
         *self.tcp[port as usize].lock() = None;
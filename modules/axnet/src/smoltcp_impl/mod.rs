@@ -3,7 +3,8 @@ mod listen_table;
 mod tcp;
 mod udp;
 
-use alloc::{collections::VecDeque, vec};
+use alloc::string::ToString;
+use alloc::{collections::VecDeque, vec, vec::Vec};
 use core::cell::RefCell;
 use core::ops::DerefMut;
 
@@ -14,11 +15,12 @@ use driver_net::{DevError, NetBufferBox, NetBufferPool};
 use lazy_init::LazyInit;
 use smoltcp::iface::{Config, Interface, SocketHandle, SocketSet};
 use smoltcp::phy::{Device, DeviceCapabilities, Medium, RxToken, TxToken};
-use smoltcp::socket::{self, AnySocket};
+use smoltcp::socket::{self, AnySocket, Socket};
 use smoltcp::time::Instant;
 use smoltcp::wire::{EthernetAddress, HardwareAddress, IpAddress, IpCidr};
 
 use self::listen_table::ListenTable;
+use crate::{SocketAddr, SocketInfo};
 
 pub use self::dns::resolve_socket_addr;
 pub use self::tcp::TcpSocket;
@@ -120,6 +122,33 @@ impl<'a> SocketSetWrapper<'a> {
         self.0.lock().remove(handle);
         debug!("socket {}: destroyed", handle);
     }
+
+    /// Returns the information of TCP and UDP sockets in the set.
+    pub fn infos(&self) -> Vec<SocketInfo> {
+        let set = self.0.lock();
+        let bound_port = |port| (port != 0).then_some(port);
+        set.iter()
+            .filter_map(|(_, socket)| match socket {
+                Socket::Tcp(socket) => Some(SocketInfo {
+                    protocol: "tcp",
+                    local_addr: socket.local_endpoint(),
+                    peer_addr: socket.remote_endpoint(),
+                    state: socket.state().to_string(),
+                }),
+                Socket::Udp(socket) => {
+                    let endpoint = socket.endpoint();
+                    let addr = endpoint.addr.unwrap_or(IpAddress::v4(0, 0, 0, 0));
+                    Some(SocketInfo {
+                        protocol: "udp",
+                        local_addr: bound_port(endpoint.port).map(|p| SocketAddr::new(addr, p)),
+                        peer_addr: None,
+                        state: if socket.is_open() { "OPEN" } else { "CLOSED" }.into(),
+                    })
+                }
+                _ => None,
+            })
+            .collect()
+    }
 }
 
 impl InterfaceWrapper {
@@ -285,6 +314,21 @@ fn snoop_tcp_packet(buf: &[u8]) -> Result<(), smoltcp::wire::Error> {
     Ok(())
 }
 
+/// Returns the information of all TCP and UDP sockets, including the ports
+/// that TCP sockets listen on.
+pub fn sockets() -> Vec<SocketInfo> {
+    let listening = LISTEN_TABLE
+        .listening_ports()
+        .into_iter()
+        .map(|port| SocketInfo {
+            protocol: "tcp",
+            local_addr: Some(SocketAddr::new(IpAddress::v4(0, 0, 0, 0), port)),
+            peer_addr: None,
+            state: "LISTEN".into(),
+        });
+    listening.chain(SOCKET_SET.infos()).collect()
+}
+
 pub(crate) fn init(mut net_dev: AxNetDevice) {
     let pool = NetBufferPool::new(NET_BUF_POOL_SIZE, NET_BUF_LEN).unwrap();
     NET_BUF_POOL.init_by(pool);
@@ -0,0 +1,105 @@
+//! Platform devices exposed in `/dev`.
+
+use alloc::sync::Arc;
+use axfs_vfs::{VfsNodeAttr, VfsNodeOps, VfsNodePerm, VfsNodeType, VfsResult};
+
+pub fn init() {
+    info!("Initialize devfs entries...");
+    axfs::add_device("console", Arc::new(ConsoleDev));
+    #[cfg(feature = "display")]
+    axfs::add_device("fb0", Arc::new(FrameBufferDev));
+}
+
+/// The console of [`axhal`], like `/dev/console` on Linux.
+///
+/// Reads are non-blocking, they only return the bytes that have been
+/// received.
+struct ConsoleDev;
+
+impl VfsNodeOps for ConsoleDev {
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        Ok(VfsNodeAttr::new(
+            VfsNodePerm::default_file(),
+            VfsNodeType::CharDevice,
+            0,
+            0,
+        ))
+    }
+
+    fn read_at(&self, _offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        let mut read_len = 0;
+        while read_len < buf.len() {
+            match axhal::console::getchar() {
+                Some(c) => buf[read_len] = c,
+                None => break,
+            }
+            read_len += 1;
+        }
+        Ok(read_len)
+    }
+
+    fn write_at(&self, _offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        axhal::console::write_bytes(buf);
+        Ok(buf.len())
+    }
+
+    fn truncate(&self, _size: u64) -> VfsResult {
+        Ok(())
+    }
+
+    axfs_vfs::impl_vfs_non_dir_default! {}
+}
+
+/// The framebuffer of the main display, like `/dev/fb0` on Linux.
+///
+/// It can only be accessed by reads and writes at offsets in the framebuffer,
+/// and the screen is flushed after each write.
+#[cfg(feature = "display")]
+struct FrameBufferDev;
+
+#[cfg(feature = "display")]
+impl VfsNodeOps for FrameBufferDev {
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        let size = axdisplay::framebuffer_info().fb_size as u64;
+        Ok(VfsNodeAttr::new(
+            VfsNodePerm::default_file(),
+            VfsNodeType::CharDevice,
+            size,
+            0,
+        ))
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        let info = axdisplay::framebuffer_info();
+        let offset = offset.min(info.fb_size as u64) as usize;
+        let len = buf.len().min(info.fb_size - offset);
+        // SAFETY: the range is checked to be in the framebuffer.
+        unsafe {
+            let src = (info.fb_base_vaddr as *const u8).add(offset);
+            core::ptr::copy_nonoverlapping(src, buf.as_mut_ptr(), len);
+        }
+        Ok(len)
+    }
+
+    fn write_at(&self, offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        let info = axdisplay::framebuffer_info();
+        let offset = offset.min(info.fb_size as u64) as usize;
+        let len = buf.len().min(info.fb_size - offset);
+        if len == 0 && !buf.is_empty() {
+            return Err(axfs_vfs::VfsError::StorageFull);
+        }
+        // SAFETY: the range is checked to be in the framebuffer.
+        unsafe {
+            let dst = (info.fb_base_vaddr as *mut u8).add(offset);
+            core::ptr::copy_nonoverlapping(buf.as_ptr(), dst, len);
+        }
+        axdisplay::framebuffer_flush();
+        Ok(len)
+    }
+
+    fn truncate(&self, _size: u64) -> VfsResult {
+        Ok(()) // the size of the framebuffer is fixed
+    }
+
+    axfs_vfs::impl_vfs_non_dir_default! {}
+}
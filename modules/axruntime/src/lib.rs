@@ -10,7 +10,9 @@
 //! - `irq`: Enable interrupt handling support.
 //! - `multitask`: Enable multi-threading support.
 //! - `smp`: Enable SMP (symmetric multiprocessing) support.
-//! - `fs`: Enable filesystem support.
+//! - `fs`: Enable filesystem support. Kernel states such as memory usage and
+//!   tasks are exposed in `/proc`, and platform devices such as the console are
+//!   exposed in `/dev`.
 //! - `net`: Enable networking support.
 //! - `display`: Enable graphics support.
 //!
@@ -22,10 +24,18 @@
 #[macro_use]
 extern crate axlog;
 
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
 #[cfg(all(target_os = "none", not(test)))]
 mod lang_items;
 mod trap;
 
+#[cfg(feature = "fs")]
+mod devfs;
+#[cfg(feature = "fs")]
+mod procfs;
+
 #[cfg(feature = "smp")]
 mod mp;
 
@@ -159,13 +169,23 @@ pub extern "C" fn rust_main(cpu_id: usize, dtb: usize) -> ! {
         let all_devices = axdriver::init_drivers();
 
         #[cfg(feature = "fs")]
-        axfs::init_filesystems(all_devices.block);
+        {
+            // no real-time clock yet, file times are counted from boot
+            axfs::set_clock(axhal::time::current_time);
+            axfs::init_filesystems(all_devices.block);
+        }
 
         #[cfg(feature = "net")]
         axnet::init_network(all_devices.net);
 
         #[cfg(feature = "display")]
         axdisplay::init_display(all_devices.display);
+
+        #[cfg(feature = "fs")]
+        {
+            self::devfs::init();
+            self::procfs::init();
+        }
     }
 
     #[cfg(feature = "smp")]
@@ -186,6 +206,9 @@ pub extern "C" fn rust_main(cpu_id: usize, dtb: usize) -> ! {
 
     unsafe { main() };
 
+    #[cfg(feature = "fs")]
+    axfs::uninit_filesystems();
+
     #[cfg(feature = "multitask")]
     axtask::exit(0);
     #[cfg(not(feature = "multitask"))]
@@ -224,15 +247,12 @@ fn init_allocator() {
 #[cfg(feature = "paging")]
 fn remap_kernel_memory() -> Result<(), axhal::paging::PagingError> {
     use axhal::mem::{memory_regions, phys_to_virt};
-    use axhal::paging::PageTable;
-    use lazy_init::LazyInit;
-
-    static KERNEL_PAGE_TABLE: LazyInit<PageTable> = LazyInit::new();
+    use axhal::paging::{init_kernel_page_table, kernel_page_table, PageTable};
 
     if axhal::cpu::this_cpu_is_bsp() {
-        let mut kernel_page_table = PageTable::try_new()?;
+        let mut page_table = PageTable::try_new()?;
         for r in memory_regions() {
-            kernel_page_table.map_region(
+            page_table.map_region(
                 phys_to_virt(r.paddr),
                 r.paddr,
                 r.size,
@@ -240,10 +260,11 @@ fn remap_kernel_memory() -> Result<(), axhal::paging::PagingError> {
                 true,
             )?;
         }
-        KERNEL_PAGE_TABLE.init_by(kernel_page_table);
+        init_kernel_page_table(page_table);
     }
 
-    unsafe { axhal::arch::write_page_table_root(KERNEL_PAGE_TABLE.root_paddr()) };
+    let root_paddr = kernel_page_table().lock().root_paddr();
+    unsafe { axhal::arch::write_page_table_root(root_paddr) };
     Ok(())
 }
 
@@ -0,0 +1,71 @@
+//! Kernel states exposed in `/proc`.
+
+use alloc::format;
+use alloc::string::String;
+
+pub fn init() {
+    info!("Initialize procfs entries...");
+    axfs::add_proc_file("meminfo", meminfo);
+    axfs::add_proc_file("uptime", uptime);
+    #[cfg(feature = "multitask")]
+    axfs::add_proc_file("tasks", tasks);
+    #[cfg(feature = "irq")]
+    axfs::add_proc_file("interrupts", interrupts);
+    #[cfg(feature = "net")]
+    axfs::add_proc_file("net/sockets", sockets);
+}
+
+fn meminfo() -> String {
+    let allocator = axalloc::global_allocator();
+    format!(
+        "UsedBytes:      {:>12}\n\
+         AvailableBytes: {:>12}\n\
+         UsedPages:      {:>12}\n\
+         AvailablePages: {:>12}\n",
+        allocator.used_bytes(),
+        allocator.available_bytes(),
+        allocator.used_pages(),
+        allocator.available_pages(),
+    )
+}
+
+/// Seconds since boot, with two decimal places like Linux.
+fn uptime() -> String {
+    let now = axhal::time::current_time();
+    format!("{}.{:02}\n", now.as_secs(), now.subsec_millis() / 10)
+}
+
+#[cfg(feature = "multitask")]
+fn tasks() -> String {
+    let mut content = String::from("   ID STATE    NAME\n");
+    for task in axtask::all_tasks() {
+        let (id, state) = (task.id().as_u64(), format!("{:?}", task.state()));
+        content += &format!("{:>5} {:<8} {}\n", id, state, task.name());
+    }
+    content
+}
+
+#[cfg(feature = "irq")]
+fn interrupts() -> String {
+    let mut content = String::from(" IRQ      COUNT\n");
+    for (irq_num, count) in axhal::irq::irq_counts() {
+        content += &format!("{:>4} {:>10}\n", irq_num, count);
+    }
+    content
+}
+
+#[cfg(feature = "net")]
+fn sockets() -> String {
+    let addr = |addr: Option<axnet::SocketAddr>| addr.map_or("*".into(), |a| format!("{}", a));
+    let mut content = format!("{:<5} {:<21} {:<21} STATE\n", "PROTO", "LOCAL", "PEER");
+    for socket in axnet::sockets() {
+        content += &format!(
+            "{:<5} {:<21} {:<21} {}\n",
+            socket.protocol,
+            addr(socket.local_addr),
+            addr(socket.peer_addr),
+            socket.state,
+        );
+    }
+    content
+}
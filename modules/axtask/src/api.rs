@@ -1,13 +1,21 @@
 //! Task APIs for multi-task configuration.
 
-use alloc::{string::String, sync::Arc};
+use alloc::{string::String, sync::Arc, vec::Vec};
 
-pub(crate) use crate::run_queue::{AxRunQueue, RUN_QUEUE};
+pub(crate) use crate::run_queue::{current_run_queue, select_run_queue};
 
 #[doc(cfg(feature = "multitask"))]
-pub use crate::task::{CurrentTask, TaskId, TaskInner};
+pub use crate::cpumask::CpuMask;
+#[doc(cfg(feature = "multitask"))]
+pub use crate::task::{CurrentTask, TaskId, TaskInner, TaskState};
 #[doc(cfg(feature = "multitask"))]
 pub use crate::wait_queue::WaitQueue;
+#[cfg(feature = "sched_edf")]
+#[doc(cfg(feature = "sched_edf"))]
+pub use scheduler::EDFParams;
+#[cfg(feature = "sched_rt")]
+#[doc(cfg(feature = "sched_rt"))]
+pub use scheduler::RTPolicy as SchedPolicy;
 
 /// The reference type of a task.
 pub type AxTaskRef = Arc<AxTask>;
@@ -23,6 +31,13 @@ cfg_if::cfg_if! {
     } else if #[cfg(feature = "sched_cfs")] {
         pub(crate) type AxTask = scheduler::CFSTask<TaskInner>;
         pub(crate) type Scheduler = scheduler::CFScheduler<TaskInner>;
+    } else if #[cfg(feature = "sched_rt")] {
+        const MAX_TIME_SLICE: usize = 5;
+        pub(crate) type AxTask = scheduler::RTCFSTask<TaskInner, MAX_TIME_SLICE>;
+        pub(crate) type Scheduler = scheduler::RTCFScheduler<TaskInner, MAX_TIME_SLICE>;
+    } else if #[cfg(feature = "sched_edf")] {
+        pub(crate) type AxTask = scheduler::EDFTask<TaskInner>;
+        pub(crate) type Scheduler = scheduler::EDFScheduler<TaskInner>;
     }
 }
 
@@ -83,18 +98,39 @@ pub fn init_scheduler_secondary() {
 #[doc(cfg(feature = "irq"))]
 pub fn on_timer_tick() {
     crate::timers::check_events();
-    RUN_QUEUE.lock().scheduler_timer_tick();
+    current_run_queue().scheduler_timer_tick();
 }
 
 /// Spawns a new task with the given parameters.
 ///
+/// The new task is allowed to run on the same CPUs as the current task.
+///
 /// Returns the task reference.
 pub fn spawn_raw<F>(f: F, name: String, stack_size: usize) -> AxTaskRef
 where
     F: FnOnce() + Send + 'static,
 {
     let task = TaskInner::new(f, name, stack_size);
-    RUN_QUEUE.lock().add_task(task.clone());
+    select_run_queue(&task).add_task(task.clone());
+    task
+}
+
+/// Spawns a new task with the given parameters, which is only allowed to run
+/// on the CPUs in `cpumask`.
+///
+/// Returns the task reference.
+///
+/// # Panics
+///
+/// Panics if `cpumask` is empty.
+pub fn spawn_raw_on<F>(f: F, name: String, stack_size: usize, cpumask: CpuMask) -> AxTaskRef
+where
+    F: FnOnce() + Send + 'static,
+{
+    assert!(!cpumask.is_empty(), "empty CPU mask");
+    let task = TaskInner::new(f, name, stack_size);
+    task.set_cpumask(cpumask);
+    select_run_queue(&task).add_task(task.clone());
     task
 }
 
@@ -111,7 +147,36 @@ where
     spawn_raw(f, "".into(), axconfig::TASK_STACK_SIZE)
 }
 
-/// Set the priority for current task.
+/// Spawns a new task with the default parameters, which is pinned to the CPU
+/// `cpu_id`.
+///
+/// Returns the task reference.
+///
+/// # Panics
+///
+/// Panics if there is no such CPU.
+pub fn spawn_on<F>(cpu_id: usize, f: F) -> AxTaskRef
+where
+    F: FnOnce() + Send + 'static,
+{
+    assert!(cpu_id < axconfig::SMP, "no CPU {}", cpu_id);
+    spawn_raw_on(
+        f,
+        "".into(),
+        axconfig::TASK_STACK_SIZE,
+        CpuMask::one_shot(cpu_id),
+    )
+}
+
+/// Returns all tasks that have not been dropped, in the order of their IDs.
+///
+/// Exited tasks are included until they are no longer referenced.
+pub fn all_tasks() -> Vec<AxTaskRef> {
+    crate::task::all_tasks()
+}
+
+/// Sets the priority of a task, which may be the current task, or any other
+/// task that has not exited.
 ///
 /// The range of the priority is dependent on the underlying scheduler. For
 /// example, in the [CFS] scheduler, the priority is the nice value, ranging from
@@ -120,14 +185,97 @@ where
 /// Returns `true` if the priority is set successfully.
 ///
 /// [CFS]: https://en.wikipedia.org/wiki/Completely_Fair_Scheduler
-pub fn set_priority(prio: isize) -> bool {
-    RUN_QUEUE.lock().set_current_priority(prio)
+pub fn set_priority(task: &AxTaskRef, prio: isize) -> bool {
+    crate::run_queue::set_priority(task, prio)
+}
+
+/// Gets the priority of a task, see [`set_priority`].
+///
+/// It is always 0 if the underlying scheduler does not support priorities.
+pub fn get_priority(task: &AxTaskRef) -> isize {
+    crate::run_queue::get_priority(task)
+}
+
+/// Sets the scheduling policy and the priority of a task, which may be the
+/// current task, or any other task that has not exited.
+///
+/// The priority is the real-time priority from 0 to 99 for real-time policies,
+/// where larger values mean higher priorities, or the nice value from -20 to
+/// 19 for the [`Normal`](SchedPolicy::Normal) policy.
+///
+/// Returns `true` if the policy is set successfully.
+#[cfg(feature = "sched_rt")]
+#[doc(cfg(feature = "sched_rt"))]
+pub fn set_scheduler(task: &AxTaskRef, policy: SchedPolicy, prio: isize) -> bool {
+    crate::run_queue::set_policy(task, policy, prio)
+}
+
+/// Gets the scheduling policy of a task.
+#[cfg(feature = "sched_rt")]
+#[doc(cfg(feature = "sched_rt"))]
+pub fn get_scheduler(task: &AxTaskRef) -> SchedPolicy {
+    task.policy()
+}
+
+/// Sets the timing parameters of a task, which may be the current task, or
+/// any other task that has not exited. A new job of the task is released
+/// immediately.
+///
+/// The parameters are in timer ticks, see [`axconfig::TICKS_PER_SEC`]. The
+/// task becomes a best-effort one if `params` is [`None`].
+///
+/// The bandwidth of the task is reserved in the run queue it is in, and it is
+/// not migrated to other CPUs until its parameters are cleared.
+///
+/// Returns `false` if the parameters are invalid, or the task fails the
+/// admission test of the run queue it is in, or is being migrated by
+/// [`set_affinity`].
+#[cfg(feature = "sched_edf")]
+#[doc(cfg(feature = "sched_edf"))]
+pub fn set_edf_params(task: &AxTaskRef, params: Option<EDFParams>) -> bool {
+    crate::run_queue::set_edf_params(task, params)
+}
+
+/// Gets the timing parameters of a task, or [`None`] if it is a best-effort
+/// task.
+#[cfg(feature = "sched_edf")]
+#[doc(cfg(feature = "sched_edf"))]
+pub fn get_edf_params(task: &AxTaskRef) -> Option<EDFParams> {
+    task.params()
+}
+
+/// Sets the CPUs that the current task is allowed to run on.
+///
+/// If the current CPU is not in `cpumask`, the current task is migrated to
+/// one of them immediately. The tasks spawned by the current task later are
+/// allowed to run on the same CPUs.
+///
+/// Returns `false` if `cpumask` contains no CPU, or the current task is a
+/// periodic task of the EDF scheduler, which cannot be migrated, and
+/// `cpumask` excludes the current CPU.
+pub fn set_affinity(cpumask: CpuMask) -> bool {
+    if cpumask.is_empty() {
+        return false;
+    }
+    let rq = current_run_queue();
+    if !rq.set_current_cpumask(cpumask) {
+        return false;
+    }
+    if !cpumask.get(axhal::cpu::this_cpu_id()) {
+        rq.yield_current();
+    }
+    true
+}
+
+/// Gets the CPUs that the current task is allowed to run on.
+pub fn get_affinity() -> CpuMask {
+    current().cpumask()
 }
 
 /// Current task gives up the CPU time voluntarily, and switches to another
 /// ready task.
 pub fn yield_now() {
-    RUN_QUEUE.lock().yield_current();
+    current_run_queue().yield_current();
 }
 
 /// Current task is going to sleep for the given duration.
@@ -142,14 +290,14 @@ pub fn sleep(dur: core::time::Duration) {
 /// If the feature `irq` is not enabled, it uses busy-wait instead.
 pub fn sleep_until(deadline: axhal::time::TimeValue) {
     #[cfg(feature = "irq")]
-    RUN_QUEUE.lock().sleep_until(deadline);
+    current_run_queue().sleep_until(deadline);
     #[cfg(not(feature = "irq"))]
     axhal::time::busy_wait_until(deadline);
 }
 
 /// Exits the current task.
 pub fn exit(exit_code: i32) -> ! {
-    RUN_QUEUE.lock().exit_current(exit_code)
+    current_run_queue().exit_current(exit_code)
 }
 
 /// The idle task routine.
@@ -0,0 +1,135 @@
+use core::fmt;
+
+const BITS: usize = usize::BITS as usize;
+const WORDS: usize = axconfig::SMP.div_ceil(BITS);
+
+/// A set of CPUs, which a task is allowed to run on.
+///
+/// CPUs are identified by their IDs, ranging from 0 to [`axconfig::SMP`]
+/// (exclusive). IDs out of the range are ignored.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CpuMask {
+    words: [usize; WORDS],
+}
+
+impl CpuMask {
+    /// Creates an empty set.
+    pub const fn new() -> Self {
+        Self { words: [0; WORDS] }
+    }
+
+    /// Creates a set of all CPUs.
+    pub const fn full() -> Self {
+        let mut mask = Self::new();
+        let mut cpu = 0;
+        while cpu < axconfig::SMP {
+            mask.words[cpu / BITS] |= 1 << (cpu % BITS);
+            cpu += 1;
+        }
+        mask
+    }
+
+    /// Creates a set of only one CPU.
+    pub const fn one_shot(cpu: usize) -> Self {
+        let mut mask = Self::new();
+        if cpu < axconfig::SMP {
+            mask.words[cpu / BITS] = 1 << (cpu % BITS);
+        }
+        mask
+    }
+
+    /// Creates a set from a bitmap, where the bit `i` of the word `i / BITS`
+    /// indicates whether the CPU `i` is in the set.
+    ///
+    /// The words beyond the number of CPUs are ignored.
+    pub fn from_raw_bits(words: &[usize]) -> Self {
+        let mut mask = Self::new();
+        for (dst, src) in mask.words.iter_mut().zip(words) {
+            *dst = *src;
+        }
+        mask & Self::full()
+    }
+
+    /// Returns the bitmap of the set, see [`CpuMask::from_raw_bits`].
+    pub fn as_raw_bits(&self) -> &[usize] {
+        &self.words
+    }
+
+    /// Whether the CPU is in the set.
+    pub const fn get(&self, cpu: usize) -> bool {
+        cpu < axconfig::SMP && self.words[cpu / BITS] & (1 << (cpu % BITS)) != 0
+    }
+
+    /// Adds the CPU to the set, or removes it if `value` is `false`.
+    pub fn set(&mut self, cpu: usize, value: bool) {
+        if cpu < axconfig::SMP {
+            if value {
+                self.words[cpu / BITS] |= 1 << (cpu % BITS);
+            } else {
+                self.words[cpu / BITS] &= !(1 << (cpu % BITS));
+            }
+        }
+    }
+
+    /// Whether the set is empty.
+    pub fn is_empty(&self) -> bool {
+        self.words.iter().all(|&w| w == 0)
+    }
+
+    /// Returns the number of CPUs in the set.
+    pub fn len(&self) -> usize {
+        self.words.iter().map(|w| w.count_ones() as usize).sum()
+    }
+
+    /// Returns the smallest CPU ID in the set.
+    pub fn first(&self) -> Option<usize> {
+        self.iter().next()
+    }
+
+    /// Returns an iterator over the CPU IDs in the set, in ascending order.
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..axconfig::SMP).filter(|&cpu| self.get(cpu))
+    }
+}
+
+impl Default for CpuMask {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl core::ops::BitAnd for CpuMask {
+    type Output = Self;
+    fn bitand(mut self, rhs: Self) -> Self {
+        for (w, r) in self.words.iter_mut().zip(rhs.words) {
+            *w &= r;
+        }
+        self
+    }
+}
+
+impl core::ops::BitOr for CpuMask {
+    type Output = Self;
+    fn bitor(mut self, rhs: Self) -> Self {
+        for (w, r) in self.words.iter_mut().zip(rhs.words) {
+            *w |= r;
+        }
+        self
+    }
+}
+
+impl FromIterator<usize> for CpuMask {
+    fn from_iter<I: IntoIterator<Item = usize>>(iter: I) -> Self {
+        let mut mask = Self::new();
+        for cpu in iter {
+            mask.set(cpu, true);
+        }
+        mask
+    }
+}
+
+impl fmt::Debug for CpuMask {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
@@ -12,6 +12,10 @@
 //! - `irq`: Interrupts are enabled. If this feature is enabled, timer-based
 //!    APIs can be used, such as [`sleep`], [`sleep_until`], and
 //!    [`WaitQueue::wait_timeout`].
+//! - `smp`: Multiple CPUs are used. Each CPU has its own run queue, tasks are
+//!   woken up on the CPU they last ran on, and idle CPUs steal tasks from the
+//!   others. Run queues are also balanced at timer ticks if `irq` is enabled.
+//!   Tasks can be restricted to some CPUs by [`spawn_on`] or [`set_affinity`].
 //! - `preempt`: Enable preemptive scheduling.
 //! - `sched_fifo`: Use the [FIFO cooperative scheduler][1]. It also enables the
 //!   `multitask` feature if it is enabled. This feature is enabled by default.
@@ -19,10 +23,22 @@
 //!   the `multitask` and `preempt` features if it is enabled.
 //! - `sched_cfs`: Use the [Completely Fair Scheduler][3]. It also enables the
 //!   the `multitask` and `preempt` features if it is enabled.
+//! - `sched_rt`: Use the [real-time scheduler][4] with the CFS for normal
+//!   tasks, see [`set_scheduler`]. A ready real-time task preempts normal
+//!   tasks within a timer tick. It also enables the `multitask` and `preempt`
+//!   features if it is enabled.
+//! - `sched_edf`: Use the [Earliest Deadline First scheduler][5] for periodic
+//!   tasks, see [`set_edf_params`]. Tasks without timing parameters run when
+//!   no periodic tasks are ready. The admission control is done by each run
+//!   queue, and periodic tasks are never migrated to other CPUs, so they are
+//!   better moved to the intended CPUs by [`set_affinity`] first.
+//!   It also enables the `multitask` and `preempt` features if it is enabled.
 //!
 //! [1]: scheduler::FifoScheduler
 //! [2]: scheduler::RRScheduler
 //! [3]: scheduler::CFScheduler
+//! [4]: scheduler::RTCFScheduler
+//! [5]: scheduler::EDFScheduler
 
 #![cfg_attr(not(test), no_std)]
 #![feature(doc_cfg)]
@@ -33,6 +49,7 @@ cfg_if::cfg_if! {
         #[macro_use]
         extern crate log;
         extern crate alloc;
+        mod cpumask;
         mod run_queue;
         mod task;
         mod wait_queue;
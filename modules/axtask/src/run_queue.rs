@@ -1,72 +1,185 @@
 use alloc::collections::VecDeque;
 use alloc::sync::Arc;
+use core::ops::Deref;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use kernel_guard::NoPreemptIrqSave;
 use lazy_init::LazyInit;
 use scheduler::BaseScheduler;
-use spinlock::SpinNoIrq;
+use spinlock::{SpinNoIrq, SpinRaw};
 
 use crate::task::{CurrentTask, TaskState};
-use crate::{AxTaskRef, Scheduler, TaskInner, WaitQueue};
-
-// TODO: per-CPU
-pub(crate) static RUN_QUEUE: LazyInit<SpinNoIrq<AxRunQueue>> = LazyInit::new();
+use crate::{AxTaskRef, CpuMask, Scheduler, TaskInner, WaitQueue};
 
-// TODO: per-CPU
-static EXITED_TASKS: SpinNoIrq<VecDeque<AxTaskRef>> = SpinNoIrq::new(VecDeque::new());
+/// The number of timer ticks between two load balancing of a run queue.
+#[cfg(feature = "irq")]
+const BALANCE_INTERVAL_TICKS: usize = 4;
 
-static WAIT_FOR_EXIT: WaitQueue = WaitQueue::new();
+/// The run queues of all CPUs, indexed by the CPU IDs.
+static RUN_QUEUES: [LazyInit<AxRunQueue>; axconfig::SMP] = {
+    #[allow(clippy::declare_interior_mutable_const)]
+    const UNINIT: LazyInit<AxRunQueue> = LazyInit::new();
+    [UNINIT; axconfig::SMP]
+};
 
 #[percpu::def_percpu]
 static IDLE_TASK: LazyInit<AxTaskRef> = LazyInit::new();
 
+/// The task switched out by the last context switch on this CPU, whose
+/// `on_cpu` flag is cleared by the next task once the switch is done.
+#[cfg(feature = "smp")]
+#[percpu::def_percpu]
+static PREV_TASK: Option<AxTaskRef> = None;
+
+/// The run queue of a CPU.
+///
+/// Only the CPU itself switches tasks with its run queue, with IRQs and
+/// preemption disabled, while other CPUs may add tasks to it or steal tasks
+/// from it. So only the scheduler is locked, and only for a short time.
 pub(crate) struct AxRunQueue {
-    scheduler: Scheduler,
+    cpu_id: usize,
+    scheduler: SpinRaw<Scheduler>,
+    /// The number of tasks in the scheduler, which is read without the lock
+    /// for load balancing.
+    num_tasks: AtomicUsize,
+    #[cfg(feature = "irq")]
+    ticks: AtomicUsize,
+    /// The tasks exited on this CPU, dropped by the gc task of the CPU.
+    exited_tasks: SpinNoIrq<VecDeque<AxTaskRef>>,
+    wait_for_exit: WaitQueue,
+}
+
+/// A reference to the run queue of a CPU, IRQs and preemption are disabled on
+/// the current CPU until it is dropped.
+///
+/// The current task may be resumed on another CPU after it is switched out,
+/// so the reference must not be used to switch tasks after that.
+pub(crate) struct AxRunQueueRef {
+    inner: &'static AxRunQueue,
+    _guard: NoPreemptIrqSave,
+}
+
+impl Deref for AxRunQueueRef {
+    type Target = AxRunQueue;
+    fn deref(&self) -> &Self::Target {
+        self.inner
+    }
+}
+
+/// Returns the run queue of the current CPU.
+pub(crate) fn current_run_queue() -> AxRunQueueRef {
+    // the CPU must not change after it is read
+    let guard = NoPreemptIrqSave::new();
+    AxRunQueueRef {
+        inner: &RUN_QUEUES[axhal::cpu::this_cpu_id()],
+        _guard: guard,
+    }
+}
+
+/// Whether the task may be moved to the run queue of another CPU.
+///
+/// A periodic task of the EDF scheduler stays in the run queue that admitted
+/// it, as its bandwidth is reserved there.
+fn is_migratable(task: &AxTaskRef) -> bool {
+    #[cfg(feature = "sched_edf")]
+    if task.params().is_some() {
+        return false;
+    }
+    let _ = task;
+    true
+}
+
+/// Returns the run queue to put the task in when it is ready, which is the
+/// one of the CPU the task last ran on, whose cache may still be warm.
+///
+/// If the task is no longer allowed to run on that CPU, the first CPU it is
+/// allowed to run on is selected instead.
+pub(crate) fn select_run_queue(task: &AxTaskRef) -> AxRunQueueRef {
+    let cpumask = task.cpumask();
+    let cpu_id = match task.cpu_id() {
+        cpu_id if cpumask.get(cpu_id) => cpu_id,
+        _ => cpumask.first().expect("empty CPU mask"),
+    };
+    AxRunQueueRef {
+        inner: &RUN_QUEUES[cpu_id],
+        _guard: NoPreemptIrqSave::new(),
+    }
 }
 
 impl AxRunQueue {
-    pub fn new() -> SpinNoIrq<Self> {
-        let gc_task = TaskInner::new(gc_entry, "gc".into(), axconfig::TASK_STACK_SIZE);
+    fn new(cpu_id: usize) -> Self {
+        let gc_task = TaskInner::new(
+            move || gc_entry(cpu_id),
+            "gc".into(),
+            axconfig::TASK_STACK_SIZE,
+        );
+        gc_task.set_cpumask(CpuMask::one_shot(cpu_id));
         let mut scheduler = Scheduler::new();
         scheduler.add_task(gc_task);
-        SpinNoIrq::new(Self { scheduler })
+        Self {
+            cpu_id,
+            scheduler: SpinRaw::new(scheduler),
+            num_tasks: AtomicUsize::new(1),
+            #[cfg(feature = "irq")]
+            ticks: AtomicUsize::new(0),
+            exited_tasks: SpinNoIrq::new(VecDeque::new()),
+            wait_for_exit: WaitQueue::new(),
+        }
     }
 
-    pub fn add_task(&mut self, task: AxTaskRef) {
-        debug!("task spawn: {}", task.id_name());
+    pub fn add_task(&self, task: AxTaskRef) {
+        debug!("task spawn: {} on CPU {}", task.id_name(), self.cpu_id);
         assert!(task.is_ready());
-        self.scheduler.add_task(task);
+        let mut scheduler = self.scheduler.lock();
+        task.set_cpu_id(self.cpu_id); // under the lock, see `set_priority()`
+        scheduler.add_task(task);
+        self.num_tasks.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Sets the CPU mask of the current task. Returns `false` if the task
+    /// cannot be migrated but the mask excludes this CPU.
+    pub fn set_current_cpumask(&self, cpumask: CpuMask) -> bool {
+        let curr = crate::current();
+        // checked with the lock held, see `set_edf_params()`
+        let _scheduler = self.scheduler.lock();
+        if !cpumask.get(self.cpu_id) && !is_migratable(curr.as_task_ref()) {
+            return false;
+        }
+        curr.set_cpumask(cpumask);
+        true
     }
 
     #[cfg(feature = "irq")]
-    pub fn scheduler_timer_tick(&mut self) {
+    pub fn scheduler_timer_tick(&self) {
         let curr = crate::current();
-        if !curr.is_idle() && self.scheduler.task_tick(curr.as_task_ref()) {
+        if curr.is_idle() {
+            #[cfg(feature = "sched_edf")]
+            self.scheduler.lock().idle_tick();
+        } else if self.scheduler.lock().task_tick(curr.as_task_ref()) {
             #[cfg(feature = "preempt")]
             curr.set_preempt_pending(true);
         }
+        if self.ticks.fetch_add(1, Ordering::Relaxed) % BALANCE_INTERVAL_TICKS == 0 {
+            self.balance();
+        }
     }
 
-    pub fn yield_current(&mut self) {
+    pub fn yield_current(&self) {
         let curr = crate::current();
         debug!("task yield: {}", curr.id_name());
         assert!(curr.is_running());
         self.resched(false);
     }
 
-    pub fn set_current_priority(&mut self, prio: isize) -> bool {
-        self.scheduler
-            .set_priority(crate::current().as_task_ref(), prio)
-    }
-
     #[cfg(feature = "preempt")]
-    pub fn preempt_resched(&mut self) {
+    pub fn preempt_resched(&self) {
         let curr = crate::current();
         assert!(curr.is_running());
 
-        // When we get the mutable reference of the run queue, we must
-        // have held the `SpinNoIrq` lock with both IRQs and preemption
-        // disabled. So we need to set `current_disable_count` to 1 in
-        // `can_preempt()` to obtain the preemption permission before
-        //  locking the run queue.
+        // When we get the reference of the run queue, we must have disabled
+        // both IRQs and preemption. So we need to set `current_disable_count`
+        // to 1 in `can_preempt()` to obtain the preemption permission before
+        // getting the run queue.
         let can_preempt = curr.can_preempt(1);
 
         debug!(
@@ -81,25 +194,25 @@ impl AxRunQueue {
         }
     }
 
-    pub fn exit_current(&mut self, exit_code: i32) -> ! {
+    pub fn exit_current(&self, exit_code: i32) -> ! {
         let curr = crate::current();
         debug!("task exit: {}, exit_code={}", curr.id_name(), exit_code);
         assert!(curr.is_running());
         assert!(!curr.is_idle());
         if curr.is_init() {
-            EXITED_TASKS.lock().clear();
+            self.exited_tasks.lock().clear();
             axhal::misc::terminate();
         } else {
             curr.set_state(TaskState::Exited);
-            curr.notify_exit(exit_code, self);
-            EXITED_TASKS.lock().push_back(curr.clone());
-            WAIT_FOR_EXIT.notify_one_locked(false, self);
+            curr.notify_exit(exit_code);
+            self.exited_tasks.lock().push_back(curr.clone());
+            self.wait_for_exit.notify_one(false);
             self.resched(false);
         }
         unreachable!("task exited!");
     }
 
-    pub fn block_current<F>(&mut self, wait_queue_push: F)
+    pub fn block_current<F>(&self, wait_queue_push: F)
     where
         F: FnOnce(AxTaskRef),
     {
@@ -112,17 +225,23 @@ impl AxRunQueue {
         #[cfg(feature = "preempt")]
         assert!(curr.can_preempt(1));
 
+        // it can be woken up as soon as it is in the wait queue, even before
+        // it is switched out, see `switch_to()`.
         curr.set_state(TaskState::Blocked);
         wait_queue_push(curr.clone());
         self.resched(false);
     }
 
-    pub fn unblock_task(&mut self, task: AxTaskRef, resched: bool) {
-        debug!("task unblock: {}", task.id_name());
-        if task.is_blocked() {
-            task.set_state(TaskState::Ready);
-            self.scheduler.add_task(task); // TODO: priority
-            if resched {
+    pub fn unblock_task(&self, task: AxTaskRef, resched: bool) {
+        debug!("task unblock: {} on CPU {}", task.id_name(), self.cpu_id);
+        // it may be woken up by a timer and a wait queue at the same time
+        if task.transition_state(TaskState::Blocked, TaskState::Ready) {
+            let mut scheduler = self.scheduler.lock();
+            task.set_cpu_id(self.cpu_id);
+            scheduler.add_task(task); // TODO: priority
+            drop(scheduler);
+            self.num_tasks.fetch_add(1, Ordering::Relaxed);
+            if resched && self.cpu_id == axhal::cpu::this_cpu_id() {
                 #[cfg(feature = "preempt")]
                 crate::current().set_preempt_pending(true);
             }
@@ -130,7 +249,7 @@ impl AxRunQueue {
     }
 
     #[cfg(feature = "irq")]
-    pub fn sleep_until(&mut self, deadline: axhal::time::TimeValue) {
+    pub fn sleep_until(&self, deadline: axhal::time::TimeValue) {
         let curr = crate::current();
         debug!("task sleep: {}, deadline={:?}", curr.id_name(), deadline);
         assert!(curr.is_running());
@@ -138,8 +257,8 @@ impl AxRunQueue {
 
         let now = axhal::time::current_time();
         if now < deadline {
-            crate::timers::set_alarm_wakeup(deadline, curr.clone());
             curr.set_state(TaskState::Blocked);
+            crate::timers::set_alarm_wakeup(deadline, curr.clone());
             self.resched(false);
         }
     }
@@ -148,22 +267,95 @@ impl AxRunQueue {
 impl AxRunQueue {
     /// Common reschedule subroutine. If `preempt`, keep current task's time
     /// slice, otherwise reset it.
-    fn resched(&mut self, preempt: bool) {
+    fn resched(&self, preempt: bool) {
         let prev = crate::current();
         if prev.is_running() {
             prev.set_state(TaskState::Ready);
-            if !prev.is_idle() {
-                self.scheduler.put_prev_task(prev.clone(), preempt);
+            if prev.is_idle() {
+                // the idle task is not in the run queue
+            } else if prev.cpumask().get(self.cpu_id) {
+                self.scheduler.lock().put_prev_task(prev.clone(), preempt);
+                self.num_tasks.fetch_add(1, Ordering::Relaxed);
+            } else {
+                // its CPU mask has changed, migrate it to an allowed CPU, which
+                // waits for its context to be saved before it runs the task
+                select_run_queue(prev.as_task_ref()).add_task(prev.clone());
             }
         }
-        let next = self.scheduler.pick_next_task().unwrap_or_else(|| unsafe {
-            // Safety: IRQs must be disabled at this time.
-            IDLE_TASK.current_ref_raw().get_unchecked().clone()
-        });
+        let next = self
+            .pick_next_task()
+            .or_else(|| self.steal_task())
+            .unwrap_or_else(|| unsafe {
+                // Safety: IRQs must be disabled at this time.
+                IDLE_TASK.current_ref_raw().get_unchecked().clone()
+            });
         self.switch_to(prev, next);
     }
 
-    fn switch_to(&mut self, prev_task: CurrentTask, next_task: AxTaskRef) {
+    fn pick_next_task(&self) -> Option<AxTaskRef> {
+        let task = self.scheduler.lock().pick_next_task();
+        if task.is_some() {
+            self.num_tasks.fetch_sub(1, Ordering::Relaxed);
+        }
+        task
+    }
+
+    /// Takes a task from the run queue of another CPU, when there is nothing
+    /// to run on the CPU `cpu_id`.
+    ///
+    /// A queue locked by others is skipped rather than waited for. So is a
+    /// queue whose next task is not allowed to run on the CPU or cannot be
+    /// migrated, which is left unchanged.
+    fn try_pick_next_task(&self, cpu_id: usize) -> Option<AxTaskRef> {
+        if self.num_tasks.load(Ordering::Relaxed) == 0 {
+            return None;
+        }
+        let task = self
+            .scheduler
+            .try_lock()?
+            .pick_next_task_if(|task| task.cpumask().get(cpu_id) && is_migratable(task))?;
+        self.num_tasks.fetch_sub(1, Ordering::Relaxed);
+        Some(task)
+    }
+
+    /// Steals a task from the run queues of other CPUs, starting from the
+    /// next CPU so that the thieves spread over the victims.
+    fn steal_task(&self) -> Option<AxTaskRef> {
+        RUN_QUEUES
+            .iter()
+            .cycle()
+            .skip(self.cpu_id + 1)
+            .take(axconfig::SMP - 1)
+            .filter_map(LazyInit::try_get)
+            .find_map(|rq| rq.try_pick_next_task(self.cpu_id))
+            .inspect(|task| debug!("task steal: {} by CPU {}", task.id_name(), self.cpu_id))
+    }
+
+    /// Moves a task from the busiest run queue to this one, if the busiest one
+    /// has at least two more tasks.
+    #[cfg(feature = "irq")]
+    fn balance(&self) {
+        let num_tasks = self.num_tasks.load(Ordering::Relaxed);
+        let busiest = RUN_QUEUES
+            .iter()
+            .filter_map(LazyInit::try_get)
+            .max_by_key(|rq| rq.num_tasks.load(Ordering::Relaxed));
+        match busiest {
+            Some(rq) if rq.num_tasks.load(Ordering::Relaxed) > num_tasks + 1 => {
+                if let Some(task) = rq.try_pick_next_task(self.cpu_id) {
+                    debug!("task balance: CPU {} -> CPU {}", rq.cpu_id, self.cpu_id);
+                    self.add_task(task);
+                    #[cfg(feature = "preempt")]
+                    if crate::current().is_idle() {
+                        crate::current().set_preempt_pending(true);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn switch_to(&self, prev_task: CurrentTask, next_task: AxTaskRef) {
         trace!(
             "context switch: {} -> {}",
             prev_task.id_name(),
@@ -176,6 +368,19 @@ impl AxRunQueue {
             return;
         }
 
+        // The next task may have been woken up before it is switched out on
+        // another CPU, wait for its context to be saved.
+        #[cfg(feature = "smp")]
+        {
+            while next_task.on_cpu() {
+                core::hint::spin_loop();
+            }
+            next_task.set_on_cpu(true);
+            // Safety: IRQs are disabled at this time.
+            unsafe { *PREV_TASK.current_ref_mut_raw() = Some(prev_task.clone()) };
+        }
+        next_task.set_cpu_id(self.cpu_id);
+
         unsafe {
             let prev_ctx_ptr = prev_task.ctx_mut_ptr();
             let next_ctx_ptr = next_task.ctx_mut_ptr();
@@ -188,16 +393,79 @@ impl AxRunQueue {
             CurrentTask::set_current(prev_task, next_task);
             (*prev_ctx_ptr).switch_to(&*next_ctx_ptr);
         }
+        #[cfg(feature = "smp")]
+        clear_prev_task_on_cpu();
+    }
+}
+
+/// Calls `f` with the scheduler that the task is in, or the one of the CPU
+/// it is running on.
+///
+/// The CPU ID of a task is only changed with the scheduler of the new CPU
+/// locked, or when the task is in no scheduler, so the task stays in the
+/// locked scheduler if its CPU ID is unchanged after locking.
+fn with_task_scheduler<R>(task: &AxTaskRef, f: impl FnOnce(&mut Scheduler) -> R) -> R {
+    let _guard = NoPreemptIrqSave::new();
+    loop {
+        let cpu_id = task.cpu_id();
+        let mut scheduler = RUN_QUEUES[cpu_id].scheduler.lock();
+        if task.cpu_id() == cpu_id {
+            return f(&mut scheduler);
+        }
+    }
+}
+
+/// Sets the priority of a task, which may be ready in the run queue of any
+/// CPU, running, or blocked.
+pub(crate) fn set_priority(task: &AxTaskRef, prio: isize) -> bool {
+    with_task_scheduler(task, |scheduler| scheduler.set_priority(task, prio))
+}
+
+/// Gets the priority of a task.
+pub(crate) fn get_priority(task: &AxTaskRef) -> isize {
+    with_task_scheduler(task, |scheduler| scheduler.get_priority(task))
+}
+
+/// Sets the scheduling policy and the priority of a task.
+#[cfg(feature = "sched_rt")]
+pub(crate) fn set_policy(task: &AxTaskRef, policy: crate::SchedPolicy, prio: isize) -> bool {
+    with_task_scheduler(task, |scheduler| scheduler.set_policy(task, policy, prio))
+}
+
+/// Sets the timing parameters of a task for the EDF scheduler, which admits
+/// the task to the run queue it is in.
+///
+/// It fails if the task is leaving the CPU of the run queue, since the task
+/// cannot be migrated once it has the parameters.
+#[cfg(feature = "sched_edf")]
+pub(crate) fn set_edf_params(task: &AxTaskRef, params: Option<crate::EDFParams>) -> bool {
+    with_task_scheduler(task, |scheduler| {
+        if params.is_some() && !task.cpumask().get(task.cpu_id()) {
+            return false;
+        }
+        scheduler.set_params(task, params)
+    })
+}
+
+/// Marks the task switched out by the last context switch on this CPU as no
+/// longer running, after its context is saved. It must be called by the task
+/// switched in.
+#[cfg(feature = "smp")]
+pub(crate) fn clear_prev_task_on_cpu() {
+    // Safety: IRQs are disabled during context switches.
+    if let Some(prev) = unsafe { PREV_TASK.current_ref_mut_raw() }.take() {
+        prev.set_on_cpu(false);
     }
 }
 
-fn gc_entry() {
+fn gc_entry(cpu_id: usize) {
+    let rq = &RUN_QUEUES[cpu_id];
     loop {
         // Drop all exited tasks and recycle resources.
-        let n = EXITED_TASKS.lock().len();
+        let n = rq.exited_tasks.lock().len();
         for _ in 0..n {
             // Do not do the slow drops in the critical section.
-            let task = EXITED_TASKS.lock().pop_front();
+            let task = rq.exited_tasks.lock().pop_front();
             if let Some(task) = task {
                 if Arc::strong_count(&task) == 1 {
                     // If I'm the last holder of the task, drop it immediately.
@@ -205,29 +473,39 @@ fn gc_entry() {
                 } else {
                     // Otherwise (e.g, `switch_to` is not compeleted, held by the
                     // joiner, etc), push it back and wait for them to drop first.
-                    EXITED_TASKS.lock().push_back(task);
+                    rq.exited_tasks.lock().push_back(task);
                 }
             }
         }
-        WAIT_FOR_EXIT.wait();
+        rq.wait_for_exit.wait();
     }
 }
 
 pub(crate) fn init() {
     const IDLE_TASK_STACK_SIZE: usize = 4096;
+    let cpu_id = axhal::cpu::this_cpu_id();
     let idle_task = TaskInner::new(|| crate::run_idle(), "idle".into(), IDLE_TASK_STACK_SIZE);
+    idle_task.set_cpumask(CpuMask::one_shot(cpu_id));
     IDLE_TASK.with_current(|i| i.init_by(idle_task.clone()));
 
     let main_task = TaskInner::new_init("main".into());
     main_task.set_state(TaskState::Running);
+    #[cfg(feature = "smp")]
+    main_task.set_on_cpu(true);
 
-    RUN_QUEUE.init_by(AxRunQueue::new());
+    RUN_QUEUES[cpu_id].init_by(AxRunQueue::new(cpu_id));
     unsafe { CurrentTask::init_current(main_task) }
 }
 
 pub(crate) fn init_secondary() {
+    let cpu_id = axhal::cpu::this_cpu_id();
     let idle_task = TaskInner::new_init("idle".into());
+    idle_task.set_cpumask(CpuMask::one_shot(cpu_id));
     idle_task.set_state(TaskState::Running);
+    #[cfg(feature = "smp")]
+    idle_task.set_on_cpu(true);
     IDLE_TASK.with_current(|i| i.init_by(idle_task.clone()));
+
+    RUN_QUEUES[cpu_id].init_by(AxRunQueue::new(cpu_id));
     unsafe { CurrentTask::init_current(idle_task) }
 }
@@ -1,27 +1,35 @@
-use alloc::{boxed::Box, string::String, sync::Arc};
+use alloc::collections::BTreeMap;
+use alloc::sync::{Arc, Weak};
+use alloc::{boxed::Box, string::String, vec::Vec};
+use core::any::{Any, TypeId};
 use core::ops::Deref;
-use core::sync::atomic::{AtomicBool, AtomicI32, AtomicU64, AtomicU8, Ordering};
+use core::sync::atomic::{AtomicBool, AtomicI32, AtomicU64, AtomicU8, AtomicUsize, Ordering};
 use core::{alloc::Layout, cell::UnsafeCell, fmt, ptr::NonNull};
 
-#[cfg(feature = "preempt")]
-use core::sync::atomic::AtomicUsize;
-
 use axhal::arch::TaskContext;
 use memory_addr::{align_up_4k, VirtAddr};
+use spinlock::SpinNoIrq;
 
-use crate::{AxRunQueue, AxTask, AxTaskRef, WaitQueue};
+use crate::{AxTask, AxTaskRef, CpuMask, WaitQueue};
 
 /// A unique identifier for a thread.
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
 pub struct TaskId(u64);
 
+/// All tasks that have not been dropped, indexed by their IDs.
+static TASK_LIST: SpinNoIrq<BTreeMap<u64, Weak<AxTask>>> = SpinNoIrq::new(BTreeMap::new());
+
 /// The possible states of a task.
 #[repr(u8)]
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
-pub(crate) enum TaskState {
+pub enum TaskState {
+    /// The task is running on a CPU.
     Running = 1,
+    /// The task is in the run queue, waiting to be scheduled.
     Ready = 2,
+    /// The task is waiting for an event, e.g., in a wait queue or sleeping.
     Blocked = 3,
+    /// The task has exited.
     Exited = 4,
 }
 
@@ -34,6 +42,14 @@ pub struct TaskInner {
 
     entry: Option<*mut dyn FnOnce()>,
     state: AtomicU8,
+    /// The CPU that the task is running on, or in the run queue of, or last
+    /// ran on.
+    cpu_id: AtomicUsize,
+    /// The CPUs that the task is allowed to run on.
+    cpumask: SpinNoIrq<CpuMask>,
+    /// Whether the task is running on a CPU, or its context is being saved.
+    #[cfg(feature = "smp")]
+    on_cpu: AtomicBool,
 
     in_wait_queue: AtomicBool,
     #[cfg(feature = "irq")]
@@ -47,10 +63,37 @@ pub struct TaskInner {
     exit_code: AtomicI32,
     wait_for_exit: WaitQueue,
 
+    locals: SpinNoIrq<BTreeMap<TypeId, LocalValue>>,
+
     kstack: Option<TaskStack>,
     ctx: UnsafeCell<TaskContext>,
 }
 
+/// A value stored in a task by [`TaskInner::with_local`], along with the
+/// function to copy it to the tasks spawned by the task.
+struct LocalValue {
+    value: Box<dyn Any + Send>,
+    copy: fn(&dyn Any) -> Box<dyn Any + Send>,
+}
+
+impl LocalValue {
+    fn new<T: Any + Clone + Send>(value: T) -> Self {
+        Self {
+            value: Box::new(value),
+            copy: |value| Box::new(value.downcast_ref::<T>().unwrap().clone()),
+        }
+    }
+}
+
+impl Clone for LocalValue {
+    fn clone(&self) -> Self {
+        Self {
+            value: (self.copy)(self.value.as_ref()),
+            copy: self.copy,
+        }
+    }
+}
+
 impl TaskId {
     fn new() -> Self {
         static ID_COUNTER: AtomicU64 = AtomicU64::new(1);
@@ -90,6 +133,25 @@ impl TaskInner {
         self.name.as_str()
     }
 
+    /// Gets the state of the task.
+    #[inline]
+    pub fn state(&self) -> TaskState {
+        self.state.load(Ordering::Acquire).into()
+    }
+
+    /// Gets the ID of the CPU that the task is running on, or in the run queue
+    /// of, or last ran on.
+    #[inline]
+    pub fn cpu_id(&self) -> usize {
+        self.cpu_id.load(Ordering::Acquire)
+    }
+
+    /// Gets the set of CPUs that the task is allowed to run on.
+    #[inline]
+    pub fn cpumask(&self) -> CpuMask {
+        *self.cpumask.lock()
+    }
+
     /// Get a combined string of the task ID and name.
     pub fn id_name(&self) -> alloc::string::String {
         alloc::format!("Task({}, {:?})", self.id.as_u64(), self.name)
@@ -103,6 +165,27 @@ impl TaskInner {
             .wait_until(|| self.state() == TaskState::Exited);
         Some(self.exit_code.load(Ordering::Acquire))
     }
+
+    /// Calls `f` with the value of type `T` stored in the task, which is
+    /// created by [`T::default()`](Default::default) on first use.
+    ///
+    /// Each type has one value per task, and new tasks start with copies of
+    /// the values of the task that spawns them. It is used for per-task state
+    /// of other modules, such as the current directory.
+    ///
+    /// The values of the task are locked with IRQs disabled while `f` runs,
+    /// so `f` should be short, and must not block or call this function on the
+    /// same task.
+    pub fn with_local<T, R>(&self, f: impl FnOnce(&mut T) -> R) -> R
+    where
+        T: Any + Clone + Default + Send,
+    {
+        let mut locals = self.locals.lock();
+        let local = locals
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| LocalValue::new(T::default()));
+        f(local.value.downcast_mut().unwrap())
+    }
 }
 
 // private methods
@@ -115,6 +198,10 @@ impl TaskInner {
             is_init: false,
             entry: None,
             state: AtomicU8::new(TaskState::Ready as u8),
+            cpu_id: AtomicUsize::new(0),
+            cpumask: SpinNoIrq::new(CpuMask::full()),
+            #[cfg(feature = "smp")]
+            on_cpu: AtomicBool::new(false),
             in_wait_queue: AtomicBool::new(false),
             #[cfg(feature = "irq")]
             in_timer_list: AtomicBool::new(false),
@@ -124,6 +211,7 @@ impl TaskInner {
             preempt_disable_count: AtomicUsize::new(0),
             exit_code: AtomicI32::new(0),
             wait_for_exit: WaitQueue::new(),
+            locals: SpinNoIrq::new(BTreeMap::new()),
             kstack: None,
             ctx: UnsafeCell::new(TaskContext::new()),
         }
@@ -135,6 +223,11 @@ impl TaskInner {
     {
         let mut t = Self::new_common(TaskId::new(), name);
         debug!("new task: {}", t.id_name());
+        *t.cpu_id.get_mut() = axhal::cpu::this_cpu_id();
+        if let Some(curr) = crate::current_may_uninit() {
+            *t.locals.get_mut() = curr.locals.lock().clone();
+            *t.cpumask.get_mut() = curr.cpumask();
+        }
         let kstack = TaskStack::alloc(align_up_4k(stack_size));
         t.entry = Some(Box::into_raw(Box::new(entry)));
         t.ctx.get_mut().init(task_entry as usize, kstack.top());
@@ -142,22 +235,29 @@ impl TaskInner {
         if t.name == "idle" {
             t.is_idle = true;
         }
-        Arc::new(AxTask::new(t))
+        t.into_ref()
     }
 
     pub(crate) fn new_init(name: String) -> AxTaskRef {
         // init_task does not change PC and SP, so `entry` and `kstack` fields are not used.
         let mut t = Self::new_common(TaskId::new(), name);
+        *t.cpu_id.get_mut() = axhal::cpu::this_cpu_id();
         t.is_init = true;
         if t.name == "idle" {
             t.is_idle = true;
         }
-        Arc::new(AxTask::new(t))
+        t.into_ref()
     }
 
-    #[inline]
-    pub(crate) fn state(&self) -> TaskState {
-        self.state.load(Ordering::Acquire).into()
+    /// Wraps the task into [`AxTaskRef`], and adds it to the task list.
+    fn into_ref(self) -> AxTaskRef {
+        let id = self.id.as_u64();
+        #[cfg(feature = "sched_rt")]
+        let task = Arc::new(AxTask::new(scheduler::RTTask::new(self)));
+        #[cfg(not(feature = "sched_rt"))]
+        let task = Arc::new(AxTask::new(self));
+        TASK_LIST.lock().insert(id, Arc::downgrade(&task));
+        task
     }
 
     #[inline]
@@ -165,6 +265,15 @@ impl TaskInner {
         self.state.store(state as u8, Ordering::Release)
     }
 
+    /// Changes the state from `from` to `to`, returns whether the task was in
+    /// the `from` state.
+    #[inline]
+    pub(crate) fn transition_state(&self, from: TaskState, to: TaskState) -> bool {
+        self.state
+            .compare_exchange(from as u8, to as u8, Ordering::AcqRel, Ordering::Acquire)
+            .is_ok()
+    }
+
     #[inline]
     pub(crate) fn is_running(&self) -> bool {
         matches!(self.state(), TaskState::Running)
@@ -175,11 +284,6 @@ impl TaskInner {
         matches!(self.state(), TaskState::Ready)
     }
 
-    #[inline]
-    pub(crate) fn is_blocked(&self) -> bool {
-        matches!(self.state(), TaskState::Blocked)
-    }
-
     #[inline]
     pub(crate) const fn is_init(&self) -> bool {
         self.is_init
@@ -190,6 +294,28 @@ impl TaskInner {
         self.is_idle
     }
 
+    #[inline]
+    pub(crate) fn set_cpu_id(&self, cpu_id: usize) {
+        self.cpu_id.store(cpu_id, Ordering::Release);
+    }
+
+    #[inline]
+    pub(crate) fn set_cpumask(&self, cpumask: CpuMask) {
+        *self.cpumask.lock() = cpumask;
+    }
+
+    #[inline]
+    #[cfg(feature = "smp")]
+    pub(crate) fn on_cpu(&self) -> bool {
+        self.on_cpu.load(Ordering::Acquire)
+    }
+
+    #[inline]
+    #[cfg(feature = "smp")]
+    pub(crate) fn set_on_cpu(&self, on_cpu: bool) {
+        self.on_cpu.store(on_cpu, Ordering::Release);
+    }
+
     #[inline]
     pub(crate) fn in_wait_queue(&self) -> bool {
         self.in_wait_queue.load(Ordering::Acquire)
@@ -243,16 +369,16 @@ impl TaskInner {
     fn current_check_preempt_pending() {
         let curr = crate::current();
         if curr.need_resched.load(Ordering::Acquire) && curr.can_preempt(0) {
-            let mut rq = crate::RUN_QUEUE.lock();
+            let rq = crate::current_run_queue();
             if curr.need_resched.load(Ordering::Acquire) {
                 rq.preempt_resched();
             }
         }
     }
 
-    pub(crate) fn notify_exit(&self, exit_code: i32, rq: &mut AxRunQueue) {
+    pub(crate) fn notify_exit(&self, exit_code: i32) {
         self.exit_code.store(exit_code, Ordering::Release);
-        self.wait_for_exit.notify_all_locked(false, rq);
+        self.wait_for_exit.notify_all(false);
     }
 
     #[inline]
@@ -274,9 +400,19 @@ impl fmt::Debug for TaskInner {
 impl Drop for TaskInner {
     fn drop(&mut self) {
         debug!("task drop: {}", self.id_name());
+        TASK_LIST.lock().remove(&self.id.as_u64());
     }
 }
 
+/// Returns all tasks that have not been dropped, in the order of their IDs.
+pub(crate) fn all_tasks() -> Vec<AxTaskRef> {
+    TASK_LIST
+        .lock()
+        .values()
+        .filter_map(Weak::upgrade)
+        .collect()
+}
+
 struct TaskStack {
     ptr: NonNull<u8>,
     layout: Layout,
@@ -355,8 +491,8 @@ impl Deref for CurrentTask {
 }
 
 extern "C" fn task_entry() -> ! {
-    // release the lock that was implicitly held across the reschedule
-    unsafe { crate::RUN_QUEUE.force_unlock() };
+    #[cfg(feature = "smp")]
+    crate::run_queue::clear_prev_task_on_cpu();
     #[cfg(feature = "irq")]
     axhal::arch::enable_irqs();
     let task = crate::current();
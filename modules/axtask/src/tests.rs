@@ -128,3 +128,101 @@ fn test_task_join() {
         assert_eq!(tasks[i].join(), Some(i as _));
     }
 }
+
+#[test]
+fn test_all_tasks() {
+    let _lock = SERIAL.lock();
+    INIT.call_once(axtask::init_scheduler);
+
+    let find = |id: u64| {
+        axtask::all_tasks()
+            .into_iter()
+            .find(|t| t.id().as_u64() == id)
+    };
+    let curr = find(current().id().as_u64()).unwrap();
+    assert_eq!(curr.state(), axtask::TaskState::Running);
+
+    let task = axtask::spawn_raw(axtask::yield_now, "list".into(), 0x1000);
+    let id = task.id().as_u64();
+    let found = find(id).unwrap();
+    assert_eq!(
+        (found.name(), found.state()),
+        ("list", axtask::TaskState::Ready)
+    );
+    drop(found);
+
+    assert_eq!(task.join(), Some(0));
+    assert_eq!(find(id).unwrap().state(), axtask::TaskState::Exited);
+    drop(task);
+    while find(id).is_some() {
+        axtask::yield_now(); // wait for the gc task to drop it
+    }
+}
+
+#[test]
+fn test_task_locals() {
+    let _lock = SERIAL.lock();
+    INIT.call_once(axtask::init_scheduler);
+
+    #[derive(Clone, Default)]
+    struct Dir(String);
+
+    current().with_local(|dir: &mut Dir| dir.0 = "/parent".into());
+    let task = axtask::spawn(|| {
+        // copied from the parent, but changed independently
+        let parent = current().with_local(|dir: &mut Dir| dir.0.clone());
+        current().with_local(|dir: &mut Dir| dir.0 = "/child".into());
+        let grandchild = axtask::spawn(|| {
+            let dir = current().with_local(|dir: &mut Dir| dir.0.clone());
+            axtask::exit((dir == "/child") as _);
+        });
+        assert_eq!(grandchild.join(), Some(1));
+        axtask::exit((parent == "/parent") as _);
+    });
+    assert_eq!(task.join(), Some(1));
+    let dir = current().with_local(|dir: &mut Dir| dir.0.clone());
+    assert_eq!(dir, "/parent");
+    assert_eq!(current().with_local(|n: &mut u32| *n), 0);
+}
+
+#[test]
+fn test_affinity() {
+    let _lock = SERIAL.lock();
+    INIT.call_once(axtask::init_scheduler);
+
+    let cpu0 = axtask::CpuMask::one_shot(0);
+    assert_eq!(axtask::get_affinity(), axtask::CpuMask::full());
+    assert_eq!(cpu0.iter().collect::<Vec<_>>(), [0]);
+    assert_eq!(
+        axtask::CpuMask::from_raw_bits(&[!0]),
+        axtask::CpuMask::full()
+    );
+    assert!(!axtask::set_affinity(axtask::CpuMask::new()));
+
+    let task = axtask::spawn_on(0, || {
+        let inherited = axtask::spawn(|| axtask::exit(axtask::get_affinity().len() as _));
+        assert_eq!(inherited.cpumask(), axtask::CpuMask::one_shot(0));
+        assert_eq!(inherited.join(), Some(1));
+        axtask::exit(current().cpu_id() as _);
+    });
+    assert_eq!(task.cpumask(), cpu0);
+    assert_eq!(task.join(), Some(0));
+
+    assert!(axtask::set_affinity(cpu0));
+    assert_eq!(axtask::get_affinity(), cpu0);
+    axtask::yield_now();
+    assert!(axtask::set_affinity(axtask::CpuMask::full()));
+}
+
+#[test]
+fn test_priority() {
+    let _lock = SERIAL.lock();
+    INIT.call_once(axtask::init_scheduler);
+
+    // the FIFO scheduler does not support priorities
+    let task = axtask::spawn(axtask::yield_now);
+    assert!(!axtask::set_priority(&task, 5));
+    assert_eq!(axtask::get_priority(&task), 0);
+    assert!(!axtask::set_priority(current().as_task_ref(), -5));
+    assert_eq!(task.join(), Some(0));
+}
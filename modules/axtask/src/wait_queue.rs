@@ -1,8 +1,9 @@
 use alloc::collections::VecDeque;
 use alloc::sync::Arc;
+use kernel_guard::IrqSave;
 use spinlock::SpinRaw;
 
-use crate::{AxRunQueue, AxTaskRef, CurrentTask, RUN_QUEUE};
+use crate::{current_run_queue, select_run_queue, AxTaskRef, CurrentTask};
 
 /// A queue to store sleeping tasks.
 ///
@@ -27,7 +28,7 @@ use crate::{AxRunQueue, AxTaskRef, CurrentTask, RUN_QUEUE};
 /// assert_eq!(VALUE.load(Ordering::Relaxed), 1);
 /// ```
 pub struct WaitQueue {
-    queue: SpinRaw<VecDeque<AxTaskRef>>, // we already disabled IRQs when lock the `RUN_QUEUE`
+    queue: SpinRaw<VecDeque<AxTaskRef>>, // locked with IRQs disabled
 }
 
 impl WaitQueue {
@@ -50,8 +51,7 @@ impl WaitQueue {
         // the event from another queue.
         if curr.in_wait_queue() {
             // wake up by timer (timeout).
-            // `RUN_QUEUE` is not locked here, so disable IRQs.
-            let _guard = kernel_guard::IrqSave::new();
+            let _guard = IrqSave::new();
             self.queue.lock().retain(|t| !curr.ptr_eq(t));
             curr.set_in_wait_queue(false);
         }
@@ -65,7 +65,7 @@ impl WaitQueue {
     /// Blocks the current task and put it into the wait queue, until other task
     /// notifies it.
     pub fn wait(&self) {
-        RUN_QUEUE.lock().block_current(|task| {
+        current_run_queue().block_current(|task| {
             task.set_in_wait_queue(true);
             self.queue.lock().push_back(task)
         });
@@ -82,13 +82,16 @@ impl WaitQueue {
         F: Fn() -> bool,
     {
         loop {
-            let mut rq = RUN_QUEUE.lock();
+            let rq = current_run_queue();
+            // the wait queue is locked until the task is in it, so that it is
+            // not notified between checking the condition and blocking
+            let mut wq = self.queue.lock();
             if condition() {
                 break;
             }
-            rq.block_current(|task| {
+            rq.block_current(move |task| {
                 task.set_in_wait_queue(true);
-                self.queue.lock().push_back(task);
+                wq.push_back(task);
             });
         }
         self.cancel_events(crate::current());
@@ -105,11 +108,12 @@ impl WaitQueue {
             curr.id_name(),
             deadline
         );
-        crate::timers::set_alarm_wakeup(deadline, curr.clone());
 
-        RUN_QUEUE.lock().block_current(|task| {
+        current_run_queue().block_current(|task| {
             task.set_in_wait_queue(true);
-            self.queue.lock().push_back(task)
+            self.queue.lock().push_back(task.clone());
+            // the alarm may fire on another CPU at once
+            crate::timers::set_alarm_wakeup(deadline, task);
         });
         let timeout = curr.in_wait_queue(); // still in the wait queue, must have timed out
         self.cancel_events(curr);
@@ -133,18 +137,23 @@ impl WaitQueue {
             curr.id_name(),
             deadline
         );
-        crate::timers::set_alarm_wakeup(deadline, curr.clone());
 
         let mut timeout = true;
         while axhal::time::current_time() < deadline {
-            let mut rq = RUN_QUEUE.lock();
+            let rq = current_run_queue();
+            let mut wq = self.queue.lock();
             if condition() {
                 timeout = false;
                 break;
             }
-            rq.block_current(|task| {
+            rq.block_current(move |task| {
                 task.set_in_wait_queue(true);
-                self.queue.lock().push_back(task);
+                wq.push_back(task.clone());
+                drop(wq);
+                // the alarm is used up if it fired while the task was running
+                if !task.in_timer_list() {
+                    crate::timers::set_alarm_wakeup(deadline, task);
+                }
             });
         }
         self.cancel_events(curr);
@@ -156,9 +165,13 @@ impl WaitQueue {
     /// If `resched` is true, the current task will be preempted when the
     /// preemption is enabled.
     pub fn notify_one(&self, resched: bool) -> bool {
-        let mut rq = RUN_QUEUE.lock();
-        if !self.queue.lock().is_empty() {
-            self.notify_one_locked(resched, &mut rq)
+        let task = {
+            let _guard = IrqSave::new();
+            self.queue.lock().pop_front()
+        };
+        if let Some(task) = task {
+            unblock(task, resched);
+            true
         } else {
             false
         }
@@ -169,16 +182,7 @@ impl WaitQueue {
     /// If `resched` is true, the current task will be preempted when the
     /// preemption is enabled.
     pub fn notify_all(&self, resched: bool) {
-        loop {
-            let mut rq = RUN_QUEUE.lock();
-            if let Some(task) = self.queue.lock().pop_front() {
-                task.set_in_wait_queue(false);
-                rq.unblock_task(task, resched);
-            } else {
-                break;
-            }
-            drop(rq); // we must unlock `RUN_QUEUE` after unlocking `self.queue`.
-        }
+        while self.notify_one(resched) {}
     }
 
     /// Wake up the given task in the wait queue.
@@ -186,31 +190,23 @@ impl WaitQueue {
     /// If `resched` is true, the current task will be preempted when the
     /// preemption is enabled.
     pub fn notify_task(&mut self, resched: bool, task: &AxTaskRef) -> bool {
-        let mut rq = RUN_QUEUE.lock();
-        let mut wq = self.queue.lock();
-        if let Some(index) = wq.iter().position(|t| Arc::ptr_eq(t, task)) {
-            task.set_in_wait_queue(false);
-            rq.unblock_task(wq.remove(index).unwrap(), resched);
-            true
-        } else {
-            false
-        }
-    }
-
-    pub(crate) fn notify_one_locked(&self, resched: bool, rq: &mut AxRunQueue) -> bool {
-        if let Some(task) = self.queue.lock().pop_front() {
-            task.set_in_wait_queue(false);
-            rq.unblock_task(task, resched);
+        let task = {
+            let _guard = IrqSave::new();
+            let mut wq = self.queue.lock();
+            let index = wq.iter().position(|t| Arc::ptr_eq(t, task));
+            index.and_then(|index| wq.remove(index))
+        };
+        if let Some(task) = task {
+            unblock(task, resched);
             true
         } else {
             false
         }
     }
+}
 
-    pub(crate) fn notify_all_locked(&self, resched: bool, rq: &mut AxRunQueue) {
-        while let Some(task) = self.queue.lock().pop_front() {
-            task.set_in_wait_queue(false);
-            rq.unblock_task(task, resched);
-        }
-    }
+/// Wakes up a task removed from a wait queue, on the CPU it last ran on.
+fn unblock(task: AxTaskRef, resched: bool) {
+    task.set_in_wait_queue(false);
+    select_run_queue(&task).unblock_task(task, resched);
 }
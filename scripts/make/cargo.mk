@@ -21,6 +21,11 @@ else
 endif
 
 features-$(FS) += libax/fs
+
+ifneq ($(INITRAMFS),)
+  features-y += libax/initramfs
+  export AX_INITRAMFS := $(abspath $(INITRAMFS))
+endif
 features-$(NET) += libax/net
 features-$(GRAPHIC) += libax/display
 
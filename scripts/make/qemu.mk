@@ -26,9 +26,11 @@ qemu_args-aarch64 := \
 
 qemu_args-y := -m 128M -smp $(SMP) $(qemu_args-$(ARCH))
 
-qemu_args-$(FS) += \
-  -device virtio-blk-$(vdev-suffix),drive=disk0 \
-  -drive id=disk0,if=none,format=raw,file=$(DISK_IMG)
+ifneq ($(DISK_IMG),)
+  qemu_args-$(FS) += \
+    -device virtio-blk-$(vdev-suffix),drive=disk0 \
+    -drive id=disk0,if=none,format=raw,file=$(DISK_IMG)
+endif
 
 qemu_args-$(NET) += \
   -device virtio-net-$(vdev-suffix),netdev=net0 \
@@ -24,6 +24,9 @@ struct dirent {
 
 int closedir(DIR *);
 DIR *fdopendir(int);
+DIR *opendir(const char *);
+struct dirent *readdir(DIR *);
+void rewinddir(DIR *);
 int dirfd(DIR *);
 
 #define DT_UNKNOWN 0
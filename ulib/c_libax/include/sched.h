@@ -0,0 +1,36 @@
+#ifndef _SCHED_H
+#define _SCHED_H
+
+#include <stddef.h>
+#include <string.h>
+#include <sys/types.h>
+
+#define CPU_SETSIZE 1024
+
+typedef struct cpu_set_t {
+    unsigned long __bits[CPU_SETSIZE / 8 / sizeof(long)];
+} cpu_set_t;
+
+#define __CPU_WORD(i) ((i) / 8 / sizeof(long))
+#define __CPU_BIT(i)  (1UL << ((i) % (8 * sizeof(long))))
+#define __CPU_op_S(i, size, set, op)                                                     \
+    ((i) / 8U >= (size) ? 0 : (((unsigned long *)(set))[__CPU_WORD(i)] op __CPU_BIT(i)))
+
+#define CPU_SET_S(i, size, set)   __CPU_op_S(i, size, set, |=)
+#define CPU_CLR_S(i, size, set)   __CPU_op_S(i, size, set, &= ~)
+#define CPU_ISSET_S(i, size, set) (__CPU_op_S(i, size, set, &) != 0)
+#define CPU_ZERO_S(size, set)     memset(set, 0, size)
+
+#define CPU_SET(i, set)   CPU_SET_S(i, sizeof(cpu_set_t), set)
+#define CPU_CLR(i, set)   CPU_CLR_S(i, sizeof(cpu_set_t), set)
+#define CPU_ISSET(i, set) CPU_ISSET_S(i, sizeof(cpu_set_t), set)
+#define CPU_ZERO(set)     CPU_ZERO_S(sizeof(cpu_set_t), set)
+
+#ifdef AX_CONFIG_MULTITASK
+
+int sched_setaffinity(pid_t pid, size_t cpusetsize, const cpu_set_t *mask);
+int sched_getaffinity(pid_t pid, size_t cpusetsize, cpu_set_t *mask);
+
+#endif // AX_CONFIG_MULTITASK
+
+#endif // _SCHED_H
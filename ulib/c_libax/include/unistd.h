@@ -18,6 +18,7 @@ int ftruncate(int fd, off_t length);
 
 int access(const char *pathname, int mode);
 char *getcwd(char *buf, size_t size);
+int chdir(const char *path);
 off_t lseek(int fd, off_t offset, int whence);
 int fsync(int fd);
 int fchown(int fd, uid_t owner, gid_t group);
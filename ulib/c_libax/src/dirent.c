@@ -1,6 +1,7 @@
 #include <dirent.h>
 #include <errno.h>
 #include <fcntl.h>
+#include <libax.h>
 #include <stdio.h>
 #include <stdlib.h>
 #include <string.h>
@@ -40,6 +41,48 @@ DIR *fdopendir(int fd)
     return dir;
 }
 
+#ifdef AX_CONFIG_FS
+DIR *opendir(const char *name)
+{
+    int fd;
+    DIR *dir;
+
+    if ((fd = open(name, O_RDONLY | O_DIRECTORY)) < 0) {
+        return 0;
+    }
+    if (!(dir = fdopendir(fd))) {
+        close(fd);
+        return 0;
+    }
+    return dir;
+}
+
+struct dirent *readdir(DIR *dir)
+{
+    struct dirent *de;
+
+    if (dir->buf_pos >= dir->buf_end) {
+        int len = ax_getdents64(dir->fd, dir->buf, sizeof dir->buf);
+        if (len <= 0) {
+            return 0;
+        }
+        dir->buf_end = len;
+        dir->buf_pos = 0;
+    }
+    de = (void *)(dir->buf + dir->buf_pos);
+    dir->buf_pos += de->d_reclen;
+    dir->tell = de->d_off;
+    return de;
+}
+
+void rewinddir(DIR *dir)
+{
+    lseek(dir->fd, 0, SEEK_SET);
+    dir->buf_pos = dir->buf_end = 0;
+    dir->tell = 0;
+}
+#endif
+
 #endif
 
 int dirfd(DIR *d)
@@ -1,5 +1,21 @@
+#include <libax.h>
 #include <stddef.h>
 #include <stdio.h>
+#include <sys/mman.h>
+
+#if defined(AX_CONFIG_FS) && defined(AX_CONFIG_PAGING)
+
+void *mmap(void *addr, size_t len, int prot, int flags, int fildes, off_t off)
+{
+    return ax_mmap(addr, len, prot, flags, fildes, off);
+}
+
+int munmap(void *addr, size_t length)
+{
+    return ax_munmap(addr, length);
+}
+
+#else
 
 // TODO:
 void *mmap(void *addr, size_t len, int prot, int flags, int fildes, off_t off)
@@ -15,6 +31,8 @@ int munmap(void *addr, size_t length)
     return 0;
 }
 
+#endif
+
 // TODO:
 void *mremap(void *old_address, size_t old_size, size_t new_size, int flags,
              ... /* void *new_address */)
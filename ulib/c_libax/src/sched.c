@@ -0,0 +1,16 @@
+#include <libax.h>
+#include <sched.h>
+
+#ifdef AX_CONFIG_MULTITASK
+
+int sched_setaffinity(pid_t pid, size_t cpusetsize, const cpu_set_t *mask)
+{
+    return ax_sched_setaffinity(pid, cpusetsize, mask);
+}
+
+int sched_getaffinity(pid_t pid, size_t cpusetsize, cpu_set_t *mask)
+{
+    return ax_sched_getaffinity(pid, cpusetsize, mask);
+}
+
+#endif // AX_CONFIG_MULTITASK
@@ -1,16 +1,24 @@
+#include <libax.h>
 #include <stdio.h>
+#include <sys/stat.h>
+#include <sys/statfs.h>
 #include <sys/types.h>
 
-// TODO:
+#ifdef AX_CONFIG_FS
+
 int fchmod(int fd, mode_t mode)
 {
-    unimplemented();
-    return 0;
+    return ax_fchmod(fd, mode);
+}
+
+int statfs(const char *path, struct statfs *buf)
+{
+    return ax_statfs(path, buf);
 }
 
-// TODO:
 int mkdir(const char *pathname, mode_t mode)
 {
-    unimplemented();
-    return 0;
+    return ax_mkdir(pathname, mode);
 }
+
+#endif
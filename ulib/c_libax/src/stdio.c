@@ -229,3 +229,12 @@ char *fgets(char *restrict s, int n, FILE *restrict f)
 }
 
 #endif
+
+#ifdef AX_CONFIG_FS
+
+int rename(const char *old, const char *new)
+{
+    return ax_rename(old, new);
+}
+
+#endif
@@ -160,13 +160,15 @@ int gettimeofday(struct timeval *tv, struct timezone *tz)
     return 0;
 }
 
-// TODO:
+#ifdef AX_CONFIG_FS
+
 int utimes(const char *filename, const struct timeval times[2])
 {
-    unimplemented();
-    return 0;
+    return ax_utimes(filename, times);
 }
 
+#endif
+
 // TODO: Should match _clk,
 int clock_gettime(clockid_t _clk, struct timespec *ts)
 {
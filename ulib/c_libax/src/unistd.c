@@ -97,18 +97,19 @@ ssize_t readlink(const char *path, char *buf, size_t bufsiz)
     return 0;
 }
 
-// TODO:
 int unlink(const char *pathname)
 {
-    unimplemented();
-    return 0;
+    return ax_unlink(pathname);
 }
 
-// TODO:
 int rmdir(const char *pathname)
 {
-    unimplemented();
-    return 0;
+    return ax_rmdir(pathname);
+}
+
+int chdir(const char *path)
+{
+    return ax_chdir(path);
 }
 
 off_t lseek(int fd, off_t offset, int whence)
@@ -116,25 +117,19 @@ off_t lseek(int fd, off_t offset, int whence)
     return ax_lseek(fd, offset, whence);
 }
 
-// TODO:
 int fsync(int fd)
 {
-    unimplemented();
-    return 0;
+    return ax_fsync(fd);
 }
 
-// TODO:
 int fchown(int fd, uid_t owner, gid_t group)
 {
-    unimplemented("owner: %x group: %x", owner, group);
-    return 0;
+    return ax_fchown(fd, owner, group);
 }
 
-// TODO:
 int ftruncate(int fd, off_t length)
 {
-    unimplemented();
-    return 0;
+    return ax_ftruncate(fd, length);
 }
 
 #endif
@@ -57,6 +57,8 @@ typedef struct {{
         let include_dir = crate_dir.join("../c_libax/include");
         let allow_types = [
             "stat",
+            "statfs",
+            "dirent",
             "size_t",
             "ssize_t",
             "off_t",
@@ -68,6 +70,7 @@ typedef struct {{
             "fd.*",
             "timeval",
             "pthread_.*",
+            "cpu_set_t",
             "epoll_event",
         ];
         let allow_vars = [
@@ -82,6 +85,8 @@ typedef struct {{
             "SOL_.*",
             "EPOLL_CTL_.*",
             "EPOLL.*",
+            "PROT_.*",
+            "MAP_.*",
         ];
 
         #[derive(Debug)]
@@ -5,7 +5,7 @@ use axerrno::{LinuxError, LinuxResult};
 
 use core::ffi::{c_int, c_void};
 use flatten_objects::FlattenObjects;
-use spin::RwLock;
+use spin::Mutex;
 
 pub const AX_FILE_LIMIT: usize = 1024;
 
@@ -18,32 +18,71 @@ pub trait FileLike: Send + Sync {
     fn set_nonblocking(&self, nonblocking: bool) -> LinuxResult;
 }
 
-lazy_static::lazy_static! {
-    static ref FD_TABLE: RwLock<FlattenObjects<Arc<dyn FileLike>, AX_FILE_LIMIT>> = {
+/// The file descriptor table of a task.
+///
+/// It is stored in each task if multitasking is enabled, and new tasks start
+/// with a copy of the table of the task that spawns them. The table is shared
+/// until one of the tasks changes it, so spawning a task does not copy it.
+#[derive(Clone)]
+struct FdTable(Arc<FileTable>);
+
+type FileTable = FlattenObjects<Arc<dyn FileLike>, AX_FILE_LIMIT>;
+
+impl Default for FdTable {
+    fn default() -> Self {
         let mut fd_table = FlattenObjects::new();
         fd_table.add_at(0, Arc::new(stdin()) as _).unwrap(); // stdin
         fd_table.add_at(1, Arc::new(stdout()) as _).unwrap(); // stdout
         fd_table.add_at(2, Arc::new(stdout()) as _).unwrap(); // stderr
-        RwLock::new(fd_table)
-    };
+        Self(Arc::new(fd_table))
+    }
+}
+
+impl FdTable {
+    /// Returns the table for modification, copying it first if it is shared
+    /// with other tasks.
+    fn get_mut(&mut self) -> &mut FileTable {
+        if Arc::get_mut(&mut self.0).is_none() {
+            let mut fd_table = FlattenObjects::new();
+            for fd in 0..AX_FILE_LIMIT {
+                if let Some(f) = self.0.get(fd) {
+                    fd_table.add_at(fd, f.clone());
+                }
+            }
+            self.0 = Arc::new(fd_table);
+        }
+        Arc::get_mut(&mut self.0).unwrap()
+    }
+}
+
+lazy_static::lazy_static! {
+    /// The file descriptor table before the scheduler is initialized, or of
+    /// the only task if multitasking is disabled.
+    static ref INIT_FD_TABLE: Mutex<FdTable> = Mutex::new(FdTable::default());
+}
+
+/// Calls `f` with the file descriptor table of the current task.
+///
+/// Files removed from the table should be dropped after it returns, as
+/// closing them may block.
+fn with_fd_table<R>(f: impl FnOnce(&mut FdTable) -> R) -> R {
+    #[cfg(feature = "multitask")]
+    if let Some(curr) = axtask::current_may_uninit() {
+        return curr.with_local(f);
+    }
+    f(&mut INIT_FD_TABLE.lock())
 }
 
 pub fn get_file_like(fd: c_int) -> LinuxResult<Arc<dyn FileLike>> {
-    FD_TABLE
-        .read()
-        .get(fd as usize)
-        .cloned()
-        .ok_or(LinuxError::EBADF)
+    with_fd_table(|fd_table| fd_table.0.get(fd as usize).cloned()).ok_or(LinuxError::EBADF)
 }
 
 pub fn add_file_like(f: Arc<dyn FileLike>) -> LinuxResult<c_int> {
-    Ok(FD_TABLE.write().add(f).ok_or(LinuxError::EMFILE)? as _)
+    Ok(with_fd_table(|fd_table| fd_table.get_mut().add(f)).ok_or(LinuxError::EMFILE)? as _)
 }
 
 pub fn close_file_like(fd: c_int) -> LinuxResult {
-    let f = FD_TABLE
-        .write()
-        .remove(fd as usize)
+    let f = with_fd_table(|fd_table| fd_table.get_mut().remove(fd as usize))
         .ok_or(LinuxError::EBADF)?;
     drop(f);
     Ok(())
@@ -138,9 +177,7 @@ pub unsafe extern "C" fn ax_dup3(old_fd: c_int, new_fd: c_int, flags: c_int) ->
         }
 
         let f = get_file_like(old_fd)?;
-        FD_TABLE
-            .write()
-            .add_at(new_fd as usize, f)
+        with_fd_table(|fd_table| fd_table.get_mut().add_at(new_fd as usize, f))
             .ok_or(LinuxError::EMFILE)?;
 
         if flags as u32 & ctypes::O_CLOEXEC != 0 {
@@ -1,11 +1,13 @@
 use alloc::sync::Arc;
 use axerrno::{LinuxError, LinuxResult};
-use core::ffi::{c_char, c_int};
+use axfs::fops::{self, DirEntry, FileAttr};
+use core::ffi::{c_char, c_int, c_void};
 
 use super::{ctypes, fd_ops::FileLike, utils::char_ptr_to_str};
-use crate::fs::OpenOptions;
+use crate::fs::{OpenOptions, Permissions};
 use crate::io::{prelude::*, PollState, SeekFrom};
 use crate::sync::Mutex;
+use crate::time::Instant;
 
 pub struct File(Mutex<crate::fs::File>);
 
@@ -39,21 +41,7 @@ impl FileLike for File {
 
     fn stat(&self) -> LinuxResult<ctypes::stat> {
         let metadata = self.0.lock().metadata()?;
-        let metadata = metadata.raw_metadata();
-        let ty = metadata.file_type() as u8;
-        let perm = metadata.perm().bits() as u32;
-        let st_mode = ((ty as u32) << 12) | perm;
-        Ok(ctypes::stat {
-            st_ino: 1,
-            st_nlink: 1,
-            st_mode,
-            st_uid: 1000,
-            st_gid: 1000,
-            st_size: metadata.size() as _,
-            st_blocks: metadata.blocks() as _,
-            st_blksize: 512,
-            ..Default::default()
-        })
+        Ok(attr_to_stat(metadata.raw_metadata()))
     }
 
     fn into_any(self: Arc<Self>) -> Arc<dyn core::any::Any + Send + Sync> {
@@ -72,6 +60,139 @@ impl FileLike for File {
     }
 }
 
+pub struct Directory(Mutex<fops::Directory>);
+
+impl Directory {
+    fn open(path: &str) -> LinuxResult<Self> {
+        let mut opts = fops::OpenOptions::new();
+        opts.read(true);
+        Ok(Self(Mutex::new(fops::Directory::open_dir(path, &opts)?)))
+    }
+
+    fn add_to_fd_table(self) -> LinuxResult<c_int> {
+        super::fd_ops::add_file_like(Arc::new(self))
+    }
+
+    fn from_fd(fd: c_int) -> LinuxResult<Arc<Self>> {
+        let f = super::fd_ops::get_file_like(fd)?;
+        f.into_any()
+            .downcast::<Self>()
+            .map_err(|_| LinuxError::ENOTDIR)
+    }
+
+    /// Moves the cursor to the entry at the given index, which is also the
+    /// `d_off` of the previous entry.
+    fn seek(&self, pos: SeekFrom) -> LinuxResult<u64> {
+        let mut dir = self.0.lock();
+        let pos = match pos {
+            SeekFrom::Start(pos) => Some(pos),
+            SeekFrom::Current(off) => (dir.position() as u64).checked_add_signed(off),
+            SeekFrom::End(_) => None,
+        };
+        let pos = pos.ok_or(LinuxError::EINVAL)?;
+        dir.seek(pos as usize);
+        Ok(pos)
+    }
+
+    /// Reads as many entries as `buf` can hold as `struct dirent` records,
+    /// returns the number of bytes written.
+    fn read_dirents(&self, buf: &mut [u8]) -> LinuxResult<usize> {
+        use core::mem::offset_of;
+        const NAME_OFFSET: usize = offset_of!(ctypes::dirent, d_name);
+        let mut dir = self.0.lock();
+        let mut entry = [DirEntry::default()];
+        let mut len = 0;
+        loop {
+            let pos = dir.position();
+            if dir.read_dir(&mut entry)? == 0 {
+                break;
+            }
+            let name = entry[0].name_as_bytes();
+            let reclen = (NAME_OFFSET + name.len() + 1).next_multiple_of(8);
+            if len + reclen > buf.len() {
+                // keep the entry for the next read
+                dir.seek(pos);
+                if len == 0 {
+                    return Err(LinuxError::EINVAL);
+                }
+                break;
+            }
+
+            // inode numbers are not available from the entries, the position
+            // is used instead
+            let rec = &mut buf[len..len + reclen];
+            rec.fill(0);
+            let mut put = |offset: usize, bytes: &[u8]| {
+                rec[offset..offset + bytes.len()].copy_from_slice(bytes);
+            };
+            let ino = pos as ctypes::ino_t + 1;
+            let next = pos as ctypes::off_t + 1;
+            let ty = entry[0].entry_type() as u8;
+            put(offset_of!(ctypes::dirent, d_ino), &ino.to_ne_bytes());
+            put(offset_of!(ctypes::dirent, d_off), &next.to_ne_bytes());
+            put(
+                offset_of!(ctypes::dirent, d_reclen),
+                &(reclen as u16).to_ne_bytes(),
+            );
+            put(offset_of!(ctypes::dirent, d_type), &[ty]);
+            put(NAME_OFFSET, name);
+            len += reclen;
+        }
+        Ok(len)
+    }
+}
+
+impl FileLike for Directory {
+    fn read(&self, _buf: &mut [u8]) -> LinuxResult<usize> {
+        Err(LinuxError::EISDIR)
+    }
+
+    fn write(&self, _buf: &[u8]) -> LinuxResult<usize> {
+        Err(LinuxError::EBADF)
+    }
+
+    fn stat(&self) -> LinuxResult<ctypes::stat> {
+        let attr = self.0.lock().get_attr()?;
+        Ok(attr_to_stat(&attr))
+    }
+
+    fn into_any(self: Arc<Self>) -> Arc<dyn core::any::Any + Send + Sync> {
+        self
+    }
+
+    fn poll(&self) -> LinuxResult<PollState> {
+        Ok(PollState {
+            readable: true,
+            writable: false,
+        })
+    }
+
+    fn set_nonblocking(&self, _nonblocking: bool) -> LinuxResult {
+        Ok(())
+    }
+}
+
+/// Convert [`FileAttr`] to `struct stat`.
+fn attr_to_stat(attr: &FileAttr) -> ctypes::stat {
+    let ty = attr.file_type() as u8;
+    let perm = attr.perm().bits() as u32;
+    let st_mode = ((ty as u32) << 12) | perm;
+    ctypes::stat {
+        st_ino: attr.ino() as _,
+        st_nlink: attr.nlink() as _,
+        st_mode,
+        st_uid: attr.uid() as _,
+        st_gid: attr.gid() as _,
+        st_size: attr.size() as _,
+        st_blocks: attr.blocks() as _,
+        st_blksize: 512,
+        st_atim: attr.atime().into(),
+        st_mtim: attr.mtime().into(),
+        st_ctim: attr.ctime().into(),
+        ..Default::default()
+    }
+}
+
 /// Convert open flags to [`OpenOptions`].
 fn flags_to_options(flags: c_int, _mode: ctypes::mode_t) -> OpenOptions {
     let flags = flags as u32;
@@ -96,7 +217,11 @@ fn flags_to_options(flags: c_int, _mode: ctypes::mode_t) -> OpenOptions {
     options
 }
 
-/// Open a file by `filename` and insert it into the file descriptor table.
+/// Open a file or directory by `filename` and insert it into the file
+/// descriptor table.
+///
+/// Directories are opened as such if `O_DIRECTORY` is given, or if they are
+/// opened read-only, so that their entries can be read by `ax_getdents64`.
 ///
 /// Return its index in the file table (`fd`). Return `EMFILE` if it already
 /// has the maximum number of files open.
@@ -109,8 +234,15 @@ pub unsafe extern "C" fn ax_open(
     let filename = char_ptr_to_str(filename);
     debug!("ax_open <= {:?} {:#o} {:#o}", filename, flags, mode);
     ax_call_body!(ax_open, {
+        let filename = filename?;
+        let read_only = flags as u32 & 0b11 == ctypes::O_RDONLY;
+        if flags as u32 & ctypes::O_DIRECTORY != 0
+            || (read_only && crate::fs::metadata(filename).is_ok_and(|md| md.is_dir()))
+        {
+            return Directory::open(filename)?.add_to_fd_table();
+        }
         let options = flags_to_options(flags, mode);
-        let file = options.open(filename?)?;
+        let file = options.open(filename)?;
         File::new(file).add_to_fd_table()
     })
 }
@@ -132,11 +264,59 @@ pub unsafe extern "C" fn ax_lseek(
             2 => SeekFrom::End(offset as _),
             _ => return Err(LinuxError::EINVAL),
         };
+        if let Ok(dir) = Directory::from_fd(fd) {
+            return dir.seek(pos);
+        }
         let off = File::from_fd(fd)?.0.lock().seek(pos)?;
         Ok(off)
     })
 }
 
+/// Read the entries of the directory indicated by `fd` into `buf` as
+/// `struct dirent` records, each of which is `d_reclen` bytes long.
+///
+/// Return the number of bytes read, or 0 at the end of the directory.
+#[no_mangle]
+pub unsafe extern "C" fn ax_getdents64(fd: c_int, buf: *mut c_void, len: usize) -> ctypes::ssize_t {
+    debug!("ax_getdents64 <= {} {:#x} {}", fd, buf as usize, len);
+    ax_call_body!(ax_getdents64, {
+        if buf.is_null() {
+            return Err(LinuxError::EFAULT);
+        }
+        let dst = unsafe { core::slice::from_raw_parts_mut(buf as *mut u8, len) };
+        Directory::from_fd(fd)?.read_dirents(dst)
+    })
+}
+
+/// Truncate or extend the file indicated by `fd` to `length` bytes.
+///
+/// Return 0 if success.
+#[no_mangle]
+pub unsafe extern "C" fn ax_ftruncate(fd: c_int, length: ctypes::off_t) -> c_int {
+    debug!("ax_ftruncate <= {} {}", fd, length);
+    ax_call_body!(ax_ftruncate, {
+        if length < 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        File::from_fd(fd)?.0.lock().set_len(length as u64)?;
+        Ok(0)
+    })
+}
+
+/// Write the modified data of the file indicated by `fd` to the disk.
+///
+/// Return 0 if success.
+#[no_mangle]
+pub unsafe extern "C" fn ax_fsync(fd: c_int) -> c_int {
+    debug!("ax_fsync <= {}", fd);
+    ax_call_body!(ax_fsync, {
+        if Directory::from_fd(fd).is_err() {
+            File::from_fd(fd)?.0.lock().flush()?;
+        }
+        Ok(0)
+    })
+}
+
 /// Get the file metadata by `path` and write into `buf`.
 ///
 /// Return 0 if success.
@@ -166,7 +346,160 @@ pub unsafe extern "C" fn ax_lstat(path: *const c_char, buf: *mut ctypes::stat) -
         if buf.is_null() {
             return Err(LinuxError::EFAULT);
         }
-        unsafe { *buf = Default::default() }; // TODO
+        let metadata = crate::fs::symlink_metadata(path?)?;
+        unsafe { *buf = attr_to_stat(metadata.raw_metadata()) };
+        Ok(0)
+    })
+}
+
+/// Get the attributes of the filesystem containing `path` and write into
+/// `buf`.
+///
+/// Return 0 if success.
+#[no_mangle]
+pub unsafe extern "C" fn ax_statfs(path: *const c_char, buf: *mut ctypes::statfs) -> c_int {
+    let path = char_ptr_to_str(path);
+    debug!("ax_statfs <= {:?} {:#x}", path, buf as usize);
+    ax_call_body!(ax_statfs, {
+        if buf.is_null() {
+            return Err(LinuxError::EFAULT);
+        }
+        let info = crate::fs::statfs(path?)?;
+        unsafe {
+            *buf = ctypes::statfs {
+                f_type: info.fs_type as _,
+                f_bsize: info.block_size as _,
+                f_blocks: info.blocks,
+                f_bfree: info.blocks_free,
+                f_bavail: info.blocks_avail,
+                f_files: info.files,
+                f_ffree: info.files_free,
+                f_namelen: info.name_max as _,
+                f_frsize: info.block_size as _,
+                ..Default::default()
+            }
+        };
+        Ok(0)
+    })
+}
+
+/// Change the permission bits of the file indicated by `fd`.
+///
+/// Return 0 if success.
+#[no_mangle]
+pub unsafe extern "C" fn ax_fchmod(fd: c_int, mode: ctypes::mode_t) -> c_int {
+    debug!("ax_fchmod <= {} {:#o}", fd, mode);
+    ax_call_body!(ax_fchmod, {
+        let perm = Permissions::from_bits_truncate(mode as u16);
+        File::from_fd(fd)?.0.lock().set_permissions(perm)?;
+        Ok(0)
+    })
+}
+
+/// Change the owner and group of the file indicated by `fd`, the ID is not
+/// changed if it is `-1`.
+///
+/// Return 0 if success.
+#[no_mangle]
+pub unsafe extern "C" fn ax_fchown(fd: c_int, owner: ctypes::uid_t, group: ctypes::gid_t) -> c_int {
+    debug!("ax_fchown <= {} {} {}", fd, owner, group);
+    ax_call_body!(ax_fchown, {
+        let uid = (owner != ctypes::uid_t::MAX).then_some(owner);
+        let gid = (group != ctypes::gid_t::MAX).then_some(group);
+        File::from_fd(fd)?.0.lock().set_owner(uid, gid)?;
+        Ok(0)
+    })
+}
+
+/// Change the access and modification times of the file at `path` to
+/// `times[0]` and `times[1]`, or to the current time if `times` is NULL.
+///
+/// Return 0 if success.
+#[no_mangle]
+pub unsafe extern "C" fn ax_utimes(path: *const c_char, times: *const ctypes::timeval) -> c_int {
+    let path = char_ptr_to_str(path);
+    debug!("ax_utimes <= {:?} {:#x}", path, times as usize);
+    ax_call_body!(ax_utimes, {
+        let (atime, mtime) = if times.is_null() {
+            let now = Instant::now().as_duration();
+            (now, now)
+        } else {
+            let times = unsafe { core::slice::from_raw_parts(times, 2) };
+            (times[0].into(), times[1].into())
+        };
+        crate::fs::set_times(path?, Some(atime), Some(mtime))?;
+        Ok(0)
+    })
+}
+
+/// Rename the file or directory `old` to `new`, replacing `new` if it exists.
+///
+/// Return 0 if success.
+#[no_mangle]
+pub unsafe extern "C" fn ax_rename(old: *const c_char, new: *const c_char) -> c_int {
+    let old = char_ptr_to_str(old);
+    let new = char_ptr_to_str(new);
+    debug!("ax_rename <= {:?} {:?}", old, new);
+    ax_call_body!(ax_rename, {
+        crate::fs::rename(old?, new?)?;
+        Ok(0)
+    })
+}
+
+/// Create a directory at `path` with the permission bits `mode`, which are
+/// ignored if the filesystem does not support permissions.
+///
+/// Return 0 if success.
+#[no_mangle]
+pub unsafe extern "C" fn ax_mkdir(path: *const c_char, mode: ctypes::mode_t) -> c_int {
+    let path = char_ptr_to_str(path);
+    debug!("ax_mkdir <= {:?} {:#o}", path, mode);
+    ax_call_body!(ax_mkdir, {
+        let path = path?;
+        crate::fs::create_dir(path)?;
+        // the mode is ignored by filesystems without permissions, e.g. FAT
+        match crate::fs::set_permissions(path, Permissions::from_bits_truncate(mode as u16)) {
+            Ok(()) | Err(crate::io::Error::Unsupported) => Ok(0),
+            Err(e) => Err(e.into()),
+        }
+    })
+}
+
+/// Remove the empty directory at `path`.
+///
+/// Return 0 if success.
+#[no_mangle]
+pub unsafe extern "C" fn ax_rmdir(path: *const c_char) -> c_int {
+    let path = char_ptr_to_str(path);
+    debug!("ax_rmdir <= {:?}", path);
+    ax_call_body!(ax_rmdir, {
+        crate::fs::remove_dir(path?)?;
+        Ok(0)
+    })
+}
+
+/// Remove the file or symbolic link at `path`.
+///
+/// Return 0 if success.
+#[no_mangle]
+pub unsafe extern "C" fn ax_unlink(path: *const c_char) -> c_int {
+    let path = char_ptr_to_str(path);
+    debug!("ax_unlink <= {:?}", path);
+    ax_call_body!(ax_unlink, {
+        crate::fs::remove_file(path?)?;
+        Ok(0)
+    })
+}
+
+/// Change the current directory to `path`.
+///
+/// Return 0 if success.
+#[no_mangle]
+pub unsafe extern "C" fn ax_chdir(path: *const c_char) -> c_int {
+    let path = char_ptr_to_str(path);
+    debug!("ax_chdir <= {:?}", path);
+    ax_call_body!(ax_chdir, {
+        crate::env::set_current_dir(path?)?;
         Ok(0)
     })
 }
@@ -191,3 +524,56 @@ pub unsafe extern "C" fn ax_getcwd(buf: *mut c_char, size: usize) -> *mut c_char
         }
     })
 }
+
+/// Map `len` bytes of the file indicated by `fd` from `off` into memory.
+///
+/// Only shared mappings, or private ones that are read-only, are supported.
+/// The address is always chosen by the system, `addr` is ignored.
+///
+/// Return the start address of the mapping.
+#[cfg(feature = "paging")]
+#[no_mangle]
+pub unsafe extern "C" fn ax_mmap(
+    addr: *mut c_void,
+    len: usize,
+    prot: c_int,
+    flags: c_int,
+    fd: c_int,
+    off: ctypes::off_t,
+) -> *mut c_void {
+    debug!(
+        "ax_mmap <= {:#x} {} {:#x} {:#x} {} {}",
+        addr as usize, len, prot, flags, fd, off
+    );
+    ax_call_body!(ax_mmap, {
+        let (prot, flags) = (prot as u32, flags as u32);
+        let writable = prot & ctypes::PROT_WRITE != 0;
+        let shared = match flags & ctypes::MAP_TYPE {
+            ctypes::MAP_SHARED | ctypes::MAP_SHARED_VALIDATE => true,
+            ctypes::MAP_PRIVATE => false,
+            _ => return Err(LinuxError::EINVAL),
+        };
+        if flags & (ctypes::MAP_FIXED | ctypes::MAP_ANONYMOUS) != 0 || off < 0 {
+            return Err(LinuxError::EINVAL);
+        } else if writable && !shared {
+            return Err(LinuxError::EOPNOTSUPP);
+        }
+        let file = File::from_fd(fd)?;
+        let vaddr = file.0.lock().mmap(off as u64, len, writable)?;
+        Ok(vaddr as *mut c_void)
+    })
+}
+
+/// Remove the mapping at `addr` created by `ax_mmap`, `len` must be the length
+/// of the whole mapping.
+///
+/// Return 0 if success.
+#[cfg(feature = "paging")]
+#[no_mangle]
+pub unsafe extern "C" fn ax_munmap(addr: *mut c_void, len: usize) -> c_int {
+    debug!("ax_munmap <= {:#x} {}", addr as usize, len);
+    ax_call_body!(ax_munmap, {
+        crate::fs::munmap(addr as usize, len)?;
+        Ok(0)
+    })
+}
@@ -23,6 +23,8 @@ mod malloc;
 mod pipe;
 #[cfg(feature = "multitask")]
 mod pthread;
+#[cfg(feature = "multitask")]
+mod sched;
 #[cfg(feature = "net")]
 mod socket;
 #[cfg(feature = "fp_simd")]
@@ -65,7 +67,13 @@ pub use self::malloc::{ax_free, ax_malloc};
 pub use self::fd_ops::{ax_close, ax_dup, ax_dup3, ax_fcntl, ax_fstat, ax_read, ax_write};
 
 #[cfg(feature = "fs")]
-pub use self::file::{ax_getcwd, ax_lseek, ax_lstat, ax_open, ax_stat};
+pub use self::file::{ax_chdir, ax_fchmod, ax_fchown, ax_fsync, ax_ftruncate, ax_getcwd};
+#[cfg(feature = "fs")]
+pub use self::file::{ax_getdents64, ax_lseek, ax_lstat, ax_mkdir, ax_open, ax_rename};
+#[cfg(feature = "fs")]
+pub use self::file::{ax_rmdir, ax_stat, ax_statfs, ax_unlink, ax_utimes};
+#[cfg(all(feature = "fs", feature = "paging"))]
+pub use self::file::{ax_mmap, ax_munmap};
 
 #[cfg(feature = "net")]
 pub use self::socket::{
@@ -79,6 +87,8 @@ pub use self::pthread::mutex::{
 };
 #[cfg(feature = "multitask")]
 pub use self::pthread::{ax_getpid, ax_pthread_create, ax_pthread_exit, ax_pthread_join};
+#[cfg(feature = "multitask")]
+pub use self::sched::{ax_sched_getaffinity, ax_sched_setaffinity};
 
 #[cfg(feature = "pipe")]
 pub use self::pipe::ax_pipe;
@@ -0,0 +1,68 @@
+use core::ffi::c_int;
+use core::mem::{size_of, size_of_val};
+
+use axerrno::{LinuxError, LinuxResult};
+use axtask::CpuMask;
+
+use super::ctypes;
+
+/// Only the calling thread is supported, whose ID is 0 or its own ID.
+fn check_current(pid: c_int) -> LinuxResult {
+    if pid == 0 || pid as u64 == axtask::current().id().as_u64() {
+        Ok(())
+    } else {
+        Err(LinuxError::ESRCH)
+    }
+}
+
+/// Sets the CPUs that the thread is allowed to run on.
+///
+/// The CPUs beyond `cpusetsize` bytes of `mask`, or not present, are ignored.
+#[no_mangle]
+pub unsafe extern "C" fn ax_sched_setaffinity(
+    pid: c_int,
+    cpusetsize: usize,
+    mask: *const ctypes::cpu_set_t,
+) -> c_int {
+    debug!(
+        "ax_sched_setaffinity <= {} {} {:#x}",
+        pid, cpusetsize, mask as usize
+    );
+    ax_call_body!(ax_sched_setaffinity, {
+        check_current(pid)?;
+        super::utils::check_null_ptr(mask)?;
+        let words = cpusetsize.min(size_of::<ctypes::cpu_set_t>()) / size_of::<usize>();
+        let bits = unsafe { core::slice::from_raw_parts(mask as *const usize, words) };
+        if !axtask::set_affinity(CpuMask::from_raw_bits(bits)) {
+            return Err(LinuxError::EINVAL);
+        }
+        Ok(0)
+    })
+}
+
+/// Gets the CPUs that the thread is allowed to run on.
+#[no_mangle]
+pub unsafe extern "C" fn ax_sched_getaffinity(
+    pid: c_int,
+    cpusetsize: usize,
+    mask: *mut ctypes::cpu_set_t,
+) -> c_int {
+    debug!(
+        "ax_sched_getaffinity <= {} {} {:#x}",
+        pid, cpusetsize, mask as usize
+    );
+    ax_call_body!(ax_sched_getaffinity, {
+        check_current(pid)?;
+        super::utils::check_null_mut_ptr(mask)?;
+        let cpumask = axtask::get_affinity();
+        let bits = cpumask.as_raw_bits();
+        if cpusetsize < size_of_val(bits) || cpusetsize % size_of::<usize>() != 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        let words = cpusetsize / size_of::<usize>();
+        let dst = unsafe { core::slice::from_raw_parts_mut(mask as *mut usize, words) };
+        dst.fill(0);
+        dst[..bits.len()].copy_from_slice(bits);
+        Ok(0)
+    })
+}
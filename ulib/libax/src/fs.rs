@@ -1,5 +1,11 @@
 //! Filesystem manipulation operations.
 
 pub use axfs::api::{canonicalize, metadata, read, read_to_string, remove_file, write};
-pub use axfs::api::{create_dir, create_dir_all, read_dir, remove_dir};
-pub use axfs::api::{DirEntry, File, FileType, Metadata, OpenOptions, Permissions, ReadDir};
+pub use axfs::api::{chown, set_permissions, set_times, symlink_metadata};
+pub use axfs::api::{create_dir, create_dir_all, read_dir, remove_dir, rename};
+pub use axfs::api::{format, mount, statfs, umount};
+
+#[cfg(feature = "paging")]
+pub use axfs::api::munmap;
+pub use axfs::api::{DirEntry, File, FileSystemInfo, FileType, Metadata, OpenOptions};
+pub use axfs::api::{FsType, Permissions, ReadDir};
@@ -9,7 +9,8 @@
 //!     - `fp_simd`: Enable floating point and SIMD support.
 //! - Memory
 //!     - `alloc`: Enable dynamic memory allocation.
-//!     - `paging`: Enable page table manipulation.
+//!     - `paging`: Enable page table manipulation, and memory-mapped files if
+//!       `fs` is enabled.
 //! - Interrupts:
 //!     - `irq`: Enable interrupt handling support. This feature is required for
 //!       some multitask operations, such as [`sync::WaitQueue::wait_timeout`] and
@@ -18,8 +19,16 @@
 //!     - `multitask`: Enable multi-threading support.
 //!     - `sched_fifo`: Use the FIFO cooperative scheduler.
 //!     - `sched_rr`: Use the Round-robin preemptive scheduler.
+//!     - `sched_cfs`: Use the Completely Fair Scheduler.
+//!     - `sched_rt`: Use the real-time scheduler for tasks of real-time
+//!       policies, and the Completely Fair Scheduler for the others.
+//!     - `sched_edf`: Use the Earliest Deadline First scheduler for periodic
+//!       tasks.
 //! - Device and upperlayer stack
 //!     - `fs`: Enable file system support.
+//!     - `initramfs`: Embed the `cpio` or `tar` archive given by the
+//!       `AX_INITRAMFS` environment variable in the kernel image, and use it as
+//!       the root file system if there are no disks.
 //!     - `net`: Enable networking support.
 //!     - `display`: Enable graphics support.
 //!     - `bus-mmio`: Use device tree to probe all MMIO devices.
@@ -6,8 +6,14 @@ use axerrno::ax_err_type;
 use axtask::AxTaskRef;
 use core::cell::UnsafeCell;
 
+#[cfg(feature = "sched_edf")]
+#[doc(cfg(feature = "sched_edf"))]
+pub use axtask::EDFParams;
+#[cfg(feature = "sched_rt")]
+#[doc(cfg(feature = "sched_rt"))]
+pub use axtask::SchedPolicy;
 #[doc(cfg(feature = "multitask"))]
-pub use axtask::{current, set_priority, TaskId as ThreadId};
+pub use axtask::{current, get_affinity, set_affinity, CpuMask, TaskId as ThreadId};
 
 /// Thread factory, which can be used in order to configure the properties of
 /// a new thread.
@@ -20,6 +26,8 @@ pub struct Builder {
     name: Option<String>,
     // The size of the stack for the spawned thread in bytes
     stack_size: Option<usize>,
+    // The CPUs that the spawned thread is allowed to run on
+    affinity: Option<CpuMask>,
 }
 
 impl Builder {
@@ -29,6 +37,7 @@ impl Builder {
         Builder {
             name: None,
             stack_size: None,
+            affinity: None,
         }
     }
 
@@ -44,6 +53,15 @@ impl Builder {
         self
     }
 
+    /// Sets the CPUs that the new thread is allowed to run on.
+    ///
+    /// By default, the new thread is allowed to run on the same CPUs as the
+    /// current thread.
+    pub fn affinity(mut self, cpumask: CpuMask) -> Builder {
+        self.affinity = Some(cpumask);
+        self
+    }
+
     /// Spawns a new thread by taking ownership of the `Builder`, and returns an
     /// [`io::Result`] to its [`JoinHandle`].
     ///
@@ -66,6 +84,9 @@ impl Builder {
         F: Send + 'static,
         T: Send + 'static,
     {
+        if self.affinity.is_some_and(|cpumask| cpumask.is_empty()) {
+            return Err(ax_err_type!(InvalidInput, "empty CPU mask"));
+        }
         let name = self.name.unwrap_or_default();
         let stack_size = self.stack_size.unwrap_or(axconfig::TASK_STACK_SIZE);
 
@@ -84,7 +105,10 @@ impl Builder {
             drop(their_packet);
         };
 
-        let task = axtask::spawn_raw(main, name, stack_size);
+        let task = match self.affinity {
+            Some(cpumask) => axtask::spawn_raw_on(main, name, stack_size, cpumask),
+            None => axtask::spawn_raw(main, name, stack_size),
+        };
         Ok(JoinHandle {
             task,
             packet: my_packet,
@@ -92,6 +116,66 @@ impl Builder {
     }
 }
 
+/// Sets the priority of the current thread.
+///
+/// The range of the priority is dependent on the underlying scheduler. For
+/// example, in the CFS scheduler, the priority is the nice value, ranging from
+/// -20 to 19.
+///
+/// Returns `true` if the priority is set successfully.
+#[doc(cfg(feature = "multitask"))]
+pub fn set_priority(prio: isize) -> bool {
+    axtask::set_priority(current().as_task_ref(), prio)
+}
+
+/// Gets the priority of the current thread.
+#[doc(cfg(feature = "multitask"))]
+pub fn get_priority() -> isize {
+    axtask::get_priority(current().as_task_ref())
+}
+
+/// Sets the scheduling policy and the priority of the current thread.
+///
+/// The priority is the real-time priority from 0 to 99 for real-time policies,
+/// or the nice value from -20 to 19 for the [`Normal`](SchedPolicy::Normal)
+/// policy.
+///
+/// Returns `true` if the policy is set successfully.
+#[cfg(feature = "sched_rt")]
+#[doc(cfg(feature = "sched_rt"))]
+pub fn set_scheduler(policy: SchedPolicy, prio: isize) -> bool {
+    axtask::set_scheduler(current().as_task_ref(), policy, prio)
+}
+
+/// Gets the scheduling policy of the current thread.
+#[cfg(feature = "sched_rt")]
+#[doc(cfg(feature = "sched_rt"))]
+pub fn get_scheduler() -> SchedPolicy {
+    axtask::get_scheduler(current().as_task_ref())
+}
+
+/// Sets the timing parameters of the current thread, in timer ticks, which
+/// makes it a periodic thread of the EDF scheduler. A new job is released
+/// immediately.
+///
+/// The thread is then kept on the current CPU, and [`set_affinity`] fails if
+/// the new CPUs exclude it.
+///
+/// Returns `false` if the parameters are invalid, or the thread fails the
+/// admission test of the current CPU.
+#[cfg(feature = "sched_edf")]
+#[doc(cfg(feature = "sched_edf"))]
+pub fn set_edf_params(params: Option<EDFParams>) -> bool {
+    axtask::set_edf_params(current().as_task_ref(), params)
+}
+
+/// Gets the timing parameters of the current thread.
+#[cfg(feature = "sched_edf")]
+#[doc(cfg(feature = "sched_edf"))]
+pub fn get_edf_params() -> Option<EDFParams> {
+    axtask::get_edf_params(current().as_task_ref())
+}
+
 /// Current thread gives up the CPU time voluntarily, and switches to another
 /// ready thread.
 ///
@@ -171,6 +255,48 @@ impl<T> JoinHandle<T> {
         &self.task
     }
 
+    /// Sets the priority of the associated thread, see [`set_priority`].
+    ///
+    /// Returns `true` if the priority is set successfully.
+    pub fn set_priority(&self, prio: isize) -> bool {
+        axtask::set_priority(&self.task, prio)
+    }
+
+    /// Gets the priority of the associated thread.
+    pub fn get_priority(&self) -> isize {
+        axtask::get_priority(&self.task)
+    }
+
+    /// Sets the scheduling policy and the priority of the associated thread,
+    /// see [`set_scheduler`].
+    #[cfg(feature = "sched_rt")]
+    #[doc(cfg(feature = "sched_rt"))]
+    pub fn set_scheduler(&self, policy: SchedPolicy, prio: isize) -> bool {
+        axtask::set_scheduler(&self.task, policy, prio)
+    }
+
+    /// Gets the scheduling policy of the associated thread.
+    #[cfg(feature = "sched_rt")]
+    #[doc(cfg(feature = "sched_rt"))]
+    pub fn get_scheduler(&self) -> SchedPolicy {
+        axtask::get_scheduler(&self.task)
+    }
+
+    /// Sets the timing parameters of the associated thread, see
+    /// [`set_edf_params`].
+    #[cfg(feature = "sched_edf")]
+    #[doc(cfg(feature = "sched_edf"))]
+    pub fn set_edf_params(&self, params: Option<EDFParams>) -> bool {
+        axtask::set_edf_params(&self.task, params)
+    }
+
+    /// Gets the timing parameters of the associated thread.
+    #[cfg(feature = "sched_edf")]
+    #[doc(cfg(feature = "sched_edf"))]
+    pub fn get_edf_params(&self) -> Option<EDFParams> {
+        axtask::get_edf_params(&self.task)
+    }
+
     /// Waits for the associated thread to finish.
     ///
     /// This function will return immediately if the associated thread has